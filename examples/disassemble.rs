@@ -0,0 +1,33 @@
+//! Print a human readable listing of a raster job file, or of the data
+//! sent to a printer in a pcap / pcapng USB capture.
+//!
+//! ```
+//! cargo run --example disassemble -- label.prn
+//! cargo run --example disassemble -- windows-driver.pcapng
+//! ```
+
+use ql_label::{protocol, UsbCapture};
+
+fn main() {
+    let path = match std::env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: disassemble <file>");
+            std::process::exit(2);
+        }
+    };
+    let listing = if path.ends_with(".pcap") || path.ends_with(".pcapng") {
+        UsbCapture::load(&path).map(|capture| capture.disassemble())
+    } else {
+        std::fs::read(&path)
+            .map(|job| protocol::disassemble(&job))
+            .map_err(|err| format!("Failed to read {}: {}", path, err))
+    };
+    match listing {
+        Ok(listing) => print!("{}", listing),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
@@ -4,6 +4,7 @@ use qrcode::QrCode;
 use std::env;
 
 #[derive(Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 enum PrintOption {
     TestLabelNormalRes,
     TestLabelHighRes,
@@ -156,6 +157,7 @@ fn main() {
     };
 }
 
+#[allow(dead_code)]
 struct Label {
     counter: u16,
 }
@@ -176,7 +178,7 @@ impl Iterator for Label {
             buffer.invert();
             let bytes = buffer.to_bytes();
             let bw = step_filter_normal(80, length, bytes);
-            self.counter = self.counter - 1;
+            self.counter -= 1;
             Some(bw)
         } else {
             None
@@ -207,7 +209,7 @@ impl Iterator for Label2 {
 
             let bytes = buffer.to_luma8().into_raw();
             let bw = step_filter_normal(80, length, bytes);
-            self.counter = self.counter - 1;
+            self.counter -= 1;
             Some(bw)
         } else {
             None
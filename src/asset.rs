@@ -0,0 +1,217 @@
+//! Artwork with a fixed physical size.
+//!
+//! A bitmap drawn with `Canvas::draw_gray()` maps one pixel to one dot, so the
+//! same logo prints half as long when `Config::high_resolution()` doubles the
+//! dots along the tape. An `Asset` is registered once with its size in
+//! millimetres, as bitmaps rendered for several resolutions or as a vector
+//! drawing, and `Canvas::draw_asset()` picks and scales the variant matching
+//! the canvas.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::layout::{Canvas, Orientation, Resolution};
+
+type Draw = dyn Fn(&mut Canvas) + Send + Sync;
+
+/// Bitmap registered with `Asset::bitmap()`.
+#[derive(Debug, Clone)]
+struct Bitmap {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+/// Label artwork printed at the same physical size in every resolution.
+///
+/// Variants are chosen in this order:
+/// * The vector drawing, rendered at the resolution of the canvas.
+/// * The smallest bitmap with at least as many pixels as the canvas has dots
+///   on both axes, scaled down.
+/// * The largest bitmap, scaled up.
+///
+/// Bitmaps are scaled by picking the nearest pixel, which keeps barcodes and
+/// line art sharp.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{Asset, Canvas, Resolution};
+/// // A 10 x 5 mm logo rendered for 300 x 300 and 300 x 600 DPI
+/// let logo = Asset::new(10.0, 5.0)
+///     .bitmap(118, 59, vec![0; 118 * 59])
+///     .bitmap(118, 118, vec![0; 118 * 118]);
+///
+/// for resolution in [Resolution::Normal, Resolution::High] {
+///     let mut canvas = Canvas::blank(20.0, 20.0, resolution);
+///     canvas.draw_asset(0.0, 0.0, &logo);
+///
+///     // The last black row is 5 mm down the label in both modes
+///     let rows = resolution.dots_along(5.0);
+///     assert_eq!(canvas.dot(0, rows - 1), Some(0));
+///     assert_eq!(canvas.dot(0, rows), Some(255));
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Asset {
+    width: f32,
+    height: f32,
+    bitmaps: Vec<Bitmap>,
+    vector: Option<Arc<Draw>>,
+}
+
+impl fmt::Debug for Asset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Asset")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("bitmaps", &self.bitmaps)
+            .field("vector", &self.vector.is_some())
+            .finish()
+    }
+}
+
+impl Asset {
+    /// Asset of `width` x `height` millimetres without any variant yet.
+    pub fn new(width: f32, height: f32) -> Self {
+        Asset {
+            width: width.max(0.0),
+            height: height.max(0.0),
+            bitmaps: Vec::new(),
+            vector: None,
+        }
+    }
+
+    /// Register grayscale pixels (width × height bytes, 0 = black) covering
+    /// the whole asset. Their resolution follows from the size of the asset.
+    ///
+    /// Bitmaps whose data does not match their size are ignored.
+    pub fn bitmap(mut self, width: u32, height: u32, pixels: Vec<u8>) -> Self {
+        let size = (width as usize).checked_mul(height as usize);
+        if width > 0 && height > 0 && size == Some(pixels.len()) {
+            self.bitmaps.push(Bitmap {
+                width,
+                height,
+                pixels,
+            });
+        }
+        self
+    }
+
+    /// Register a vector drawing, preferred over the bitmaps.
+    ///
+    /// `draw` receives a blank canvas of the size of the asset at the
+    /// resolution being printed and draws on it in millimetres.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Asset, Canvas, Resolution};
+    /// let frame = Asset::new(8.0, 8.0).vector(|canvas: &mut Canvas| {
+    ///     canvas.stroke_rect(0.0, 0.0, 8.0, 8.0, 0.5);
+    /// });
+    ///
+    /// let mut canvas = Canvas::blank(20.0, 20.0, Resolution::High);
+    /// canvas.draw_asset(2.0, 2.0, &frame);
+    /// assert_eq!(canvas.dot(30, Resolution::High.dots_along(2.0)), Some(0));
+    /// ```
+    pub fn vector<F>(self, draw: F) -> Self
+    where
+        F: Fn(&mut Canvas) + Send + Sync + 'static,
+    {
+        Asset {
+            vector: Some(Arc::new(draw)),
+            ..self
+        }
+    }
+
+    /// Size of the asset in millimetres, as `(width, height)`.
+    pub fn size_mm(&self) -> (f32, f32) {
+        (self.width, self.height)
+    }
+
+    /// Render the asset on a blank canvas of its size, `None` without any
+    /// variant.
+    pub(crate) fn render(
+        &self,
+        resolution: Resolution,
+        orientation: Orientation,
+    ) -> Option<Canvas> {
+        let mut canvas = match orientation {
+            Orientation::Portrait => Canvas::blank(self.width, self.height, resolution),
+            Orientation::Landscape => {
+                Canvas::blank(self.height, self.width, resolution).orientation(orientation)
+            }
+        };
+        let (width, height) = (canvas.width_dots(), canvas.length_dots());
+
+        if let Some(draw) = &self.vector {
+            draw(&mut canvas);
+            return Some(canvas);
+        }
+
+        let bitmap = self
+            .bitmaps
+            .iter()
+            .filter(|bitmap| bitmap.width >= width && bitmap.height >= height)
+            .min_by_key(|bitmap| bitmap.width as u64 * bitmap.height as u64)
+            .or_else(|| {
+                self.bitmaps
+                    .iter()
+                    .max_by_key(|bitmap| bitmap.width as u64 * bitmap.height as u64)
+            })?;
+        for y in 0..height {
+            let sy = y as u64 * bitmap.height as u64 / height as u64;
+            for x in 0..width {
+                let sx = x as u64 * bitmap.width as u64 / width as u64;
+                let value = bitmap.pixels[(sy * bitmap.width as u64 + sx) as usize];
+                canvas.set_dot(x, y, value);
+            }
+        }
+        Some(canvas)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitmap_size() {
+        let asset = Asset::new(10.0, 5.0)
+            .bitmap(4, 2, vec![0; 8])
+            .bitmap(4, 2, vec![0; 7])
+            .bitmap(0, 2, Vec::new())
+            // Would wrap to 4 pixels in u32
+            .bitmap(u32::MAX / 2 + 1, 2, vec![0; 4]);
+        assert_eq!(asset.bitmaps.len(), 1);
+        assert!(Asset::new(10.0, 5.0)
+            .render(Resolution::Normal, Orientation::Portrait)
+            .is_none());
+    }
+
+    #[test]
+    fn test_render_picks_bitmap() {
+        // 2 x 1 mm is 24 x 12 dots, or 24 rows in high resolution
+        let asset = Asset::new(2.0, 1.0)
+            .bitmap(24, 12, vec![0; 24 * 12])
+            .bitmap(48, 48, vec![255; 48 * 48])
+            .bitmap(24, 24, vec![128; 24 * 24]);
+        let normal = asset
+            .render(Resolution::Normal, Orientation::Portrait)
+            .unwrap();
+        assert_eq!((normal.width_dots(), normal.length_dots()), (24, 12));
+        assert_eq!(normal.dot(23, 11), Some(0));
+        let high = asset
+            .render(Resolution::High, Orientation::Portrait)
+            .unwrap();
+        assert_eq!((high.width_dots(), high.length_dots()), (24, 24));
+        assert_eq!(high.dot(23, 23), Some(128));
+
+        // Scaled up from the largest bitmap
+        let small = Asset::new(2.0, 1.0).bitmap(2, 1, vec![0, 255]);
+        let canvas = small
+            .render(Resolution::Normal, Orientation::Portrait)
+            .unwrap();
+        assert_eq!(canvas.dot(11, 11), Some(0));
+        assert_eq!(canvas.dot(12, 0), Some(255));
+    }
+}
@@ -0,0 +1,431 @@
+//! Barcode and QR code labels.
+//!
+//! Enabled with the `barcode` feature. `BarcodeLabel` encodes Code 128 and
+//! EAN-13 barcodes itself and QR codes with the `qrcode` crate, and draws
+//! them with modules snapped to whole dots, so every bar prints with the
+//! same width in normal and high resolution.
+//!
+//! # Example
+//! ```rust
+//! use ql_label::{BarcodeLabel, ContinuousType, Media};
+//!
+//! let media = Media::Continuous(ContinuousType::Continuous62);
+//! let matrix = BarcodeLabel::code128("QL-820NWB")?.height_mm(12.0).to_matrix(media);
+//! assert_eq!(matrix[0].len(), 90);
+//! # Ok::<(), String>(())
+//! ```
+
+use crate::{
+    layout::{dots_to_mm, mm_to_dots, Canvas, Resolution},
+    media::Media,
+    Matrix,
+};
+
+/// Bar and space widths of the Code 128 symbols 0 to 106, the last one
+/// being the stop pattern without its final bar.
+const CODE128: [&str; 107] = [
+    "212222", "222122", "222221", "121223", "121322", "131222", "122213", "122312", "132212",
+    "221213", "221312", "231212", "112232", "122132", "122231", "113222", "123122", "123221",
+    "223211", "221132", "221231", "213212", "223112", "312131", "311222", "321122", "321221",
+    "312212", "322112", "322211", "212123", "212321", "232121", "111323", "131123", "131321",
+    "112313", "132113", "132311", "211313", "231113", "231311", "112133", "112331", "132131",
+    "113123", "113321", "133121", "313121", "211331", "231131", "213113", "213311", "213131",
+    "311123", "311321", "331121", "312113", "312311", "332111", "314111", "221411", "431111",
+    "111224", "111422", "121124", "121421", "141122", "141221", "112214", "112412", "122114",
+    "122411", "142112", "142211", "241211", "221114", "413111", "241112", "134111", "111242",
+    "121142", "121241", "114212", "124112", "124211", "411212", "421112", "421211", "212141",
+    "214121", "412121", "111143", "111341", "131141", "114113", "114311", "411113", "411311",
+    "113141", "114131", "311141", "411131", "211412", "211214", "211232", "233111",
+];
+const CODE128_START_B: usize = 104;
+const CODE128_START_C: usize = 105;
+const CODE128_CODE_B: usize = 100;
+const CODE128_CODE_C: usize = 99;
+const CODE128_STOP: usize = 106;
+
+/// EAN-13 L codes of the digits; R codes are their complement and G codes
+/// the R codes reversed.
+const EAN_L: [&str; 10] = [
+    "0001101", "0011001", "0010011", "0111101", "0100011", "0110001", "0101111", "0111011",
+    "0110111", "0001011",
+];
+/// Whether each of the digits 2 to 7 uses a G code, by first digit.
+const EAN_PARITY: [&str; 10] = [
+    "LLLLLL", "LLGLGG", "LLGGLG", "LLGGGL", "LGLLGG", "LGGLLG", "LGGGLL", "LGLGLG", "LGLGGL",
+    "LGGLGL",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Symbol {
+    /// Modules of a 1D barcode, 1 for a bar.
+    Linear(Vec<u8>),
+    /// Rows of a square 2D code, 1 for a dark module.
+    Square { width: usize, modules: Vec<u8> },
+}
+
+/// A label holding a single barcode or QR code.
+///
+/// 1D barcodes run along the longer side of the label, which is along the
+/// tape on continuous media. The code is centered on the printable area,
+/// continuous tape is cut to its length including the quiet zone. Codes
+/// larger than a die-cut label are clipped, use a smaller module size.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{BarcodeLabel, DieCutType, Media, Resolution};
+/// let media = Media::DieCut(DieCutType::DieCut29x90);
+/// let label = BarcodeLabel::ean13("400638133393")?
+///     .module_mm(0.339)
+///     .resolution(Resolution::High);
+///
+/// assert_eq!(label.modules(), 95);
+/// let matrix = label.to_matrix(media);
+/// // The 1061 dots of the label, twice in high resolution
+/// assert_eq!(matrix.len(), 1061 * 2);
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct BarcodeLabel {
+    symbol: Symbol,
+    module: f32,
+    height: f32,
+    quiet_zone: u32,
+    resolution: Resolution,
+}
+
+impl BarcodeLabel {
+    fn linear(modules: Vec<u8>) -> Self {
+        BarcodeLabel {
+            symbol: Symbol::Linear(modules),
+            module: 0.254,
+            height: 10.0,
+            quiet_zone: 10,
+            resolution: Resolution::Normal,
+        }
+    }
+
+    /// Code 128 barcode of printable ASCII `data`.
+    ///
+    /// Data made of an even number of digits is encoded with code set C,
+    /// two digits per symbol; long runs of digits switch to it too.
+    pub fn code128(data: &str) -> Result<Self, String> {
+        Ok(Self::linear(code128_modules(data)?))
+    }
+
+    /// EAN-13 barcode of 12 digits, or of 13 digits with a valid check
+    /// digit.
+    pub fn ean13(data: &str) -> Result<Self, String> {
+        Ok(Self::linear(ean13_modules(data)?))
+    }
+
+    /// QR code of `data` with error correction level M.
+    pub fn qr(data: &str) -> Result<Self, String> {
+        let code = qrcode::QrCode::new(data.as_bytes())
+            .map_err(|e| format!("Cannot encode QR code: {}", e))?;
+        let modules = code
+            .to_colors()
+            .into_iter()
+            .map(|color| (color == qrcode::Color::Dark) as u8)
+            .collect();
+        Ok(BarcodeLabel {
+            symbol: Symbol::Square {
+                width: code.width(),
+                modules,
+            },
+            module: 0.508,
+            height: 0.0,
+            quiet_zone: 4,
+            resolution: Resolution::Normal,
+        })
+    }
+
+    /// Width of a module in millimetres, snapped to whole dots.
+    ///
+    /// Defaults to 3 dots (0.254 mm) for barcodes and 6 dots for QR codes.
+    pub fn module_mm(self, mm: f32) -> Self {
+        BarcodeLabel { module: mm, ..self }
+    }
+
+    /// Height of the bars in millimetres, 10 mm by default. Ignored for QR
+    /// codes.
+    pub fn height_mm(self, mm: f32) -> Self {
+        BarcodeLabel { height: mm, ..self }
+    }
+
+    /// Blank modules kept on both ends of the code on continuous tape, 10
+    /// for barcodes and 4 for QR codes by default.
+    pub fn quiet_zone(self, modules: u32) -> Self {
+        BarcodeLabel {
+            quiet_zone: modules,
+            ..self
+        }
+    }
+
+    /// Resolution the label is printed at.
+    pub fn resolution(self, resolution: Resolution) -> Self {
+        BarcodeLabel { resolution, ..self }
+    }
+
+    /// Number of modules of a barcode, or per row of a QR code.
+    pub fn modules(&self) -> usize {
+        match &self.symbol {
+            Symbol::Linear(modules) => modules.len(),
+            Symbol::Square { width, .. } => *width,
+        }
+    }
+
+    /// Render the code on a canvas for `media`, e.g. to add text.
+    pub fn to_canvas(&self, media: Media) -> Canvas {
+        let res = self.resolution;
+        let canvas = Canvas::new(media, res);
+        let continuous = matches!(media, Media::Continuous(_));
+        let quiet = |step: u32, dpi: u32| dots_to_mm(step * self.quiet_zone * 2, dpi);
+
+        match &self.symbol {
+            Symbol::Linear(modules) => {
+                let (across, along) = canvas.size_mm();
+                if continuous || along > across {
+                    let step = res.dots_along(self.module).max(1);
+                    let length = dots_to_mm(step * modules.len() as u32, res.vertical_dpi());
+                    let mut canvas = if continuous {
+                        canvas.length_mm(length + quiet(step, res.vertical_dpi()))
+                    } else {
+                        canvas
+                    };
+                    let (across, along) = canvas.size_mm();
+                    let height = self.height.min(across);
+                    let (x, y) = centered(across, along, height, length);
+                    canvas.draw_barcode_along(x, y, modules, self.module, height);
+                    canvas
+                } else {
+                    let mut canvas = canvas;
+                    let step = res.dots_across(self.module).max(1);
+                    let width = dots_to_mm(step * modules.len() as u32, res.horizontal_dpi());
+                    let height = self.height.min(along);
+                    let (x, y) = centered(across, along, width, height);
+                    canvas.draw_barcode(x, y, modules, self.module, height);
+                    canvas
+                }
+            }
+            Symbol::Square { width, modules } => {
+                let rows = modules.len().div_ceil((*width).max(1)) as u32;
+                let step_x = res.dots_across(self.module).max(1);
+                let step_y = res.dots_along(self.module).max(1);
+                let size_x = dots_to_mm(step_x * *width as u32, res.horizontal_dpi());
+                let size_y = dots_to_mm(step_y * rows, res.vertical_dpi());
+                let mut canvas = if continuous {
+                    canvas.length_mm(size_y + quiet(step_y, res.vertical_dpi()))
+                } else {
+                    canvas
+                };
+                let (across, along) = canvas.size_mm();
+                let (x, y) = centered(across, along, size_x, size_y);
+                canvas.draw_matrix_code(x, y, *width, modules, self.module);
+                canvas
+            }
+        }
+    }
+
+    /// Printed width and height of the code on `canvas` in millimetres,
+    /// without quiet zone, as drawn by `draw()`.
+    pub(crate) fn size_mm_on(&self, canvas: &Canvas) -> (f32, f32) {
+        let (dpi_x, dpi_y) = canvas.dpi();
+        let step_x = mm_to_dots(self.module, dpi_x).max(1);
+        match &self.symbol {
+            Symbol::Linear(modules) => (
+                dots_to_mm(step_x * modules.len() as u32, dpi_x),
+                self.height,
+            ),
+            Symbol::Square { width, modules } => {
+                let rows = modules.len().div_ceil((*width).max(1)) as u32;
+                let step_y = mm_to_dots(self.module, dpi_y).max(1);
+                (
+                    dots_to_mm(step_x * *width as u32, dpi_x),
+                    dots_to_mm(step_y * rows, dpi_y),
+                )
+            }
+        }
+    }
+
+    /// Draw the code on `canvas` with its top-left corner at `x`, `y`
+    /// millimetres, the bars of a barcode along the x axis.
+    pub(crate) fn draw(&self, canvas: &mut Canvas, x: f32, y: f32) {
+        match &self.symbol {
+            Symbol::Linear(modules) => {
+                canvas.draw_barcode(x, y, modules, self.module, self.height);
+            }
+            Symbol::Square { width, modules } => {
+                canvas.draw_matrix_code(x, y, *width, modules, self.module);
+            }
+        }
+    }
+
+    /// Render the code to printer rows for `media`.
+    pub fn to_matrix(&self, media: Media) -> Matrix {
+        self.to_canvas(media).to_matrix()
+    }
+}
+
+/// Top-left corner centering a `width` x `height` box on the area.
+fn centered(area_x: f32, area_y: f32, width: f32, height: f32) -> (f32, f32) {
+    (
+        ((area_x - width) / 2.0).max(0.0),
+        ((area_y - height) / 2.0).max(0.0),
+    )
+}
+
+/// Append the modules of the bars and spaces of `widths`, starting with a bar.
+fn push_widths(modules: &mut Vec<u8>, widths: &str) {
+    for (i, width) in widths.bytes().enumerate() {
+        let bar = (i % 2 == 0) as u8;
+        modules.extend(std::iter::repeat_n(bar, (width - b'0') as usize));
+    }
+}
+
+fn code128_modules(data: &str) -> Result<Vec<u8>, String> {
+    if data.is_empty() {
+        return Err("Code 128 needs at least one character".to_string());
+    }
+    if let Some(c) = data.chars().find(|c| !(' '..='~').contains(c)) {
+        return Err(format!("Code 128 cannot encode {:?}", c));
+    }
+    let bytes = data.as_bytes();
+    let digits_at = |start: usize| {
+        bytes[start..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count()
+    };
+
+    let mut symbols = Vec::new();
+    let leading = digits_at(0);
+    let mut code_c = leading >= 4 || (leading == bytes.len() && leading.is_multiple_of(2));
+    symbols.push(if code_c {
+        CODE128_START_C
+    } else {
+        CODE128_START_B
+    });
+    let mut i = 0;
+    while i < bytes.len() {
+        let digits = digits_at(i);
+        if code_c && digits >= 2 {
+            symbols.push(((bytes[i] - b'0') * 10 + (bytes[i + 1] - b'0')) as usize);
+            i += 2;
+            continue;
+        }
+        if code_c {
+            symbols.push(CODE128_CODE_B);
+            code_c = false;
+        } else if digits >= 6 || (digits >= 4 && digits == bytes.len() - i) {
+            // Switch for an even number of digits, the odd one stays in B
+            if digits % 2 == 1 {
+                symbols.push((bytes[i] - b' ') as usize);
+                i += 1;
+            }
+            symbols.push(CODE128_CODE_C);
+            code_c = true;
+            continue;
+        }
+        symbols.push((bytes[i] - b' ') as usize);
+        i += 1;
+    }
+    let checksum = symbols
+        .iter()
+        .enumerate()
+        .map(|(position, &symbol)| position.max(1) * symbol)
+        .sum::<usize>()
+        % 103;
+    symbols.push(checksum);
+    symbols.push(CODE128_STOP);
+
+    let mut modules = Vec::new();
+    for symbol in symbols {
+        push_widths(&mut modules, CODE128[symbol]);
+    }
+    // Final bar of the stop pattern
+    modules.extend([1, 1]);
+    Ok(modules)
+}
+
+fn ean13_modules(data: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<u8> = data.bytes().map(|b| b.wrapping_sub(b'0')).collect();
+    if !(digits.len() == 12 || digits.len() == 13) || digits.iter().any(|&d| d > 9) {
+        return Err(format!("EAN-13 needs 12 or 13 digits, got {:?}", data));
+    }
+    let sum: u32 = digits[..12]
+        .iter()
+        .enumerate()
+        .map(|(i, &d)| d as u32 * if i % 2 == 0 { 1 } else { 3 })
+        .sum();
+    let check = ((10 - sum % 10) % 10) as u8;
+    if digits.len() == 13 && digits[12] != check {
+        return Err(format!(
+            "Invalid EAN-13 check digit in {}, expected {}",
+            data, check
+        ));
+    }
+
+    let bits = |code: &str| code.bytes().map(|b| b - b'0').collect::<Vec<u8>>();
+    let mut modules = vec![1, 0, 1];
+    let parity = EAN_PARITY[digits[0] as usize].as_bytes();
+    for (i, &digit) in digits[1..7].iter().enumerate() {
+        let l = bits(EAN_L[digit as usize]);
+        if parity[i] == b'G' {
+            modules.extend(l.iter().rev().map(|m| 1 - m));
+        } else {
+            modules.extend(l);
+        }
+    }
+    modules.extend([0, 1, 0, 1, 0]);
+    for &digit in digits[7..12].iter().chain([check].iter()) {
+        modules.extend(bits(EAN_L[digit as usize]).iter().map(|m| 1 - m));
+    }
+    modules.extend([1, 0, 1]);
+    Ok(modules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_string(modules: &[u8]) -> String {
+        modules.iter().map(|&m| (b'0' + m) as char).collect()
+    }
+
+    #[test]
+    fn test_code128() {
+        for pattern in CODE128.iter() {
+            let width: u32 = pattern.bytes().map(|b| (b - b'0') as u32).sum();
+            assert_eq!(width, 11, "{}", pattern);
+        }
+
+        // Start B, "A", checksum (104 + 33) % 103 = 34, stop
+        let modules = code128_modules("A").unwrap();
+        assert_eq!(
+            to_string(&modules),
+            "11010010000".to_string() + "10100011000" + "10001011000" + "1100011101011"
+        );
+
+        // All digits use code set C, a long run in text switches to it
+        assert_eq!(code128_modules("1234").unwrap().len(), 11 * 5 + 2);
+        assert_eq!(code128_modules("AB123456").unwrap().len(), 11 * 9 + 2);
+        assert_eq!(code128_modules("A12345").unwrap().len(), 11 * 8 + 2);
+        assert!(code128_modules("é").is_err());
+        assert!(code128_modules("").is_err());
+    }
+
+    #[test]
+    fn test_ean13() {
+        let modules = ean13_modules("400638133393").unwrap();
+        assert_eq!(modules.len(), 95);
+        assert_eq!(ean13_modules("4006381333931").unwrap(), modules);
+        assert!(ean13_modules("4006381333932").is_err());
+        assert!(ean13_modules("40063813339").is_err());
+
+        // First digit 4 (LGLLGG): 0 in L, 0 in G, then the R code of the check digit 1
+        let text = to_string(&modules);
+        assert_eq!(&text[3..10], "0001101");
+        assert_eq!(&text[10..17], "0100111");
+        assert_eq!(&text[85..92], "1100110");
+    }
+}
@@ -0,0 +1,780 @@
+//! Command line tool printing label images.
+//!
+//! ```text
+//! ql-label print --model QL-820NWB --serial 000G0Z000000 --media 62 label.png
+//! magick label.svg -resize 720x -depth 8 gray:- | ql-label print --raw -
+//! ql-label filter --model QL-820NWB --media 62 page.ras > job.bin
+//! ql-label list --network
+//! ql-label daemon --model QL-820NWB --serial 000G0Z000000 --socket /run/ql-label.sock
+//! ql-label daemon --model QL-800 --socket /run/ql-label.sock --metrics 127.0.0.1:9898
+//! ```
+
+use std::env;
+use std::io::Read;
+use std::process;
+use std::time::Duration;
+
+use image::DynamicImage;
+use ql_label::{
+    decode_cups_pages, step_filter_normal, Canvas, Config, LabelImage, Matrix, Media, Model,
+    Preview, Printer, Resampling, Resolution, NORMAL_PRINTER_WIDTH,
+};
+
+#[cfg(unix)]
+use ql_label::{Emulator, JobInfo, JobQueue, JobState, MediaState, Throttle};
+#[cfg(unix)]
+use serde_json::{json, Value};
+#[cfg(unix)]
+use std::io::{BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::sync::Arc;
+
+fn print_usage() {
+    println!("Usage: ql-label print [OPTIONS] <FILE>");
+    println!("       ql-label filter [OPTIONS] [FILE]");
+    println!("       ql-label daemon [OPTIONS] --socket <PATH>");
+    println!("       ql-label list [--network]");
+    println!();
+    println!(
+        "Print a grayscale image {} pixels wide, the width of the print head.",
+        NORMAL_PRINTER_WIDTH
+    );
+    println!("FILE is a PNG (or any format supported by the image crate), '-' reads stdin.");
+    println!();
+    println!("Options:");
+    println!("  --model <MODEL>      Printer model, e.g. QL-820NWB [env: DEFAULT_MODEL]");
+    println!("  --serial <SERIAL>    Serial number of the printer, the first one of the model");
+    println!("                       is used when omitted [env: SERIAL]");
+    println!("  --media <LABEL>      brother_ql label identifier, e.g. 62 or 29x90 [default: 62]");
+    println!("  --profile <NAME>     Printer profile from ~/.config/ql-label/profiles.toml,");
+    println!("                       replaces --model, --serial and --media");
+    println!("  --raw                Input is raw 8-bit grayscale instead of an image file");
+    println!("  --threshold <LEVEL>  Gray levels up to LEVEL print black [default: 128]");
+    println!("  --resample <FILTER>  Scale the image to fit the media and dither it, with the");
+    println!("                       filter nearest, triangle, catmullrom or lanczos3");
+    println!("  --compress           Send compressed raster data");
+    println!("  --output <FILE>      Write the encoded job to FILE instead of printing, '-'");
+    println!("                       writes stdout, e.g. for 'lp -o raw'");
+    println!("  --preview <FILE>     Write a PNG of the label as printed on the media to FILE");
+    println!("                       instead of printing");
+    println!("  --usb                Print the filtered pages instead of writing stdout");
+    println!("  --network            list also finds printers on the network with mDNS");
+    println!("  --socket <PATH>      Control socket of the daemon, see below");
+    println!("  --emulate            Run the daemon on a virtual printer, for testing clients");
+    println!("  --job-pause <SECS>   Daemon pause between jobs [default: 0]");
+    println!("  --cooling-pause <SECS>");
+    println!("                       Daemon pause after the printer had to cool down [default: 0]");
+    println!("  --wait-for-media <SECS>");
+    println!("                       Wait up to SECS for the operator to install the media");
+    println!("                       instead of failing a job printed on other media");
+    println!("  --retries <COUNT>    Daemon retries of a job failing with a USB error or");
+    println!("                       timeout [default: 0]");
+    println!("  --journal <DIR>      Keep the daemon's queued jobs in DIR and print the jobs");
+    println!("                       left there by a previous run");
+    println!("  --metrics <ADDR>     Serve the daemon's Prometheus metrics at");
+    println!("                       http://ADDR/metrics, e.g. 127.0.0.1:9898");
+    println!();
+    println!("filter converts the pages of CUPS raster or PNM images (P4, P5 or P6) to a job");
+    println!("for the media and writes it to stdout, for use as the filter of a CUPS queue.");
+    println!("FILE defaults to stdin.");
+    println!();
+    println!("list shows the attached Brother USB devices with their model and serial number.");
+    println!("With --network it also lists the printers announced on the local network, with");
+    println!("the serial number read over SNMP.");
+    println!();
+    println!("The daemon prints the jobs submitted to a Unix socket one after another. Every");
+    println!("line sent to the socket is a JSON request answered by a JSON line:");
+    println!(r#"  {{"cmd":"submit","files":["a.png","b.png"]}}  -> {{"ok":true,"id":1}}"#);
+    println!(r#"  {{"cmd":"submit","file":"b.png","priority":5}} -> {{"ok":true,"id":2}}"#);
+    println!(r#"  {{"cmd":"cancel","id":1}}                     -> {{"ok":true}}"#);
+    println!(r#"  {{"cmd":"queue"}}                             -> {{"ok":true,"jobs":[...]}}"#);
+    println!(
+        r#"  {{"cmd":"status"}}                            -> {{"ok":true,"printer":{{...}}}}"#
+    );
+}
+
+struct Args {
+    model: Option<String>,
+    serial: Option<String>,
+    media: String,
+    profile: Option<String>,
+    raw: bool,
+    threshold: u8,
+    resample: Option<Resampling>,
+    compress: bool,
+    output: Option<String>,
+    preview: Option<String>,
+    usb: bool,
+    network: bool,
+    socket: Option<String>,
+    emulate: bool,
+    job_pause: Duration,
+    cooling_pause: Duration,
+    media_change_timeout: Option<Duration>,
+    retries: u32,
+    journal: Option<String>,
+    metrics: Option<String>,
+    input: Option<String>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut parsed = Args {
+            model: env::var("DEFAULT_MODEL").ok(),
+            serial: env::var("SERIAL").ok(),
+            media: "62".to_string(),
+            profile: None,
+            raw: false,
+            threshold: 128,
+            resample: None,
+            compress: false,
+            output: None,
+            preview: None,
+            usb: false,
+            network: false,
+            socket: None,
+            emulate: false,
+            job_pause: Duration::ZERO,
+            cooling_pause: Duration::ZERO,
+            media_change_timeout: None,
+            retries: 0,
+            journal: None,
+            metrics: None,
+            input: None,
+        };
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = || {
+                iter.next()
+                    .cloned()
+                    .ok_or_else(|| format!("Missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--model" => parsed.model = Some(value()?),
+                "--serial" => parsed.serial = Some(value()?),
+                "--media" => parsed.media = value()?,
+                "--profile" => parsed.profile = Some(value()?),
+                "--raw" => parsed.raw = true,
+                "--threshold" => {
+                    let level = value()?;
+                    parsed.threshold = level
+                        .parse()
+                        .map_err(|_| format!("Invalid threshold: {}", level))?;
+                }
+                "--resample" => parsed.resample = Some(parse_resampling(&value()?)?),
+                "--compress" => parsed.compress = true,
+                "--output" => parsed.output = Some(value()?),
+                "--preview" => parsed.preview = Some(value()?),
+                "--usb" => parsed.usb = true,
+                "--network" => parsed.network = true,
+                "--socket" => parsed.socket = Some(value()?),
+                "--emulate" => parsed.emulate = true,
+                "--job-pause" => parsed.job_pause = parse_seconds(&value()?)?,
+                "--cooling-pause" => parsed.cooling_pause = parse_seconds(&value()?)?,
+                "--wait-for-media" => parsed.media_change_timeout = Some(parse_seconds(&value()?)?),
+                "--retries" => {
+                    let count = value()?;
+                    parsed.retries = count
+                        .parse()
+                        .map_err(|_| format!("Invalid retry count: {}", count))?;
+                }
+                "--journal" => parsed.journal = Some(value()?),
+                "--metrics" => parsed.metrics = Some(value()?),
+                "-" => parsed.input = Some(arg.clone()),
+                _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+                _ => parsed.input = Some(arg.clone()),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn config(&self) -> Result<Config, String> {
+        let config = match &self.profile {
+            Some(name) => {
+                // Options of the profile are only overridden when given
+                let config = load_profile(name)?;
+                let compress = self.compress || config.settings().compress;
+                config.compress(compress)
+            }
+            None => {
+                let model = parse_model(self.model.as_deref().ok_or("Missing --model")?)?;
+                let media: Media = self
+                    .media
+                    .parse()
+                    .map_err(|_| format!("Unknown media: {}", self.media))?;
+                match &self.serial {
+                    Some(serial) => Config::new(model, serial.clone(), media),
+                    None => Config::new_any(model, media),
+                }
+                .compress(self.compress)
+            }
+        };
+        Ok(match self.media_change_timeout {
+            Some(timeout) => config.wait_for_media_change(timeout),
+            None => config,
+        })
+    }
+}
+
+#[cfg(feature = "profiles")]
+fn load_profile(name: &str) -> Result<Config, String> {
+    Config::from_profile(name)
+}
+
+#[cfg(not(feature = "profiles"))]
+fn load_profile(_name: &str) -> Result<Config, String> {
+    Err("--profile needs ql-label built with the profiles feature".to_string())
+}
+
+/// Parse a model name in any case, with or without the dash after "QL".
+fn parse_model(name: &str) -> Result<Model, String> {
+    let wanted = name.replace('-', "").to_ascii_uppercase();
+    Model::ALL
+        .iter()
+        .copied()
+        .find(|model| format!("{:?}", model) == wanted)
+        // Other names of a model, such as QL-820NW
+        .or_else(|| name.to_ascii_uppercase().parse().ok())
+        .ok_or_else(|| format!("Unsupported model: {}", name))
+}
+
+fn parse_resampling(name: &str) -> Result<Resampling, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "nearest" => Ok(Resampling::Nearest),
+        "triangle" => Ok(Resampling::Triangle),
+        "catmullrom" => Ok(Resampling::CatmullRom),
+        "lanczos3" => Ok(Resampling::Lanczos3),
+        _ => Err(format!("Unknown resampling filter: {}", name)),
+    }
+}
+
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|seconds: &f64| seconds.is_finite() && *seconds >= 0.0)
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| format!("Invalid number of seconds: {}", value))
+}
+
+fn read_input(path: &str) -> Result<Vec<u8>, String> {
+    if path == "-" {
+        let mut buf = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut buf)
+            .map_err(|e| format!("Failed to read stdin: {}", e))?;
+        Ok(buf)
+    } else {
+        std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))
+    }
+}
+
+/// Convert an image, or raw grayscale pixels if `raw` is set, to printer rows.
+///
+/// With `fit`, the image is scaled to the media with the given filter and
+/// dithered instead of having to match the print head width.
+fn load_matrix(
+    bytes: Vec<u8>,
+    raw: bool,
+    threshold: u8,
+    fit: Option<(Media, Resampling)>,
+) -> Result<Matrix, String> {
+    if let (Some((media, filter)), false) = (fit, raw) {
+        let image = image::load_from_memory(&bytes)
+            .map_err(|e| format!("Failed to decode image: {}", e))?
+            .to_luma8();
+        let mut canvas = Canvas::new(media, Resolution::Normal);
+        if let Media::Continuous(_) = media {
+            let (width, _) = canvas.size_mm();
+            let length = width * image.height() as f32 / image.width().max(1) as f32;
+            canvas = canvas.length_mm(length);
+        }
+        canvas.fit_image(&image, filter);
+        return Ok(canvas.to_matrix_dithered());
+    }
+
+    let (width, pixels) = if raw {
+        (NORMAL_PRINTER_WIDTH, bytes)
+    } else {
+        let image = image::load_from_memory(&bytes)
+            .map_err(|e| format!("Failed to decode image: {}", e))?
+            .to_luma8();
+        (image.width(), image.into_raw())
+    };
+
+    if width != NORMAL_PRINTER_WIDTH || pixels.is_empty() || pixels.len() % width as usize != 0 {
+        return Err(format!(
+            "The image must be {} pixels wide, got {} bytes of {} pixel wide data",
+            NORMAL_PRINTER_WIDTH,
+            pixels.len(),
+            width
+        ));
+    }
+    let length = (pixels.len() / width as usize) as u32;
+    Ok(step_filter_normal(threshold, length, pixels))
+}
+
+fn print(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args)?;
+    let config = args.config()?;
+    let input = args
+        .input
+        .as_deref()
+        .ok_or("Missing input file, use '-' to read stdin")?;
+    let fit = args.resample.map(|filter| (config.media(), filter));
+    let matrix = load_matrix(read_input(input)?, args.raw, args.threshold, fit)?;
+    if let Some(path) = &args.preview {
+        return Preview::new(config.media()).save_png(&matrix, path);
+    }
+    match &args.output {
+        Some(path) if path == "-" => config
+            .write_job(vec![matrix].into_iter(), std::io::stdout().lock())
+            .map_err(|e| format!("Failed to write the job: {}", e)),
+        Some(path) => config
+            .save_job(vec![matrix].into_iter(), path)
+            .map_err(|e| format!("Failed to write {}: {}", path, e)),
+        None => {
+            let printer = Printer::new(config).map_err(|e| e.to_string())?;
+            printer
+                .print(vec![matrix].into_iter())
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Convert the pages rasterized by a print queue, as CUPS raster or PNM
+/// images, to a job for the media of the queue.
+fn filter(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args)?;
+    let config = args.config()?;
+    let pages = decode_cups_pages(&read_input(args.input.as_deref().unwrap_or("-"))?)?;
+    if pages.is_empty() {
+        return Err("No pages in the input".into());
+    }
+    let label = LabelImage::new(config.media(), config.model())
+        .resampling(args.resample.unwrap_or_default())
+        .threshold(args.threshold);
+    let matrices: Vec<Matrix> = pages
+        .into_iter()
+        .map(|page| label.render(&DynamicImage::ImageLuma8(page)))
+        .collect();
+    if args.usb {
+        let printer = Printer::new(config).map_err(|e| e.to_string())?;
+        return printer
+            .print(matrices.into_iter())
+            .map_err(|e| e.to_string());
+    }
+    config
+        .write_job(matrices.into_iter(), std::io::stdout().lock())
+        .map_err(|e| format!("Failed to write the job: {}", e))
+}
+
+fn list(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args)?;
+    let printers = Printer::enumerate().map_err(|e| e.to_string())?;
+    if printers.is_empty() {
+        println!("No Brother USB device found");
+    }
+    for printer in printers {
+        let model = match printer.model {
+            Some(model) => format!("{:?}", model),
+            None => format!("Unknown ({:04x})", printer.product_id),
+        };
+        let serial = printer.serial.as_deref().unwrap_or("(not readable)");
+        println!(
+            "{:<16} {:<16} bus {:03} device {:03}",
+            model, serial, printer.bus, printer.address
+        );
+    }
+    if !args.network {
+        return Ok(());
+    }
+
+    let printers = Printer::discover_network(Duration::from_secs(2)).map_err(|e| e.to_string())?;
+    if printers.is_empty() {
+        println!("No Brother printer found on the network");
+    }
+    for mut printer in printers {
+        if let Err(err) = printer.query_snmp(Duration::from_secs(1)) {
+            eprintln!("No SNMP answer from {}: {}", printer.address, err);
+        }
+        let model = match printer.model {
+            Some(model) => format!("{:?}", model),
+            None => "Unknown".to_string(),
+        };
+        let serial = printer.serial.as_deref().unwrap_or("(not readable)");
+        println!(
+            "{:<16} {:<16} {} {}",
+            model,
+            serial,
+            printer.socket_addr(),
+            printer.name
+        );
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn daemon(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args)?;
+    let socket = args.socket.clone().ok_or("Missing --socket")?;
+    // Before opening the printer, which a running daemon holds
+    remove_stale_socket(&socket)?;
+    let config = args.config()?;
+    let media = config.media();
+    let printer = if args.emulate {
+        let emulator = Emulator::new(config.model(), Some(config.media()));
+        Printer::with_transport(config, emulator)
+    } else {
+        Printer::new(config)
+    };
+    let printer = printer.map_err(|e| e.to_string())?;
+    let throttle = Throttle::new()
+        .job_pause(args.job_pause)
+        .cooling_pause(args.cooling_pause);
+    let queue = Arc::new(JobQueue::with_throttle(printer.handle(), throttle));
+    queue.set_retry(args.retries, Duration::from_secs(1));
+    if let Some(dir) = &args.journal {
+        let restored = queue.persist(dir)?;
+        if !restored.is_empty() {
+            eprintln!("Restored {} jobs from {}", restored.len(), dir);
+        }
+    }
+
+    if let Some(addr) = &args.metrics {
+        let listener =
+            TcpListener::bind(addr).map_err(|e| format!("Failed to bind {}: {}", addr, e))?;
+        let queue = queue.clone();
+        std::thread::spawn(move || serve_metrics(listener, &queue));
+    }
+
+    let listener =
+        UnixListener::bind(&socket).map_err(|e| format!("Failed to bind {}: {}", socket, e))?;
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let queue = queue.clone();
+                let threshold = args.threshold;
+                std::thread::spawn(move || serve_client(stream, &queue, threshold, media));
+            }
+            Err(err) => eprintln!("Failed to accept a connection: {}", err),
+        }
+    }
+    Ok(())
+}
+
+/// Remove a socket left behind by a previous run, which blocks the bind.
+///
+/// A socket a running daemon still accepts connections on, and anything
+/// else at that path, is not ours to remove.
+#[cfg(unix)]
+fn remove_stale_socket(socket: &str) -> Result<(), String> {
+    let metadata = match std::fs::symlink_metadata(socket) {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(()),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(format!("{} exists and is not a socket", socket));
+    }
+    match UnixStream::connect(socket) {
+        Ok(_) => Err(format!("A daemon is already running on {}", socket)),
+        Err(err) if err.kind() == std::io::ErrorKind::ConnectionRefused => {
+            std::fs::remove_file(socket).map_err(|e| format!("Failed to remove {}: {}", socket, e))
+        }
+        Err(err) => Err(format!("Failed to check {}: {}", socket, err)),
+    }
+}
+
+/// Answer `GET /metrics` with `Printer::metrics()`, one request per
+/// connection.
+#[cfg(unix)]
+fn serve_metrics(listener: TcpListener, queue: &JobQueue) {
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("Failed to accept a metrics connection: {}", err);
+                continue;
+            }
+        };
+        let mut request = [0u8; 1024];
+        let (status, body) = match stream.read(&mut request) {
+            Ok(n) if request[..n].starts_with(b"GET /metrics ") => {
+                ("200 OK", queue.printer().metrics())
+            }
+            Ok(_) => ("404 Not Found", String::new()),
+            Err(_) => continue,
+        };
+        let _ = write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+    }
+}
+
+/// Answer the JSON requests of one client until it disconnects.
+#[cfg(unix)]
+fn serve_client(stream: UnixStream, queue: &JobQueue, threshold: u8, media: Media) {
+    let reader = match stream.try_clone() {
+        Ok(stream) => BufReader::new(stream),
+        Err(_) => return,
+    };
+    let mut writer = stream;
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match handle_request(&line, queue, threshold, media) {
+            Ok(mut response) => {
+                response["ok"] = json!(true);
+                response
+            }
+            Err(err) => json!({ "ok": false, "error": err }),
+        };
+        if writeln!(writer, "{}", response).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(unix)]
+fn handle_request(
+    line: &str,
+    queue: &JobQueue,
+    threshold: u8,
+    media: Media,
+) -> Result<Value, String> {
+    let request: Value =
+        serde_json::from_str(line).map_err(|e| format!("Invalid request: {}", e))?;
+    match request["cmd"].as_str() {
+        Some("submit") => {
+            let files: Vec<&str> = match (&request["files"], request["file"].as_str()) {
+                (Value::Array(files), _) => files.iter().filter_map(Value::as_str).collect(),
+                (_, Some(file)) => vec![file],
+                _ => return Err("Missing files".to_string()),
+            };
+            let raw = request["raw"].as_bool().unwrap_or(false);
+            let threshold = request["threshold"]
+                .as_u64()
+                .map_or(threshold, |level| level.min(255) as u8);
+            let fit = match request["resample"].as_str() {
+                Some(name) => Some((media, parse_resampling(name)?)),
+                None => None,
+            };
+            let pages = files
+                .into_iter()
+                .map(|file| load_matrix(read_input(file)?, raw, threshold, fit))
+                .collect::<Result<Vec<_>, String>>()?;
+            let priority = request["priority"]
+                .as_u64()
+                .map_or(0, |priority| priority.min(255) as u8);
+            Ok(json!({ "id": queue.submit_with_priority(pages, priority) }))
+        }
+        Some("cancel") => {
+            let id = request["id"].as_u64().ok_or("Missing id")?;
+            if queue.cancel(id) {
+                Ok(json!({}))
+            } else {
+                Err(format!("Job {} is unknown or finished", id))
+            }
+        }
+        Some("queue") => {
+            let jobs: Vec<Value> = queue.jobs().iter().map(job_json).collect();
+            Ok(json!({ "jobs": jobs }))
+        }
+        Some("status") => {
+            let printer = queue.printer();
+            let media_state = match printer.media_state() {
+                MediaState::Ok => json!({ "state": "ok" }),
+                MediaState::Low { remaining_mm } => {
+                    json!({ "state": "low", "remaining_mm": remaining_mm })
+                }
+                MediaState::Empty => json!({ "state": "empty" }),
+            };
+            let printing = queue
+                .jobs()
+                .into_iter()
+                .find(|job| !job.state.is_finished() && job.state != JobState::Queued)
+                .map(|job| job.id);
+            let mut response = json!({ "media_state": media_state, "printing": printing });
+            // Asking the printer waits for the job being printed
+            if printing.is_none() {
+                let status = printer.check_status().map_err(|e| e.to_string())?;
+                let errors = status.errors();
+                let error = (!errors.is_empty()).then(|| errors.to_string());
+                response["printer"] = json!({
+                    "model": format!("{:?}", status.model()),
+                    "media": status.media().map(|media| media.brother_ql_id()),
+                    "error": error,
+                    "phase": format!("{:?}", status.phase()),
+                });
+            }
+            Ok(response)
+        }
+        Some(cmd) => Err(format!("Unknown command: {}", cmd)),
+        None => Err("Missing cmd".to_string()),
+    }
+}
+
+#[cfg(unix)]
+fn job_json(job: &JobInfo) -> Value {
+    let (state, error) = match &job.state {
+        JobState::Queued => ("queued", None),
+        JobState::Printing => ("printing", None),
+        JobState::WaitingForMedia => ("waiting_for_media", None),
+        JobState::Done => ("done", None),
+        JobState::Failed(err) => ("failed", Some(err)),
+        JobState::Cancelled => ("cancelled", None),
+    };
+    json!({
+        "id": job.id,
+        "pages": job.pages,
+        "printed": job.printed,
+        "priority": job.priority,
+        "retries": job.retries,
+        "state": state,
+        "error": error,
+    })
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("print") => print(&args[1..]),
+        Some("filter") => filter(&args[1..]),
+        Some("list") => list(&args[1..]),
+        #[cfg(unix)]
+        Some("daemon") => daemon(&args[1..]),
+        Some("--help") | Some("-h") | None => {
+            print_usage();
+            return;
+        }
+        Some(command) => Err(format!("Unknown command: {}", command)),
+    };
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ql_label::DieCutType;
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        Args::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse_model() {
+        for name in [
+            "QL-820NWB",
+            "QL820NWB",
+            "ql-820nwb",
+            "ql820nwb",
+            "Ql-820Nwb",
+        ] {
+            assert_eq!(parse_model(name), Ok(Model::QL820NWB), "{}", name);
+        }
+        assert_eq!(parse_model("ql-1115nwb"), Ok(Model::QL1115NWB));
+        assert_eq!(parse_model("QL500"), Ok(Model::QL500));
+        assert_eq!(parse_model("ql-820nw"), Ok(Model::QL820NWB));
+        assert!(parse_model("QL-9999").is_err());
+        assert!(parse_model("").is_err());
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parsed = args(&[
+            "--model",
+            "ql800",
+            "--serial",
+            "000G0Z000000",
+            "--media",
+            "29x90",
+            "--threshold",
+            "100",
+            "--resample",
+            "Lanczos3",
+            "--job-pause",
+            "1.5",
+            "--metrics",
+            "127.0.0.1:9898",
+            "label.png",
+        ])
+        .unwrap();
+        assert_eq!(parsed.threshold, 100);
+        assert!(matches!(parsed.resample, Some(Resampling::Lanczos3)));
+        assert_eq!(parsed.job_pause, Duration::from_millis(1500));
+        assert_eq!(parsed.metrics.as_deref(), Some("127.0.0.1:9898"));
+        assert_eq!(parsed.input.as_deref(), Some("label.png"));
+
+        let config = parsed.config().unwrap();
+        assert_eq!(config.model(), Model::QL800);
+        assert_eq!(config.serial(), "000G0Z000000");
+        assert_eq!(config.media(), Media::DieCut(DieCutType::DieCut29x90));
+
+        assert_eq!(args(&["-"]).unwrap().input.as_deref(), Some("-"));
+        assert_eq!(
+            args(&["--profile", "lab"]).unwrap().profile.as_deref(),
+            Some("lab")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_remove_stale_socket() {
+        let dir = env::temp_dir().join(format!("ql-label-socket-{}", process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("daemon.sock");
+        let path = socket.to_str().unwrap();
+
+        // A running daemon keeps its socket
+        let listener = UnixListener::bind(&socket).unwrap();
+        assert!(remove_stale_socket(path)
+            .unwrap_err()
+            .contains("already running"));
+        assert!(socket.exists());
+
+        // Left behind by a daemon that exited
+        drop(listener);
+        remove_stale_socket(path).unwrap();
+        assert!(!socket.exists());
+        remove_stale_socket(path).unwrap();
+
+        std::fs::write(&socket, b"").unwrap();
+        assert!(remove_stale_socket(path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_invalid_args() {
+        assert!(args(&["--threshold", "300"]).is_err());
+        assert!(args(&["--job-pause", "-1"]).is_err());
+        assert!(args(&["--retries", "many"]).is_err());
+        assert!(args(&["--bogus"]).is_err());
+        assert_eq!(
+            args(&["--model"]).err(),
+            Some("Missing value for --model".to_string())
+        );
+        let unknown_media = args(&["--model", "QL-800", "--media", "99x99"]).unwrap();
+        assert!(unknown_media.config().is_err());
+        let unknown_model = args(&["--model", "QL-9999"]).unwrap();
+        assert!(unknown_model.config().is_err());
+    }
+}
@@ -0,0 +1,85 @@
+//! Cache of encoded pages.
+//!
+//! Kiosks reprint the same few labels over and over. With
+//! `Config::encoding_cache()` a printer keeps the raster commands of the
+//! pages it printed last, keyed by the hash of their content, so a repeated
+//! page is sent without shifting and compressing its rows again. Pages with
+//! the same hash are compared row by row, a hash collision is encoded like
+//! any other page. The cache belongs to one printer and is cleared whenever
+//! its configuration changes.
+
+use std::collections::VecDeque;
+
+use crate::Matrix;
+
+/// Raster commands of recently encoded pages with the lines they encode,
+/// least recently used first.
+#[derive(Debug)]
+pub(crate) struct EncodingCache {
+    capacity: usize,
+    entries: VecDeque<(u64, Matrix, Vec<u8>)>,
+}
+
+impl EncodingCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        EncodingCache {
+            capacity,
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Raster commands of the page `lines` with the hash `key`.
+    pub(crate) fn get(&mut self, key: u64, lines: &Matrix) -> Option<Vec<u8>> {
+        let index = self
+            .entries
+            .iter()
+            .position(|(k, l, _)| *k == key && l == lines)?;
+        let entry = self.entries.remove(index)?;
+        let rows = entry.2.clone();
+        self.entries.push_back(entry);
+        Some(rows)
+    }
+
+    /// Remember the raster commands `rows` of the page `lines` with the hash
+    /// `key`, dropping the least recently used page when the cache is full.
+    pub(crate) fn insert(&mut self, key: u64, lines: Matrix, rows: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.entries.retain(|(k, _, _)| *k != key);
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((key, lines, rows));
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EncodingCache;
+
+    #[test]
+    fn test_least_recently_used() {
+        let page = |byte| vec![vec![byte; 90]];
+        let mut cache = EncodingCache::new(2);
+        cache.insert(1, page(1), vec![1]);
+        cache.insert(2, page(2), vec![2]);
+        assert_eq!(cache.get(1, &page(1)), Some(vec![1]));
+
+        // 2 is the least recently used page
+        cache.insert(3, page(3), vec![3]);
+        assert_eq!(cache.get(2, &page(2)), None);
+        assert_eq!(cache.get(1, &page(1)), Some(vec![1]));
+        assert_eq!(cache.get(3, &page(3)), Some(vec![3]));
+
+        // Another page with the same hash is not a hit
+        assert_eq!(cache.get(3, &page(4)), None);
+
+        cache.clear();
+        assert_eq!(cache.get(1, &page(1)), None);
+    }
+}
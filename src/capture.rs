@@ -0,0 +1,312 @@
+//! Raster streams extracted from USB captures.
+//!
+//! Captures of the traffic between a driver and a printer show what other
+//! software actually sends. `UsbCapture` reads pcap and pcapng files written
+//! by Wireshark or tcpdump with Linux usbmon or Windows USBPcap, keeps the
+//! bulk OUT data of every device and hands it to `protocol::disassemble()`
+//! or `protocol::decode()`.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::protocol;
+
+// Link types of USB captures
+const LINKTYPE_USB_LINUX: u32 = 189;
+const LINKTYPE_USBPCAP: u32 = 249;
+const LINKTYPE_USB_LINUX_MMAPPED: u32 = 220;
+
+// pcapng block types
+const SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const ENHANCED_PACKET: u32 = 0x0000_0006;
+
+const TRANSFER_BULK: u8 = 3;
+
+/// Bulk OUT data of a USB capture, one stream per device.
+///
+/// # Example
+/// ```rust,no_run
+/// # use ql_label::UsbCapture;
+/// let capture = UsbCapture::load("windows-driver.pcapng")?;
+/// for (bus, device) in capture.devices() {
+///     println!("Bus {} device {}", bus, device);
+/// }
+/// print!("{}", capture.disassemble());
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsbCapture {
+    streams: BTreeMap<(u16, u16), Vec<u8>>,
+}
+
+impl UsbCapture {
+    /// Read a pcap or pcapng capture file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let bytes =
+            std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::parse(&bytes)
+    }
+
+    /// Parse a pcap or pcapng capture.
+    ///
+    /// Packets of other link types are skipped, so a capture of several
+    /// interfaces only needs one of them to be a USB bus.
+    ///
+    /// # Returns
+    /// * `Ok(UsbCapture)` - Bulk OUT data found in the capture
+    /// * `Err(String)` - The data is not a pcap or pcapng file, or is cut off
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let mut capture = UsbCapture::default();
+        match bytes.get(..4) {
+            Some([0x0A, 0x0D, 0x0D, 0x0A]) => capture.parse_pcapng(bytes)?,
+            Some(magic) => {
+                let little_endian = match magic {
+                    [0xD4, 0xC3, 0xB2, 0xA1] | [0x4D, 0x3C, 0xB2, 0xA1] => true,
+                    [0xA1, 0xB2, 0xC3, 0xD4] | [0xA1, 0xB2, 0x3C, 0x4D] => false,
+                    _ => return Err("Not a pcap or pcapng capture".to_string()),
+                };
+                capture.parse_pcap(bytes, little_endian)?;
+            }
+            None => return Err("Not a pcap or pcapng capture".to_string()),
+        }
+        Ok(capture)
+    }
+
+    /// Bus and device numbers of the devices data was sent to.
+    pub fn devices(&self) -> Vec<(u16, u16)> {
+        self.streams.keys().copied().collect()
+    }
+
+    /// Data sent to the device `device` on `bus`.
+    pub fn stream(&self, bus: u16, device: u16) -> Option<&[u8]> {
+        self.streams.get(&(bus, device)).map(Vec::as_slice)
+    }
+
+    /// The stream most likely carrying print jobs, the longest one.
+    pub fn raster_stream(&self) -> Option<&[u8]> {
+        self.streams
+            .values()
+            .max_by_key(|stream| stream.len())
+            .map(Vec::as_slice)
+    }
+
+    /// Listing of `raster_stream()`, see `protocol::disassemble()`.
+    pub fn disassemble(&self) -> String {
+        protocol::disassemble(self.raster_stream().unwrap_or_default())
+    }
+
+    fn parse_pcap(&mut self, bytes: &[u8], little_endian: bool) -> Result<(), String> {
+        let u32_at = |pos: usize| read_u32(bytes, pos, little_endian);
+        let link_type = u32_at(20).ok_or("pcap header is cut off")?;
+
+        let mut pos = 24;
+        while pos < bytes.len() {
+            let len = u32_at(pos + 8).ok_or("pcap record header is cut off")? as usize;
+            let data = bytes
+                .get(pos + 16..pos + 16 + len)
+                .ok_or("pcap record is cut off")?;
+            self.packet(link_type, data, little_endian);
+            pos += 16 + len;
+        }
+        Ok(())
+    }
+
+    fn parse_pcapng(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let mut little_endian = true;
+        let mut link_types = Vec::new();
+
+        let mut pos = 0;
+        while pos < bytes.len() {
+            if read_u32(bytes, pos, true) == Some(SECTION_HEADER) {
+                little_endian = match bytes.get(pos + 8..pos + 12) {
+                    Some([0x4D, 0x3C, 0x2B, 0x1A]) => true,
+                    Some([0x1A, 0x2B, 0x3C, 0x4D]) => false,
+                    _ => return Err("Invalid pcapng section header".to_string()),
+                };
+                link_types.clear();
+            }
+            let u32_at = |pos: usize| read_u32(bytes, pos, little_endian);
+            let block_type = u32_at(pos).ok_or("pcapng block is cut off")?;
+            let len = u32_at(pos + 4).ok_or("pcapng block is cut off")? as usize;
+            if len < 12 || pos + len > bytes.len() {
+                return Err(format!("Invalid pcapng block length at byte {}", pos));
+            }
+
+            match block_type {
+                INTERFACE_DESCRIPTION => {
+                    let link_type = read_u16(bytes, pos + 8, little_endian)
+                        .ok_or("pcapng interface block is cut off")?;
+                    link_types.push(link_type as u32);
+                }
+                ENHANCED_PACKET => {
+                    let interface = u32_at(pos + 8).ok_or("pcapng packet is cut off")?;
+                    let captured = u32_at(pos + 20).ok_or("pcapng packet is cut off")? as usize;
+                    let data = bytes
+                        .get(pos + 28..pos + 28 + captured)
+                        .ok_or("pcapng packet is cut off")?;
+                    if let Some(&link_type) = link_types.get(interface as usize) {
+                        self.packet(link_type, data, little_endian);
+                    }
+                }
+                _ => {}
+            }
+            pos += len;
+        }
+        Ok(())
+    }
+
+    /// Keep the data of a bulk OUT transfer.
+    fn packet(&mut self, link_type: u32, data: &[u8], little_endian: bool) {
+        let transfer = match link_type {
+            LINKTYPE_USB_LINUX => usbmon_transfer(data, 48, little_endian),
+            LINKTYPE_USB_LINUX_MMAPPED => usbmon_transfer(data, 64, little_endian),
+            LINKTYPE_USBPCAP => usbpcap_transfer(data),
+            _ => None,
+        };
+        if let Some((device, payload)) = transfer {
+            self.streams
+                .entry(device)
+                .or_default()
+                .extend_from_slice(payload);
+        }
+    }
+}
+
+/// Bulk OUT data of a Linux usbmon packet with a header of `header_len`.
+fn usbmon_transfer(
+    data: &[u8],
+    header_len: usize,
+    little_endian: bool,
+) -> Option<((u16, u16), &[u8])> {
+    let (event, transfer, endpoint, device) =
+        (*data.get(8)?, *data.get(9)?, *data.get(10)?, *data.get(11)?);
+    let bus = read_u16(data, 12, little_endian)?;
+    let len = read_u32(data, 36, little_endian)? as usize;
+    // Data sent to the device is part of the submission
+    if event != b'S' || transfer != TRANSFER_BULK || endpoint & 0x80 != 0 || len == 0 {
+        return None;
+    }
+    let payload = data.get(header_len..header_len + len)?;
+    Some(((bus, device as u16), payload))
+}
+
+/// Bulk OUT data of a Windows USBPcap packet.
+fn usbpcap_transfer(data: &[u8]) -> Option<((u16, u16), &[u8])> {
+    let header_len = read_u16(data, 0, true)? as usize;
+    let info = *data.get(16)?;
+    let bus = read_u16(data, 17, true)?;
+    let device = read_u16(data, 19, true)?;
+    let (endpoint, transfer) = (*data.get(21)?, *data.get(22)?);
+    let len = read_u32(data, 23, true)? as usize;
+    // Bit 0 of info is set for packets coming from the device
+    if info & 0x01 != 0 || transfer != TRANSFER_BULK || endpoint & 0x80 != 0 || len == 0 {
+        return None;
+    }
+    let payload = data.get(header_len..header_len + len)?;
+    Some(((bus, device), payload))
+}
+
+fn read_u16(bytes: &[u8], pos: usize, little_endian: bool) -> Option<u16> {
+    let b = bytes.get(pos..pos + 2)?;
+    let b = [b[0], b[1]];
+    Some(if little_endian {
+        u16::from_le_bytes(b)
+    } else {
+        u16::from_be_bytes(b)
+    })
+}
+
+fn read_u32(bytes: &[u8], pos: usize, little_endian: bool) -> Option<u32> {
+    let b = bytes.get(pos..pos + 4)?;
+    let b = [b[0], b[1], b[2], b[3]];
+    Some(if little_endian {
+        u32::from_le_bytes(b)
+    } else {
+        u32::from_be_bytes(b)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usbmon_packet(event: u8, endpoint: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x00; 64];
+        packet[8] = event;
+        packet[9] = TRANSFER_BULK;
+        packet[10] = endpoint;
+        packet[11] = 5;
+        packet[12..14].copy_from_slice(&1u16.to_le_bytes());
+        packet[36..40].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    fn usbpcap_packet(info: u8, endpoint: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x00; 27];
+        packet[0..2].copy_from_slice(&27u16.to_le_bytes());
+        packet[16] = info;
+        packet[17..19].copy_from_slice(&2u16.to_le_bytes());
+        packet[19..21].copy_from_slice(&7u16.to_le_bytes());
+        packet[21] = endpoint;
+        packet[22] = TRANSFER_BULK;
+        packet[23..27].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    #[test]
+    fn test_pcap_usbmon() {
+        let mut file = vec![0xD4, 0xC3, 0xB2, 0xA1, 2, 0, 4, 0];
+        file.extend_from_slice(&[0x00; 12]);
+        file.extend_from_slice(&LINKTYPE_USB_LINUX_MMAPPED.to_le_bytes());
+        for packet in [
+            usbmon_packet(b'S', 0x02, &[0x1B, 0x40]),
+            usbmon_packet(b'S', 0x81, &[0xFF; 32]),
+            usbmon_packet(b'C', 0x02, &[]),
+            usbmon_packet(b'S', 0x02, &[0x1A]),
+        ] {
+            file.extend_from_slice(&[0x00; 8]);
+            file.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            file.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            file.extend_from_slice(&packet);
+        }
+
+        let capture = UsbCapture::parse(&file).unwrap();
+        assert_eq!(capture.devices(), vec![(1, 5)]);
+        assert_eq!(capture.raster_stream(), Some(&[0x1B, 0x40, 0x1A][..]));
+    }
+
+    #[test]
+    fn test_pcapng_usbpcap() {
+        let block = |block_type: u32, body: &[u8]| {
+            let len = (12 + body.len()).div_ceil(4) * 4;
+            let mut block = block_type.to_le_bytes().to_vec();
+            block.extend_from_slice(&(len as u32).to_le_bytes());
+            block.extend_from_slice(body);
+            block.resize(len - 4, 0x00);
+            block.extend_from_slice(&(len as u32).to_le_bytes());
+            block
+        };
+        let packet = |data: Vec<u8>| {
+            let mut body = vec![0x00; 20];
+            body[12..16].copy_from_slice(&(data.len() as u32).to_le_bytes());
+            body.extend_from_slice(&data);
+            block(ENHANCED_PACKET, &body)
+        };
+
+        let mut file = block(SECTION_HEADER, &[0x4D, 0x3C, 0x2B, 0x1A, 1, 0, 0, 0]);
+        file.extend(block(INTERFACE_DESCRIPTION, &[249, 0, 0, 0, 0, 0, 0, 0]));
+        file.extend(packet(usbpcap_packet(0x00, 0x02, &[0x1B, 0x69, 0x53])));
+        file.extend(packet(usbpcap_packet(0x01, 0x81, &[0x80; 32])));
+
+        let capture = UsbCapture::parse(&file).unwrap();
+        assert_eq!(capture.stream(2, 7), Some(&[0x1B, 0x69, 0x53][..]));
+        assert_eq!(
+            capture.disassemble(),
+            "0000  ESC i S       Status request\n"
+        );
+    }
+}
@@ -0,0 +1,348 @@
+//! Labels composed of several elements.
+//!
+//! `Canvas` draws immediately, so a continuous label has to be given its
+//! length before anything is placed on it. `Layout` collects rectangles,
+//! assets, text, images and barcodes with their positions in millimetres
+//! first, cuts continuous tape to the furthest element and then draws them
+//! in the order they were added.
+
+#[cfg(feature = "image")]
+use image::DynamicImage;
+
+#[cfg(feature = "barcode")]
+use crate::barcode::BarcodeLabel;
+#[cfg(feature = "image")]
+use crate::layout::Resampling;
+#[cfg(feature = "text")]
+use crate::text::FontChain;
+use crate::{
+    asset::Asset,
+    layout::{Canvas, Orientation, Resolution},
+    media::Media,
+    Matrix,
+};
+
+/// Element of a `Layout`, drawn at a position.
+enum Element<'a> {
+    Rect {
+        width: f32,
+        height: f32,
+        line: Option<f32>,
+    },
+    Asset(&'a Asset),
+    #[cfg(feature = "text")]
+    Text {
+        text: String,
+        fonts: &'a FontChain,
+        size: f32,
+    },
+    #[cfg(feature = "image")]
+    Image {
+        width: f32,
+        height: f32,
+        image: &'a DynamicImage,
+        filter: Resampling,
+    },
+    #[cfg(feature = "barcode")]
+    Barcode(&'a BarcodeLabel),
+}
+
+impl Element<'_> {
+    /// Width and height of the element on `canvas` in millimetres.
+    #[cfg_attr(not(feature = "barcode"), allow(unused_variables))]
+    fn size_mm(&self, canvas: &Canvas) -> (f32, f32) {
+        match self {
+            Element::Rect { width, height, .. } => (*width, *height),
+            Element::Asset(asset) => asset.size_mm(),
+            #[cfg(feature = "text")]
+            Element::Text { text, fonts, size } => (fonts.text_width(text, *size), *size),
+            #[cfg(feature = "image")]
+            Element::Image { width, height, .. } => (*width, *height),
+            #[cfg(feature = "barcode")]
+            Element::Barcode(code) => code.size_mm_on(canvas),
+        }
+    }
+
+    #[cfg_attr(not(feature = "image"), allow(unused_variables))]
+    fn draw(&self, canvas: &mut Canvas, x: f32, y: f32, orientation: Orientation) {
+        match self {
+            Element::Rect {
+                width,
+                height,
+                line: None,
+            } => canvas.fill_rect(x, y, *width, *height),
+            Element::Rect {
+                width,
+                height,
+                line: Some(line),
+            } => canvas.stroke_rect(x, y, *width, *height, *line),
+            Element::Asset(asset) => canvas.draw_asset(x, y, asset),
+            #[cfg(feature = "text")]
+            Element::Text { text, fonts, size } => {
+                canvas.draw_text(x, y, text, fonts, *size);
+            }
+            #[cfg(feature = "image")]
+            Element::Image {
+                width,
+                height,
+                image,
+                filter,
+            } => {
+                let resolution = canvas.resolution();
+                let mut frame = match orientation {
+                    Orientation::Portrait => Canvas::blank(*width, *height, resolution),
+                    Orientation::Landscape => {
+                        Canvas::blank(*height, *width, resolution).orientation(orientation)
+                    }
+                };
+                frame.fit_image(&crate::render::flatten(image), *filter);
+                canvas.draw_canvas(x, y, &frame);
+            }
+            #[cfg(feature = "barcode")]
+            Element::Barcode(code) => code.draw(canvas, x, y),
+        }
+    }
+}
+
+/// A label composed of elements placed in millimetres.
+///
+/// Positions are measured from the top-left corner of the printable area
+/// inside the margin, like on `Canvas`. Elements are drawn in the order they
+/// were added, later ones on top. Continuous tape is cut where the furthest
+/// element ends, plus the margin, unless `length_mm()` is set.
+///
+/// Text, images and barcodes are added with `add_text()`, `add_image()` and
+/// `add_barcode()` when the `text`, `image` and `barcode` features are
+/// enabled.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{Asset, Canvas, ContinuousType, Layout, Media, Resolution};
+/// let logo = Asset::new(10.0, 10.0).vector(|canvas: &mut Canvas| {
+///     canvas.fill_rect(0.0, 0.0, 10.0, 10.0);
+/// });
+///
+/// let media = Media::Continuous(ContinuousType::Continuous62);
+/// let matrix = Layout::new(media)
+///     .margin_mm(2.0)
+///     .add_frame(0.0, 0.0, 54.0, 20.0, 0.5)
+///     .add_asset(40.0, 5.0, &logo)
+///     .add_rect(5.0, 25.0, 20.0, 5.0)
+///     .render();
+///
+/// // 30 mm of content and the margins
+/// assert_eq!(matrix.len(), Resolution::Normal.dots_along(34.0) as usize);
+/// assert_eq!(matrix[0].len(), 90);
+/// ```
+pub struct Layout<'a> {
+    media: Media,
+    resolution: Resolution,
+    orientation: Orientation,
+    margin: f32,
+    length: Option<f32>,
+    elements: Vec<(f32, f32, Element<'a>)>,
+}
+
+impl<'a> Layout<'a> {
+    /// Empty layout for `media` in normal resolution, portrait orientation
+    /// and without margin.
+    pub fn new(media: Media) -> Self {
+        Layout {
+            media,
+            resolution: Resolution::Normal,
+            orientation: Orientation::Portrait,
+            margin: 0.0,
+            length: None,
+            elements: Vec::new(),
+        }
+    }
+
+    /// Resolution the label is printed at.
+    pub fn resolution(self, resolution: Resolution) -> Self {
+        Layout { resolution, ..self }
+    }
+
+    /// Orientation of the content, see `Canvas::orientation()`.
+    pub fn orientation(self, orientation: Orientation) -> Self {
+        Layout {
+            orientation,
+            ..self
+        }
+    }
+
+    /// Margin in millimetres around the printable area, see
+    /// `Canvas::margin_mm()`.
+    pub fn margin_mm(self, mm: f32) -> Self {
+        Layout { margin: mm, ..self }
+    }
+
+    /// Fixed length of a continuous label in millimetres, margins included.
+    /// Die-cut labels keep their length.
+    pub fn length_mm(self, mm: f32) -> Self {
+        Layout {
+            length: Some(mm),
+            ..self
+        }
+    }
+
+    fn add(mut self, x: f32, y: f32, element: Element<'a>) -> Self {
+        self.elements.push((x, y, element));
+        self
+    }
+
+    /// Add a black rectangle.
+    ///
+    /// # Arguments
+    /// * `x`, `y` - Top-left corner in millimetres
+    /// * `width`, `height` - Size in millimetres
+    pub fn add_rect(self, x: f32, y: f32, width: f32, height: f32) -> Self {
+        let rect = Element::Rect {
+            width,
+            height,
+            line: None,
+        };
+        self.add(x, y, rect)
+    }
+
+    /// Add the outline of a rectangle, e.g. a border around the label.
+    ///
+    /// # Arguments
+    /// * `x`, `y` - Top-left corner in millimetres
+    /// * `width`, `height` - Outer size in millimetres
+    /// * `line` - Line thickness in millimetres
+    pub fn add_frame(self, x: f32, y: f32, width: f32, height: f32, line: f32) -> Self {
+        let frame = Element::Rect {
+            width,
+            height,
+            line: Some(line),
+        };
+        self.add(x, y, frame)
+    }
+
+    /// Add an asset at its physical size, see `Canvas::draw_asset()`.
+    pub fn add_asset(self, x: f32, y: f32, asset: &'a Asset) -> Self {
+        self.add(x, y, Element::Asset(asset))
+    }
+
+    /// Add a line of text, see `Canvas::draw_text()`.
+    ///
+    /// Requires the `text` feature.
+    ///
+    /// # Arguments
+    /// * `x`, `y` - Top-left corner of the line in millimetres
+    /// * `text` - Text to draw
+    /// * `fonts` - Fonts used to render the text
+    /// * `size` - Font size (em height) in millimetres
+    #[cfg(feature = "text")]
+    pub fn add_text(self, x: f32, y: f32, text: &str, fonts: &'a FontChain, size: f32) -> Self {
+        let text = Element::Text {
+            text: text.to_string(),
+            fonts,
+            size,
+        };
+        self.add(x, y, text)
+    }
+
+    /// Add an image scaled to fit a box and centered in it.
+    ///
+    /// Transparent areas are white. Use `Resampling::Nearest` for line art
+    /// and codes; photos look best with `Resampling::Lanczos3` and
+    /// `to_canvas().to_matrix_dithered()`.
+    ///
+    /// Requires the `image` feature.
+    ///
+    /// # Arguments
+    /// * `x`, `y` - Top-left corner of the box in millimetres
+    /// * `width`, `height` - Size of the box in millimetres
+    /// * `image` - Image to draw
+    /// * `filter` - Filter used to scale the image
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{DieCutType, Layout, Media, Resampling};
+    /// use image::{DynamicImage, GrayImage, Luma};
+    ///
+    /// let photo = DynamicImage::ImageLuma8(GrayImage::from_pixel(40, 20, Luma([0])));
+    /// let canvas = Layout::new(Media::DieCut(DieCutType::DieCut62x29))
+    ///     .add_image(0.0, 0.0, 20.0, 20.0, &photo, Resampling::Triangle)
+    ///     .to_canvas();
+    ///
+    /// // Twice as wide as tall, the image is centered 5 mm down the box
+    /// assert_eq!(canvas.dot(10, 20), Some(255));
+    /// assert_eq!(canvas.dot(10, 100), Some(0));
+    /// ```
+    #[cfg(feature = "image")]
+    pub fn add_image(
+        self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        image: &'a DynamicImage,
+        filter: Resampling,
+    ) -> Self {
+        let image = Element::Image {
+            width,
+            height,
+            image,
+            filter,
+        };
+        self.add(x, y, image)
+    }
+
+    /// Add a barcode with its bars along the x axis, or a QR code, without
+    /// quiet zone. The resolution of `code` is ignored.
+    ///
+    /// Requires the `barcode` feature.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{BarcodeLabel, ContinuousType, Layout, Media};
+    /// let code = BarcodeLabel::qr("QL-820NWB")?;
+    /// let matrix = Layout::new(Media::Continuous(ContinuousType::Continuous29))
+    ///     .margin_mm(1.0)
+    ///     .add_barcode(0.0, 0.0, &code)
+    ///     .render();
+    ///
+    /// // 21 rows of 6 dots and the margins
+    /// assert_eq!(matrix.len(), 21 * 6 + 2 * 12);
+    /// # Ok::<(), String>(())
+    /// ```
+    #[cfg(feature = "barcode")]
+    pub fn add_barcode(self, x: f32, y: f32, code: &'a BarcodeLabel) -> Self {
+        self.add(x, y, Element::Barcode(code))
+    }
+
+    /// Draw the elements on a canvas for the media, e.g. to dither it.
+    pub fn to_canvas(&self) -> Canvas {
+        let mut canvas = Canvas::new(self.media, self.resolution).orientation(self.orientation);
+        if let Media::Continuous(_) = self.media {
+            let length = self.length.unwrap_or_else(|| {
+                let end = self
+                    .elements
+                    .iter()
+                    .map(|(x, y, element)| {
+                        let (width, height) = element.size_mm(&canvas);
+                        match self.orientation {
+                            Orientation::Portrait => y + height,
+                            Orientation::Landscape => x + width,
+                        }
+                    })
+                    .fold(0.0, f32::max);
+                end + 2.0 * self.margin
+            });
+            canvas = canvas.length_mm(length);
+        }
+        let mut canvas = canvas.margin_mm(self.margin);
+
+        for (x, y, element) in &self.elements {
+            element.draw(&mut canvas, *x, *y, self.orientation);
+        }
+        canvas
+    }
+
+    /// Render the label to printer rows.
+    pub fn render(&self) -> Matrix {
+        self.to_canvas().to_matrix()
+    }
+}
@@ -0,0 +1,431 @@
+//! Pages handed to a CUPS filter.
+//!
+//! A print queue rasterizes documents before the printer's filter runs, as
+//! CUPS raster (`application/vnd.cups-raster`) or as PNM images from
+//! simpler spoolers. `decode_cups_pages()` turns either into grayscale
+//! pages, which `LabelImage` converts to printer rows for the media of the
+//! queue; see the `filter` command of the `ql-label` tool.
+//!
+//! Requires the `image` feature.
+
+use image::GrayImage;
+
+// Size of the page header of CUPS raster version 1, and versions 2 and 3
+const HEADER_V1: usize = 420;
+const HEADER_V2: usize = 1796;
+
+// Fields of the page header used here, see cups_page_header2_t
+const WIDTH: usize = 372;
+const HEIGHT: usize = 376;
+const BITS_PER_COLOR: usize = 384;
+const BITS_PER_PIXEL: usize = 388;
+const BYTES_PER_LINE: usize = 392;
+const COLOR_ORDER: usize = 396;
+const COLOR_SPACE: usize = 400;
+
+// Largest page decoded, in bytes of its pixels and of the grayscale image:
+// a meter of 102 mm labels at 600 dpi in RGB takes about 170 MB
+const MAX_PAGE_BYTES: usize = 1 << 28;
+
+// Color spaces, see cups_cspace_t
+const CSPACE_W: u32 = 0;
+const CSPACE_RGB: u32 = 1;
+const CSPACE_K: u32 = 3;
+const CSPACE_SW: u32 = 18;
+const CSPACE_SRGB: u32 = 19;
+
+/// Decode the pages of a CUPS raster stream (versions 1 to 3) or of PNM
+/// images (PBM, PGM or PPM, several images may follow each other).
+///
+/// White, black, gray and RGB color spaces with 1 or 8 bits per color are
+/// supported, which covers what the generic PPDs of label printers request.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::decode_cups_pages;
+/// // Two 4 x 2 pixel gray images
+/// let mut pgm = b"P5 4 2 255\n".to_vec();
+/// pgm.extend_from_slice(&[0, 0, 255, 255, 0, 0, 255, 255]);
+/// let input = [pgm.clone(), pgm].concat();
+///
+/// let pages = decode_cups_pages(&input)?;
+/// assert_eq!(pages.len(), 2);
+/// assert_eq!(pages[1].dimensions(), (4, 2));
+/// assert_eq!(pages[1].get_pixel(0, 1)[0], 0);
+/// # Ok::<(), String>(())
+/// ```
+pub fn decode_cups_pages(data: &[u8]) -> Result<Vec<GrayImage>, String> {
+    match data.get(..4) {
+        Some(b"RaSt") => cups_raster(&data[4..], HEADER_V1, true, false),
+        Some(b"tSaR") => cups_raster(&data[4..], HEADER_V1, false, false),
+        Some(b"RaS2") => cups_raster(&data[4..], HEADER_V2, true, true),
+        Some(b"2SaR") => cups_raster(&data[4..], HEADER_V2, false, true),
+        Some(b"RaS3") => cups_raster(&data[4..], HEADER_V2, true, false),
+        Some(b"3SaR") => cups_raster(&data[4..], HEADER_V2, false, false),
+        _ if data.first() == Some(&b'P') => pnm(data),
+        _ => Err("Input is neither CUPS raster nor a PNM image".into()),
+    }
+}
+
+/// Pages of a CUPS raster stream after its synchronization word.
+fn cups_raster(
+    mut data: &[u8],
+    header_size: usize,
+    big_endian: bool,
+    compressed: bool,
+) -> Result<Vec<GrayImage>, String> {
+    let mut pages = Vec::new();
+    while !data.is_empty() {
+        let header = data
+            .get(..header_size)
+            .ok_or("CUPS raster ends in a page header")?;
+        let field = |offset: usize| {
+            let bytes = [
+                header[offset],
+                header[offset + 1],
+                header[offset + 2],
+                header[offset + 3],
+            ];
+            if big_endian {
+                u32::from_be_bytes(bytes)
+            } else {
+                u32::from_le_bytes(bytes)
+            }
+        };
+        let format = Format {
+            width: field(WIDTH),
+            height: field(HEIGHT),
+            bits_per_color: field(BITS_PER_COLOR),
+            bits_per_pixel: field(BITS_PER_PIXEL),
+            bytes_per_line: field(BYTES_PER_LINE) as usize,
+            color_space: field(COLOR_SPACE),
+        };
+        if field(COLOR_ORDER) != 0 && format.bits_per_pixel != format.bits_per_color {
+            return Err("Only chunky CUPS raster pixels are supported".into());
+        }
+        format.check()?;
+        let page_bytes = page_bytes(format.bytes_per_line, format.width, format.height)?;
+        data = &data[header_size..];
+
+        let (lines, len) = if compressed {
+            decompress(data, &format, page_bytes)?
+        } else {
+            let len = page_bytes;
+            let lines = data.get(..len).ok_or("CUPS raster ends in a page")?;
+            (lines.to_vec(), len)
+        };
+        data = &data[len..];
+        pages.push(format.to_gray(&lines));
+    }
+    Ok(pages)
+}
+
+/// Layout of the pixels of a CUPS raster page.
+struct Format {
+    width: u32,
+    height: u32,
+    bits_per_color: u32,
+    bits_per_pixel: u32,
+    bytes_per_line: usize,
+    color_space: u32,
+}
+
+impl Format {
+    fn check(&self) -> Result<(), String> {
+        let supported = match self.color_space {
+            CSPACE_W | CSPACE_SW | CSPACE_K => matches!(self.bits_per_pixel, 1 | 8),
+            CSPACE_RGB | CSPACE_SRGB => self.bits_per_color == 8 && self.bits_per_pixel == 24,
+            _ => false,
+        };
+        if !supported {
+            return Err(format!(
+                "Unsupported CUPS raster: color space {} with {} bits per pixel",
+                self.color_space, self.bits_per_pixel
+            ));
+        }
+        let bits = self.width as u64 * self.bits_per_pixel as u64;
+        if (self.bytes_per_line as u64) < bits.div_ceil(8) {
+            return Err(format!(
+                "CUPS raster lines of {} bytes are too short for {} pixels",
+                self.bytes_per_line, self.width
+            ));
+        }
+        Ok(())
+    }
+
+    /// Bytes a run of the compression repeats.
+    fn pixel_bytes(&self) -> usize {
+        (self.bits_per_pixel as usize).div_ceil(8)
+    }
+
+    /// Value of blank pixels, white paper.
+    fn blank(&self) -> u8 {
+        if self.color_space == CSPACE_K {
+            0x00
+        } else {
+            0xFF
+        }
+    }
+
+    fn to_gray(&self, lines: &[u8]) -> GrayImage {
+        GrayImage::from_fn(self.width, self.height, |x, y| {
+            let line = &lines[y as usize * self.bytes_per_line..];
+            let x = x as usize;
+            let value = match (self.color_space, self.bits_per_pixel) {
+                (_, 1) => {
+                    let set = line[x / 8] & (0x80 >> (x % 8)) != 0;
+                    // Set bits are ink in the black color space, light otherwise
+                    if set == (self.color_space == CSPACE_K) {
+                        0
+                    } else {
+                        255
+                    }
+                }
+                (CSPACE_K, _) => 255 - line[x],
+                (CSPACE_RGB, _) | (CSPACE_SRGB, _) => luma(&line[x * 3..x * 3 + 3]),
+                _ => line[x],
+            };
+            image::Luma([value])
+        })
+    }
+}
+
+/// Bytes of the pixels of a page with lines of `line` bytes, checking that
+/// the page and its grayscale image are not larger than `MAX_PAGE_BYTES`.
+fn page_bytes(line: usize, width: u32, height: u32) -> Result<usize, String> {
+    let pixels = (width as usize).checked_mul(height as usize);
+    match (line.checked_mul(height as usize), pixels) {
+        (Some(bytes), Some(pixels)) if bytes <= MAX_PAGE_BYTES && pixels <= MAX_PAGE_BYTES => {
+            Ok(bytes)
+        }
+        _ => Err(format!(
+            "Page of {} x {} pixels is too large",
+            width, height
+        )),
+    }
+}
+
+/// `page_bytes` bytes of the lines of a page of CUPS raster version 2 and
+/// the number of bytes they take in `data`.
+///
+/// Every line starts with a repeat count, followed by runs of one pixel
+/// repeated (control byte 0 to 127) or of literal pixels (129 to 255); 128
+/// clears the rest of the line.
+fn decompress(data: &[u8], format: &Format, page_bytes: usize) -> Result<(Vec<u8>, usize), String> {
+    let truncated = || "Compressed CUPS raster ends in a page".to_string();
+    let pixel = format.pixel_bytes();
+    let bpl = format.bytes_per_line;
+    // Grows with the input rather than trusting the header
+    let mut lines = Vec::new();
+    let mut pos = 0;
+    while lines.len() < page_bytes {
+        let repeat = *data.get(pos).ok_or_else(truncated)? as usize + 1;
+        pos += 1;
+        let mut line = Vec::with_capacity(bpl);
+        while line.len() < bpl {
+            let control = *data.get(pos).ok_or_else(truncated)?;
+            pos += 1;
+            match control {
+                0..=127 => {
+                    let run = data.get(pos..pos + pixel).ok_or_else(truncated)?;
+                    pos += pixel;
+                    for _ in 0..=control {
+                        line.extend_from_slice(run);
+                    }
+                }
+                128 => line.resize(bpl, format.blank()),
+                _ => {
+                    let len = (257 - control as usize) * pixel;
+                    line.extend_from_slice(data.get(pos..pos + len).ok_or_else(truncated)?);
+                    pos += len;
+                }
+            }
+        }
+        line.truncate(bpl);
+        for _ in 0..repeat {
+            lines.extend_from_slice(&line);
+        }
+    }
+    lines.truncate(page_bytes);
+    Ok((lines, pos))
+}
+
+/// Pages of concatenated binary PNM images.
+fn pnm(mut data: &[u8]) -> Result<Vec<GrayImage>, String> {
+    let mut pages = Vec::new();
+    while !data.iter().all(u8::is_ascii_whitespace) {
+        let magic = data.get(..2).ok_or("PNM image ends in its header")?;
+        let fields = if magic == b"P4" { 2 } else { 3 };
+        let mut pos = 2;
+        let mut values = Vec::new();
+        while values.len() < fields {
+            match data.get(pos).ok_or("PNM image ends in its header")? {
+                b'#' => {
+                    while data.get(pos).is_some_and(|&b| b != b'\n') {
+                        pos += 1;
+                    }
+                }
+                b if b.is_ascii_whitespace() => pos += 1,
+                _ => {
+                    let start = pos;
+                    while data.get(pos).is_some_and(u8::is_ascii_digit) {
+                        pos += 1;
+                    }
+                    let value = std::str::from_utf8(&data[start..pos])
+                        .ok()
+                        .and_then(|value| value.parse::<u32>().ok())
+                        .ok_or("Invalid PNM header")?;
+                    values.push(value);
+                }
+            }
+        }
+        // A single whitespace character separates the header from the pixels
+        pos += 1;
+        let (width, height) = (values[0], values[1]);
+        if values.get(2).is_some_and(|&max| max == 0 || max > 255) {
+            return Err("Only PNM images with 8-bit samples are supported".into());
+        }
+        let max = values.get(2).copied().unwrap_or(1);
+        let scale = |value: u8| (value as u32 * 255 / max).min(255) as u8;
+
+        let (page_bytes, image) = match magic {
+            b"P4" => {
+                let line = (width as usize).div_ceil(8);
+                let len = page_bytes(line, width, height)?;
+                let pixels = data
+                    .get(pos..pos + len)
+                    .ok_or("PBM image ends in its pixels")?;
+                let image = GrayImage::from_fn(width, height, |x, y| {
+                    let byte = pixels[y as usize * line + x as usize / 8];
+                    // Set bits are black
+                    let set = byte & (0x80 >> (x % 8)) != 0;
+                    image::Luma([if set { 0 } else { 255 }])
+                });
+                (len, image)
+            }
+            b"P5" | b"P6" => {
+                let channels = if magic == b"P5" { 1 } else { 3 };
+                let line = (width as usize).saturating_mul(channels);
+                let len = page_bytes(line, width, height)?;
+                let pixels = data
+                    .get(pos..pos + len)
+                    .ok_or("PNM image ends in its pixels")?;
+                let image = GrayImage::from_fn(width, height, |x, y| {
+                    let at = y as usize * line + x as usize * channels;
+                    let value = if channels == 1 {
+                        pixels[at]
+                    } else {
+                        luma(&pixels[at..at + 3])
+                    };
+                    image::Luma([scale(value)])
+                });
+                (len, image)
+            }
+            _ => return Err("Only binary PNM images (P4, P5 and P6) are supported".into()),
+        };
+        pages.push(image);
+        data = &data[pos + page_bytes..];
+    }
+    Ok(pages)
+}
+
+/// Gray level of an RGB pixel, ITU-R BT.601 like `image`.
+fn luma(rgb: &[u8]) -> u8 {
+    let [r, g, b] = [rgb[0] as u32, rgb[1] as u32, rgb[2] as u32];
+    ((r * 299 + g * 587 + b * 114) / 1000) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(big_endian: bool, width: u32, height: u32, bpp: u32, color_space: u32) -> Vec<u8> {
+        let mut header = vec![0x00; HEADER_V2];
+        let bytes_per_line = (width * bpp).div_ceil(8);
+        for (offset, value) in [
+            (WIDTH, width),
+            (HEIGHT, height),
+            (BITS_PER_COLOR, bpp.min(8)),
+            (BITS_PER_PIXEL, bpp),
+            (BYTES_PER_LINE, bytes_per_line),
+            (COLOR_SPACE, color_space),
+        ] {
+            let bytes = if big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            };
+            header[offset..offset + 4].copy_from_slice(&bytes);
+        }
+        header
+    }
+
+    #[test]
+    fn test_cups_raster() {
+        // Version 3, uncompressed 8-bit gray
+        let mut data = b"RaS3".to_vec();
+        data.extend(header(true, 3, 2, 8, CSPACE_SW));
+        data.extend_from_slice(&[0, 128, 255, 255, 128, 0]);
+        let pages = decode_cups_pages(&data).unwrap();
+        assert_eq!(pages[0].as_raw(), &vec![0, 128, 255, 255, 128, 0]);
+
+        // Version 2, little-endian 1-bit black: 16 pixels in 2 bytes per line
+        let mut data = b"2SaR".to_vec();
+        data.extend(header(false, 16, 3, 1, CSPACE_K));
+        // Two identical lines: 0xF0 repeated twice
+        data.extend_from_slice(&[1, 1, 0xF0]);
+        // One line of literal bytes 0x80 0x01
+        data.extend_from_slice(&[0, 255, 0x80, 0x01]);
+        let pages = decode_cups_pages(&data).unwrap();
+        let page = &pages[0];
+        assert_eq!(page.dimensions(), (16, 3));
+        assert_eq!(page.get_pixel(0, 1)[0], 0);
+        assert_eq!(page.get_pixel(4, 1)[0], 255);
+        assert_eq!(page.get_pixel(0, 2)[0], 0);
+        assert_eq!(page.get_pixel(1, 2)[0], 255);
+        assert_eq!(page.get_pixel(15, 2)[0], 0);
+
+        // Version 2, RGB with the rest of the line cleared
+        let mut data = b"RaS2".to_vec();
+        data.extend(header(true, 2, 1, 24, CSPACE_SRGB));
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 128]);
+        let pages = decode_cups_pages(&data).unwrap();
+        assert_eq!(pages[0].as_raw(), &vec![0, 255]);
+
+        // Headers of huge pages are rejected before anything is allocated
+        for (width, height) in [(1 << 28, 1 << 28), (1 << 20, 1 << 20)] {
+            let mut data = b"RaS2".to_vec();
+            data.extend(header(true, width, height, 8, CSPACE_SW));
+            data.extend_from_slice(&[0, 0, 0xFF]);
+            assert!(decode_cups_pages(&data).is_err());
+            let mut data = b"RaS3".to_vec();
+            data.extend(header(true, width, height, 8, CSPACE_SW));
+            assert!(decode_cups_pages(&data).is_err());
+        }
+
+        assert!(decode_cups_pages(b"RaS3").unwrap().is_empty());
+        assert!(decode_cups_pages(&data[..100]).is_err());
+        assert!(decode_cups_pages(b"%PDF-1.7").is_err());
+    }
+
+    #[test]
+    fn test_pnm() {
+        // A commented PBM and a PPM with 4-bit samples
+        let mut data = b"P4\n# label\n10 1\n".to_vec();
+        data.extend_from_slice(&[0x80, 0x40]);
+        data.extend_from_slice(b"P6 1 1 15\n");
+        data.extend_from_slice(&[15, 15, 15]);
+        data.push(b'\n');
+
+        let pages = decode_cups_pages(&data).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].dimensions(), (10, 1));
+        assert_eq!(pages[0].get_pixel(0, 0)[0], 0);
+        assert_eq!(pages[0].get_pixel(1, 0)[0], 255);
+        assert_eq!(pages[0].get_pixel(9, 0)[0], 0);
+        assert_eq!(pages[1].get_pixel(0, 0)[0], 255);
+
+        assert!(decode_cups_pages(b"P5 4294967295 4294967295 255\n").is_err());
+        assert!(decode_cups_pages(b"P4 100000 100000\n").is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! Built-in labels printed by `Printer::print_test_page()`,
+//! `Printer::print_calibration_page()` and
+//! `Printer::print_two_color_calibration_page()`.
+//!
+//! The test page shows the printer and media information, a millimetre ruler
+//! across the printable width, density bars and a border around the
+//! printable area. The labels only use a small built-in bitmap font, so they
+//! work without any optional feature.
+
+use crate::layout::{Canvas, Resolution, TwoColorCanvas};
+use crate::media::Media;
+use crate::model::Model;
+
+/// Size of a font pixel in millimetres.
+const FONT_PIXEL_MM: f32 = 0.25;
+/// Gray levels of the density bars, from white to black.
+const DENSITY_LEVELS: [u8; 5] = [255, 191, 128, 64, 0];
+/// Length of the calibration label on continuous media.
+const CALIBRATION_LENGTH_MM: f32 = 30.0;
+/// Length of the two-color calibration label.
+const TWO_COLOR_LENGTH_MM: f32 = 40.0;
+
+/// Render the diagnostic label for the given printer and media. The
+/// firmware version is printed when the transport reports it.
+pub(crate) fn test_page(
+    model: Model,
+    serial: &str,
+    firmware_version: Option<&str>,
+    media: Media,
+    resolution: Resolution,
+) -> Canvas {
+    let spec = media.spec();
+    let (dpi_x, dpi_y) = (resolution.horizontal_dpi(), resolution.vertical_dpi());
+    let mut lines = vec![format!("{:?}", model), format!("SN {}", serial)];
+    if let Some(version) = firmware_version {
+        lines.push(format!("FW {}", version));
+    }
+    lines.extend([
+        format!("MEDIA {}", media.name()),
+        format!("{}X{} MM", spec.width_mm(), spec.length_mm()),
+        format!("{}X{} DPI", dpi_x, dpi_y),
+    ]);
+
+    // Layout along the tape: ruler, text lines, density bars
+    let width = Canvas::new(media, resolution).size_mm().0;
+    let longest = lines.iter().map(|line| line.len()).max().unwrap_or(1);
+    let pixel = FONT_PIXEL_MM.min((width - 2.0) / (longest * 6) as f32);
+    let line_height = pixel * 10.0;
+    let text_top = 5.0;
+    let bars_top = text_top + line_height * lines.len() as f32 + 1.0;
+    let bars_height = 5.0;
+
+    let mut canvas = Canvas::new(media, resolution).length_mm(bars_top + bars_height + 2.0);
+    let (width, length) = canvas.size_mm();
+
+    canvas.stroke_rect(0.0, 0.0, width, length, 0.2);
+    draw_ruler(&mut canvas, width);
+    for (i, line) in lines.iter().enumerate() {
+        draw_string(
+            &mut canvas,
+            1.0,
+            text_top + line_height * i as f32,
+            line,
+            pixel,
+        );
+    }
+    draw_density_bars(&mut canvas, 1.0, bars_top, width - 2.0, bars_height);
+    canvas
+}
+
+/// Render marks to measure the print offset of a printer.
+///
+/// Lines along the edges of the printable area, a cross through the center
+/// and scales with 0.5 mm steps up to 3 mm around the center on both axes.
+pub(crate) fn calibration_page(media: Media, resolution: Resolution) -> Canvas {
+    let mut canvas = Canvas::new(media, resolution).length_mm(CALIBRATION_LENGTH_MM);
+    let (width, length) = canvas.size_mm();
+    let (cx, cy) = (width / 2.0, length / 2.0);
+    let line = 0.15;
+
+    canvas.stroke_rect(0.0, 0.0, width, length, 0.2);
+    canvas.fill_rect(cx - line / 2.0, 0.0, line, length);
+    canvas.fill_rect(0.0, cy - line / 2.0, width, line);
+    draw_scales(&mut canvas, cx, cy, line);
+    canvas
+}
+
+/// Render density patches and registration marks for red/black tape.
+///
+/// A row of black and a row of red patches with the levels of the density
+/// bars show how each color renders gray. Below them, a red cross is
+/// printed over black scales with 0.5 mm steps: the scale readings where
+/// the red lines cross are the offset of the red color, to pass to
+/// `set_red_offset_mm()` with the sign reversed.
+pub(crate) fn two_color_calibration_page(media: Media, resolution: Resolution) -> TwoColorCanvas {
+    let mut canvas = TwoColorCanvas::new(media, resolution).length_mm(TWO_COLOR_LENGTH_MM);
+    let (width, length) = canvas.black().size_mm();
+    let line = 0.15;
+
+    canvas.black_mut().stroke_rect(0.0, 0.0, width, length, 0.2);
+    let bars_width = width - 6.0;
+    let (black_top, red_top, height) = (3.0, 10.0, 5.0);
+    draw_string(canvas.black_mut(), 1.5, black_top + 1.5, "K", 0.3);
+    draw_string(canvas.black_mut(), 1.5, red_top + 1.5, "R", 0.3);
+    draw_density_bars(canvas.black_mut(), 4.5, black_top, bars_width, height);
+    draw_density_bars(canvas.red_mut(), 4.5, red_top, bars_width, height);
+
+    // Registration marks, the zero ticks line up with an aligned red cross
+    let (cx, cy) = (width / 2.0, (red_top + height + length) / 2.0);
+    let black = canvas.black_mut();
+    draw_scales(black, cx, cy, line);
+    black.fill_rect(cx - line / 2.0, cy, line, 2.0);
+    black.fill_rect(cx, cy - line / 2.0, 2.0, line);
+    let red = canvas.red_mut();
+    red.fill_rect(cx - line / 2.0, cy - 6.0, line, 12.0);
+    red.fill_rect(cx - 6.0, cy - line / 2.0, 12.0, line);
+    canvas
+}
+
+/// Ticks every 0.5 mm up to 3 mm around `(cx, cy)`, across the tape below
+/// `cy` and along the tape right of `cx`, numbered every millimetre.
+fn draw_scales(canvas: &mut Canvas, cx: f32, cy: f32, line: f32) {
+    for step in -6i32..=6 {
+        if step == 0 {
+            continue;
+        }
+        let offset = step as f32 * 0.5;
+        let tick = if step % 2 == 0 { 1.2 } else { 0.6 };
+        // Scale across the tape below the horizontal line
+        canvas.fill_rect(cx + offset - line / 2.0, cy, line, tick);
+        // Scale along the tape right of the vertical line
+        canvas.fill_rect(cx, cy + offset - line / 2.0, tick, line);
+
+        if step % 2 == 0 {
+            let label = (step.abs() / 2).to_string();
+            draw_string(canvas, cx + offset - 0.3, cy + 1.5, &label, 0.12);
+            draw_string(canvas, cx + 1.5, cy + offset - 0.4, &label, 0.12);
+        }
+    }
+}
+
+/// Ticks every millimetre, longer every 5 mm and numbered every 10 mm.
+fn draw_ruler(canvas: &mut Canvas, width: f32) {
+    let mut mm = 0;
+    while mm as f32 <= width {
+        let tick = match mm % 10 {
+            0 => 2.5,
+            5 => 1.5,
+            _ => 0.8,
+        };
+        canvas.fill_rect(mm as f32, 0.0, 0.15, tick);
+        if mm % 10 == 0 && mm > 0 {
+            draw_string(canvas, mm as f32 + 0.4, 1.0, &mm.to_string(), 0.15);
+        }
+        mm += 1;
+    }
+}
+
+/// Patches from white to black, dithered by `to_matrix_dithered()`.
+fn draw_density_bars(canvas: &mut Canvas, x: f32, y: f32, width: f32, height: f32) {
+    let resolution = canvas.resolution();
+    let bar = width / DENSITY_LEVELS.len() as f32;
+    let (w, h) = (resolution.dots_across(bar), resolution.dots_along(height));
+
+    for (i, &level) in DENSITY_LEVELS.iter().enumerate() {
+        let pixels = vec![level; (w * h) as usize];
+        canvas.draw_gray(x + bar * i as f32, y, w, h, &pixels);
+    }
+    canvas.stroke_rect(x, y, width, height, 0.15);
+}
+
+/// Draw upper-case text with the built-in 5x7 font.
+fn draw_string(canvas: &mut Canvas, x: f32, y: f32, text: &str, pixel: f32) {
+    for (i, c) in text.chars().enumerate() {
+        let left = x + (i * 6) as f32 * pixel;
+        for (row, bits) in glyph(c.to_ascii_uppercase()).iter().enumerate() {
+            for col in 0..5 {
+                if bits & (0x10 >> col) != 0 {
+                    let px = left + col as f32 * pixel;
+                    let py = y + row as f32 * pixel;
+                    canvas.fill_rect(px, py, pixel, pixel);
+                }
+            }
+        }
+    }
+}
+
+/// Rows of a 5x7 glyph, the most significant of the 5 bits is on the left.
+fn glyph(c: char) -> [u8; 7] {
+    match c {
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // '?'
+    }
+}
@@ -0,0 +1,637 @@
+//! Printers on the local network, found by `Printer::discover_network()`.
+//!
+//! Network models announce their raw port (`_pdl-datastream._tcp`) and LPD
+//! queue (`_printer._tcp`) with multicast DNS. The answers carry the model
+//! in their TXT records; `NetworkPrinter::query_snmp()` adds the serial
+//! number and the description from the printer's SNMP agent, and finds
+//! printers with mDNS turned off. Both speak UDP with the standard library
+//! only, no DNS or SNMP crate is needed.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::debug;
+
+use crate::error::Error;
+use crate::model::Model;
+
+const MDNS_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 0, 251)), 5353);
+/// Services queried, the raw port first.
+const SERVICES: [&str; 2] = ["_pdl-datastream._tcp.local", "_printer._tcp.local"];
+/// Port of raw printing, also used when only the LPD service is announced.
+pub(crate) const RAW_PORT: u16 = 9100;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+
+const SNMP_PORT: u16 = 161;
+/// SNMPv2-MIB sysDescr.0
+const SYS_DESCR: &[u32] = &[1, 3, 6, 1, 2, 1, 1, 1, 0];
+/// HOST-RESOURCES-MIB hrDeviceDescr.1, e.g. "Brother QL-820NWB"
+const DEVICE_DESCR: &[u32] = &[1, 3, 6, 1, 2, 1, 25, 3, 2, 1, 3, 1];
+/// Printer-MIB prtGeneralSerialNumber.1
+const SERIAL_NUMBER: &[u32] = &[1, 3, 6, 1, 2, 1, 43, 5, 1, 1, 17, 1];
+
+/// A Brother printer found on the network by `Printer::discover_network()`
+/// or `NetworkPrinter::from_snmp()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkPrinter {
+    /// Model named in the announcement or SNMP description, `None` when it
+    /// isn't supported.
+    pub model: Option<Model>,
+    /// Name of the mDNS service instance, e.g. "Brother QL-820NWB", or the
+    /// address when found with SNMP.
+    pub name: String,
+    /// IP address of the printer.
+    pub address: IpAddr,
+    /// Raw printing port, 9100 on Brother printers.
+    pub port: u16,
+    /// Serial number, `None` until read with `query_snmp()`.
+    pub serial: Option<String>,
+    /// SNMP sysDescr, e.g. "Brother NC-18002w, Firmware Ver.1.09", `None`
+    /// until read with `query_snmp()`.
+    pub description: Option<String>,
+}
+
+impl NetworkPrinter {
+    /// Address of the raw printing port.
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+
+    /// Read the serial number and description from the SNMP agent of the
+    /// printer with the community "public", and the model when the
+    /// announcement didn't name it.
+    ///
+    /// # Returns
+    /// * `Ok(())` - The fields the agent knows were filled
+    /// * `Err(Error::Io)` - The agent didn't answer within `timeout`, e.g.
+    ///   because SNMP is disabled on the printer
+    /// * `Err(Error::InvalidResponse)` - The answer could not be decoded
+    pub fn query_snmp(&mut self, timeout: Duration) -> Result<(), Error> {
+        let values = snmp_get(
+            self.address,
+            &[SYS_DESCR, DEVICE_DESCR, SERIAL_NUMBER],
+            timeout,
+        )?;
+        let mut values = values.into_iter();
+        let mut next = || values.next().flatten().filter(|value| !value.is_empty());
+        let (description, device, serial) = (next(), next(), next());
+        if self.model.is_none() {
+            self.model = device
+                .iter()
+                .chain(&description)
+                .find_map(|text| model_in(text));
+        }
+        self.description = description.or(self.description.take());
+        self.serial = serial.or(self.serial.take());
+        Ok(())
+    }
+
+    /// Identify the printer at `address` with SNMP, for networks where
+    /// multicast doesn't reach the printer or mDNS is turned off.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # use ql_label::NetworkPrinter;
+    /// let printer = NetworkPrinter::from_snmp("192.168.1.20".parse()?, Duration::from_secs(1))?;
+    /// println!("{:?} {:?}", printer.model, printer.serial);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn from_snmp(address: IpAddr, timeout: Duration) -> Result<Self, Error> {
+        let mut printer = NetworkPrinter {
+            model: None,
+            name: address.to_string(),
+            address,
+            port: RAW_PORT,
+            serial: None,
+            description: None,
+        };
+        printer.query_snmp(timeout)?;
+        Ok(printer)
+    }
+}
+
+/// Send mDNS queries for the printer services and collect the Brother
+/// printers answering within `timeout`.
+pub(crate) fn discover(timeout: Duration) -> Result<Vec<NetworkPrinter>, Error> {
+    // Queries from a port other than 5353 get unicast answers (RFC 6762
+    // legacy unicast), so no multicast group has to be joined
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.send_to(&mdns_query(), MDNS_ADDR)?;
+
+    let mut records = Records::default();
+    let deadline = Instant::now() + timeout;
+    let mut buf = [0x00; 9000];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        match socket.recv_from(&mut buf) {
+            Ok((len, from)) => {
+                if records.parse(&buf[..len], from.ip()).is_none() {
+                    debug!("Ignoring malformed mDNS answer from {}", from);
+                }
+            }
+            Err(e) if is_timeout(&e) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(records.printers())
+}
+
+/// Query for the PTR records of both printer services.
+fn mdns_query() -> Vec<u8> {
+    let mut buf = vec![0x00; 12];
+    buf[5] = SERVICES.len() as u8;
+    for service in SERVICES {
+        for label in service.split('.') {
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+        buf.push(0x00);
+        buf.extend_from_slice(&TYPE_PTR.to_be_bytes());
+        // Class IN
+        buf.extend_from_slice(&[0x00, 0x01]);
+    }
+    buf
+}
+
+/// Resource records of every mDNS answer received.
+#[derive(Default)]
+struct Records {
+    /// Service instances with the service they belong to.
+    instances: Vec<(String, String)>,
+    /// SRV: instance to host and port.
+    services: HashMap<String, (String, u16)>,
+    /// TXT: instance to key and value pairs.
+    texts: HashMap<String, Vec<(String, String)>>,
+    /// A and AAAA: host to address.
+    hosts: HashMap<String, IpAddr>,
+    /// Instance to the address of the answer, when no A record came.
+    senders: HashMap<String, IpAddr>,
+}
+
+impl Records {
+    /// Add the records of the DNS message `msg`, `None` when it is
+    /// malformed.
+    fn parse(&mut self, msg: &[u8], sender: IpAddr) -> Option<()> {
+        let count = |at: usize| u16::from_be_bytes([msg[at], msg[at + 1]]) as usize;
+        if msg.len() < 12 || msg[2] & 0x80 == 0 {
+            // Not a response
+            return None;
+        }
+        let mut pos = 12;
+        for _ in 0..count(4) {
+            pos = read_name(msg, pos)?.1 + 4;
+        }
+        let mut new_instances = Vec::new();
+        for _ in 0..count(6) + count(8) + count(10) {
+            let (name, end) = read_name(msg, pos)?;
+            let header = msg.get(end..end + 10)?;
+            let kind = u16::from_be_bytes([header[0], header[1]]);
+            let len = u16::from_be_bytes([header[8], header[9]]) as usize;
+            let data = end + 10;
+            let rdata = msg.get(data..data + len)?;
+            match kind {
+                TYPE_PTR => {
+                    let instance = read_name(msg, data)?.0;
+                    if SERVICES.iter().any(|s| s.eq_ignore_ascii_case(&name)) {
+                        new_instances.push(instance.clone());
+                        self.instances.push((instance, name.to_ascii_lowercase()));
+                    }
+                }
+                TYPE_SRV if len >= 6 => {
+                    let port = u16::from_be_bytes([rdata[4], rdata[5]]);
+                    let target = read_name(msg, data + 6)?.0;
+                    self.services.insert(name, (target, port));
+                }
+                TYPE_TXT => {
+                    let mut entries = Vec::new();
+                    let mut at = 0;
+                    while at < rdata.len() {
+                        let entry = rdata.get(at + 1..at + 1 + rdata[at] as usize)?;
+                        let entry = String::from_utf8_lossy(entry);
+                        let (key, value) = entry.split_once('=').unwrap_or((&entry, ""));
+                        entries.push((key.to_ascii_lowercase(), value.to_string()));
+                        at += 1 + rdata[at] as usize;
+                    }
+                    self.texts.insert(name, entries);
+                }
+                TYPE_A if len == 4 => {
+                    let octets = [rdata[0], rdata[1], rdata[2], rdata[3]];
+                    self.hosts.insert(name, IpAddr::from(octets));
+                }
+                TYPE_AAAA if len == 16 => {
+                    let mut octets = [0x00; 16];
+                    octets.copy_from_slice(rdata);
+                    // Keep the IPv4 address when the host has both
+                    self.hosts.entry(name).or_insert(IpAddr::from(octets));
+                }
+                _ => {}
+            }
+            pos = data + len;
+        }
+        for instance in new_instances {
+            self.senders.entry(instance).or_insert(sender);
+        }
+        Some(())
+    }
+
+    /// One printer per address, with the raw port when it is announced.
+    fn printers(&self) -> Vec<NetworkPrinter> {
+        let mut printers: Vec<NetworkPrinter> = Vec::new();
+        for (instance, service) in &self.instances {
+            let text = self.texts.get(instance).map_or(&[][..], Vec::as_slice);
+            let get = |key: &str| {
+                text.iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, value)| value.as_str())
+            };
+            let brother = [get("usb_mfg"), get("ty"), Some(instance.as_str())]
+                .iter()
+                .flatten()
+                .any(|text| text.to_ascii_lowercase().contains("brother"));
+            if !brother {
+                continue;
+            }
+            let srv = self.services.get(instance);
+            let address = srv
+                .and_then(|(host, _)| self.hosts.get(host))
+                .or_else(|| self.senders.get(instance));
+            let address = match address {
+                Some(address) => *address,
+                None => continue,
+            };
+            let raw = service == SERVICES[0];
+            let port = match srv {
+                Some((_, port)) if raw => *port,
+                _ => RAW_PORT,
+            };
+            let name = instance
+                .strip_suffix(&format!(".{}", service))
+                .unwrap_or(instance)
+                .to_string();
+            let printer = NetworkPrinter {
+                model: [
+                    get("usb_mdl"),
+                    get("product"),
+                    get("ty"),
+                    Some(name.as_str()),
+                ]
+                .iter()
+                .flatten()
+                .find_map(|text| model_in(text)),
+                name,
+                address,
+                port,
+                serial: None,
+                description: None,
+            };
+            match printers.iter_mut().find(|p| p.address == address) {
+                Some(found) if raw => *found = printer,
+                Some(_) => {}
+                None => printers.push(printer),
+            }
+        }
+        printers
+    }
+}
+
+/// Read the possibly compressed name at `pos`, returning it and the
+/// position after it.
+fn read_name(msg: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut end = None;
+    // Bound the pointers followed, a malicious message could loop
+    for _ in 0..128 {
+        let len = *msg.get(pos)? as usize;
+        match len {
+            0 => {
+                return Some((labels.join("."), end.unwrap_or(pos + 1)));
+            }
+            0xC0..=0xFF => {
+                let target = (len & 0x3F) << 8 | *msg.get(pos + 1)? as usize;
+                end.get_or_insert(pos + 2);
+                pos = target;
+            }
+            _ => {
+                let label = msg.get(pos + 1..pos + 1 + len)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + len;
+            }
+        }
+    }
+    None
+}
+
+/// Model named in `text`, e.g. "Brother QL-820NWB" or "(Brother QL-1110NWB)".
+fn model_in(text: &str) -> Option<Model> {
+    text.split(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+        .filter(|word| word.starts_with("QL"))
+        .find_map(|word| {
+            word.parse().ok().or_else(|| {
+                let name = word.replace('-', "");
+                Model::ALL
+                    .iter()
+                    .copied()
+                    .find(|model| format!("{:?}", model) == name)
+            })
+        })
+}
+
+/// Values of the OIDs read with an SNMPv2c GetRequest, `None` for the ones
+/// the agent doesn't have.
+fn snmp_get(
+    address: IpAddr,
+    oids: &[&[u32]],
+    timeout: Duration,
+) -> Result<Vec<Option<String>>, Error> {
+    let bind = match address {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(std::net::Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind((bind, 0))?;
+    socket.connect((address, SNMP_PORT))?;
+    socket.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+    let request_id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(1, |time| time.subsec_nanos())
+        & 0x7FFF_FFFF;
+    socket.send(&snmp_request(request_id, oids))?;
+
+    let mut buf = [0x00; 1500];
+    loop {
+        let len = match socket.recv(&mut buf) {
+            Ok(len) => len,
+            Err(e) if is_timeout(&e) => return Err(io::Error::from(io::ErrorKind::TimedOut).into()),
+            Err(e) => return Err(e.into()),
+        };
+        match snmp_response(&buf[..len]) {
+            Some((id, values)) if id == request_id => {
+                return Ok(oids
+                    .iter()
+                    .map(|oid| {
+                        values
+                            .iter()
+                            .find(|(found, _)| found == oid)
+                            .and_then(|(_, value)| value.clone())
+                    })
+                    .collect())
+            }
+            // A late answer to an earlier request
+            Some(_) => {}
+            None => return Err(Error::InvalidResponse(len)),
+        }
+    }
+}
+
+/// BER tag, length and `content`.
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut buf = vec![tag];
+    match content.len() {
+        len @ 0..=0x7F => buf.push(len as u8),
+        len @ 0x80..=0xFF => buf.extend_from_slice(&[0x81, len as u8]),
+        len => {
+            buf.push(0x82);
+            buf.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+    }
+    buf.extend_from_slice(content);
+    buf
+}
+
+fn encode_oid(oid: &[u32]) -> Vec<u8> {
+    let mut buf = vec![(oid[0] * 40 + oid[1]) as u8];
+    for &arc in &oid[2..] {
+        let mut bytes = vec![(arc & 0x7F) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            bytes.push((rest & 0x7F) as u8 | 0x80);
+            rest >>= 7;
+        }
+        buf.extend(bytes.iter().rev());
+    }
+    buf
+}
+
+fn decode_oid(bytes: &[u8]) -> Vec<u32> {
+    let mut oid = match bytes.first() {
+        Some(&first) => vec![first as u32 / 40, first as u32 % 40],
+        None => return Vec::new(),
+    };
+    let mut arc = 0;
+    for &byte in &bytes[1..] {
+        arc = arc << 7 | (byte & 0x7F) as u32;
+        if byte & 0x80 == 0 {
+            oid.push(arc);
+            arc = 0;
+        }
+    }
+    oid
+}
+
+fn snmp_request(request_id: u32, oids: &[&[u32]]) -> Vec<u8> {
+    let bindings: Vec<u8> = oids
+        .iter()
+        .flat_map(|oid| {
+            tlv(
+                0x30,
+                &[tlv(0x06, &encode_oid(oid)), tlv(0x05, &[])].concat(),
+            )
+        })
+        .collect();
+    let pdu = [
+        tlv(0x02, &request_id.to_be_bytes()),
+        // Error status and index
+        tlv(0x02, &[0x00]),
+        tlv(0x02, &[0x00]),
+        tlv(0x30, &bindings),
+    ]
+    .concat();
+    let message = [
+        // Version 2c
+        tlv(0x02, &[0x01]),
+        tlv(0x04, b"public"),
+        tlv(0xA0, &pdu),
+    ]
+    .concat();
+    tlv(0x30, &message)
+}
+
+/// Tag, content and the rest of `buf` after the BER element at its start.
+fn read_tlv(buf: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *buf.first()?;
+    let (len, start) = match *buf.get(1)? {
+        len @ 0..=0x7F => (len as usize, 2),
+        0x81 => (*buf.get(2)? as usize, 3),
+        0x82 => (u16::from_be_bytes([*buf.get(2)?, *buf.get(3)?]) as usize, 4),
+        _ => return None,
+    };
+    let content = buf.get(start..start + len)?;
+    Some((tag, content, &buf[start + len..]))
+}
+
+/// Request id and variable bindings of an SNMP GetResponse.
+#[allow(clippy::type_complexity)]
+fn snmp_response(buf: &[u8]) -> Option<(u32, Vec<(Vec<u32>, Option<String>)>)> {
+    let (_, message, _) = read_tlv(buf).filter(|(tag, _, _)| *tag == 0x30)?;
+    let (_, _, rest) = read_tlv(message)?;
+    let (_, _, rest) = read_tlv(rest)?;
+    let (_, pdu, _) = read_tlv(rest).filter(|(tag, _, _)| *tag == 0xA2)?;
+    let (_, id, rest) = read_tlv(pdu)?;
+    let request_id = id.iter().fold(0u32, |id, &byte| id << 8 | byte as u32);
+    let (_, _, rest) = read_tlv(rest)?;
+    let (_, _, rest) = read_tlv(rest)?;
+    let (_, mut bindings, _) = read_tlv(rest)?;
+
+    let mut values = Vec::new();
+    while !bindings.is_empty() {
+        let (_, binding, rest) = read_tlv(bindings)?;
+        let (_, oid, value) = read_tlv(binding)?;
+        let (tag, value, _) = read_tlv(value)?;
+        let value = match tag {
+            // OCTET STRING, noSuchObject and the like have other tags
+            0x04 => Some(
+                String::from_utf8_lossy(value)
+                    .trim_end_matches('\0')
+                    .trim()
+                    .to_string(),
+            ),
+            _ => None,
+        };
+        values.push((decode_oid(oid), value));
+        bindings = rest;
+    }
+    Some((request_id, values))
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(name: &str) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for label in name.split('.') {
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+        buf.push(0x00);
+        buf
+    }
+
+    fn record(owner: &[u8], kind: u16, data: &[u8]) -> Vec<u8> {
+        let mut buf = owner.to_vec();
+        buf.extend_from_slice(&kind.to_be_bytes());
+        buf.extend_from_slice(&[0x00, 0x01, 0x00, 0x00, 0x11, 0x94]);
+        buf.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        buf.extend_from_slice(data);
+        buf
+    }
+
+    #[test]
+    fn test_mdns_answer() {
+        let mut msg = vec![0x00, 0x00, 0x84, 0x00, 0, 0, 0, 1, 0, 0, 0, 3];
+        let service = msg.len();
+        msg.extend(record(
+            &name("_pdl-datastream._tcp.local"),
+            TYPE_PTR,
+            &name("Brother QL-820NWB._pdl-datastream._tcp.local"),
+        ));
+        // The instance name compressed as a pointer to the service name
+        let instance = [b"\x11Brother QL-820NWB".to_vec(), vec![0xC0, service as u8]].concat();
+        let mut srv = vec![0, 0, 0, 0, 0x23, 0x8C];
+        srv.extend(name("BRW0080.local"));
+        msg.extend(record(&instance, TYPE_SRV, &srv));
+        msg.extend(record(
+            &instance,
+            TYPE_TXT,
+            b"\x0Fusb_MFG=Brother\x11usb_MDL=QL-820NWB",
+        ));
+        msg.extend(record(&name("BRW0080.local"), TYPE_A, &[192, 168, 1, 20]));
+
+        let mut records = Records::default();
+        let sender = IpAddr::from([192, 168, 1, 99]);
+        records.parse(&msg, sender).unwrap();
+        let printers = records.printers();
+        assert_eq!(
+            printers,
+            vec![NetworkPrinter {
+                model: Some(Model::QL820NWB),
+                name: "Brother QL-820NWB".into(),
+                address: IpAddr::from([192, 168, 1, 20]),
+                port: 9100,
+                serial: None,
+                description: None,
+            }]
+        );
+
+        assert!(records.parse(&msg[..40], sender).is_none());
+        assert_eq!(model_in("(Brother QL-1110NWB)"), Some(Model::QL1110NWB));
+        assert_eq!(model_in("Brother PT-P750W"), None);
+    }
+
+    #[test]
+    fn test_snmp() {
+        let request = snmp_request(7, &[SYS_DESCR, SERIAL_NUMBER]);
+        assert_eq!(request[0], 0x30);
+        assert_eq!(decode_oid(&encode_oid(SERIAL_NUMBER)), SERIAL_NUMBER);
+        assert_eq!(
+            decode_oid(&encode_oid(&[1, 3, 6, 1, 4, 1, 2435])),
+            [1, 3, 6, 1, 4, 1, 2435]
+        );
+
+        // Answer with the serial number missing
+        let bindings = [
+            tlv(
+                0x30,
+                &[
+                    tlv(0x06, &encode_oid(SYS_DESCR)),
+                    tlv(0x04, b"Brother NC-18002w"),
+                ]
+                .concat(),
+            ),
+            tlv(
+                0x30,
+                &[tlv(0x06, &encode_oid(SERIAL_NUMBER)), tlv(0x80, &[])].concat(),
+            ),
+        ]
+        .concat();
+        let pdu = [
+            tlv(0x02, &[0x07]),
+            tlv(0x02, &[0x00]),
+            tlv(0x02, &[0x00]),
+            tlv(0x30, &bindings),
+        ]
+        .concat();
+        let response = tlv(
+            0x30,
+            &[tlv(0x02, &[0x01]), tlv(0x04, b"public"), tlv(0xA2, &pdu)].concat(),
+        );
+        let (id, values) = snmp_response(&response).unwrap();
+        assert_eq!(id, 7);
+        assert_eq!(
+            values,
+            vec![
+                (SYS_DESCR.to_vec(), Some("Brother NC-18002w".to_string())),
+                (SERIAL_NUMBER.to_vec(), None),
+            ]
+        );
+        assert!(snmp_response(&response[..20]).is_none());
+    }
+}
@@ -0,0 +1,110 @@
+//! `embedded-graphics` integration.
+//!
+//! Enabled with the `embedded-graphics` feature. `Canvas` implements
+//! `DrawTarget` with `BinaryColor` (`On` is black) and `TwoColorCanvas` with
+//! `LabelColor`, so text, shapes and images from the embedded-graphics
+//! ecosystem can be drawn straight onto a label.
+//!
+//! Coordinates are in dots of the printable area, margins are ignored.
+//!
+//! # Example
+//! ```rust
+//! use embedded_graphics::{
+//!     mono_font::{ascii::FONT_10X20, MonoTextStyle},
+//!     pixelcolor::BinaryColor,
+//!     prelude::*,
+//!     primitives::{Circle, PrimitiveStyle},
+//!     text::Text,
+//! };
+//! use ql_label::{Canvas, ContinuousType, Media, Resolution};
+//!
+//! let media = Media::Continuous(ContinuousType::Continuous62);
+//! let mut canvas = Canvas::new(media, Resolution::Normal).length_mm(20.0);
+//!
+//! Circle::new(Point::new(10, 10), 100)
+//!     .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 4))
+//!     .draw(&mut canvas)?;
+//! Text::new("Hello", Point::new(130, 60), MonoTextStyle::new(&FONT_10X20, BinaryColor::On))
+//!     .draw(&mut canvas)?;
+//!
+//! let matrix = canvas.to_matrix();
+//! # Ok::<(), core::convert::Infallible>(())
+//! ```
+
+use core::convert::Infallible;
+use embedded_graphics_core::{
+    pixelcolor::{raw::RawU2, BinaryColor},
+    prelude::*,
+};
+
+use crate::layout::{Canvas, LabelColor, TwoColorCanvas};
+
+impl PixelColor for LabelColor {
+    type Raw = RawU2;
+}
+
+impl From<RawU2> for LabelColor {
+    fn from(raw: RawU2) -> Self {
+        match raw.into_inner() {
+            1 => Self::Black,
+            2 => Self::Red,
+            _ => Self::White,
+        }
+    }
+}
+
+impl From<BinaryColor> for LabelColor {
+    fn from(color: BinaryColor) -> Self {
+        match color {
+            BinaryColor::On => Self::Black,
+            BinaryColor::Off => Self::White,
+        }
+    }
+}
+
+impl OriginDimensions for Canvas {
+    fn size(&self) -> Size {
+        Size::new(self.width_dots(), self.length_dots())
+    }
+}
+
+impl DrawTarget for Canvas {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x >= 0 && point.y >= 0 {
+                let value = if color.is_on() { 0 } else { 255 };
+                self.set_dot(point.x as u32, point.y as u32, value);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OriginDimensions for TwoColorCanvas {
+    fn size(&self) -> Size {
+        Size::new(self.width_dots(), self.length_dots())
+    }
+}
+
+impl DrawTarget for TwoColorCanvas {
+    type Color = LabelColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            if point.x >= 0 && point.y >= 0 {
+                self.set_dot(point.x as u32, point.y as u32, color);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,471 @@
+//! Virtual printer for tests without hardware.
+//!
+//! `Emulator` implements `Transport`, so a `Printer` created with
+//! `Printer::with_transport()` runs complete jobs against it. The emulator
+//! decodes the command stream, answers status requests, reports the
+//! receiving → printing → completed phases of every page and keeps the
+//! printed raster, which can be saved as PNG with the `image` feature.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::media::{ContinuousType, Media};
+use crate::model::Model;
+use crate::protocol::{self, Command, PrinterError};
+use crate::template::{self, Template};
+use crate::transport::Transport;
+use crate::Matrix;
+
+// Status type (byte 18) and phase (byte 19) of the status messages
+const REPLY_TO_REQUEST: u8 = 0x00;
+const COMPLETED: u8 = 0x01;
+const ERROR: u8 = 0x02;
+const NOTIFICATION: u8 = 0x05;
+const PHASE_CHANGE: u8 = 0x06;
+const RECEIVING: u8 = 0x00;
+const PRINTING: u8 = 0x01;
+// Notification number (byte 22)
+const COOLING_STARTED: u8 = 0x03;
+const COOLING_FINISHED: u8 = 0x04;
+const RASTER_MODE: u8 = 0x01;
+
+/// A page printed by the `Emulator`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EmulatedPage {
+    /// Raster lines printed in black, uncompressed.
+    pub black: Matrix,
+    /// Raster lines printed in red, empty unless the page was sent in
+    /// two-color mode.
+    pub red: Matrix,
+    /// The page ended the job (Control-Z) and was ejected.
+    pub ejected: bool,
+    /// The print information flagged the page as the starting page of a
+    /// job or document.
+    pub starting_page: bool,
+}
+
+impl EmulatedPage {
+    /// Render the page as it would come out of the printer.
+    ///
+    /// Raster lines run from top to bottom and the first pin is on the right,
+    /// so the image matches the one the page was created from.
+    ///
+    /// Requires the `image` feature.
+    #[cfg(feature = "image")]
+    pub fn to_image(&self) -> image::RgbImage {
+        let width = self.black.first().map_or(0, |row| row.len() as u32 * 8);
+        let height = self.black.len().max(self.red.len()) as u32;
+        let is_set = |matrix: &Matrix, x: u32, y: u32| {
+            let pin = (width - 1 - x) as usize;
+            matrix
+                .get(y as usize)
+                .and_then(|row| row.get(pin / 8))
+                .is_some_and(|byte| byte & (0x80 >> (pin % 8)) != 0)
+        };
+
+        image::RgbImage::from_fn(width, height, |x, y| {
+            if is_set(&self.black, x, y) {
+                image::Rgb([0, 0, 0])
+            } else if is_set(&self.red, x, y) {
+                image::Rgb([255, 0, 0])
+            } else {
+                image::Rgb([255, 255, 255])
+            }
+        })
+    }
+
+    /// Save the page as PNG, see `to_image()`.
+    ///
+    /// Requires the `image` feature.
+    #[cfg(feature = "image")]
+    pub fn save_png<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        self.to_image()
+            .save_with_format(path, image::ImageFormat::Png)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+/// In-memory printer answering like a real device.
+///
+/// Clones share the same state: pass one clone to
+/// `Printer::with_transport()` and inspect the printed pages or inject
+/// errors through another.
+///
+/// A page is rejected with `PrinterError::NoMedia` or
+/// `PrinterError::InvalidMedia` when its print information doesn't match the
+/// installed media, and with the error set by `set_error()` until it is
+/// cleared.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{Config, ContinuousType, Emulator, Error, Media, Model, Printer, PrinterError};
+/// let media = Media::Continuous(ContinuousType::Continuous62);
+/// let emulator = Emulator::new(Model::QL800, Some(media));
+/// let config = Config::new(Model::QL800, "emulator".to_string(), media);
+/// let printer = Printer::with_transport(config, emulator.clone())?;
+///
+/// emulator.set_error(Some(PrinterError::CoverOpen));
+/// let result = printer.print(vec![vec![vec![0xFF; 90]; 100]].into_iter());
+/// assert!(matches!(result, Err(Error::PrinterError(PrinterError::CoverOpen))));
+///
+/// emulator.set_error(None);
+/// printer.print(vec![vec![vec![0xFF; 90]; 100]].into_iter())?;
+/// assert_eq!(emulator.pages()[0].black.len(), 100);
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Clone)]
+pub struct Emulator {
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    model: u8,
+    width: usize,
+    media: Option<Media>,
+    error: Option<PrinterError>,
+    cooling: bool,
+    auto_power_off: u8,
+    auto_power_on: bool,
+    received: Vec<u8>,
+    // Start of the bytes in `received` not decoded yet
+    pending: usize,
+    replies: VecDeque<[u8; 32]>,
+    compression: bool,
+    page: EmulatedPage,
+    // Set once an error was reported for the page, it is not printed
+    failed: bool,
+    pages: Vec<EmulatedPage>,
+    // Command mode set with `ESC i a`
+    mode: u8,
+    // Template being filled in template mode, and its selected object
+    template: Template,
+    object: String,
+    templates: Vec<Template>,
+}
+
+impl Emulator {
+    /// Create an emulator of `model` with `media` installed, `None` for an
+    /// empty printer.
+    ///
+    /// # Panics
+    /// Panics if `model` is not one of the models reporting their status
+    /// over USB (QL-600, QL-700 and later series).
+    pub fn new(model: Model, media: Option<Media>) -> Self {
+        let code = model
+            .code()
+            .unwrap_or_else(|| panic!("{:?} can't be emulated", model));
+        Emulator {
+            state: Arc::new(Mutex::new(State {
+                model: code,
+                width: model.pins() as usize / 8,
+                media,
+                error: None,
+                cooling: false,
+                auto_power_off: 0,
+                auto_power_on: false,
+                received: Vec::new(),
+                pending: 0,
+                replies: VecDeque::new(),
+                compression: false,
+                page: EmulatedPage::default(),
+                failed: false,
+                pages: Vec::new(),
+                mode: RASTER_MODE,
+                template: Template::new(0),
+                object: String::new(),
+                templates: Vec::new(),
+            })),
+        }
+    }
+
+    /// Install other media, `None` removes the roll.
+    pub fn set_media(&self, media: Option<Media>) {
+        self.state().media = media;
+    }
+
+    /// Report `error` until it is cleared with `None`.
+    ///
+    /// The printer sends an error status right away, and every page printed
+    /// while the error is set fails.
+    pub fn set_error(&self, error: Option<PrinterError>) {
+        let mut state = self.state();
+        state.error = error;
+        if let Some(error) = error {
+            let status = state.status(ERROR, RECEIVING, Some(error));
+            state.replies.push_back(status);
+        }
+    }
+
+    /// Overheat while `cooling` is set: the print head cools down during
+    /// every page printed, reported by the cooling started and finished
+    /// notifications.
+    pub fn set_cooling(&self, cooling: bool) {
+        self.state().cooling = cooling;
+    }
+
+    /// Auto power-off time in minutes and the auto power-on setting, as
+    /// set with `Printer::set_auto_power_off()` and `Printer::set_power_on()`.
+    pub fn power_settings(&self) -> (u8, bool) {
+        let state = self.state();
+        (state.auto_power_off, state.auto_power_on)
+    }
+
+    /// Pages printed so far.
+    pub fn pages(&self) -> Vec<EmulatedPage> {
+        self.state().pages.clone()
+    }
+
+    /// Templates printed in P-touch Template mode so far, with the objects
+    /// filled, see `Printer::print_template()`.
+    ///
+    /// The emulator doesn't store templates, any key prints.
+    pub fn templates(&self) -> Vec<Template> {
+        self.state().templates.clone()
+    }
+
+    /// Every byte written to the emulator.
+    pub fn received(&self) -> Vec<u8> {
+        self.state().received.clone()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Transport for Emulator {
+    fn write(&self, buf: &[u8], _timeout: Duration) -> Result<usize, Error> {
+        let mut state = self.state();
+        state.received.extend_from_slice(buf);
+        while let Some(len) = state.execute_next() {
+            state.pending += len;
+        }
+        Ok(buf.len())
+    }
+
+    fn read(&self, buf: &mut [u8], _timeout: Duration) -> Result<usize, Error> {
+        let status = self
+            .state()
+            .replies
+            .pop_front()
+            .ok_or(Error::ReadStatusTimeout)?;
+        let n = buf.len().min(status.len());
+        buf[..n].copy_from_slice(&status[..n]);
+        Ok(n)
+    }
+}
+
+impl State {
+    /// Decode and run the next command, returning the number of bytes it
+    /// takes, or `None` when it isn't complete yet.
+    fn execute_next(&mut self) -> Option<usize> {
+        let buf = &self.received[self.pending..];
+        // ESC commands like the status request work in every mode
+        if self.mode == template::TEMPLATE_MODE && buf.first() != Some(&0x1B) {
+            let (command, len) = template::Command::parse(buf)?;
+            self.execute_template(command);
+            Some(len)
+        } else {
+            let (command, len) = Command::parse(buf)?;
+            self.execute(command);
+            Some(len)
+        }
+    }
+
+    fn execute(&mut self, command: Command) {
+        match command {
+            Command::Initialize => {
+                self.compression = false;
+                self.page = EmulatedPage::default();
+                self.failed = false;
+            }
+            Command::StatusRequest => {
+                let status = self.status(REPLY_TO_REQUEST, RECEIVING, self.error);
+                self.replies.push_back(status);
+            }
+            Command::SwitchMode(mode) => self.mode = mode,
+            Command::Compression(mode) => self.compression = mode == 0x02,
+            Command::PrinterSetting { setting, value } => match setting {
+                protocol::AUTO_POWER_OFF => self.auto_power_off = value.saturating_mul(10),
+                protocol::AUTO_POWER_ON => self.auto_power_on = value != 0,
+                _ => {}
+            },
+            Command::PrintInformation {
+                media_type,
+                width_mm,
+                length_mm,
+                starting_page,
+                ..
+            } => match self.media {
+                None => self.fail(PrinterError::NoMedia),
+                Some(media) => {
+                    let spec = media.spec();
+                    let matches = match media {
+                        Media::Continuous(_) => media_type == 0x0A && width_mm == spec.width_mm(),
+                        Media::DieCut(_) => {
+                            media_type == 0x0B
+                                && width_mm == spec.width_mm()
+                                && length_mm == spec.length_mm()
+                        }
+                    };
+                    if !matches {
+                        self.fail(PrinterError::InvalidMedia);
+                    }
+                    self.page.starting_page = starting_page == 0x00;
+                }
+            },
+            Command::Raster(data) => {
+                let row = if self.compression {
+                    protocol::unpack_bits(&data)
+                } else {
+                    Ok(data)
+                };
+                match row {
+                    Ok(row) => self.page.black.push(row),
+                    Err(_) => self.fail(PrinterError::CommunicationError),
+                }
+            }
+            Command::TwoColorRaster { color: 0x02, data } => self.page.red.push(data),
+            Command::TwoColorRaster { data, .. } => self.page.black.push(data),
+            Command::ZeroRaster => self.page.black.push(vec![0x00; self.width]),
+            Command::Print | Command::PrintAndEject => {
+                if let Some(error) = self.error {
+                    self.fail(error);
+                }
+                let mut page = std::mem::take(&mut self.page);
+                if !self.failed {
+                    page.ejected = command == Command::PrintAndEject;
+                    self.pages.push(page);
+                    self.report_printed();
+                }
+                self.failed = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn execute_template(&mut self, command: template::Command) {
+        match command {
+            template::Command::Initialize => {
+                self.template = Template::new(self.template.key());
+                self.object.clear();
+            }
+            template::Command::Select(key) => self.template = Template::new(key),
+            template::Command::SelectObject(name) => self.object = name,
+            template::Command::InsertData(data) => {
+                let object = self.object.clone();
+                self.template.push_object(object, data);
+            }
+            template::Command::Copies(copies) => {
+                self.template = self.template.clone().copies(copies);
+            }
+            template::Command::Print => {
+                if let Some(error) = self.error {
+                    self.fail(error);
+                }
+                if !self.failed {
+                    self.templates.push(self.template.clone());
+                    self.report_printed();
+                }
+                self.failed = false;
+            }
+            template::Command::Unknown(_) => {}
+        }
+    }
+
+    /// Report the phases of printing a label.
+    fn report_printed(&mut self) {
+        let status = self.status(PHASE_CHANGE, PRINTING, None);
+        self.replies.push_back(status);
+        if self.cooling {
+            for notification in [COOLING_STARTED, COOLING_FINISHED] {
+                let mut status = self.status(NOTIFICATION, PRINTING, None);
+                status[22] = notification;
+                self.replies.push_back(status);
+            }
+        }
+        for (status_type, phase) in [(COMPLETED, PRINTING), (PHASE_CHANGE, RECEIVING)] {
+            let status = self.status(status_type, phase, None);
+            self.replies.push_back(status);
+        }
+    }
+
+    /// Reject the current page, reporting `error` once.
+    fn fail(&mut self, error: PrinterError) {
+        if !self.failed {
+            self.failed = true;
+            let status = self.status(ERROR, RECEIVING, Some(error));
+            self.replies.push_back(status);
+        }
+    }
+
+    fn status(&self, status_type: u8, phase: u8, error: Option<PrinterError>) -> [u8; 32] {
+        let mut buf = [0x00; 32];
+        buf[..4].copy_from_slice(&[0x80, 0x20, 0x42, 0x34]);
+        buf[4] = self.model;
+        let (err_1, err_2) = error.map_or((0, 0), |error| error.status_bytes());
+        buf[8] = err_1;
+        buf[9] = err_2;
+        if let Some(media) = self.media {
+            let spec = media.spec();
+            buf[10] = spec.width_mm();
+            buf[17] = spec.length_mm();
+            buf[11] = match media {
+                Media::Continuous(_) => 0x0A,
+                Media::DieCut(_) => 0x0B,
+            };
+            buf[25] = match media {
+                Media::Continuous(ContinuousType::Continuous62Red) => 0x81,
+                _ => 0x01,
+            };
+        }
+        buf[18] = status_type;
+        buf[19] = phase;
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::DieCutType;
+    use crate::printer::{Config, Printer};
+
+    #[test]
+    fn test_emulated_job() {
+        let media = Media::DieCut(DieCutType::DieCut29x90);
+        let emulator = Emulator::new(Model::QL820NWB, Some(media));
+        let config = Config::new(Model::QL820NWB, "emulator".to_string(), media).compress(true);
+        let printer = Printer::with_transport(config, emulator.clone()).unwrap();
+
+        let mut row = vec![0x00; 90];
+        row[10] = 0x5A;
+        let pages = vec![vec![row.clone(); 20], vec![vec![0xFF; 90]; 30]];
+        printer.print(pages.into_iter()).unwrap();
+
+        let printed = emulator.pages();
+        assert_eq!(printed.len(), 2);
+        assert_eq!(printed[0].black, vec![row; 20]);
+        assert!(!printed[0].ejected);
+        assert_eq!(printed[1].black.len(), 30);
+        assert!(printed[1].ejected);
+
+        // The captured stream decodes to the same job
+        let job = protocol::decode(&emulator.received()).unwrap();
+        assert_eq!(job.media, Some(media));
+        assert!(job.settings.compress);
+        assert_eq!(
+            job.pages,
+            vec![printed[0].black.clone(), printed[1].black.clone()]
+        );
+
+        // A different roll rejects the next job
+        emulator.set_media(Some(Media::Continuous(ContinuousType::Continuous62)));
+        let result = printer.print(vec![vec![vec![0xFF; 90]; 10]].into_iter());
+        assert!(matches!(result, Err(Error::MediaMismatch { .. })));
+        assert_eq!(emulator.pages().len(), 2);
+    }
+}
@@ -3,8 +3,8 @@
 //! This module defines all possible errors that can occur during printer
 //! communication, configuration, and print operations.
 
-use crate::Media;
-use rusb;
+use crate::protocol::{Phase, PrinterError};
+use crate::{Media, Model};
 use thiserror::Error;
 
 /// Main error type for P-Touch printer operations.
@@ -17,6 +17,7 @@ pub enum Error {
     ///
     /// Wraps underlying rusb errors for device communication issues,
     /// timeouts, or permission problems.
+    #[cfg(feature = "usb")]
     #[error(transparent)]
     UsbError(#[from] rusb::Error),
 
@@ -53,6 +54,23 @@ pub enum Error {
     #[error("Media mismatch: expected {expected:?}, found {actual:?}")]
     MediaMismatch { expected: Media, actual: Media },
 
+    /// Printer is already opened by another process.
+    ///
+    /// Returned by `Printer::new()` when the lock enabled with
+    /// `Config::lock_dir()` is held, or the USB interface is claimed by
+    /// someone else. Contains the PID of the holder when it is known.
+    #[error("Printer is in use{}", .0.map(|pid| format!(" by PID {}", pid)).unwrap_or_default())]
+    PrinterInUse(Option<u32>),
+
+    /// The printer is bound to the Brother driver, so libusb can't open it.
+    ///
+    /// On Windows the printer driver installed with P-touch Editor owns the
+    /// USB device. Bind the printer to WinUSB instead, e.g. with Zadig, or
+    /// keep the driver and print encoded jobs through a raw spooler queue,
+    /// see `Config::save_job()`. Contains the USB product id.
+    #[error("The Brother printer driver owns the printer (USB product id {0:04x}); bind it to WinUSB, e.g. with Zadig, or print through a raw spooler queue")]
+    DriverClaimedDevice(u16),
+
     #[error("Status request return no response")]
     ReadStatusTimeout,
 
@@ -64,101 +82,215 @@ pub enum Error {
     PrintTimeout,
 
     #[error("Unexpected printer phase: {0:?}")]
-    UnexpectedPhase(crate::printer::Phase),
+    UnexpectedPhase(Phase),
 
-    /// Hardware-level printer error.
+    /// A page of the job has no black dots.
     ///
-    /// Wraps printer-specific errors reported by the device itself,
-    /// such as cover open, media issues, or mechanical problems.
+    /// Returned when `Config::blank_pages(BlankPages::Fail)` is set. `page`
+    /// counts the pages of the job from 0, the pages before it were printed.
+    #[error("Page {page} is blank")]
+    BlankPage { page: usize },
+
+    /// Writing an encoded job failed.
+    ///
+    /// Returned by `Config::write_job()` and `Config::save_job()`.
     #[error(transparent)]
-    PrinterError(PrinterError),
-}
+    Io(#[from] std::io::Error),
 
-/// Hardware-specific errors reported by the printer.
-///
-/// These errors are parsed from the printer's status response and indicate
-/// physical problems with the device that need user intervention.
-#[derive(Error, Debug)]
-pub enum PrinterError {
-    // Following errors are read from printer status
-    #[error("No media is installed")]
-    NoMedia,
+    /// The job was cancelled with its `CancelToken`.
+    ///
+    /// Returned by `Printer::print_with_cancel()`. The printer was reset,
+    /// the labels printed before are kept.
+    #[error("Print job was cancelled")]
+    Cancelled,
 
-    #[error("End of media")]
-    EndOfMedia,
+    /// A raster line doesn't fit the print head.
+    ///
+    /// Rows must have `Model::row_bytes()` bytes. Wide printers also accept
+    /// 90-byte rows made for normal-width media. `row` counts the rows of
+    /// the page as sent, after the print offset. The page is not sent.
+    #[error("Raster line {row} has {actual} bytes, the printer needs {expected} bytes")]
+    InvalidRaster {
+        row: usize,
+        expected: usize,
+        actual: usize,
+    },
 
-    #[error("Cutter jam")]
-    CutterJam,
+    /// A page has more raster lines than the die-cut label holds.
+    ///
+    /// The limit counts the red and black lines of two-color pages. Longer
+    /// continuous pages are printed in segments instead, see
+    /// `Config::max_page_rows()`. The page is not sent.
+    #[error("Page of {count} raster lines, the label holds {max} lines")]
+    InvalidRasterCount { count: u32, max: u32 },
 
-    #[error("Printer is in use")]
-    PrinterInUse,
+    /// Options of a `ConfigBuilder` the model or media can't print with.
+    ///
+    /// Returned by `ConfigBuilder::build()` and `Config::validate()`, with
+    /// every conflict found.
+    #[error("Conflicting configuration: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    ConfigConflicts(Vec<ConfigConflict>),
 
-    #[error("Printer if offline")]
-    PrinterOffline,
+    /// Hardware-level printer error.
+    ///
+    /// Wraps printer-specific errors reported by the device itself,
+    /// such as cover open, media issues, or mechanical problems.
+    #[error(transparent)]
+    PrinterError(PrinterError),
+}
 
-    #[error("Installed media is not match")]
-    InvalidMedia,
+/// An option of a configuration the model or media doesn't support.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ConfigConflict {
+    /// Two-color printing on a model printing black only.
+    #[error("{0:?} doesn't print two colors")]
+    TwoColors(Model),
 
-    #[error("Expansion buffer is full")]
-    BufferFull,
+    /// Two-color printing on media without red, only `Continuous62Red`
+    /// prints red.
+    #[error("{0:?} has no red, two colors need Continuous62Red")]
+    TwoColorsMedia(Media),
 
-    #[error("Communication error")]
-    CommunicationError,
+    /// Red/black tape in a model printing black only.
+    #[error("{0:?} doesn't support red/black tape")]
+    RedMedia(Model),
 
-    #[error("Cover is open")]
-    CoverOpen,
+    /// 600 DPI on a model printing 300 DPI only.
+    #[error("{0:?} doesn't print in high resolution")]
+    HighResolution(Model),
 
-    #[error("Media can not be fed")]
-    FeedMediaFail,
+    /// 600 DPI with two colors, which the printers don't support.
+    #[error("High resolution is not available with two colors")]
+    HighResolutionTwoColors,
 
-    #[error("System error")]
-    SystemError,
+    /// Media wider than the print head of the model.
+    #[error("{media:?} is too wide for {model:?}")]
+    WideMedia { model: Model, media: Media },
 
-    #[error("Unknown error")]
-    UnknownError((u8, u8)),
+    /// A feed amount the media doesn't accept: 35 to 1500 dots on
+    /// continuous tape, 0 on die-cut labels.
+    #[error("Feed of {feed} dots is not valid for {media:?}")]
+    Feed { feed: u16, media: Media },
 }
 
-impl PrinterError {
-    /// Parse printer error from 32-byte status buffer.
-    ///
-    /// Analyzes bytes 8 and 9 of the printer status response to determine
-    /// the specific error condition reported by the hardware.
-    ///
-    /// # Arguments
-    /// * `buf` - 32-byte status response from printer
-    ///
-    /// # Returns
-    /// Parsed printer error or `UnknownError((0, 0))` if no error
-    pub fn from_buf(buf: [u8; 32]) -> Self {
-        let err_1 = buf[8];
-        let err_2 = buf[9];
-
-        match err_1 {
-            0b0000_0001 => Self::NoMedia,
-            0b0000_0010 => Self::EndOfMedia,
-            0b0000_0100 => Self::CutterJam,
-            0b0001_0000 => Self::PrinterInUse,
-            0b0010_0000 => Self::PrinterOffline,
-            _ => match err_2 {
-                0b0000_0001 => Self::InvalidMedia,
-                0b0000_0010 => Self::BufferFull,
-                0b0000_0100 => Self::CommunicationError,
-                0b0001_0000 => Self::CoverOpen,
-                0b0100_0000 => Self::FeedMediaFail,
-                0b1000_0000 => Self::SystemError,
-                _ => Self::UnknownError((err_1, err_2)),
-            },
+/// Stable numeric codes of all errors and their names.
+///
+/// Codes are never reused or renumbered. Hardware errors reported by the
+/// printer use the range 100-199.
+const ERROR_CODES: [(u16, &str); 31] = [
+    (1, "UsbError"),
+    (2, "DeviceOffline"),
+    (3, "DeviceListNotReadable"),
+    (4, "MissingEndpoint"),
+    (5, "InvalidResponse"),
+    (6, "InvalidConfig"),
+    (7, "NoMediaInstalled"),
+    (8, "MediaMismatch"),
+    (9, "ReadStatusTimeout"),
+    (10, "PrintTimeout"),
+    (11, "UnexpectedPhase"),
+    (12, "PrinterInUse"),
+    (13, "BlankPage"),
+    (14, "Io"),
+    (15, "Cancelled"),
+    (16, "InvalidRaster"),
+    (17, "InvalidRasterCount"),
+    (18, "ConfigConflicts"),
+    (19, "DriverClaimedDevice"),
+    (101, "PrinterError::NoMedia"),
+    (102, "PrinterError::EndOfMedia"),
+    (103, "PrinterError::CutterJam"),
+    (104, "PrinterError::PrinterInUse"),
+    (105, "PrinterError::PrinterOffline"),
+    (106, "PrinterError::InvalidMedia"),
+    (107, "PrinterError::BufferFull"),
+    (108, "PrinterError::CommunicationError"),
+    (109, "PrinterError::CoverOpen"),
+    (110, "PrinterError::FeedMediaFail"),
+    (111, "PrinterError::SystemError"),
+    (199, "PrinterError::UnknownError"),
+];
+
+impl Error {
+    /// Stable numeric code of this error.
+    ///
+    /// Useful for FFI consumers and log aggregation, which should not depend
+    /// on the English messages. Hardware errors use the code of the
+    /// `PrinterError`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Error, PrinterError};
+    /// let err = Error::PrinterError(PrinterError::CoverOpen);
+    /// assert_eq!(err.code(), 109);
+    /// assert_eq!(Error::code_name(109), Some("PrinterError::CoverOpen"));
+    /// assert_eq!(Error::DeviceOffline.code(), 2);
+    /// ```
+    pub fn code(&self) -> u16 {
+        match self {
+            #[cfg(feature = "usb")]
+            Self::UsbError(_) => 1,
+            Self::DeviceOffline => 2,
+            Self::DeviceListNotReadable => 3,
+            Self::MissingEndpoint => 4,
+            Self::InvalidResponse(_) => 5,
+            Self::InvalidConfig(_) => 6,
+            Self::NoMediaInstalled => 7,
+            Self::MediaMismatch { .. } => 8,
+            Self::ReadStatusTimeout => 9,
+            Self::PrintTimeout => 10,
+            Self::UnexpectedPhase(_) => 11,
+            Self::PrinterInUse(_) => 12,
+            Self::BlankPage { .. } => 13,
+            Self::Io(_) => 14,
+            Self::Cancelled => 15,
+            Self::InvalidRaster { .. } => 16,
+            Self::InvalidRasterCount { .. } => 17,
+            Self::ConfigConflicts(_) => 18,
+            Self::DriverClaimedDevice(_) => 19,
+            Self::PrinterError(err) => err.code(),
         }
     }
 
-    /// Check if this represents a "no error" state.
+    /// Whether the error may go away when the job is sent again, like a
+    /// timeout, a failed USB transfer or an unplugged printer. Errors the
+    /// operator has to fix, such as an opened cover or the wrong media, are
+    /// not transient.
     ///
-    /// Returns `true` if the printer is reporting no error condition.
-    /// Used to distinguish between actual errors and normal status.
-    ///
-    /// # Returns
-    /// `true` if no error is present, `false` otherwise
-    pub fn is_no_error(&self) -> bool {
-        matches!(self, Self::UnknownError((0, 0)))
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Error, PrinterError};
+    /// assert!(Error::ReadStatusTimeout.is_transient());
+    /// assert!(!Error::PrinterError(PrinterError::CoverOpen).is_transient());
+    /// ```
+    pub fn is_transient(&self) -> bool {
+        match self {
+            #[cfg(feature = "usb")]
+            Self::UsbError(err) => matches!(
+                err,
+                rusb::Error::Io
+                    | rusb::Error::NoDevice
+                    | rusb::Error::Busy
+                    | rusb::Error::Timeout
+                    | rusb::Error::Pipe
+                    | rusb::Error::Interrupted
+                    | rusb::Error::Overflow
+            ),
+            Self::DeviceOffline
+            | Self::InvalidResponse(_)
+            | Self::ReadStatusTimeout
+            | Self::PrintTimeout
+            | Self::Io(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Name of the error with the numeric `code`, e.g. `DeviceOffline` or
+    /// `PrinterError::CoverOpen`. Returns `None` for unknown codes.
+    pub fn code_name(code: u16) -> Option<&'static str> {
+        ERROR_CODES
+            .iter()
+            .find(|&&(c, _)| c == code)
+            .map(|&(_, name)| name)
     }
 }
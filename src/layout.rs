@@ -0,0 +1,1030 @@
+//! Label layout in physical units.
+//!
+//! This module lets label content be described in millimetres. Positions and
+//! sizes are converted to printer dots for the active resolution and media,
+//! so a layout prints at the same physical size in both normal (300 DPI) and
+//! high (300 x 600 DPI) resolution modes.
+
+use crate::{
+    asset::Asset,
+    media::Media,
+    utils::{self, floyd_steinberg, step_filter, Sharpen, TwoColorMatrix},
+    Matrix,
+};
+
+const MM_PER_INCH: f32 = 25.4;
+
+/// Shortest continuous label the printer accepts, in dots at 300 DPI.
+pub(crate) const MIN_CONTINUOUS_LENGTH_DOTS: u32 = 150;
+
+/// Resolution of the print head across the tape width in dots per inch.
+///
+/// The head always prints 300 dots per inch across the tape; high resolution
+/// mode only doubles the resolution along the feed direction.
+pub const HEAD_DPI: u32 = 300;
+
+/// Print resolution mode.
+///
+/// Matches `Config::high_resolution()`. Use `Config::resolution()` to obtain
+/// the value for a given configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// 300 x 300 DPI.
+    Normal,
+    /// 300 DPI across the tape, 600 DPI along the tape.
+    High,
+}
+
+impl Resolution {
+    /// Resolution across the tape width in dots per inch.
+    pub fn horizontal_dpi(&self) -> u32 {
+        HEAD_DPI
+    }
+
+    /// Resolution along the tape (feed direction) in dots per inch.
+    pub fn vertical_dpi(&self) -> u32 {
+        match self {
+            Self::Normal => HEAD_DPI,
+            Self::High => HEAD_DPI * 2,
+        }
+    }
+
+    /// Convert a distance across the tape from millimetres to dots.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::Resolution;
+    /// assert_eq!(Resolution::Normal.dots_across(25.4), 300);
+    /// assert_eq!(Resolution::High.dots_across(25.4), 300);
+    /// ```
+    pub fn dots_across(&self, mm: f32) -> u32 {
+        mm_to_dots(mm, self.horizontal_dpi())
+    }
+
+    /// Convert a distance along the tape from millimetres to dots.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::Resolution;
+    /// assert_eq!(Resolution::Normal.dots_along(25.4), 300);
+    /// assert_eq!(Resolution::High.dots_along(25.4), 600);
+    /// ```
+    pub fn dots_along(&self, mm: f32) -> u32 {
+        mm_to_dots(mm, self.vertical_dpi())
+    }
+}
+
+/// Direction of the content on the tape.
+///
+/// In `Portrait` the x axis runs across the tape and the y axis along it,
+/// exactly like the rows sent to the printer. In `Landscape` the content is
+/// composed along the tape: the x axis runs along the tape and the y axis
+/// across it, and the canvas is rotated 90° clockwise when converted, so the
+/// top edge of the design ends up on the right edge of the tape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// x across the tape, y along the tape.
+    Portrait,
+    /// x along the tape, y across the tape.
+    Landscape,
+}
+
+/// Filter used to scale images, see `Canvas::fit_image()`.
+///
+/// Requires the `image` feature.
+#[cfg(feature = "image")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Resampling {
+    /// Nearest pixel. Keeps edges sharp without gray fringes, for barcodes,
+    /// QR codes and line art.
+    #[default]
+    Nearest,
+    /// Linear interpolation.
+    Triangle,
+    /// Cubic interpolation, sharper than `Triangle`.
+    CatmullRom,
+    /// Lanczos with a window of 3, the smoothest gradients for photos.
+    Lanczos3,
+}
+
+#[cfg(feature = "image")]
+impl Resampling {
+    fn filter_type(self) -> image::imageops::FilterType {
+        use image::imageops::FilterType;
+        match self {
+            Resampling::Nearest => FilterType::Nearest,
+            Resampling::Triangle => FilterType::Triangle,
+            Resampling::CatmullRom => FilterType::CatmullRom,
+            Resampling::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Convert millimetres to dots at the given resolution, rounding to the nearest dot.
+///
+/// Negative distances are treated as zero.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::mm_to_dots;
+/// assert_eq!(mm_to_dots(62.0, 300), 732);
+/// ```
+pub fn mm_to_dots(mm: f32, dpi: u32) -> u32 {
+    (mm.max(0.0) * dpi as f32 / MM_PER_INCH).round() as u32
+}
+
+/// Convert dots at the given resolution to millimetres.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::dots_to_mm;
+/// assert_eq!(dots_to_mm(600, 600), 25.4);
+/// ```
+pub fn dots_to_mm(dots: u32, dpi: u32) -> f32 {
+    dots as f32 * MM_PER_INCH / dpi as f32
+}
+
+/// Drawing surface covering the printable area of a media.
+///
+/// All drawing methods take millimetres measured from the top-left corner of
+/// the printable area (after margins). The canvas keeps track of where that
+/// area sits on the print head, so `to_matrix()` returns rows ready for
+/// `Printer::print()` without any manual offset calculation. Rows for
+/// 102 mm media cover the 1296 pins of wide printers.
+///
+/// Pixels are stored as 8-bit grayscale where `0` is black and `255` is white.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{Canvas, ContinuousType, Media, Resolution};
+/// let media = Media::Continuous(ContinuousType::Continuous29);
+/// let mut canvas = Canvas::new(media, Resolution::High).length_mm(40.0);
+/// canvas.fill_rect(0.0, 0.0, 10.0, 10.0);
+///
+/// let matrix = canvas.to_matrix();
+/// assert_eq!(matrix.len(), 945); // 40mm at 600 DPI
+/// assert_eq!(matrix[0].len(), 90);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Canvas {
+    media: Option<Media>,
+    resolution: Resolution,
+    orientation: Orientation,
+    pins: u32,
+    left: u32,
+    width: u32,
+    length: u32,
+    margin_x: u32,
+    margin_y: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    /// Create a blank canvas for the printable area of `media`.
+    ///
+    /// Die-cut media use the label length from the media specification.
+    /// Continuous media start at the shortest printable length; use
+    /// `length_mm()` to set the desired label length.
+    ///
+    /// # Arguments
+    /// * `media` - Media the label will be printed on
+    /// * `resolution` - Resolution mode used for printing
+    pub fn new(media: Media, resolution: Resolution) -> Self {
+        let spec = media.spec();
+        let length_300 = match media {
+            Media::Continuous(_) => MIN_CONTINUOUS_LENGTH_DOTS,
+            Media::DieCut(_) => spec.length_dots(),
+        };
+        let length = match resolution {
+            Resolution::Normal => length_300,
+            Resolution::High => length_300 * 2,
+        };
+        let width = spec.effective_dots();
+        // 102 mm media only fits the print head of wide printers
+        let pins = if spec.left_margin_dots() + width > crate::NORMAL_PRINTER_WIDTH {
+            crate::WIDE_PRINTER_WIDTH
+        } else {
+            crate::NORMAL_PRINTER_WIDTH
+        };
+
+        Canvas {
+            media: Some(media),
+            resolution,
+            orientation: Orientation::Portrait,
+            pins,
+            left: spec.left_margin_dots(),
+            width,
+            length,
+            margin_x: 0,
+            margin_y: 0,
+            pixels: vec![255; (width * length) as usize],
+        }
+    }
+
+    /// Create a blank canvas of arbitrary size that is not tied to a media.
+    ///
+    /// Useful for small designs that are later placed on a label with
+    /// `draw_canvas()` or `Imposition`. When converted with `to_matrix()` the
+    /// content starts at the first pin of the print head.
+    ///
+    /// # Arguments
+    /// * `width` - Width in millimetres
+    /// * `length` - Length in millimetres
+    /// * `resolution` - Resolution mode used for printing
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Canvas, Resolution};
+    /// let canvas = Canvas::blank(20.0, 20.0, Resolution::Normal);
+    /// assert_eq!(canvas.width_dots(), 236);
+    /// assert!(canvas.media().is_none());
+    /// ```
+    pub fn blank(width: f32, length: f32, resolution: Resolution) -> Self {
+        let width = resolution
+            .dots_across(width)
+            .min(crate::NORMAL_PRINTER_WIDTH);
+        let length = resolution.dots_along(length);
+
+        Canvas {
+            media: None,
+            resolution,
+            orientation: Orientation::Portrait,
+            pins: crate::NORMAL_PRINTER_WIDTH,
+            left: 0,
+            width,
+            length,
+            margin_x: 0,
+            margin_y: 0,
+            pixels: vec![255; (width * length) as usize],
+        }
+    }
+
+    /// Set the orientation of the content.
+    ///
+    /// Switching the orientation swaps the axes of the canvas and clears it,
+    /// so call this before drawing. The label length set with `length_mm()`
+    /// is kept.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Canvas, ContinuousType, Media, Orientation, Resolution};
+    /// let media = Media::Continuous(ContinuousType::Continuous29);
+    /// let mut canvas = Canvas::new(media, Resolution::Normal)
+    ///     .orientation(Orientation::Landscape)
+    ///     .length_mm(80.0);
+    ///
+    /// // x now runs along the tape
+    /// assert_eq!(canvas.width_dots(), 945);
+    /// assert_eq!(canvas.length_dots(), 306);
+    /// canvas.fill_rect(0.0, 0.0, 80.0, 2.0);
+    ///
+    /// let matrix = canvas.to_matrix();
+    /// assert_eq!(matrix.len(), 945);
+    /// ```
+    pub fn orientation(self, orientation: Orientation) -> Self {
+        if orientation == self.orientation {
+            return self;
+        }
+        let (width, length) = (self.length, self.width);
+        Canvas {
+            orientation,
+            width,
+            length,
+            margin_x: self.margin_y,
+            margin_y: self.margin_x,
+            pixels: vec![255; (width * length) as usize],
+            ..self
+        }
+    }
+
+    /// Set the label length in millimetres.
+    ///
+    /// Die-cut media keep the length of the label, any other canvas is
+    /// resized. Existing content is kept where it still fits.
+    pub fn length_mm(self, mm: f32) -> Self {
+        let dots = self.resolution.dots_along(mm);
+        match (self.media, self.orientation) {
+            (Some(Media::DieCut(_)), _) => self,
+            (_, Orientation::Portrait) => {
+                let width = self.width;
+                self.resize(width, dots)
+            }
+            (_, Orientation::Landscape) => {
+                let length = self.length;
+                self.resize(dots, length)
+            }
+        }
+    }
+
+    /// Set a uniform margin in millimetres around the printable area.
+    ///
+    /// Drawing coordinates are measured from the inner corner of the margin
+    /// and anything drawn into the margin is clipped.
+    pub fn margin_mm(self, mm: f32) -> Self {
+        Canvas {
+            margin_x: self.x_dots(mm),
+            margin_y: self.y_dots(mm),
+            ..self
+        }
+    }
+
+    /// Media this canvas was created for, `None` for a blank canvas.
+    pub fn media(&self) -> Option<Media> {
+        self.media
+    }
+
+    /// Resolution mode this canvas was created for.
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    /// Size of the canvas along the x axis in dots.
+    ///
+    /// This is the width of the printable area in portrait orientation and
+    /// the label length in landscape orientation.
+    pub fn width_dots(&self) -> u32 {
+        self.width
+    }
+
+    /// Size of the canvas along the y axis in dots.
+    ///
+    /// This is the label length in portrait orientation and the width of the
+    /// printable area in landscape orientation.
+    pub fn length_dots(&self) -> u32 {
+        self.length
+    }
+
+    /// Size of the drawable area inside the margins in millimetres, as `(width, length)`.
+    pub fn size_mm(&self) -> (f32, f32) {
+        let (dpi_x, dpi_y) = self.dpi();
+        (
+            dots_to_mm(self.width.saturating_sub(self.margin_x * 2), dpi_x),
+            dots_to_mm(self.length.saturating_sub(self.margin_y * 2), dpi_y),
+        )
+    }
+
+    /// Read a single dot of the printable area, ignoring margins.
+    ///
+    /// Returns `None` when the position is outside the canvas.
+    pub fn dot(&self, x: u32, y: u32) -> Option<u8> {
+        if x < self.width && y < self.length {
+            Some(self.pixels[(y * self.width + x) as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Set a single dot of the printable area, ignoring margins.
+    ///
+    /// Positions outside the canvas are silently clipped.
+    pub fn set_dot(&mut self, x: u32, y: u32, value: u8) {
+        if x < self.width && y < self.length {
+            self.pixels[(y * self.width + x) as usize] = value;
+        }
+    }
+
+    /// Fill a rectangle with black.
+    ///
+    /// # Arguments
+    /// * `x`, `y` - Top-left corner in millimetres
+    /// * `width`, `height` - Size in millimetres
+    pub fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        let (x0, y0) = self.to_dots(x, y);
+        let x1 = x0 + self.x_dots(width);
+        let y1 = y0 + self.y_dots(height);
+        self.fill_dots(x0, y0, x1, y1, 0);
+    }
+
+    /// Draw the outline of a rectangle in black.
+    ///
+    /// # Arguments
+    /// * `x`, `y` - Top-left corner in millimetres
+    /// * `width`, `height` - Outer size in millimetres
+    /// * `line` - Line thickness in millimetres
+    pub fn stroke_rect(&mut self, x: f32, y: f32, width: f32, height: f32, line: f32) {
+        self.fill_rect(x, y, width, line);
+        self.fill_rect(x, y + height - line, width, line);
+        self.fill_rect(x, y, line, height);
+        self.fill_rect(x + width - line, y, line, height);
+    }
+
+    /// Copy grayscale pixels onto the canvas, one pixel per dot.
+    ///
+    /// The source image must already be scaled for the canvas resolution.
+    ///
+    /// # Arguments
+    /// * `x`, `y` - Top-left corner in millimetres
+    /// * `width`, `height` - Source image size in pixels
+    /// * `pixels` - Grayscale data (width × height bytes, 0 = black)
+    pub fn draw_gray(&mut self, x: f32, y: f32, width: u32, height: u32, pixels: &[u8]) {
+        let (x0, y0) = self.to_dots(x, y);
+        for sy in 0..height {
+            for sx in 0..width {
+                if let Some(&value) = pixels.get((sy * width + sx) as usize) {
+                    self.put(x0 + sx, y0 + sy, value);
+                }
+            }
+        }
+    }
+
+    /// Scale `image` to fit the drawable area and center it.
+    ///
+    /// The aspect ratio is kept in millimetres, so images print undistorted
+    /// in high resolution too. `Resampling::Nearest` keeps barcodes and QR
+    /// codes sharp; use `Resampling::Lanczos3` and `to_matrix_dithered()` for
+    /// photos. On continuous media set the length first, see `length_mm()`.
+    ///
+    /// Requires the `image` feature.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Canvas, ContinuousType, Media, Resampling, Resolution};
+    /// use image::{GrayImage, Luma};
+    ///
+    /// // A 21 x 21 module QR code, black in the top-left quarter
+    /// let code = GrayImage::from_fn(21, 21, |x, y| Luma([if x < 10 && y < 10 { 0 } else { 255 }]));
+    ///
+    /// let media = Media::Continuous(ContinuousType::Continuous29);
+    /// let mut canvas = Canvas::new(media, Resolution::Normal).length_mm(29.0);
+    /// canvas.fit_image(&code, Resampling::Nearest);
+    ///
+    /// // No gray fringes around the modules
+    /// for y in 0..canvas.length_dots() {
+    ///     for x in 0..canvas.width_dots() {
+    ///         assert!(matches!(canvas.dot(x, y), Some(0) | Some(255)));
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "image")]
+    pub fn fit_image(&mut self, image: &image::GrayImage, filter: Resampling) {
+        let (width, height) = image.dimensions();
+        if width == 0 || height == 0 {
+            return;
+        }
+        let area_x = self.width.saturating_sub(self.margin_x * 2);
+        let area_y = self.length.saturating_sub(self.margin_y * 2);
+        let (dpi_x, dpi_y) = self.dpi();
+        // Millimetres per source pixel
+        let scale = (area_x as f32 / dpi_x as f32 / width as f32)
+            .min(area_y as f32 / dpi_y as f32 / height as f32);
+        let out_x = ((width as f32 * scale * dpi_x as f32).round() as u32).clamp(1, area_x.max(1));
+        let out_y = ((height as f32 * scale * dpi_y as f32).round() as u32).clamp(1, area_y.max(1));
+
+        let scaled = image::imageops::resize(image, out_x, out_y, filter.filter_type());
+        let x0 = self.margin_x + (area_x.saturating_sub(out_x)) / 2;
+        let y0 = self.margin_y + (area_y.saturating_sub(out_y)) / 2;
+        for (x, y, pixel) in scaled.enumerate_pixels() {
+            self.put(x0 + x, y0 + y, pixel[0]);
+        }
+    }
+
+    /// Copy another canvas onto this one.
+    ///
+    /// The source is scaled when the two canvases use a different resolution
+    /// or orientation, so it keeps its physical size.
+    ///
+    /// # Arguments
+    /// * `x`, `y` - Top-left corner in millimetres
+    /// * `source` - Canvas to copy
+    pub fn draw_canvas(&mut self, x: f32, y: f32, source: &Canvas) {
+        let (x0, y0) = self.to_dots(x, y);
+        let (src_x, src_y) = source.dpi();
+        let (dst_x, dst_y) = self.dpi();
+        let width = source.width * dst_x / src_x;
+        let length = source.length * dst_y / src_y;
+
+        for dy in 0..length {
+            let sy = dy * src_y / dst_y;
+            for dx in 0..width {
+                let sx = dx * src_x / dst_x;
+                let value = source.pixels[(sy * source.width + sx) as usize];
+                self.put(x0 + dx, y0 + dy, value);
+            }
+        }
+    }
+
+    /// Draw an asset at its physical size, picking the variant registered
+    /// for the resolution of the canvas, see `Asset`.
+    ///
+    /// # Arguments
+    /// * `x`, `y` - Top-left corner in millimetres
+    /// * `asset` - Artwork to draw
+    pub fn draw_asset(&mut self, x: f32, y: f32, asset: &Asset) {
+        if let Some(rendered) = asset.render(self.resolution, self.orientation) {
+            self.draw_canvas(x, y, &rendered);
+        }
+    }
+
+    /// Draw a 1D barcode with its modules along the x axis.
+    ///
+    /// `modules` holds one entry per module where `1` is a bar and `0` a
+    /// space, which is exactly what `encode()` returns for the symbologies of
+    /// the `barcoders` crate. Each module is snapped to a whole number of dots
+    /// so all bars of the same width print identically.
+    ///
+    /// # Arguments
+    /// * `x`, `y` - Top-left corner in millimetres
+    /// * `modules` - Encoded barcode, one byte per module
+    /// * `module` - Width of a single module in millimetres (at least one dot)
+    /// * `height` - Bar height in millimetres
+    ///
+    /// # Returns
+    /// The printed width of the barcode in millimetres.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Canvas, Resolution};
+    /// // e.g. `barcoders::sym::code39::Code39::new("QL")?.encode()`
+    /// let modules = [1, 0, 1, 1, 0, 0, 1, 0, 1];
+    ///
+    /// let mut canvas = Canvas::blank(20.0, 10.0, Resolution::High);
+    /// let width = canvas.draw_barcode(0.0, 0.0, &modules, 0.254, 10.0);
+    ///
+    /// // 0.254mm is 3 dots at 300 DPI, 27 dots in total
+    /// assert!((width - 2.286).abs() < 1e-4);
+    /// assert_eq!(canvas.dot(2, 0), Some(0));
+    /// assert_eq!(canvas.dot(3, 0), Some(255));
+    /// ```
+    pub fn draw_barcode(
+        &mut self,
+        x: f32,
+        y: f32,
+        modules: &[u8],
+        module: f32,
+        height: f32,
+    ) -> f32 {
+        let (x0, y0) = self.to_dots(x, y);
+        let step = self.x_dots(module).max(1);
+        let y1 = y0 + self.y_dots(height);
+
+        for (i, _) in modules.iter().enumerate().filter(|(_, &m)| m != 0) {
+            let left = x0 + i as u32 * step;
+            self.fill_dots(left, y0, left + step, y1, 0);
+        }
+        dots_to_mm(step * modules.len() as u32, self.dpi().0)
+    }
+
+    /// Draw a 2D code such as a QR code, with square modules.
+    ///
+    /// `modules` holds the rows of the symbol one after another, one byte per
+    /// module where `1` is dark, e.g. the `to_colors()` of the `qrcode` crate
+    /// mapped to 0 and 1. Modules are snapped to whole dots on both axes, so
+    /// they stay square in high resolution mode.
+    ///
+    /// # Arguments
+    /// * `x`, `y` - Top-left corner in millimetres
+    /// * `width` - Number of modules per row
+    /// * `modules` - Encoded symbol, one byte per module
+    /// * `module` - Size of a single module in millimetres (at least one dot)
+    ///
+    /// # Returns
+    /// The printed width and height of the code in millimetres.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Canvas, Resolution};
+    /// let modules = [1, 0, 0, 1];
+    ///
+    /// let mut canvas = Canvas::blank(10.0, 10.0, Resolution::High);
+    /// let (width, height) = canvas.draw_matrix_code(0.0, 0.0, 2, &modules, 0.254);
+    ///
+    /// // Modules are 3 dots wide and 6 dots high at 300 x 600 DPI
+    /// assert_eq!(canvas.dot(2, 5), Some(0));
+    /// assert_eq!(canvas.dot(3, 5), Some(255));
+    /// assert_eq!(canvas.dot(2, 6), Some(255));
+    /// assert_eq!(canvas.dot(3, 6), Some(0));
+    /// assert!((width - height).abs() < 1e-4);
+    /// ```
+    pub fn draw_matrix_code(
+        &mut self,
+        x: f32,
+        y: f32,
+        width: usize,
+        modules: &[u8],
+        module: f32,
+    ) -> (f32, f32) {
+        if width == 0 {
+            return (0.0, 0.0);
+        }
+        let (x0, y0) = self.to_dots(x, y);
+        let step_x = self.x_dots(module).max(1);
+        let step_y = self.y_dots(module).max(1);
+
+        for (i, _) in modules.iter().enumerate().filter(|(_, &m)| m != 0) {
+            let left = x0 + (i % width) as u32 * step_x;
+            let top = y0 + (i / width) as u32 * step_y;
+            self.fill_dots(left, top, left + step_x, top + step_y, 0);
+        }
+        let rows = modules.len().div_ceil(width) as u32;
+        let (dpi_x, dpi_y) = self.dpi();
+        (
+            dots_to_mm(step_x * width as u32, dpi_x),
+            dots_to_mm(step_y * rows, dpi_y),
+        )
+    }
+
+    /// Draw a 1D barcode with its modules along the y axis.
+    ///
+    /// Same as `draw_barcode()` but rotated by 90°. In portrait orientation
+    /// the modules follow the feed direction, which suits long barcodes on
+    /// continuous tape. Module widths are snapped to the resolution of that
+    /// axis, so they keep the same physical size in high resolution mode.
+    ///
+    /// # Returns
+    /// The printed length of the barcode in millimetres.
+    pub fn draw_barcode_along(
+        &mut self,
+        x: f32,
+        y: f32,
+        modules: &[u8],
+        module: f32,
+        height: f32,
+    ) -> f32 {
+        let (x0, y0) = self.to_dots(x, y);
+        let step = self.y_dots(module).max(1);
+        let x1 = x0 + self.x_dots(height);
+
+        for (i, _) in modules.iter().enumerate().filter(|(_, &m)| m != 0) {
+            let top = y0 + i as u32 * step;
+            self.fill_dots(x0, top, x1, top + step, 0);
+        }
+        dots_to_mm(step * modules.len() as u32, self.dpi().1)
+    }
+
+    /// Sharpen everything drawn so far with an unsharp mask, see `sharpen()`.
+    ///
+    /// Call it after drawing scaled images and before `to_matrix()` or
+    /// `to_matrix_dithered()`, so small text survives the conversion.
+    pub fn sharpen(&mut self, settings: Sharpen) {
+        utils::sharpen(self.width, self.length, &mut self.pixels, settings);
+    }
+
+    /// Convert the canvas to printer rows.
+    ///
+    /// The printable area is placed at the media's left offset on the print
+    /// head and binarized with a 50% threshold. Landscape canvases are
+    /// rotated into print orientation first.
+    pub fn to_matrix(&self) -> Matrix {
+        step_filter(128, self.pins, self.raster_count(), &self.head_pixels())
+    }
+
+    /// Convert the canvas to printer rows using Floyd–Steinberg dithering.
+    ///
+    /// Gray areas such as anti-aliased edges or photos are approximated with a
+    /// pattern of black dots instead of being cut off at a threshold.
+    pub fn to_matrix_dithered(&self) -> Matrix {
+        let rows = self.raster_count();
+        let mut head = self.head_pixels();
+        floyd_steinberg(self.pins, rows, &mut head);
+        step_filter(128, self.pins, rows, &head)
+    }
+
+    /// Number of printer rows, i.e. the label length in dots.
+    pub(crate) fn raster_count(&self) -> u32 {
+        match self.orientation {
+            Orientation::Portrait => self.length,
+            Orientation::Landscape => self.width,
+        }
+    }
+
+    /// Pins of the print head the rows cover, 1296 for 102 mm media.
+    #[cfg_attr(not(feature = "image"), allow(dead_code))]
+    pub(crate) fn pins(&self) -> u32 {
+        self.pins
+    }
+
+    /// Grayscale pixels covering the whole print head width.
+    pub(crate) fn head_pixels(&self) -> Vec<u8> {
+        let rows = self.raster_count();
+        let mut head = vec![255u8; (self.pins * rows) as usize];
+        match self.orientation {
+            Orientation::Portrait => {
+                for y in 0..self.length {
+                    let src = (y * self.width) as usize;
+                    let dst = (y * self.pins + self.left) as usize;
+                    head[dst..dst + self.width as usize]
+                        .copy_from_slice(&self.pixels[src..src + self.width as usize]);
+                }
+            }
+            Orientation::Landscape => {
+                // Rotate 90° clockwise: x becomes the row, the top edge the right edge
+                for y in 0..self.length {
+                    let column = self.left + self.length - 1 - y;
+                    for x in 0..self.width {
+                        head[(x * self.pins + column) as usize] =
+                            self.pixels[(y * self.width + x) as usize];
+                    }
+                }
+            }
+        }
+        head
+    }
+
+    /// Resolution of the x and y axes in dots per inch.
+    pub(crate) fn dpi(&self) -> (u32, u32) {
+        let (across, along) = (
+            self.resolution.horizontal_dpi(),
+            self.resolution.vertical_dpi(),
+        );
+        match self.orientation {
+            Orientation::Portrait => (across, along),
+            Orientation::Landscape => (along, across),
+        }
+    }
+
+    fn x_dots(&self, mm: f32) -> u32 {
+        mm_to_dots(mm, self.dpi().0)
+    }
+
+    fn y_dots(&self, mm: f32) -> u32 {
+        mm_to_dots(mm, self.dpi().1)
+    }
+
+    pub(crate) fn to_dots(&self, x: f32, y: f32) -> (u32, u32) {
+        (
+            self.margin_x + self.x_dots(x),
+            self.margin_y + self.y_dots(y),
+        )
+    }
+
+    /// Write a dot only if it lies inside the margins.
+    pub(crate) fn put(&mut self, x: u32, y: u32, value: u8) {
+        if x >= self.margin_x
+            && y >= self.margin_y
+            && x < self.width.saturating_sub(self.margin_x)
+            && y < self.length.saturating_sub(self.margin_y)
+        {
+            self.pixels[(y * self.width + x) as usize] = value;
+        }
+    }
+
+    fn fill_dots(&mut self, x0: u32, y0: u32, x1: u32, y1: u32, value: u8) {
+        for y in y0..y1.min(self.length) {
+            for x in x0..x1.min(self.width) {
+                self.put(x, y, value);
+            }
+        }
+    }
+
+    fn resize(self, width: u32, length: u32) -> Self {
+        let mut pixels = vec![255u8; (width * length) as usize];
+        let keep = self.width.min(width) as usize;
+        for y in 0..self.length.min(length) {
+            let src = (y * self.width) as usize;
+            let dst = (y * width) as usize;
+            pixels[dst..dst + keep].copy_from_slice(&self.pixels[src..src + keep]);
+        }
+        Canvas {
+            width,
+            length,
+            pixels,
+            ..self
+        }
+    }
+}
+
+/// N-up imposition of several small designs on one label.
+///
+/// The drawable area of the target canvas is divided into a grid of equal
+/// cells separated by gutters, and each design is centred in its own cell.
+/// Printing several small labels side by side on wide tape saves media
+/// compared to printing them one after another.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{Canvas, ContinuousType, Imposition, Media, Resolution};
+/// let media = Media::Continuous(ContinuousType::Continuous62);
+/// let mut label = Canvas::new(media, Resolution::Normal).length_mm(30.0);
+///
+/// let mut jar = Canvas::blank(12.0, 25.0, Resolution::Normal);
+/// jar.stroke_rect(0.0, 0.0, 12.0, 25.0, 0.5);
+///
+/// Imposition::new(4, 1)
+///     .gutter_mm(2.0)
+///     .cut_marks(true)
+///     .impose(&mut label, &[jar.clone(), jar.clone(), jar.clone(), jar]);
+///
+/// assert_eq!(Imposition::new(4, 1).cells(&label).len(), 4);
+/// let matrix = label.to_matrix();
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Imposition {
+    columns: u32,
+    rows: u32,
+    gutter: f32,
+    cut_marks: bool,
+}
+
+impl Imposition {
+    /// Create a grid with `columns` cells across the tape and `rows` cells along it.
+    pub fn new(columns: u32, rows: u32) -> Self {
+        Imposition {
+            columns: columns.max(1),
+            rows: rows.max(1),
+            gutter: 0.0,
+            cut_marks: false,
+        }
+    }
+
+    /// Set the space between cells in millimetres.
+    pub fn gutter_mm(self, mm: f32) -> Self {
+        Imposition { gutter: mm, ..self }
+    }
+
+    /// Draw dashed cut guides through the middle of every gutter.
+    pub fn cut_marks(self, flag: bool) -> Self {
+        Imposition {
+            cut_marks: flag,
+            ..self
+        }
+    }
+
+    /// Cells of the grid for a canvas as `(x, y, width, height)` in millimetres.
+    ///
+    /// Cells are returned row by row, starting at the top-left corner.
+    pub fn cells(&self, canvas: &Canvas) -> Vec<(f32, f32, f32, f32)> {
+        let (width, length) = canvas.size_mm();
+        let cell_w = (width - self.gutter * (self.columns - 1) as f32) / self.columns as f32;
+        let cell_h = (length - self.gutter * (self.rows - 1) as f32) / self.rows as f32;
+
+        let mut cells = Vec::new();
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                cells.push((
+                    column as f32 * (cell_w + self.gutter),
+                    row as f32 * (cell_h + self.gutter),
+                    cell_w,
+                    cell_h,
+                ));
+            }
+        }
+        cells
+    }
+
+    /// Place `designs` on `canvas`, one per cell.
+    ///
+    /// Extra designs beyond the number of cells are ignored and unused cells
+    /// stay blank. Designs larger than their cell are not clipped to it and
+    /// overlap the neighbouring cells.
+    pub fn impose(&self, canvas: &mut Canvas, designs: &[Canvas]) {
+        let cells = self.cells(canvas);
+
+        for ((x, y, w, h), design) in cells.iter().zip(designs.iter()) {
+            let (design_w, design_h) = design.size_mm();
+            canvas.draw_canvas(
+                x + ((w - design_w) / 2.0).max(0.0),
+                y + ((h - design_h) / 2.0).max(0.0),
+                design,
+            );
+        }
+
+        if self.cut_marks {
+            self.draw_cut_marks(canvas, &cells);
+        }
+    }
+
+    fn draw_cut_marks(&self, canvas: &mut Canvas, cells: &[(f32, f32, f32, f32)]) {
+        const LINE: f32 = 0.2;
+        const DASH: f32 = 1.0;
+        let (width, length) = canvas.size_mm();
+
+        for &(x, _, w, _) in cells.iter().take(self.columns as usize - 1) {
+            let gx = x + w + self.gutter / 2.0 - LINE / 2.0;
+            let mut y = 0.0;
+            while y < length {
+                canvas.fill_rect(gx, y, LINE, DASH);
+                y += DASH * 2.0;
+            }
+        }
+
+        for row in 0..self.rows as usize - 1 {
+            let (_, y, _, h) = cells[row * self.columns as usize];
+            let gy = y + h + self.gutter / 2.0 - LINE / 2.0;
+            let mut x = 0.0;
+            while x < width {
+                canvas.fill_rect(x, gy, DASH, LINE);
+                x += DASH * 2.0;
+            }
+        }
+    }
+}
+
+/// Ink used on two-color media.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelColor {
+    White,
+    Black,
+    Red,
+}
+
+/// Drawing surface for two-color (black and red) labels.
+///
+/// Holds one `Canvas` per color covering the same printable area. Draw on
+/// each color with the usual millimetre based methods, or set individual
+/// dots with `set_dot()` which keeps the two colors from overlapping.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{ContinuousType, LabelColor, Media, Resolution, TwoColorCanvas};
+/// let media = Media::Continuous(ContinuousType::Continuous62Red);
+/// let mut canvas = TwoColorCanvas::new(media, Resolution::Normal).length_mm(30.0);
+/// canvas.black_mut().stroke_rect(0.0, 0.0, 60.0, 30.0, 1.0);
+/// canvas.red_mut().fill_rect(5.0, 5.0, 20.0, 20.0);
+/// canvas.set_dot(0, 0, LabelColor::Red);
+///
+/// let two_color = canvas.to_two_color_matrix();
+/// assert_eq!(two_color.black.len(), two_color.red.len());
+/// ```
+#[derive(Debug, Clone)]
+pub struct TwoColorCanvas {
+    black: Canvas,
+    red: Canvas,
+}
+
+impl TwoColorCanvas {
+    /// Create a blank two-color canvas for the printable area of `media`.
+    ///
+    /// See `Canvas::new()` for how the initial length is chosen.
+    pub fn new(media: Media, resolution: Resolution) -> Self {
+        let canvas = Canvas::new(media, resolution);
+        TwoColorCanvas {
+            black: canvas.clone(),
+            red: canvas,
+        }
+    }
+
+    /// Set the label length in millimetres, see `Canvas::length_mm()`.
+    pub fn length_mm(self, mm: f32) -> Self {
+        TwoColorCanvas {
+            black: self.black.length_mm(mm),
+            red: self.red.length_mm(mm),
+        }
+    }
+
+    /// Set the orientation of the content, see `Canvas::orientation()`.
+    pub fn orientation(self, orientation: Orientation) -> Self {
+        TwoColorCanvas {
+            black: self.black.orientation(orientation),
+            red: self.red.orientation(orientation),
+        }
+    }
+
+    /// Set a uniform margin in millimetres, see `Canvas::margin_mm()`.
+    pub fn margin_mm(self, mm: f32) -> Self {
+        TwoColorCanvas {
+            black: self.black.margin_mm(mm),
+            red: self.red.margin_mm(mm),
+        }
+    }
+
+    /// Canvas holding the black content.
+    pub fn black(&self) -> &Canvas {
+        &self.black
+    }
+
+    /// Mutable canvas holding the black content.
+    pub fn black_mut(&mut self) -> &mut Canvas {
+        &mut self.black
+    }
+
+    /// Canvas holding the red content.
+    pub fn red(&self) -> &Canvas {
+        &self.red
+    }
+
+    /// Mutable canvas holding the red content.
+    pub fn red_mut(&mut self) -> &mut Canvas {
+        &mut self.red
+    }
+
+    /// Width of the printable area in dots.
+    pub fn width_dots(&self) -> u32 {
+        self.black.width_dots()
+    }
+
+    /// Length of the label in dots.
+    pub fn length_dots(&self) -> u32 {
+        self.black.length_dots()
+    }
+
+    /// Set a single dot of the printable area, ignoring margins.
+    ///
+    /// A dot can only hold one color, so the other color is cleared.
+    pub fn set_dot(&mut self, x: u32, y: u32, color: LabelColor) {
+        let (black, red) = match color {
+            LabelColor::White => (255, 255),
+            LabelColor::Black => (0, 255),
+            LabelColor::Red => (255, 0),
+        };
+        self.black.set_dot(x, y, black);
+        self.red.set_dot(x, y, red);
+    }
+
+    /// Convert the canvas to two-color printer rows.
+    pub fn to_two_color_matrix(&self) -> TwoColorMatrix {
+        TwoColorMatrix {
+            black: self.black.to_matrix(),
+            red: self.red.to_matrix(),
+        }
+    }
+}
@@ -5,28 +5,163 @@
 //! # Example
 //!
 //! ```rust,no_run
-//! use ptouch::{Config, ContinuousType, Media, Model, Printer};
+//! use ql_label::{Config, ContinuousType, Media, Model, Printer};
 //! 
 //! let media = Media::Continuous(ContinuousType::Continuous29);
 //! let model = Model::QL820NWB;
 //! let config = Config::new(model, "serial".to_string(), media);
 //! let printer = Printer::new(config).unwrap();
 //! ```
+//!
+//! # `no_std`
+//!
+//! The raster protocol, media and model tables build without the standard
+//! library when the default `std` feature is disabled, see `protocol`.
+//! Everything that talks to USB or the file system needs `std`.
+//!
+//! `Printer::new()` needs the default `usb` feature, which links libusb
+//! through rusb. Without it the crate still lays out and encodes labels, e.g.
+//! for a service that prepares jobs printed by another machine, and prints
+//! through any other `Transport` such as the `Emulator`.
+//!
+//! # Tracing
+//!
+//! The optional `tracing` feature instruments the printer with `tracing`
+//! spans: `open`, `print_job` with the model, serial number and media,
+//! `page` with the page number, rows and bytes (a page per segment of a
+//! banner, without bytes as it is streamed), and `bulk_transfer` with the
+//! bytes written and the time it took. Every status message received is an
+//! event with its type, phase and errors. The `log` messages stay as they
+//! are.
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+#[cfg(feature = "std")]
+mod asset;
+#[cfg(all(feature = "std", feature = "barcode"))]
+mod barcode;
+#[cfg(feature = "std")]
+mod cache;
+#[cfg(feature = "std")]
+mod capture;
+#[cfg(feature = "std")]
+mod compose;
+#[cfg(all(test, feature = "std"))]
+mod conformance;
+#[cfg(all(feature = "std", feature = "image"))]
+mod cups;
+#[cfg(feature = "std")]
+mod diagnostic;
+#[cfg(feature = "std")]
+mod discovery;
+#[cfg(all(feature = "std", feature = "embedded-graphics"))]
+mod embedded;
+#[cfg(feature = "std")]
+mod emulator;
+#[cfg(feature = "std")]
 mod error;
+#[cfg(feature = "std")]
+mod layout;
+#[cfg(feature = "std")]
+mod lock;
 mod media;
+#[cfg(feature = "std")]
+mod merge;
+#[cfg(feature = "std")]
+mod metrics;
 mod model;
+#[cfg(all(feature = "std", feature = "image"))]
+mod preview;
+#[cfg(feature = "std")]
 mod printer;
+#[cfg(all(feature = "std", feature = "profiles"))]
+mod profile;
+pub mod protocol;
+#[cfg(feature = "std")]
+mod queue;
+#[cfg(feature = "std")]
+mod raster;
+#[cfg(all(feature = "std", feature = "image"))]
+mod render;
+#[cfg(all(feature = "std", feature = "image"))]
+mod scan;
+#[cfg(all(feature = "std", feature = "tiny-skia"))]
+mod skia;
+#[cfg(feature = "std")]
+mod stats;
+pub mod template;
+#[cfg(all(feature = "std", feature = "text"))]
+mod text;
+#[cfg(feature = "std")]
+mod transport;
+#[cfg(feature = "std")]
 mod utils;
 
 pub use crate::{
-    error::{Error, PrinterError},
-    media::{ContinuousType, DieCutType, Media},
+    media::{ContinuousType, DieCutType, InvalidMediaName, Media, MediaSpec, PrintableRect},
     model::Model,
-    printer::{Config, Printer, Status},
-    utils::{convert_rgb_to_two_color, step_filter_normal, step_filter_wide, TwoColorMatrix},
+    protocol::{ExpandedMode, PrinterError, PrinterErrors, Settings, Status},
+    template::Template,
 };
 
+#[cfg(feature = "std")]
+pub use crate::{
+    asset::Asset,
+    capture::UsbCapture,
+    compose::Layout,
+    discovery::NetworkPrinter,
+    emulator::{EmulatedPage, Emulator},
+    error::{ConfigConflict, Error},
+    layout::{
+        dots_to_mm, mm_to_dots, Canvas, Imposition, LabelColor, Orientation, Resolution,
+        TwoColorCanvas, HEAD_DPI,
+    },
+    merge::{MailMerge, Record, TextTemplate},
+    printer::{
+        BlankPages, CancelToken, Config, ConfigBuilder, DeviceInfo, DiscoveredPrinter, MediaState,
+        PrintEvent, Printer, PrinterHandle, StatusEvent,
+    },
+    queue::{JobId, JobInfo, JobQueue, JobState, Priority, Throttle},
+    raster::{Page, RasterFn, RasterIter, RasterSource, ShiftedRows},
+    stats::{MediaUsage, Statistics},
+    transport::{MockTransport, Transport},
+    utils::{
+        convert_rgb_to_two_color, convert_rgb_to_two_color_with, dither_normal, dither_wide,
+        enhance_contrast, flip_horizontal, flip_vertical, rotate, rotate_gray, sharpen,
+        split_pages, step_filter_normal, step_filter_rotated, step_filter_wide, trim_blank_rows,
+        ColorThresholds, Contrast, Dither, Rotation, Sharpen, TwoColorMatrix,
+    },
+};
+
+#[cfg(all(feature = "std", feature = "barcode"))]
+pub use crate::barcode::BarcodeLabel;
+#[cfg(all(feature = "std", feature = "image"))]
+pub use crate::cups::decode_cups_pages;
+#[cfg(all(feature = "std", feature = "image"))]
+pub use crate::layout::Resampling;
+#[cfg(all(feature = "std", feature = "csv"))]
+pub use crate::merge::csv_records;
+#[cfg(all(feature = "std", feature = "json"))]
+pub use crate::merge::{json_records, serde_record};
+#[cfg(all(feature = "std", feature = "image"))]
+pub use crate::preview::Preview;
+#[cfg(all(feature = "std", feature = "profiles"))]
+pub use crate::profile::Profiles;
+#[cfg(all(feature = "std", feature = "image"))]
+pub use crate::render::LabelImage;
+#[cfg(all(feature = "std", feature = "image"))]
+pub use crate::scan::{Deskew, ScanContent};
+#[cfg(all(feature = "std", feature = "tiny-skia"))]
+pub use crate::skia::SkiaCanvas;
+#[cfg(all(feature = "std", feature = "text"))]
+pub use crate::text::{Font, FontChain, TextAlign, TextLabel};
+#[cfg(all(feature = "bluetooth", target_os = "linux"))]
+pub use crate::transport::BluetoothTransport;
+#[cfg(all(feature = "std", feature = "image"))]
+pub use crate::utils::step_filter_image;
+
 /// Type alias for 1-bit bitmap data used by printers.
 ///
 /// Each inner `Vec<u8>` represents a single row of pixels, with 8 pixels
@@ -34,7 +169,7 @@ pub use crate::{
 /// 
 /// For normal printers: each row should be 90 bytes (720 pixels / 8)
 /// For wide printers: each row should be 162 bytes (1296 pixels / 8)
-pub type Matrix = Vec<Vec<u8>>;
+pub type Matrix = alloc::vec::Vec<alloc::vec::Vec<u8>>;
 
 /// Width in pixels for normal P-Touch printers (QL-720NW, QL-800, QL-820NWB).
 ///
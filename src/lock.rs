@@ -0,0 +1,101 @@
+//! Advisory lock that keeps other processes away from a printer.
+//!
+//! Jobs of two processes talking to the same printer interleave and corrupt
+//! each other. When `Config::lock_dir()` is set, `Printer::new()` takes an
+//! exclusive file lock named after the printer's serial number and records
+//! its PID in the file, so a second process gets `Error::PrinterInUse`
+//! telling who holds the printer. The lock is released when the `Printer`
+//! is dropped or the process exits.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+use crate::error::Error;
+
+/// Exclusive lock on a printer, held as long as the value lives.
+#[derive(Debug)]
+pub(crate) struct SerialLock {
+    // Closing the file releases the lock
+    _file: File,
+}
+
+impl SerialLock {
+    /// Lock the printer with `serial` using a lock file in `dir`.
+    pub(crate) fn acquire(dir: &Path, serial: &str) -> Result<Self, Error> {
+        let path = lock_path(dir, serial);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|e| {
+                Error::InvalidConfig(format!("Failed to open {}: {}", path.display(), e))
+            })?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut text = String::new();
+                // Reading fails on platforms with mandatory locks, the PID is optional
+                let pid = file
+                    .read_to_string(&mut text)
+                    .ok()
+                    .and_then(|_| text.trim().parse().ok());
+                return Err(Error::PrinterInUse(pid));
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(Error::InvalidConfig(format!(
+                    "Failed to lock {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        }
+
+        // The PID is informational only, failing to write it is not fatal
+        let written = file
+            .set_len(0)
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| write!(file, "{}", std::process::id()))
+            .and_then(|_| file.flush());
+        if let Err(e) = written {
+            warn!("Failed to record PID in {}: {}", path.display(), e);
+        }
+
+        Ok(SerialLock { _file: file })
+    }
+}
+
+/// Lock file of the printer with `serial`, e.g. `ql-label-000G0Z000000.lock`.
+fn lock_path(dir: &Path, serial: &str) -> PathBuf {
+    let name: String = serial
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    dir.join(format!("ql-label-{}.lock", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_second_lock_reports_pid() {
+        let dir = std::env::temp_dir();
+        let serial = format!("test/{}", std::process::id());
+
+        let lock = SerialLock::acquire(&dir, &serial).unwrap();
+        match SerialLock::acquire(&dir, &serial) {
+            Err(Error::PrinterInUse(pid)) => assert_eq!(pid, Some(std::process::id())),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        drop(lock);
+        assert!(SerialLock::acquire(&dir, &serial).is_ok());
+        std::fs::remove_file(lock_path(&dir, &serial)).ok();
+    }
+}
@@ -1,3 +1,6 @@
+use alloc::{format, string::String, vec::Vec};
+use core::str::FromStr;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Media {
     Continuous(ContinuousType),
@@ -5,6 +8,7 @@ pub enum Media {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ContinuousType {
     Continuous12,
     Continuous29,
@@ -13,9 +17,11 @@ pub enum ContinuousType {
     Continuous54,
     Continuous62,
     Continuous62Red,
+    Continuous102,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DieCutType {
     DieCut17x54,
     DieCut17x87,
@@ -29,18 +35,17 @@ pub enum DieCutType {
     DieCut60x86,
     DieCut62x29,
     DieCut62x100,
+    DieCut102x152,
     DieCut12Dia,
     DieCut24Dia,
     DieCut58Dia,
 }
 
-#[allow(dead_code)]
 struct MediaSize {
     mm: f32,
     dots: u32,
 }
 
-#[allow(dead_code)]
 struct Width {
     mm: u8,
     left: u32,
@@ -54,6 +59,20 @@ struct Length {
     dots: u32,
 }
 
+/// Printable area of a media on its print head in dots at 300 DPI, as seen
+/// on the printed label. 102 mm media is measured on the 1296 pins of wide
+/// printers, all other media on a normal-width head.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrintableRect {
+    /// First printable dot from the left edge of the image, see
+    /// `MediaSpec::left_margin_dots()`.
+    pub x: u32,
+    /// Width of the printable area.
+    pub width: u32,
+    /// Length of the printable area, 0 for continuous media.
+    pub length: u32,
+}
+
 #[allow(dead_code)]
 pub struct MediaSpec {
     id: u16,
@@ -67,10 +86,79 @@ impl MediaSpec {
     pub fn width_mm(&self) -> u8 {
         self.width.mm
     }
-    
+
     pub fn length_mm(&self) -> u8 {
         self.length.mm
     }
+
+    /// Dots of the print head left of the printable area, as seen on the
+    /// printed label, see `PrintableRect`. The left edge of the image is the end of
+    /// a raster line, the first pin prints the right edge.
+    pub fn left_margin_dots(&self) -> u32 {
+        self.width.left
+    }
+
+    /// Width of the printable area in dots.
+    pub fn effective_dots(&self) -> u32 {
+        self.width.effective
+    }
+
+    /// Dots of the print head right of the printable area, counted from
+    /// the first pin of a raster line. 12 on both heads for 62 mm and
+    /// 102 mm media.
+    pub fn right_margin_dots(&self) -> u32 {
+        self.width.right
+    }
+
+    /// Length of the printable area of a die-cut label in dots at 300 DPI,
+    /// 0 for continuous media.
+    pub fn length_dots(&self) -> u32 {
+        self.length.dots
+    }
+
+    /// Unprinted margin the firmware keeps at both ends of a label, in dots
+    /// at 300 DPI.
+    pub fn feed_margin_dots(&self) -> u32 {
+        self.margin.dots
+    }
+
+    /// Distance from the leading edge of a die-cut label to the first
+    /// raster line the firmware prints in dots at 300 DPI, see
+    /// `offset_mm()`. `None` for continuous media.
+    pub fn offset_dots(&self) -> Option<u32> {
+        self.offset.as_ref().map(|offset| offset.dots)
+    }
+
+    /// Printable area on the print head in dots, see `PrintableRect`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{DieCutType, Media, PrintableRect};
+    /// let spec = Media::DieCut(DieCutType::DieCut29x90).spec();
+    /// let rect = spec.printable_rect();
+    /// assert_eq!(rect, PrintableRect { x: 408, width: 306, length: 1061 });
+    /// assert_eq!(rect.x + rect.width + spec.right_margin_dots(), 720);
+    /// ```
+    pub fn printable_rect(&self) -> PrintableRect {
+        PrintableRect {
+            x: self.width.left,
+            width: self.width.effective,
+            length: self.length.dots,
+        }
+    }
+
+    /// Distance from the leading edge of a die-cut label to the first
+    /// raster line the firmware prints, in millimetres. `None` for
+    /// continuous media.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{DieCutType, Media};
+    /// assert_eq!(Media::DieCut(DieCutType::DieCut29x90).spec().offset_mm(), Some(3.0));
+    /// ```
+    pub fn offset_mm(&self) -> Option<f32> {
+        self.offset.as_ref().map(|offset| offset.mm)
+    }
 }
 
 impl Media {
@@ -161,6 +249,18 @@ impl Media {
                     margin: MediaSize { mm: 1.5, dots: 18 },
                     offset: None,
                 },
+                ContinuousType::Continuous102 => MediaSpec {
+                    id: 260,
+                    width: Width {
+                        mm: 102,
+                        left: 120,
+                        effective: 1164,
+                        right: 12,
+                    },
+                    length: Length { mm: 0, dots: 0 },
+                    margin: MediaSize { mm: 1.5, dots: 18 },
+                    offset: None,
+                },
             },
             Self::DieCut(t) => match t {
                 DieCutType::DieCut17x54 => MediaSpec {
@@ -310,6 +410,21 @@ impl Media {
                     margin: MediaSize { mm: 1.5, dots: 18 },
                     offset: Some(MediaSize { mm: 3.0, dots: 35 }),
                 },
+                DieCutType::DieCut102x152 => MediaSpec {
+                    id: 366,
+                    width: Width {
+                        mm: 102,
+                        left: 120,
+                        effective: 1164,
+                        right: 12,
+                    },
+                    length: Length {
+                        mm: 152,
+                        dots: 1809,
+                    },
+                    margin: MediaSize { mm: 1.5, dots: 18 },
+                    offset: Some(MediaSize { mm: 3.0, dots: 35 }),
+                },
                 DieCutType::DieCut12Dia => MediaSpec {
                     id: 362,
                     width: Width {
@@ -350,6 +465,159 @@ impl Media {
         }
     }
 
+    /// Every supported media type.
+    pub(crate) const ALL: [Media; 24] = [
+        Self::Continuous(ContinuousType::Continuous12),
+        Self::Continuous(ContinuousType::Continuous29),
+        Self::Continuous(ContinuousType::Continuous38),
+        Self::Continuous(ContinuousType::Continuous50),
+        Self::Continuous(ContinuousType::Continuous54),
+        Self::Continuous(ContinuousType::Continuous62),
+        Self::Continuous(ContinuousType::Continuous62Red),
+        Self::Continuous(ContinuousType::Continuous102),
+        Self::DieCut(DieCutType::DieCut17x54),
+        Self::DieCut(DieCutType::DieCut17x87),
+        Self::DieCut(DieCutType::DieCut23x23),
+        Self::DieCut(DieCutType::DieCut29x42),
+        Self::DieCut(DieCutType::DieCut29x90),
+        Self::DieCut(DieCutType::DieCut38x90),
+        Self::DieCut(DieCutType::DieCut39x48),
+        Self::DieCut(DieCutType::DieCut52x29),
+        Self::DieCut(DieCutType::DieCut54x29),
+        Self::DieCut(DieCutType::DieCut60x86),
+        Self::DieCut(DieCutType::DieCut62x29),
+        Self::DieCut(DieCutType::DieCut62x100),
+        Self::DieCut(DieCutType::DieCut102x152),
+        Self::DieCut(DieCutType::DieCut12Dia),
+        Self::DieCut(DieCutType::DieCut24Dia),
+        Self::DieCut(DieCutType::DieCut58Dia),
+    ];
+
+    /// Every supported media type with its specification.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::Media;
+    /// let widths: Vec<u8> = Media::all()
+    ///     .filter(|(media, _)| matches!(media, Media::Continuous(_)))
+    ///     .map(|(_, spec)| spec.width_mm())
+    ///     .collect();
+    /// assert_eq!(widths, [12, 29, 38, 50, 54, 62, 62, 102]);
+    /// ```
+    pub fn all() -> impl Iterator<Item = (Media, MediaSpec)> {
+        Self::ALL.iter().map(|media| (*media, media.spec()))
+    }
+
+    /// Media of the given size in millimetres, `length_mm` 0 for continuous
+    /// tape. Black tape is preferred over red/black tape of the same size.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{ContinuousType, DieCutType, Media};
+    /// assert_eq!(
+    ///     Media::find_by_dimensions(62, 100),
+    ///     Some(Media::DieCut(DieCutType::DieCut62x100))
+    /// );
+    /// assert_eq!(
+    ///     Media::find_by_dimensions(62, 0),
+    ///     Some(Media::Continuous(ContinuousType::Continuous62))
+    /// );
+    /// assert_eq!(Media::find_by_dimensions(62, 50), None);
+    /// ```
+    pub fn find_by_dimensions(width_mm: u8, length_mm: u8) -> Option<Self> {
+        Self::all()
+            .find(|(_, spec)| spec.width_mm() == width_mm && spec.length_mm() == length_mm)
+            .map(|(media, _)| media)
+    }
+
+    /// Media described by its size, e.g. `62mm-continuous`, `62mm-red`,
+    /// `29x90mm` or `24mm-round`.
+    fn from_size(s: &str) -> Option<Self> {
+        let s = s.to_ascii_lowercase();
+        let (size, kind) = s.split_once('-').unwrap_or((&s, ""));
+        let size = size.strip_suffix("mm")?;
+        match (size.split_once('x'), kind) {
+            (Some((width, length)), "" | "die-cut") => {
+                Self::find_by_dimensions(width.parse().ok()?, length.parse().ok()?)
+            }
+            (None, "" | "continuous") => Self::find_by_dimensions(size.parse().ok()?, 0),
+            (None, "red" | "continuous-red") if size == "62" => {
+                Some(Self::Continuous(ContinuousType::Continuous62Red))
+            }
+            (None, "round") => {
+                let diameter = size.parse().ok()?;
+                Self::find_by_dimensions(diameter, diameter).filter(|media| {
+                    matches!(
+                        media,
+                        Self::DieCut(
+                            DieCutType::DieCut12Dia
+                                | DieCutType::DieCut24Dia
+                                | DieCutType::DieCut58Dia
+                        )
+                    )
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Stable name of the media type, e.g. `Continuous62` or `DieCut29x90`.
+    pub(crate) fn name(&self) -> String {
+        match self {
+            Self::Continuous(t) => format!("{:?}", t),
+            Self::DieCut(t) => format!("{:?}", t),
+        }
+    }
+
+    /// Media type with the given `name()`.
+    #[cfg_attr(not(any(feature = "profiles", feature = "serde")), allow(dead_code))]
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|media| media.name() == name)
+    }
+
+    /// Label identifier used by brother_ql, e.g. `62`, `29x90` or `d24`.
+    ///
+    /// brother_ql names the 38 x 90 mm labels `39x90`. Use `parse()` for the
+    /// other direction.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{DieCutType, Media};
+    /// assert_eq!(Media::DieCut(DieCutType::DieCut24Dia).brother_ql_id(), "d24");
+    /// ```
+    pub fn brother_ql_id(&self) -> &'static str {
+        match self {
+            Self::Continuous(t) => match t {
+                ContinuousType::Continuous12 => "12",
+                ContinuousType::Continuous29 => "29",
+                ContinuousType::Continuous38 => "38",
+                ContinuousType::Continuous50 => "50",
+                ContinuousType::Continuous54 => "54",
+                ContinuousType::Continuous62 => "62",
+                ContinuousType::Continuous62Red => "62red",
+                ContinuousType::Continuous102 => "102",
+            },
+            Self::DieCut(t) => match t {
+                DieCutType::DieCut17x54 => "17x54",
+                DieCutType::DieCut17x87 => "17x87",
+                DieCutType::DieCut23x23 => "23x23",
+                DieCutType::DieCut29x42 => "29x42",
+                DieCutType::DieCut29x90 => "29x90",
+                DieCutType::DieCut38x90 => "39x90",
+                DieCutType::DieCut39x48 => "39x48",
+                DieCutType::DieCut52x29 => "52x29",
+                DieCutType::DieCut54x29 => "54x29",
+                DieCutType::DieCut60x86 => "60x86",
+                DieCutType::DieCut62x29 => "62x29",
+                DieCutType::DieCut62x100 => "62x100",
+                DieCutType::DieCut102x152 => "102x152",
+                DieCutType::DieCut12Dia => "d12",
+                DieCutType::DieCut24Dia => "d24",
+                DieCutType::DieCut58Dia => "d58",
+            },
+        }
+    }
+
     pub fn from_id(id: u16) -> Option<Self> {
         match id {
             // Document says it is 0x4A but actual value seems to be 0x0A
@@ -359,6 +627,7 @@ impl Media {
             262 => Some(Self::Continuous(ContinuousType::Continuous50)),
             261 => Some(Self::Continuous(ContinuousType::Continuous54)),
             259 => Some(Self::Continuous(ContinuousType::Continuous62)),
+            260 => Some(Self::Continuous(ContinuousType::Continuous102)),
             //   0x81 => Some(Self::Continuous(ContinuousType::Continuous62Red)),
             // Same as above, 0x0B not 0x4B
             269 => Some(Self::DieCut(DieCutType::DieCut17x54)),
@@ -373,6 +642,7 @@ impl Media {
             383 => Some(Self::DieCut(DieCutType::DieCut60x86)),
             274 => Some(Self::DieCut(DieCutType::DieCut62x29)),
             275 => Some(Self::DieCut(DieCutType::DieCut62x100)),
+            366 => Some(Self::DieCut(DieCutType::DieCut102x152)),
             362 => Some(Self::DieCut(DieCutType::DieCut12Dia)),
             363 => Some(Self::DieCut(DieCutType::DieCut24Dia)),
             273 => Some(Self::DieCut(DieCutType::DieCut58Dia)),
@@ -390,7 +660,7 @@ impl Media {
     pub fn check_feed_value(&self, feed: u16) -> Result<[u8; 2], String> {
         match self {
             Self::Continuous(_) => {
-                if feed < 35 || feed > 1500 {
+                if !(35..=1500).contains(&feed) {
                     Err(format!("Feed value {} is out range.", feed))
                 } else {
                     Ok(feed.to_le_bytes())
@@ -444,6 +714,7 @@ impl Media {
                     0x81 => Some(Self::Continuous(ContinuousType::Continuous62Red)),
                     _ => None,
                 },
+                102 => Some(Self::Continuous(ContinuousType::Continuous102)),
                 _ => None,
             },
             0x0B => match (w, l) {
@@ -460,6 +731,7 @@ impl Media {
                 (60, 86) => Some(Self::DieCut(DieCutType::DieCut60x86)),
                 (62, 29) => Some(Self::DieCut(DieCutType::DieCut62x29)),
                 (62, 100) => Some(Self::DieCut(DieCutType::DieCut62x100)),
+                (102, 152) => Some(Self::DieCut(DieCutType::DieCut102x152)),
                 (12, 12) => Some(Self::DieCut(DieCutType::DieCut12Dia)),
                 (24, 24) => Some(Self::DieCut(DieCutType::DieCut24Dia)),
                 (58, 58) => Some(Self::DieCut(DieCutType::DieCut58Dia)),
@@ -469,3 +741,62 @@ impl Media {
         }
     }
 }
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidMediaName;
+
+/// Parse a brother_ql label identifier, see `Media::brother_ql_id()`, a
+/// media name such as `Continuous62`, or a size such as `62mm-continuous`,
+/// `62mm-red`, `29x90mm` or `24mm-round`.
+///
+/// Identifiers are case insensitive and `38x90` is accepted as well as
+/// brother_ql's `39x90`.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{ContinuousType, DieCutType, Media};
+/// assert_eq!("62red".parse(), Ok(Media::Continuous(ContinuousType::Continuous62Red)));
+/// assert_eq!("29x90".parse(), Ok(Media::DieCut(DieCutType::DieCut29x90)));
+/// assert_eq!("DieCut29x90".parse(), Ok(Media::DieCut(DieCutType::DieCut29x90)));
+/// assert_eq!("62mm-continuous".parse(), Ok(Media::Continuous(ContinuousType::Continuous62)));
+/// assert_eq!("24mm-round".parse(), Ok(Media::DieCut(DieCutType::DieCut24Dia)));
+/// assert_eq!("102x152".parse(), Ok(Media::DieCut(DieCutType::DieCut102x152)));
+/// assert!("103".parse::<Media>().is_err());
+/// ```
+impl FromStr for Media {
+    type Err = InvalidMediaName;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("38x90") {
+            return Ok(Self::DieCut(DieCutType::DieCut38x90));
+        }
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|media| {
+                media.brother_ql_id().eq_ignore_ascii_case(s)
+                    || media.name().eq_ignore_ascii_case(s)
+            })
+            .or_else(|| Self::from_size(s))
+            .ok_or(InvalidMediaName)
+    }
+}
+
+/// Media are serialized with serde as their name, e.g. `"Continuous62"` or
+/// `"DieCut29x90"`. brother_ql identifiers such as `"62"` are accepted too.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Media {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Media {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Media::from_name(&name)
+            .or_else(|| name.parse().ok())
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown media: {}", name)))
+    }
+}
@@ -0,0 +1,471 @@
+//! Text templates for sequential and data driven labels.
+//!
+//! A `TextTemplate` is a string with `{placeholder}` fields that is rendered
+//! once per label. Fields are filled from record values, auto-incrementing
+//! counters or the current date and time, so asset tags and serial number
+//! labels can be produced without external scripting.
+//!
+//! # Placeholders
+//! * `{name}` - Value of the record field or counter `name`
+//! * `{name:05}` - Same, right aligned and padded with zeros to 5 characters
+//! * `{name:8}` - Same, right aligned and padded with spaces to 8 characters
+//! * `{date}`, `{time}` - Current date (`%Y-%m-%d`) and time (`%H:%M:%S`)
+//! * `{date:%y%m%d}` - Current date and time with a custom format
+//! * `{{`, `}}` - Literal braces
+//!
+//! Date formats support `%Y`, `%y`, `%m`, `%d`, `%H`, `%M`, `%S`, `%j` and `%%`.
+//!
+//! `MailMerge` binds a label template to a sequence of records and renders
+//! the labels lazily for `Printer::print()`. Records can be read from CSV
+//! with the `csv` feature, or from JSON and any `serde::Serialize` type with
+//! the `json` feature.
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::Matrix;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Field { name: String, pad: Option<Pad> },
+    DateTime(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Pad {
+    fill: char,
+    width: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Counter {
+    next: i64,
+    step: i64,
+}
+
+/// Label text with placeholders for record fields, counters and dates.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::TextTemplate;
+/// # use std::collections::HashMap;
+/// let mut template = TextTemplate::parse("{site}-{serial:05}")?.counter("serial", 41, 1);
+///
+/// let mut record = HashMap::new();
+/// record.insert("site".to_string(), "OSK".to_string());
+///
+/// assert_eq!(template.render(&record)?, "OSK-00041");
+/// assert_eq!(template.render(&record)?, "OSK-00042");
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TextTemplate {
+    segments: Vec<Segment>,
+    counters: HashMap<String, Counter>,
+    initial: HashMap<String, Counter>,
+    utc_offset: i32,
+}
+
+impl TextTemplate {
+    /// Parse a template string.
+    ///
+    /// # Returns
+    /// The template, or an error message for unbalanced braces or empty
+    /// placeholders.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => field.push(c),
+                            None => return Err(format!("Unclosed placeholder: {{{}", field)),
+                        }
+                    }
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(parse_field(&field)?);
+                }
+                '}' => return Err("Unmatched '}' in template".to_string()),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+
+        Ok(TextTemplate {
+            segments,
+            counters: HashMap::new(),
+            initial: HashMap::new(),
+            utc_offset: 0,
+        })
+    }
+
+    /// Register an auto-incrementing counter.
+    ///
+    /// The placeholder `{name}` takes the value `start` on the first render
+    /// and is increased by `step` after each one. Counters take precedence
+    /// over record fields of the same name.
+    pub fn counter(mut self, name: &str, start: i64, step: i64) -> Self {
+        let counter = Counter { next: start, step };
+        self.counters.insert(name.to_string(), counter);
+        self.initial.insert(name.to_string(), counter);
+        self
+    }
+
+    /// Offset from UTC in minutes used for date and time placeholders.
+    ///
+    /// The default is UTC. Use `540` for Japan Standard Time.
+    pub fn utc_offset(self, minutes: i32) -> Self {
+        TextTemplate {
+            utc_offset: minutes,
+            ..self
+        }
+    }
+
+    /// Restart all counters from their start values.
+    pub fn reset_counters(&mut self) {
+        self.counters = self.initial.clone();
+    }
+
+    /// Render the template with the current time and advance the counters.
+    ///
+    /// # Arguments
+    /// * `values` - Record fields by placeholder name
+    ///
+    /// # Returns
+    /// The label text, or an error message if a placeholder has no value.
+    pub fn render(&mut self, values: &HashMap<String, String>) -> Result<String, String> {
+        self.render_at(values, SystemTime::now())
+    }
+
+    /// Render the template for a given point in time and advance the counters.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::TextTemplate;
+    /// # use std::collections::HashMap;
+    /// # use std::time::{Duration, UNIX_EPOCH};
+    /// let mut template = TextTemplate::parse("{date} {time:%H:%M}")?.utc_offset(540);
+    /// let at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    ///
+    /// assert_eq!(template.render_at(&HashMap::new(), at)?, "2023-11-15 07:13");
+    /// # Ok::<(), String>(())
+    /// ```
+    pub fn render_at(
+        &mut self,
+        values: &HashMap<String, String>,
+        time: SystemTime,
+    ) -> Result<String, String> {
+        let mut output = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => output.push_str(text),
+                Segment::DateTime(format) => {
+                    output.push_str(&format_time(format, time, self.utc_offset))
+                }
+                Segment::Field { name, pad } => {
+                    let value = match (self.counters.get(name), values.get(name)) {
+                        (Some(counter), _) => counter.next.to_string(),
+                        (None, Some(value)) => value.clone(),
+                        (None, None) => return Err(format!("No value for field: {}", name)),
+                    };
+                    output.push_str(&apply_pad(&value, *pad));
+                }
+            }
+        }
+
+        for counter in self.counters.values_mut() {
+            counter.next += counter.step;
+        }
+        Ok(output)
+    }
+}
+
+fn parse_field(field: &str) -> Result<Segment, String> {
+    let (name, spec) = match field.find(':') {
+        Some(index) => (field[..index].trim(), Some(&field[index + 1..])),
+        None => (field.trim(), None),
+    };
+    if name.is_empty() {
+        return Err("Empty placeholder in template".to_string());
+    }
+
+    match (name, spec) {
+        ("date", None) => Ok(Segment::DateTime("%Y-%m-%d".to_string())),
+        ("time", None) => Ok(Segment::DateTime("%H:%M:%S".to_string())),
+        ("date", Some(format)) | ("time", Some(format)) => {
+            Ok(Segment::DateTime(format.to_string()))
+        }
+        (name, None) => Ok(Segment::Field {
+            name: name.to_string(),
+            pad: None,
+        }),
+        (name, Some(spec)) => {
+            let fill = if spec.starts_with('0') { '0' } else { ' ' };
+            let width = spec
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid format for field {}: {}", name, spec))?;
+            Ok(Segment::Field {
+                name: name.to_string(),
+                pad: Some(Pad { fill, width }),
+            })
+        }
+    }
+}
+
+fn apply_pad(value: &str, pad: Option<Pad>) -> String {
+    match pad {
+        Some(Pad { fill, width }) if value.chars().count() < width => {
+            let padding = width - value.chars().count();
+            // Keep the sign in front of zero padded negative numbers
+            match value.strip_prefix('-') {
+                Some(digits) if fill == '0' => {
+                    format!("-{}{}", "0".repeat(padding), digits)
+                }
+                _ => format!("{}{}", fill.to_string().repeat(padding), value),
+            }
+        }
+        _ => value.to_string(),
+    }
+}
+
+/// Format a point in time with a small strftime subset.
+fn format_time(format: &str, time: SystemTime, utc_offset: i32) -> String {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    } + utc_offset as i64 * 60;
+
+    let days = seconds.div_euclid(86_400);
+    let secs = seconds.rem_euclid(86_400);
+    let (year, month, day) = civil_from_days(days);
+    let day_of_year = days - days_from_civil(year, 1, 1) + 1;
+
+    let mut output = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => output.push_str(&format!("{:04}", year)),
+            Some('y') => output.push_str(&format!("{:02}", year.rem_euclid(100))),
+            Some('m') => output.push_str(&format!("{:02}", month)),
+            Some('d') => output.push_str(&format!("{:02}", day)),
+            Some('H') => output.push_str(&format!("{:02}", secs / 3600)),
+            Some('M') => output.push_str(&format!("{:02}", secs % 3600 / 60)),
+            Some('S') => output.push_str(&format!("{:02}", secs % 60)),
+            Some('j') => output.push_str(&format!("{:03}", day_of_year)),
+            Some('%') => output.push('%'),
+            Some(other) => {
+                output.push('%');
+                output.push(other);
+            }
+            None => output.push('%'),
+        }
+    }
+    output
+}
+
+/// Convert days since 1970-01-01 to a (year, month, day) date.
+///
+/// Uses Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Convert a date to days since 1970-01-01.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Field values of a single mail-merge record by column name.
+pub type Record = HashMap<String, String>;
+
+/// Lazily renders one label per record.
+///
+/// The template is any function drawing a label for a record, typically with
+/// a `Canvas` and `TextTemplate`. Pages are only rendered when the printer
+/// asks for them, so large batches never hold more than one raster in memory.
+///
+/// # Example
+/// ```rust,no_run
+/// # use ql_label::*;
+/// # use std::collections::HashMap;
+/// let media = Media::Continuous(ContinuousType::Continuous62);
+/// let config = Config::new(Model::QL800, "000G0Z000000".to_string(), media);
+/// let records = vec![
+///     HashMap::from([("name".to_string(), "Alice".to_string())]),
+///     HashMap::from([("name".to_string(), "Bob".to_string())]),
+/// ];
+///
+/// let labels = MailMerge::new(records.into_iter(), |record: &Record| {
+///     let mut canvas = Canvas::new(media, Resolution::Normal).length_mm(20.0);
+///     canvas.fill_rect(0.0, 0.0, record["name"].len() as f32 * 5.0, 10.0);
+///     canvas.to_matrix()
+/// });
+///
+/// let printer = Printer::new(config)?;
+/// printer.print(labels)?;
+/// # Ok::<(), Error>(())
+/// ```
+pub struct MailMerge<I, F> {
+    records: I,
+    template: F,
+}
+
+impl<I, F> MailMerge<I, F>
+where
+    I: Iterator<Item = Record>,
+    F: FnMut(&Record) -> Matrix,
+{
+    /// Bind a label template to a sequence of records.
+    pub fn new(records: I, template: F) -> Self {
+        MailMerge { records, template }
+    }
+}
+
+impl<I, F> Iterator for MailMerge<I, F>
+where
+    I: Iterator<Item = Record>,
+    F: FnMut(&Record) -> Matrix,
+{
+    type Item = Matrix;
+
+    fn next(&mut self) -> Option<Matrix> {
+        let record = self.records.next()?;
+        Some((self.template)(&record))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.records.size_hint()
+    }
+}
+
+/// Read records from CSV data with a header row.
+///
+/// Rows are parsed lazily as the iterator advances. Each column becomes a
+/// field named after its header.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::csv_records;
+/// let data = "sku,name\n1001,Green tea\n1002,Matcha\n";
+/// let records = csv_records(data.as_bytes())?.collect::<Result<Vec<_>, _>>()?;
+///
+/// assert_eq!(records.len(), 2);
+/// assert_eq!(records[1]["name"], "Matcha");
+/// # Ok::<(), String>(())
+/// ```
+#[cfg(feature = "csv")]
+pub fn csv_records<R: std::io::Read>(
+    reader: R,
+) -> Result<impl Iterator<Item = Result<Record, String>>, String> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("Failed to read CSV header: {}", e))?
+        .clone();
+
+    Ok(reader.into_records().map(move |row| {
+        let row = row.map_err(|e| format!("Failed to read CSV row: {}", e))?;
+        Ok(headers
+            .iter()
+            .zip(row.iter())
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect())
+    }))
+}
+
+/// Convert any serializable struct or map into a record.
+///
+/// Top-level fields become record fields. Strings are used as they are,
+/// `null` becomes an empty string and other values are written as JSON.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::serde_record;
+/// #[derive(serde::Serialize)]
+/// struct Product { sku: u32, name: &'static str }
+///
+/// let record = serde_record(&Product { sku: 1001, name: "Green tea" })?;
+/// assert_eq!(record["sku"], "1001");
+/// assert_eq!(record["name"], "Green tea");
+/// # Ok::<(), String>(())
+/// ```
+#[cfg(feature = "json")]
+pub fn serde_record<T: serde::Serialize>(value: &T) -> Result<Record, String> {
+    let value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+    json_object_to_record(value)
+}
+
+/// Read records from a JSON array of objects.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::json_records;
+/// let data = r#"[{"sku": 1001, "name": "Green tea"}, {"sku": 1002, "name": "Matcha"}]"#;
+/// let records = json_records(data.as_bytes())?;
+///
+/// assert_eq!(records[0]["sku"], "1001");
+/// # Ok::<(), String>(())
+/// ```
+#[cfg(feature = "json")]
+pub fn json_records<R: std::io::Read>(reader: R) -> Result<Vec<Record>, String> {
+    let values: Vec<serde_json::Value> =
+        serde_json::from_reader(reader).map_err(|e| format!("Failed to read JSON: {}", e))?;
+    values.into_iter().map(json_object_to_record).collect()
+}
+
+#[cfg(feature = "json")]
+fn json_object_to_record(value: serde_json::Value) -> Result<Record, String> {
+    match value {
+        serde_json::Value::Object(map) => Ok(map
+            .into_iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    serde_json::Value::String(s) => s,
+                    serde_json::Value::Null => String::new(),
+                    other => other.to_string(),
+                };
+                (name, value)
+            })
+            .collect()),
+        other => Err(format!("Record must be a JSON object, found {}", other)),
+    }
+}
@@ -0,0 +1,226 @@
+//! Prometheus metrics of a printer.
+//!
+//! `Printer::metrics()` renders the statistics of the printer, the errors of
+//! its jobs, the state of the roll and the job durations in the Prometheus
+//! text format, ready to be served at `/metrics` by a daemon or REST server.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::media::Media;
+use crate::printer::MediaState;
+use crate::stats::Statistics;
+
+/// Upper bounds of the job duration histogram in seconds.
+const DURATION_BUCKETS: [f64; 8] = [0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+/// Errors and durations of the jobs run since the printer was opened.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct JobMetrics {
+    // Failed jobs by error code
+    errors: BTreeMap<u16, u64>,
+    // Jobs per bucket of `DURATION_BUCKETS`, not cumulative
+    durations: [u64; DURATION_BUCKETS.len()],
+    duration_sum: f64,
+    duration_count: u64,
+}
+
+impl JobMetrics {
+    pub(crate) fn record_job(&mut self, duration: Duration, result: &Result<(), Error>) {
+        if let Err(err) = result {
+            *self.errors.entry(err.code()).or_default() += 1;
+        }
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = DURATION_BUCKETS.iter().position(|&le| seconds <= le) {
+            self.durations[bucket] += 1;
+        }
+        self.duration_sum += seconds;
+        self.duration_count += 1;
+    }
+}
+
+/// Render the metrics of the printer with the serial number `serial`.
+pub(crate) fn render(
+    model: &str,
+    serial: &str,
+    stats: &Statistics,
+    jobs: &JobMetrics,
+    media_state: MediaState,
+) -> String {
+    let labels = format!("model=\"{}\",serial=\"{}\"", escape(model), escape(serial));
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "ql_label_jobs_total",
+        "counter",
+        "Print jobs started.",
+    );
+    let _ = writeln!(out, "ql_label_jobs_total{{{}}} {}", labels, stats.jobs());
+
+    let usage: Vec<_> = Media::ALL
+        .iter()
+        .map(|&media| (media.name(), stats.media_usage(media)))
+        .filter(|(_, usage)| usage.labels > 0)
+        .collect();
+    header(
+        &mut out,
+        "ql_label_labels_total",
+        "counter",
+        "Labels printed per media type.",
+    );
+    for (media, usage) in &usage {
+        let _ = writeln!(
+            out,
+            "ql_label_labels_total{{{},media=\"{}\"}} {}",
+            labels, media, usage.labels
+        );
+    }
+    header(
+        &mut out,
+        "ql_label_tape_mm_total",
+        "counter",
+        "Tape consumed per media type in millimetres.",
+    );
+    for (media, usage) in &usage {
+        let _ = writeln!(
+            out,
+            "ql_label_tape_mm_total{{{},media=\"{}\"}} {}",
+            labels, media, usage.length_mm
+        );
+    }
+
+    header(
+        &mut out,
+        "ql_label_errors_total",
+        "counter",
+        "Failed print jobs by error.",
+    );
+    for (&code, count) in &jobs.errors {
+        let name = Error::code_name(code).unwrap_or("Unknown");
+        let _ = writeln!(
+            out,
+            "ql_label_errors_total{{{},error=\"{}\"}} {}",
+            labels, name, count
+        );
+    }
+
+    header(
+        &mut out,
+        "ql_label_media_state",
+        "gauge",
+        "State of the installed roll.",
+    );
+    let current = match media_state {
+        MediaState::Ok => "ok",
+        MediaState::Low { .. } => "low",
+        MediaState::Empty => "empty",
+    };
+    for state in ["ok", "low", "empty"] {
+        let _ = writeln!(
+            out,
+            "ql_label_media_state{{{},state=\"{}\"}} {}",
+            labels,
+            state,
+            (state == current) as u8
+        );
+    }
+
+    header(
+        &mut out,
+        "ql_label_job_duration_seconds",
+        "histogram",
+        "Duration of print jobs.",
+    );
+    let mut cumulative = 0;
+    for (le, count) in DURATION_BUCKETS.iter().zip(&jobs.durations) {
+        cumulative += count;
+        let _ = writeln!(
+            out,
+            "ql_label_job_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+            labels, le, cumulative
+        );
+    }
+    let _ = writeln!(
+        out,
+        "ql_label_job_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+        labels, jobs.duration_count
+    );
+    let _ = writeln!(
+        out,
+        "ql_label_job_duration_seconds_sum{{{}}} {}",
+        labels, jobs.duration_sum
+    );
+    let _ = writeln!(
+        out,
+        "ql_label_job_duration_seconds_count{{{}}} {}",
+        labels, jobs.duration_count
+    );
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Escape a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::Emulator;
+    use crate::media::{ContinuousType, Media};
+    use crate::model::Model;
+    use crate::printer::{Config, Printer};
+    use crate::protocol::PrinterError;
+
+    #[test]
+    fn test_metrics() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let emulator = Emulator::new(Model::QL800, Some(media));
+        let config = Config::new(Model::QL800, "A\"1".to_string(), media);
+        let printer = Printer::with_transport(config, emulator.clone()).unwrap();
+
+        printer
+            .print(vec![vec![vec![0xFF; 90]; 100]].into_iter())
+            .unwrap();
+        emulator.set_error(Some(PrinterError::CoverOpen));
+        assert!(printer
+            .print(vec![vec![vec![0xFF; 90]; 100]].into_iter())
+            .is_err());
+
+        let metrics = printer.metrics();
+        let labels = r#"model="QL800",serial="A\"1""#;
+        for line in [
+            format!("ql_label_jobs_total{{{}}} 2", labels),
+            format!(
+                "ql_label_labels_total{{{},media=\"Continuous62\"}} 1",
+                labels
+            ),
+            format!(
+                "ql_label_errors_total{{{},error=\"PrinterError::CoverOpen\"}} 1",
+                labels
+            ),
+            format!("ql_label_media_state{{{},state=\"ok\"}} 1", labels),
+            format!(
+                "ql_label_job_duration_seconds_bucket{{{},le=\"+Inf\"}} 2",
+                labels
+            ),
+        ] {
+            assert!(
+                metrics.lines().any(|l| l == line),
+                "{} in\n{}",
+                line,
+                metrics
+            );
+        }
+    }
+}
@@ -1,6 +1,12 @@
-use std::str::FromStr;
+use alloc::vec::Vec;
+use core::str::FromStr;
 
-#[derive(Debug, Clone, Copy)]
+use crate::media::{ContinuousType, Media};
+
+/// Printer model, serialized with serde as its name without the dash, e.g.
+/// `"QL820NWB"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Model {
     QL500,
     QL550,
@@ -39,6 +45,28 @@ impl FromStr for Model {
     }
 }
 impl Model {
+    /// Every supported model.
+    pub const ALL: [Model; 18] = [
+        Self::QL500,
+        Self::QL550,
+        Self::QL560,
+        Self::QL570,
+        Self::QL580N,
+        Self::QL600,
+        Self::QL650TD,
+        Self::QL700,
+        Self::QL710W,
+        Self::QL720NW,
+        Self::QL800,
+        Self::QL810W,
+        Self::QL820NWB,
+        Self::QL1050,
+        Self::QL1060N,
+        Self::QL1100,
+        Self::QL1110NWB,
+        Self::QL1115NWB,
+    ];
+
     pub fn from_code(code: u8) -> Self {
         match code {
             0x47 => Self::QL600,
@@ -53,6 +81,23 @@ impl Model {
         }
     }
 
+    /// Model code reported in byte 4 of a status message, the inverse of
+    /// `from_code()`.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) fn code(&self) -> Option<u8> {
+        match self {
+            Self::QL600 => Some(0x47),
+            Self::QL720NW => Some(0x37),
+            Self::QL800 => Some(0x38),
+            Self::QL810W => Some(0x39),
+            Self::QL820NWB => Some(0x41),
+            Self::QL1100 => Some(0x43),
+            Self::QL1110NWB => Some(0x44),
+            Self::QL1115NWB => Some(0x45),
+            _ => None,
+        }
+    }
+
     pub fn pid(&self) -> u16 {
         match self {
             Self::QL600 => 0x20C0,
@@ -78,11 +123,100 @@ impl Model {
         }
     }
 
-    // pub fn supported_medias(&self) -> Vec<Media> {
-    //     match self {
-    //         Self::QL800 => vec![Media::Continuous29],
-    //         Self::QL810W => vec![Media::Continuous29],
-    //         Self::QL820NWB => vec![Media::Continuous29],
-    //     }
-    // }
+    /// Bytes of a raster line, a bit for every pin of the print head: 90,
+    /// or 162 on wide printers.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::Model;
+    /// assert_eq!(Model::QL820NWB.row_bytes(), 90);
+    /// assert_eq!(Model::QL1100.row_bytes(), 162);
+    /// ```
+    pub fn row_bytes(&self) -> usize {
+        (self.pins() / 8) as usize
+    }
+
+    /// Whether the print head is 1296 pins wide, for media up to 102 mm.
+    pub fn is_wide(&self) -> bool {
+        self.pins() == crate::WIDE_PRINTER_WIDTH
+    }
+
+    /// Whether the model prints black and red on DK-22251 tape, see
+    /// `Config::two_colors()`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::Model;
+    /// assert!(Model::QL820NWB.supports_two_color());
+    /// assert!(!Model::QL720NW.supports_two_color());
+    /// ```
+    pub fn supports_two_color(&self) -> bool {
+        matches!(self, Self::QL800 | Self::QL810W | Self::QL820NWB)
+    }
+
+    /// Whether the model prints 600 DPI along the tape, see
+    /// `Config::high_resolution()`. The older models print 300 DPI only.
+    pub fn supports_high_resolution(&self) -> bool {
+        !matches!(
+            self,
+            Self::QL500 | Self::QL550 | Self::QL560 | Self::QL650TD
+        )
+    }
+
+    /// Whether the model prints templates stored in it, see
+    /// `Printer::print_template()`.
+    pub fn supports_template(&self) -> bool {
+        matches!(
+            self,
+            Self::QL810W | Self::QL820NWB | Self::QL1110NWB | Self::QL1115NWB
+        )
+    }
+
+    /// Whether the model cuts the tape itself, see `Config::enable_auto_cut()`.
+    /// The QL-500 has a manual cutter.
+    pub fn supports_auto_cut(&self) -> bool {
+        !matches!(self, Self::QL500)
+    }
+
+    /// Resolution of the print head across the tape in dots per inch, 300
+    /// on every model. High resolution doubles it along the tape, see
+    /// `supports_high_resolution()`.
+    pub fn dpi(&self) -> u32 {
+        300
+    }
+
+    /// Most raster lines of a page in normal resolution, 1 m of continuous
+    /// tape. High resolution pages take twice as many lines, and two-color
+    /// pages a red and a black line for each. Longer pages are printed in
+    /// segments, see `Config::max_page_rows()`.
+    pub fn max_raster_lines(&self) -> u32 {
+        11811
+    }
+
+    /// Whether the model prints on `media`: the media must fit the print
+    /// head, and red/black tape needs a two-color model.
+    pub fn supports_media(&self, media: &Media) -> bool {
+        let spec = media.spec();
+        let fits = spec.left_margin_dots() + spec.effective_dots() <= self.pins();
+        let red = *media == Media::Continuous(ContinuousType::Continuous62Red);
+        fits && (!red || self.supports_two_color())
+    }
+
+    /// Every media the model prints on, for offering only valid choices.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{ContinuousType, Media, Model};
+    /// let red = Media::Continuous(ContinuousType::Continuous62Red);
+    /// assert!(Model::QL820NWB.supported_medias().contains(&red));
+    /// assert!(!Model::QL720NW.supported_medias().contains(&red));
+    /// assert_eq!(Model::QL720NW.supported_medias().len(), 21);
+    /// ```
+    pub fn supported_medias(&self) -> Vec<Media> {
+        Media::ALL
+            .iter()
+            .copied()
+            .filter(|media| self.supports_media(media))
+            .collect()
+    }
 }
@@ -0,0 +1,127 @@
+//! Preview images of the rows sent to the printer.
+//!
+//! Die-cut labels are too expensive to debug a layout on. `Preview` renders
+//! the exact `Matrix` or `TwoColorMatrix` a job would print onto the tape it
+//! is printed on, margins included, so it can be saved as PNG and checked
+//! before anything is printed.
+//!
+//! Requires the `image` feature.
+
+use std::path::Path;
+
+use image::{DynamicImage, GrayImage, Rgb, RgbImage};
+
+use crate::{
+    layout::{mm_to_dots, Resolution, HEAD_DPI},
+    media::Media,
+    utils::TwoColorMatrix,
+    Matrix,
+};
+
+const TAPE: Rgb<u8> = Rgb([255, 255, 255]);
+const MARGIN: Rgb<u8> = Rgb([224, 224, 224]);
+const BLACK: Rgb<u8> = Rgb([0, 0, 0]);
+const RED: Rgb<u8> = Rgb([255, 0, 0]);
+const BLACK_IN_MARGIN: Rgb<u8> = Rgb([128, 128, 128]);
+const RED_IN_MARGIN: Rgb<u8> = Rgb([255, 160, 160]);
+
+/// Rendering of printer rows as they come out on the tape.
+///
+/// The image covers the whole width of the tape. The strips along its edges
+/// that the printer cannot print are light gray; dots set there are drawn
+/// half-tone to flag them. Rows run from top to bottom like the label is
+/// fed, and in high resolution every dot is drawn two pixels wide so the
+/// preview is not stretched.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{Canvas, DieCutType, Media, Preview, Resolution};
+/// let media = Media::DieCut(DieCutType::DieCut29x90);
+/// let mut canvas = Canvas::new(media, Resolution::Normal);
+/// canvas.fill_rect(0.0, 0.0, 5.0, 5.0);
+///
+/// let image = Preview::new(media).to_gray_image(&canvas.to_matrix());
+/// // 29 mm of tape, the printable area starts after its margin
+/// assert_eq!(image.dimensions(), (343, 1061));
+/// assert_eq!(image.get_pixel(0, 0)[0], 224);
+/// assert_eq!(image.get_pixel(20, 0)[0], 0);
+/// assert_eq!(image.get_pixel(100, 0)[0], 255);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Preview {
+    media: Media,
+    resolution: Resolution,
+}
+
+impl Preview {
+    /// Preview for rows printed on `media` in normal resolution.
+    pub fn new(media: Media) -> Self {
+        Preview {
+            media,
+            resolution: Resolution::Normal,
+        }
+    }
+
+    /// Resolution the rows are printed at, see `Config::high_resolution()`.
+    pub fn resolution(self, resolution: Resolution) -> Self {
+        Preview { resolution, ..self }
+    }
+
+    /// Render black and white printer rows.
+    pub fn to_gray_image(&self, matrix: &Matrix) -> GrayImage {
+        DynamicImage::ImageRgb8(self.render(matrix, None)).to_luma8()
+    }
+
+    /// Render two-color printer rows, the red dots in red.
+    pub fn to_rgb_image(&self, two_color: &TwoColorMatrix) -> RgbImage {
+        self.render(&two_color.black, Some(&two_color.red))
+    }
+
+    /// Save the preview of black and white printer rows as PNG, see
+    /// `to_gray_image()`.
+    pub fn save_png<P: AsRef<Path>>(&self, matrix: &Matrix, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        self.to_gray_image(matrix)
+            .save_with_format(path, image::ImageFormat::Png)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    fn render(&self, black: &Matrix, red: Option<&Matrix>) -> RgbImage {
+        let spec = self.media.spec();
+        let effective = spec.effective_dots();
+        let tape = mm_to_dots(spec.width_mm() as f32, HEAD_DPI).max(effective);
+        let side = (tape - effective) / 2;
+        // Pins right of the printable area, the same on every print head
+        let right = spec.right_margin_dots();
+        let scale = match self.resolution {
+            Resolution::Normal => 1,
+            Resolution::High => 2,
+        };
+
+        let is_set = |matrix: &Matrix, column: u32, y: u32| {
+            // Column 0 is the left edge of the tape, the first pin prints the right edge
+            let pin = (tape - 1 - column) as i64 - side as i64 + right as i64;
+            pin >= 0
+                && matrix
+                    .get(y as usize)
+                    .and_then(|row| row.get(pin as usize / 8))
+                    .is_some_and(|byte| byte & (0x80 >> (pin % 8)) != 0)
+        };
+        let height = black.len().max(red.map_or(0, Vec::len)) as u32;
+
+        RgbImage::from_fn(tape * scale, height, |x, y| {
+            let column = x / scale;
+            let printable = column >= side && column < side + effective;
+            let black = is_set(black, column, y);
+            let red = red.is_some_and(|red| is_set(red, column, y));
+            match (printable, black, red) {
+                (true, true, _) => BLACK,
+                (true, false, true) => RED,
+                (true, false, false) => TAPE,
+                (false, true, _) => BLACK_IN_MARGIN,
+                (false, false, true) => RED_IN_MARGIN,
+                (false, false, false) => MARGIN,
+            }
+        })
+    }
+}
@@ -1,32 +1,111 @@
 use log::{debug, error, info, warn};
-use rusb::{Context, Device, DeviceDescriptor, DeviceHandle, Direction, TransferType, UsbContext};
-use std::time::Duration;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{BufWriter, Write};
+use std::ops::{ControlFlow, Deref};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 use crate::{
-    error::{Error, PrinterError},
-    media::Media,
+    cache::EncodingCache,
+    diagnostic,
+    discovery::{self, NetworkPrinter},
+    error::{ConfigConflict, Error},
+    layout::{mm_to_dots, Canvas, Resolution, HEAD_DPI, MIN_CONTINUOUS_LENGTH_DOTS},
+    lock::SerialLock,
+    media::{ContinuousType, DieCutType, Media},
+    metrics::{self, JobMetrics},
     model::Model,
-    utils::TwoColorMatrix,
+    protocol::{
+        self, ExpandedMode, Notification, Phase, PrinterError, PrinterErrors, Settings, Status,
+        StatusType,
+    },
+    raster::{OffsetRows, Page, RasterIter, RasterSource, Segmented},
+    stats::Statistics,
+    template::Template,
+    transport::Transport,
+    utils::{trim_blank_rows, TwoColorMatrix},
     Matrix,
 };
 
+#[cfg(feature = "usb")]
+use crate::transport::{Endpoint, UsbConnection, UsbTransport};
+#[cfg(feature = "usb")]
+use rusb::{Context, Device, DeviceDescriptor, DeviceHandle, Direction, TransferType, UsbContext};
+
 // Vendoer id of Brother Industries, Ltd
+#[cfg(feature = "usb")]
 const VENDOR_ID: u16 = 0x04f9;
 
-#[derive(Debug, Clone, Copy)]
-#[allow(dead_code)]
-struct Endpoint {
-    config: u8,
-    iface: u8,
-    setting: u8,
-    address: u8,
-}
+// Device found by `Printer::open_device()` and its serial number
+#[cfg(feature = "usb")]
+type OpenedDevice = (
+    Device<Context>,
+    DeviceDescriptor,
+    DeviceHandle<Context>,
+    String,
+);
 
+// Interval between status requests while waiting for the operator to change media
+const MEDIA_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Page data is sent in chunks of this size by default, see `Config::chunk_size()`
+const WRITE_CHUNK_SIZE: usize = 16 * 1024;
+
+// How long the printer may report a full buffer before the job fails
+const BUFFER_FULL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A connected label printer.
+///
+/// `Printer` is `Send` and `Sync`. Jobs and status requests take an internal
+/// lock, so one printer can be shared between threads (e.g. the request
+/// handlers of a web server) with an `Arc` or a `PrinterHandle`; concurrent
+/// jobs are printed one after another.
+///
+/// # Example
+/// ```rust,no_run
+/// # use ql_label::{Config, Model, Media, ContinuousType, Printer};
+/// let config = Config::new(Model::QL800, "000G0Z000000".to_string(),
+///                         Media::Continuous(ContinuousType::Continuous62));
+/// let printer = Printer::new(config)?.handle();
+///
+/// let handles: Vec<_> = (0..4)
+///     .map(|_| {
+///         let printer = printer.clone();
+///         std::thread::spawn(move || printer.print(vec![vec![vec![0xFF; 90]; 300]].into_iter()))
+///     })
+///     .collect();
+/// for handle in handles {
+///     handle.join().unwrap()?;
+/// }
+/// # Ok::<(), ql_label::Error>(())
+/// ```
 pub struct Printer {
-    handle: Box<DeviceHandle<Context>>,
-    endpoint_out: Endpoint,
-    endpoint_in: Endpoint,
+    transport: Box<dyn Transport>,
     config: Config,
+    statistics: Mutex<Statistics>,
+    job_metrics: Mutex<JobMetrics>,
+    // Raster commands of recently printed pages, see `Config::encoding_cache()`
+    encoding_cache: Mutex<EncodingCache>,
+    // The printer state is unknown until the first job has finished cleanly
+    needs_invalidate: AtomicBool,
+    // Set when the printer reported the end of the roll, until media is found again
+    end_of_media: AtomicBool,
+    // Number of times the print head started cooling down
+    cooling_count: AtomicU64,
+    // Receivers of every status message, see `Printer::subscribe_events()`
+    subscribers: Mutex<Vec<Sender<StatusEvent>>>,
+    // Token of the running job, see `Printer::print_with_cancel()`
+    cancel: Mutex<Option<CancelToken>>,
+    // Auto power off and on as last set, the printer can't report them
+    power_settings: Mutex<(Option<u8>, Option<bool>)>,
+    // Held while talking to the device so commands of different threads never interleave
+    io_lock: Mutex<()>,
+    // Keeps other processes away from the printer, see `Config::lock_dir()`
+    _serial_lock: Option<SerialLock>,
 }
 
 impl Printer {
@@ -34,7 +113,8 @@ impl Printer {
     ///
     /// This constructor handles USB device enumeration, connection, and initialization.
     /// It will search for a Brother P-Touch printer matching the model and serial number
-    /// specified in the configuration.
+    /// specified in the configuration, or for the first printer of the model when the
+    /// configuration was created with `Config::new_any()`.
     ///
     /// # Arguments
     /// * `config` - Printer configuration containing model, serial, media, and print settings
@@ -45,63 +125,34 @@ impl Printer {
     ///
     /// # Example
     /// ```rust,no_run
-    /// # use ptouch::{Config, Model, Media, ContinuousType, Printer};
+    /// # use ql_label::{Config, Model, Media, ContinuousType, Printer};
     /// let config = Config::new(Model::QL820NWB, "E8N117P02180".to_string(), 
     ///                         Media::Continuous(ContinuousType::Continuous62));
     /// let printer = Printer::new(config)?;
-    /// # Ok::<(), ptouch::Error>(())
+    /// # Ok::<(), ql_label::Error>(())
     /// ```
-    pub fn new(config: Config) -> Result<Self, Error> {
+    #[cfg(feature = "usb")]
+    pub fn new(mut config: Config) -> Result<Self, Error> {
         // rusb::set_log_level(rusb::LogLevel::Debug);
+        #[cfg(feature = "tracing")]
+        let _span = open_span(&config, "usb").entered();
         match Context::new() {
             Ok(mut context) => {
                 match Self::open_device(&mut context, config.model.pid(), config.serial.clone()) {
-                    Ok((mut device, device_desc, handle)) => {
-                        handle.reset()?;
-
-                        let endpoint_in = match Self::find_endpoint(
-                            &mut device,
-                            &device_desc,
-                            Direction::In,
-                            TransferType::Bulk,
-                        ) {
-                            Some(endpoint) => endpoint,
-                            None => return Err(Error::MissingEndpoint),
-                        };
-
-                        let endpoint_out = match Self::find_endpoint(
-                            &mut device,
-                            &device_desc,
-                            Direction::Out,
-                            TransferType::Bulk,
-                        ) {
-                            Some(endpoint) => endpoint,
-                            None => return Err(Error::MissingEndpoint),
+                    Ok((device, device_desc, handle, serial)) => {
+                        config.serial = serial;
+                        // Locked before the reset, which would disturb another process
+                        let serial_lock = match &config.lock_dir {
+                            Some(dir) => Some(SerialLock::acquire(dir, &config.serial)?),
+                            None => None,
                         };
-
-                        // QL-800では`has_kernel_driver`が`true`となる
-                        // QL-820NWBでは`has_kernel_driver`が`false`となる
-                        // `has_kernel_driver`が`true`の場合に、カーネルドライバーをデタッチしないとエラーとなる
-                        //
-                        handle.set_auto_detach_kernel_driver(true)?;
-                        let has_kernel_driver = match handle.kernel_driver_active(0) {
-                            Ok(true) => {
-                                handle.detach_kernel_driver(0).ok();
-                                true
-                            }
-                            _ => false,
-                        };
-                        info!(" Kernel driver support is {}", has_kernel_driver);
-                        handle.set_active_configuration(1)?;
-                        handle.claim_interface(0)?;
-                        handle.set_alternate_setting(0, 0)?;
-
-                        Ok(Printer {
-                            handle: Box::new(handle),
-                            endpoint_out,
-                            endpoint_in,
-                            config,
-                        })
+                        let connection = Self::claim_device(device, &device_desc, handle)?;
+                        let transport = UsbTransport::new(
+                            config.model.pid(),
+                            config.serial.clone(),
+                            connection,
+                        );
+                        Self::from_transport(config, Box::new(transport), serial_lock).opened()
                     }
                     Err(err) => {
                         debug!("Device connection failed: {:?}", err);
@@ -113,6 +164,194 @@ impl Printer {
         }
     }
 
+    /// List the USB devices of Brother Industries, e.g. to let the user pick
+    /// a printer or to select the only one attached.
+    ///
+    /// Serial numbers can only be read from devices the process may open;
+    /// on Linux that usually needs a udev rule.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{ContinuousType, Media, Printer};
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let found = Printer::enumerate()?;
+    /// for printer in &found {
+    ///     println!("{:?} {:?} on bus {} address {}",
+    ///              printer.model, printer.serial, printer.bus, printer.address);
+    /// }
+    /// if let [only] = &found[..] {
+    ///     let config = only.config(media).expect("Unsupported printer");
+    ///     let printer = Printer::new(config)?;
+    /// }
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    #[cfg(feature = "usb")]
+    pub fn enumerate() -> Result<Vec<DiscoveredPrinter>, Error> {
+        let context = Context::new()?;
+        let mut found = Vec::new();
+        for device in context.devices()?.iter() {
+            let device_desc = match device.device_descriptor() {
+                Ok(d) => d,
+                Err(err) => {
+                    debug!("{:#?}", err);
+                    continue;
+                }
+            };
+            if device_desc.vendor_id() != VENDOR_ID {
+                continue;
+            }
+            let product_id = device_desc.product_id();
+            let serial = match device.open() {
+                Ok(handle) => Self::read_serial(&handle, &device_desc),
+                Err(err) => {
+                    debug!("Unable to open USB device: {:?}", err);
+                    None
+                }
+            };
+            found.push(DiscoveredPrinter {
+                model: Model::ALL
+                    .iter()
+                    .copied()
+                    .find(|model| model.pid() == product_id),
+                serial,
+                product_id,
+                bus: device.bus_number(),
+                address: device.address(),
+            });
+        }
+        Ok(found)
+    }
+
+    /// Find Brother printers on the local network with multicast DNS.
+    ///
+    /// Network models announce their raw printing port and LPD queue;
+    /// every printer answering within `timeout` is listed once, with the
+    /// model named in its announcement. Serial numbers are not announced,
+    /// `NetworkPrinter::query_snmp()` reads them. Printers on other subnets
+    /// or with mDNS turned off can be identified with
+    /// `NetworkPrinter::from_snmp()`.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # use ql_label::Printer;
+    /// for mut printer in Printer::discover_network(Duration::from_secs(2))? {
+    ///     printer.query_snmp(Duration::from_secs(1))?;
+    ///     println!("{:?} {:?} at {}", printer.model, printer.serial, printer.socket_addr());
+    /// }
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn discover_network(timeout: Duration) -> Result<Vec<NetworkPrinter>, Error> {
+        discovery::discover(timeout)
+    }
+
+    /// Create a printer sending its jobs through `transport` instead of USB.
+    ///
+    /// Use it with `Emulator` to run print jobs without hardware, or to
+    /// drive a printer connected by other means.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, Model, Media, ContinuousType, Emulator, Printer};
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let emulator = Emulator::new(Model::QL820NWB, Some(media));
+    ///
+    /// let config = Config::new(Model::QL820NWB, "emulator".to_string(), media);
+    /// let printer = Printer::with_transport(config, emulator.clone())?;
+    /// printer.print(vec![vec![vec![0xFF; 90]; 300]].into_iter())?;
+    ///
+    /// assert_eq!(emulator.pages().len(), 1);
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn with_transport<T: Transport + 'static>(
+        config: Config,
+        transport: T,
+    ) -> Result<Self, Error> {
+        #[cfg(feature = "tracing")]
+        let _span = open_span(&config, "custom").entered();
+        let serial_lock = match &config.lock_dir {
+            Some(dir) => Some(SerialLock::acquire(dir, &config.serial)?),
+            None => None,
+        };
+        Self::from_transport(config, Box::new(transport), serial_lock).opened()
+    }
+
+    /// Connect to the printer of `model` with the serial number `serial`,
+    /// or the first one of the model if it is empty, and print on the media
+    /// installed in it, see `Config::media_auto()`.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{Model, Printer};
+    /// let printer = Printer::new_with_detected_media(Model::QL820NWB, String::new())?;
+    /// println!("Printing on {}", printer.media().brother_ql_id());
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    #[cfg(feature = "usb")]
+    pub fn new_with_detected_media(model: Model, serial: String) -> Result<Self, Error> {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        Self::new(Config::new(model, serial, media).media_auto())
+    }
+
+    /// Finish opening the printer, detecting the media if configured.
+    fn opened(mut self) -> Result<Self, Error> {
+        if self.config.detect_media {
+            self.detect_media()?;
+        }
+        Ok(self)
+    }
+
+    /// Ask the printer for the installed media and print on it from now on.
+    ///
+    /// The feed is reset to the default of the media unless it is valid
+    /// for the media, e.g. when continuous tape replaced continuous tape.
+    ///
+    /// # Returns
+    /// * `Ok(Media)` - The installed media
+    /// * `Err(Error::NoMediaInstalled)` - No media, or media this crate
+    ///   doesn't know, is installed
+    pub fn detect_media(&mut self) -> Result<Media, Error> {
+        let status = self.check_status()?;
+        let media = status.media().ok_or(Error::NoMediaInstalled)?;
+        if media != self.config.media {
+            info!("Detected {:?}", media);
+            self.config = self.config.clone().with_media(media);
+            self.clear_encoding_cache();
+        }
+        Ok(media)
+    }
+
+    fn from_transport(
+        config: Config,
+        transport: Box<dyn Transport>,
+        serial_lock: Option<SerialLock>,
+    ) -> Self {
+        let statistics = match &config.statistics_file {
+            Some(path) => Statistics::load(path).unwrap_or_else(|err| {
+                warn!("Starting with empty statistics: {}", err);
+                Statistics::new()
+            }),
+            None => Statistics::new(),
+        };
+        let encoding_cache = EncodingCache::new(config.encoding_cache);
+
+        Printer {
+            transport,
+            config,
+            statistics: Mutex::new(statistics),
+            job_metrics: Mutex::new(JobMetrics::default()),
+            encoding_cache: Mutex::new(encoding_cache),
+            needs_invalidate: AtomicBool::new(true),
+            end_of_media: AtomicBool::new(false),
+            cooling_count: AtomicU64::new(0),
+            subscribers: Mutex::new(Vec::new()),
+            cancel: Mutex::new(None),
+            power_settings: Mutex::new((None, None)),
+            io_lock: Mutex::new(()),
+            _serial_lock: serial_lock,
+        }
+    }
+
     /// Cancel current print job and reset printer state.
     ///
     /// Sends an initialization command to cancel any ongoing print job
@@ -124,19 +363,101 @@ impl Printer {
     ///
     /// # Example
     /// ```rust,no_run
-    /// # use ptouch::{Config, Model, Media, ContinuousType, Printer};
+    /// # use ql_label::{Config, Model, Media, ContinuousType, Printer};
     /// # let config = Config::new(Model::QL820NWB, "serial".to_string(), 
     /// #                         Media::Continuous(ContinuousType::Continuous62));
     /// let printer = Printer::new(config)?;
     /// printer.cancel()?; // Cancel any ongoing job
-    /// # Ok::<(), ptouch::Error>(())
+    /// # Ok::<(), ql_label::Error>(())
     /// ```
     pub fn cancel(&self) -> Result<(), Error> {
+        let _guard = self.lock();
         let buf = self.initialize();
         self.write(buf)?;
         Ok(())
     }
 
+    /// Turn the printer off after `minutes` without a job, 0 keeps it on.
+    ///
+    /// The setting is stored by the printer and checked with a status
+    /// request. Supported by the QL-800 and QL-1100 series.
+    ///
+    /// # Returns
+    /// * `Ok(())` - The printer took the setting
+    /// * `Err(Error::InvalidConfig)` - `minutes` is not 0 to 60 in steps of 10
+    /// * `Err(Error::PrinterError)` - The printer reported an error
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, ContinuousType, Emulator, Media, Model, Printer};
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let emulator = Emulator::new(Model::QL820NWB, Some(media));
+    /// let config = Config::new(Model::QL820NWB, "emulator".to_string(), media);
+    /// let printer = Printer::with_transport(config, emulator.clone())?;
+    ///
+    /// printer.set_auto_power_off(30)?;
+    /// printer.set_power_on(true)?;
+    /// assert_eq!(emulator.power_settings(), (30, true));
+    /// assert!(printer.set_auto_power_off(45).is_err());
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn set_auto_power_off(&self, minutes: u8) -> Result<(), Error> {
+        let mut buf = Vec::new();
+        protocol::set_auto_power_off(&mut buf, minutes).map_err(Error::InvalidConfig)?;
+        self.send_setting(buf)?;
+        self.power_settings().0 = Some(minutes);
+        Ok(())
+    }
+
+    /// Turn the printer on as soon as it is plugged in, see
+    /// `set_auto_power_off()`.
+    pub fn set_power_on(&self, on: bool) -> Result<(), Error> {
+        let mut buf = Vec::new();
+        protocol::set_auto_power_on(&mut buf, on);
+        self.send_setting(buf)?;
+        self.power_settings().1 = Some(on);
+        Ok(())
+    }
+
+    /// Identification of the printer for asset management.
+    ///
+    /// The manufacturer, product, serial number and firmware version come
+    /// from the USB device descriptor and are `None` on other transports.
+    /// Raster commands can't query the firmware version, so the model is the
+    /// only thing asked from the printer itself.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, ContinuousType, Emulator, Media, Model, Printer};
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let emulator = Emulator::new(Model::QL820NWB, Some(media));
+    /// let printer = Printer::with_transport(Config::new_any(Model::QL820NWB, media), emulator)?;
+    ///
+    /// let info = printer.device_info()?;
+    /// assert_eq!(info.model, Some(Model::QL820NWB));
+    /// assert_eq!(info.firmware_version, None);
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn device_info(&self) -> Result<DeviceInfo, Error> {
+        let _guard = self.lock();
+        let mut info = self.transport.device_info();
+        info.model = Some(self.query_status()?.model());
+        Ok(info)
+    }
+
+    /// Send a printer setting command and check the status afterwards.
+    fn send_setting(&self, command: Vec<u8>) -> Result<(), Error> {
+        let _guard = self.lock();
+        let mut buf = self.reset();
+        buf.extend_from_slice(&command);
+        self.write(buf)?;
+        let status = self.query_status()?;
+        if !status.errors().is_empty() {
+            return Err(Error::PrinterError(status.errors().primary()));
+        }
+        Ok(())
+    }
+
     /// Read current printer status including media type, errors, and phase.
     ///
     /// This method is convenient for inspection when a new media is added
@@ -148,7 +469,7 @@ impl Printer {
     ///
     /// # Example
     /// ```rust,no_run
-    /// # use ptouch::{Config, Model, Media, ContinuousType, Printer};
+    /// # use ql_label::{Config, Model, Media, ContinuousType, Printer};
     /// # let config = Config::new(Model::QL820NWB, "serial".to_string(), 
     /// #                         Media::Continuous(ContinuousType::Continuous62));
     /// let printer = Printer::new(config)?;
@@ -156,11 +477,112 @@ impl Printer {
     ///     Ok(status) => println!("Printer ready: {:?}", status),
     ///     Err(e) => eprintln!("Printer error: {:?}", e),
     /// }
-    /// # Ok::<(), ptouch::Error>(())
+    /// # Ok::<(), ql_label::Error>(())
     /// ```
     pub fn check_status(&self) -> Result<Status, Error> {
-        self.request_status()?;
-        self.read_status()
+        let _guard = self.lock();
+        let status = self.query_status()?;
+        self.end_of_media.store(
+            status.media.is_none()
+                || status.errors.contains(PrinterErrors::END_OF_MEDIA)
+                || status.errors.contains(PrinterErrors::NO_MEDIA),
+            Ordering::Relaxed,
+        );
+        Ok(status)
+    }
+
+    /// State of the installed roll.
+    ///
+    /// `MediaState::Empty` once the printer has reported the end of the roll,
+    /// until `check_status()` finds media again. `MediaState::Low` when less
+    /// tape than the threshold given to `Config::low_media_warning()` is left
+    /// according to the statistics of this printer.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{Config, Model, Media, ContinuousType, MediaState, Printer};
+    /// let config = Config::new(Model::QL800, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62))
+    ///     .statistics_file("ql800.stats")
+    ///     .low_media_warning(30_480.0, 1_000.0);
+    /// let printer = Printer::new(config)?;
+    ///
+    /// if let MediaState::Low { remaining_mm } = printer.media_state() {
+    ///     println!("Only {:.0} mm left, prepare a new roll", remaining_mm);
+    /// }
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn media_state(&self) -> MediaState {
+        if self.end_of_media.load(Ordering::Relaxed) {
+            return MediaState::Empty;
+        }
+        match self.config.low_media {
+            Some((roll_length, threshold)) => {
+                let used = self.statistics().media_usage(self.config.media).length_mm;
+                let remaining_mm = (roll_length - used).max(0.0);
+                if remaining_mm < threshold {
+                    MediaState::Low { remaining_mm }
+                } else {
+                    MediaState::Ok
+                }
+            }
+            None => MediaState::Ok,
+        }
+    }
+
+    /// Open the connection to the printer again, e.g. after it was
+    /// power-cycled.
+    ///
+    /// Makes the attempts set with `Config::reconnect()`, or a single one.
+    /// The printer is initialized before the next job.
+    pub fn reconnect(&self) -> Result<(), Error> {
+        let _guard = self.lock();
+        self.reopen()
+    }
+
+    /// Number of times the printer stopped to cool down its print head since
+    /// it was opened.
+    ///
+    /// The printer pauses by itself when the head overheats during long or
+    /// back-to-back jobs. `JobQueue` compares the count before and after a
+    /// job to rest the printer, see `Throttle::cooling_pause()`.
+    pub fn cooling_count(&self) -> u64 {
+        self.cooling_count.load(Ordering::Relaxed)
+    }
+
+    /// Receive every status message the printer sends from now on.
+    ///
+    /// The printer reports phase changes, printed pages, cooling and errors
+    /// on its own while printing, the print methods only check them at a
+    /// few points. The returned channel gets each of them as a
+    /// `StatusEvent`, so another thread, e.g. of a GUI, can show live
+    /// progress of any job. Dropping the receiver ends the subscription.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, ContinuousType, Emulator, Media, Model, Printer, StatusEvent};
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let emulator = Emulator::new(Model::QL800, Some(media));
+    /// let config = Config::new(Model::QL800, "emulator".to_string(), media);
+    /// let printer = Printer::with_transport(config, emulator.clone())?;
+    ///
+    /// emulator.set_cooling(true);
+    /// let events = printer.subscribe_events();
+    /// printer.print(std::iter::once(vec![vec![0xFF; 90]; 300]))?;
+    ///
+    /// let events: Vec<StatusEvent> = events.try_iter().collect();
+    /// assert!(matches!(events[0], StatusEvent::Reply(_)));
+    /// assert!(events.iter().any(|event| matches!(event, StatusEvent::CoolingStarted(_))));
+    /// assert!(events.iter().any(|event| matches!(event, StatusEvent::PageCompleted(_))));
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn subscribe_events(&self) -> Receiver<StatusEvent> {
+        let (sender, receiver) = mpsc::channel();
+        match self.subscribers.lock() {
+            Ok(mut subscribers) => subscribers.push(sender),
+            Err(poisoned) => poisoned.into_inner().push(sender),
+        }
+        receiver
     }
 
     /// Print single-color labels.
@@ -169,11 +591,14 @@ impl Printer {
     /// use `print_two_color()` method instead.
     ///
     /// # Arguments
-    /// * `images` - Iterator of `Matrix` (`Vec<Vec<u8>>`) containing 1-bit bitmap data
+    /// * `images` - Iterator of pages, usually `Matrix` (`Vec<Vec<u8>>`) containing
+    ///   1-bit bitmap data, or any other `RasterSource` producing rows on demand
     ///
     /// # Returns
     /// * `Ok(())` - Print job completed successfully
-    /// * `Err(Error)` - Printer error, communication error, or media mismatch
+    /// * `Err(Error::PrinterError)` - Hardware error such as an opened cover. The
+    ///   job is aborted as soon as the printer reports it and the printer is reset
+    /// * `Err(Error)` - Communication error, or media mismatch
     ///
     /// # Image Format
     /// - Width: 720 pixels (90 bytes) for normal printers, 1296 pixels for wide printers
@@ -182,7 +607,7 @@ impl Printer {
     ///
     /// # Example
     /// ```rust,no_run
-    /// # use ptouch::{Config, Model, Media, ContinuousType, Printer, Matrix};
+    /// # use ql_label::{Config, Model, Media, ContinuousType, Printer, Matrix};
     /// let config = Config::new(Model::QL820NWB, "serial".to_string(), 
     ///                         Media::Continuous(ContinuousType::Continuous62));
     /// let printer = Printer::new(config)?;
@@ -191,21 +616,286 @@ impl Printer {
     /// let image_data: Matrix = vec![vec![0xFF; 90]; 300]; // 300 lines of solid black
     /// 
     /// printer.print(vec![image_data].into_iter())?;
-    /// # Ok::<(), ptouch::Error>(())
+    /// # Ok::<(), ql_label::Error>(())
     /// ```
-    pub fn print(&self, images: impl Iterator<Item = Matrix>) -> Result<(), Error> {
-        info!("Requesting printer status before print job");
+    pub fn print<S: RasterSource>(&self, images: impl Iterator<Item = S>) -> Result<(), Error> {
+        self.print_with_progress(images, |_| ControlFlow::Continue(()))
+    }
+
+    /// Print single-color labels, reporting progress after every label.
+    ///
+    /// `on_event` is called with a `PrintEvent` when the rows of a page have
+    /// been sent, after each printed label and when the roll runs low or
+    /// out. Returning `ControlFlow::Break(())` stops
+    /// the job cleanly at the current label boundary; the remaining pages are
+    /// not sent and `Ok(())` is returned.
+    ///
+    /// # Arguments
+    /// * `images` - Iterator of pages, see `print()`
+    /// * `on_event` - Called for every progress event
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{Config, Model, Media, ContinuousType, PrintEvent, Printer};
+    /// use std::ops::ControlFlow;
+    ///
+    /// let config = Config::new(Model::QL800, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62))
+    ///     .statistics_file("ql800.stats")
+    ///     .low_media_warning(30_480.0, 500.0);
+    /// let printer = Printer::new(config)?;
+    ///
+    /// let pages = (0..100).map(|_| vec![vec![0xFF; 90]; 300]);
+    /// printer.print_with_progress(pages, |event| match event {
+    ///     PrintEvent::MediaLow { .. } => ControlFlow::Break(()),
+    ///     _ => ControlFlow::Continue(()),
+    /// })?;
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn print_with_progress<S: RasterSource>(
+        &self,
+        images: impl Iterator<Item = S>,
+        mut on_event: impl FnMut(PrintEvent) -> ControlFlow<()>,
+    ) -> Result<(), Error> {
+        let _guard = self.lock();
+        let started = Instant::now();
+        self.update_statistics(|stats| stats.record_job());
+        let result = self.print_job(images, 1, &mut on_event).map(|_| ());
+        self.finish_job(&result, started);
+        result
+    }
+
+    /// Print single-color labels until `cancel` is cancelled.
+    ///
+    /// The token is checked on every progress event, see
+    /// `print_with_progress()`, and between the chunks of a page sent to the
+    /// printer, so another thread can abort a long job. A cancelled job stops
+    /// at the next check, resets the printer with the initialize command and
+    /// returns `Error::Cancelled`; the labels printed before are kept. A job
+    /// that printed all its labels before the token was checked returns
+    /// `Ok(())`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{CancelToken, Config, ContinuousType, Emulator, Error, Media, Model, Printer};
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let emulator = Emulator::new(Model::QL800, Some(media));
+    /// let config = Config::new(Model::QL800, "emulator".to_string(), media);
+    /// let printer = Printer::with_transport(config, emulator.clone())?;
+    ///
+    /// let cancel = CancelToken::new();
+    /// let pages = (0..200).map(|n| {
+    ///     if n == 2 {
+    ///         // e.g. the cancel button of a GUI, usually on another thread
+    ///         cancel.cancel();
+    ///     }
+    ///     vec![vec![0xFF; 90]; 100 + n]
+    /// });
+    /// let result = printer.print_with_cancel(pages, &cancel);
+    /// assert!(matches!(result, Err(Error::Cancelled)));
+    /// assert_eq!(emulator.pages().len(), 2);
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn print_with_cancel<S: RasterSource>(
+        &self,
+        images: impl Iterator<Item = S>,
+        cancel: &CancelToken,
+    ) -> Result<(), Error> {
+        if cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        let _guard = self.lock();
+        let started = Instant::now();
+        self.update_statistics(|stats| stats.record_job());
+        *self.cancel_token() = Some(cancel.clone());
+
+        let result = self.print_job(images, 1, &mut |_| {
+            if cancel.is_cancelled() {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        let result = match result {
+            Ok(ControlFlow::Continue(())) => Ok(()),
+            // Stopped at a label boundary, the printer has been reset
+            Ok(ControlFlow::Break(())) => Err(Error::Cancelled),
+            Err(Error::Cancelled) => {
+                info!("Print job cancelled, discarding the rows sent");
+                self.invalidate().and(Err(Error::Cancelled))
+            }
+            Err(err) => Err(err),
+        };
+
+        *self.cancel_token() = None;
+        self.finish_job(&result, started);
+        result
+    }
+
+    /// Print a single label of any length, streaming it to the printer.
+    ///
+    /// Continuous rolls allow labels over a metre long. `print()` holds the
+    /// encoded page in memory before sending it, this method encodes the
+    /// rows of `banner` as they are produced and writes them in chunks, so
+    /// together with a lazy source such as `RasterFn` the memory used does
+    /// not depend on the length of the label. `on_event` gets
+    /// `PrintEvent::RowsSent` after every chunk; returning
+    /// `ControlFlow::Break(())` stops the transfer and discards the banner.
+    /// Auto trimming, blank page detection and the encoding cache need the
+    /// whole page and don't apply.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::ops::ControlFlow;
+    /// # use ql_label::{Config, ContinuousType, Emulator, Media, Model, PrintEvent, Printer, RasterFn};
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let emulator = Emulator::new(Model::QL800, Some(media));
+    /// let config = Config::new(Model::QL800, "emulator".to_string(), media);
+    /// let printer = Printer::with_transport(config, emulator.clone())?;
+    ///
+    /// // A 1 metre long striped banner, generated row by row
+    /// let banner = RasterFn::new(11811, |y| vec![if (y / 50) % 2 == 0 { 0xFF } else { 0x00 }; 90]);
+    /// printer.print_banner(banner, |event| {
+    ///     if let PrintEvent::RowsSent { rows, total } = event {
+    ///         println!("{}%", rows * 100 / total);
+    ///     }
+    ///     ControlFlow::Continue(())
+    /// })?;
+    /// assert_eq!(emulator.pages()[0].black.len(), 11811);
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn print_banner<S: RasterSource>(
+        &self,
+        banner: S,
+        mut on_event: impl FnMut(PrintEvent) -> ControlFlow<()>,
+    ) -> Result<(), Error> {
+        let _guard = self.lock();
+        let started = Instant::now();
+        self.update_statistics(|stats| stats.record_job());
+        let result = self.print_banner_job(banner, &mut on_event);
+        self.finish_job(&result, started);
+        result
+    }
+
+    /// Print a single label of `raster_count` rows taken from `rows` as they
+    /// are produced, see `print_banner()`.
+    ///
+    /// Rows after `raster_count` are dropped, and blank rows are printed
+    /// when `rows` ends early, see `RasterIter`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, ContinuousType, Emulator, Media, Model, Printer};
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let emulator = Emulator::new(Model::QL800, Some(media));
+    /// let printer = Printer::with_transport(Config::new_any(Model::QL800, media), emulator.clone())?;
+    ///
+    /// let rows = (0..5000).map(|y| vec![if y % 2 == 0 { 0xFF } else { 0x00 }; 90]);
+    /// printer.print_streamed(rows, 5000)?;
+    /// assert_eq!(emulator.pages()[0].black.len(), 5000);
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn print_streamed(
+        &self,
+        rows: impl Iterator<Item = Vec<u8>>,
+        raster_count: u32,
+    ) -> Result<(), Error> {
+        self.print_banner(RasterIter::new(raster_count, rows), |_| {
+            ControlFlow::Continue(())
+        })
+    }
+
+    fn print_banner_job<S: RasterSource>(
+        &self,
+        banner: S,
+        on_event: &mut dyn FnMut(PrintEvent) -> ControlFlow<()>,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "tracing")]
+        let _span = self.job_span("banner").entered();
+        let status = self.query_status()?;
+        if self.wait_for_media(status, on_event)?.is_break() {
+            return Ok(());
+        }
+
+        info!("Streaming a banner of {} rows", banner.raster_count());
+        let result = self.stream_banner(banner, on_event);
+        self.handle_hardware_error(&result, on_event);
+        result
+    }
 
-        self.request_status()?;
+    /// Print `copies` labels of `image`.
+    ///
+    /// The page is encoded once and the encoded raster lines are sent for
+    /// every copy, so a run of 50 labels does not convert, offset and
+    /// compress the same image 50 times. The raster protocol has no copy
+    /// count, every copy is still transmitted to the printer.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, ContinuousType, Emulator, Media, Model, Printer};
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let emulator = Emulator::new(Model::QL800, Some(media));
+    /// let config = Config::new(Model::QL800, "emulator".to_string(), media);
+    /// let printer = Printer::with_transport(config, emulator.clone())?;
+    ///
+    /// printer.print_copies(vec![vec![0xFF; 90]; 300], 3)?;
+    /// let pages = emulator.pages();
+    /// assert_eq!(pages.len(), 3);
+    /// assert!(pages[2].ejected && !pages[1].ejected);
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn print_copies<S: RasterSource>(&self, image: S, copies: usize) -> Result<(), Error> {
+        self.print_copies_with_progress(image, copies, |_| ControlFlow::Continue(()))
+    }
+
+    /// Print `copies` labels of `image`, reporting progress after every
+    /// label, see `print_copies()` and `print_with_progress()`.
+    pub fn print_copies_with_progress<S: RasterSource>(
+        &self,
+        image: S,
+        copies: usize,
+        mut on_event: impl FnMut(PrintEvent) -> ControlFlow<()>,
+    ) -> Result<(), Error> {
+        let _guard = self.lock();
+        let started = Instant::now();
+        self.update_statistics(|stats| stats.record_job());
+        let result = match self.config.max_page_rows() {
+            // The segments of a long page read its rows once, every copy
+            // needs them again
+            Some(max_rows) if copies > 1 && image.raster_count() > max_rows => {
+                let (page, _) = buffer_page(image);
+                let pages = std::iter::repeat_n(page, copies);
+                self.print_job(pages, 1, &mut on_event)
+            }
+            _ => self.print_job(std::iter::once(image), copies, &mut on_event),
+        }
+        .map(|_| ());
+        self.finish_job(&result, started);
+        result
+    }
+
+    /// Print a job, returning `ControlFlow::Break` if `on_event` stopped it
+    /// before its last label.
+    fn print_job<S: RasterSource>(
+        &self,
+        images: impl Iterator<Item = S>,
+        copies: usize,
+        on_event: &mut dyn FnMut(PrintEvent) -> ControlFlow<()>,
+    ) -> Result<ControlFlow<()>, Error> {
+        #[cfg(feature = "tracing")]
+        let _span = self.job_span("pages").entered();
+        info!("Requesting printer status before print job");
 
-        match self.read_status() {
+        match self.query_status() {
             Ok(status) => {
                 info!("Verifying correct media is installed");
-                status.check_media(self.config.media)?;
+                if self.wait_for_media(status, on_event)?.is_break() {
+                    info!("Print job stopped before the media was changed");
+                    return Ok(ControlFlow::Break(()));
+                }
 
                 info!("Starting print job");
-                self.print_label(images)?;
-                Ok(())
+                self.print_label(images, copies, on_event)
             }
             Err(err) => {
                 error!("Failed to read printer status: {:?}", err);
@@ -218,121 +908,758 @@ impl Printer {
     ///
     /// This method is specifically designed for QL-820NWB printers with
     /// red/black tape installed. The configuration must have `two_colors(true)`
-    /// enabled for this method to work.
+    /// enabled and `Continuous62Red` media for this method to work.
+    ///
+    /// Every row of a page is sent as a black and a red raster line (`w`
+    /// command), the print information counts the rows of the page once.
     ///
     /// # Arguments
     /// * `images` - Iterator of `TwoColorMatrix` containing black and red image data
     ///
     /// # Returns
     /// * `Ok(())` - Print job completed successfully
-    /// * `Err(Error)` - Printer error, communication error, or invalid configuration
+    /// * `Err(Error::InvalidConfig)` - Two colors are not enabled or the media
+    ///   is not red/black tape, nothing was sent
+    /// * `Err(Error)` - Printer error or communication error
     ///
     /// # Example
-    /// ```rust,no_run
-    /// # use ptouch::{Config, Model, Media, ContinuousType, Printer, TwoColorMatrix};
-    /// let config = Config::new(Model::QL820NWB, "serial".to_string(), 
-    ///                         Media::Continuous(ContinuousType::Continuous62Red))
+    /// ```rust
+    /// # use ql_label::{Config, Model, Media, ContinuousType, Emulator, Printer, TwoColorMatrix};
+    /// let media = Media::Continuous(ContinuousType::Continuous62Red);
+    /// let emulator = Emulator::new(Model::QL820NWB, Some(media));
+    /// let config = Config::new(Model::QL820NWB, "emulator".to_string(), media)
     ///     .two_colors(true);
-    /// let printer = Printer::new(config)?;
-    /// 
+    /// let printer = Printer::with_transport(config, emulator.clone())?;
+    ///
     /// // Create two-color image data
-    /// let black_data = vec![vec![0u8; 90]; 300];
-    /// let red_data = vec![vec![0u8; 90]; 300];
+    /// let black_data = vec![vec![0xF0; 90]; 300];
+    /// let red_data = vec![vec![0x0F; 90]; 300];
     /// let two_color = TwoColorMatrix::new(black_data, red_data)?;
-    /// 
+    ///
     /// printer.print_two_color(vec![two_color].into_iter())?;
+    /// let page = &emulator.pages()[0];
+    /// assert_eq!((page.black.len(), page.red.len()), (300, 300));
+    /// assert_eq!(page.red[0][0], 0x0F);
     /// # Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
     pub fn print_two_color(&self, images: impl Iterator<Item = TwoColorMatrix>) -> Result<(), Error> {
         if !self.config.two_colors {
             return Err(Error::InvalidConfig("Two-color printing not enabled in config".to_string()));
         }
+        self.check_red_media()?;
 
-        info!("Requesting printer status before two-color print job");
-
-        self.request_status()?;
+        let _guard = self.lock();
+        let started = Instant::now();
+        self.update_statistics(|stats| stats.record_job());
+        let result = self.print_two_color_job(images);
+        self.finish_job(&result, started);
+        result
+    }
 
-        match self.read_status() {
-            Ok(status) => {
-                info!("Verifying correct media is installed");
-                status.check_media(self.config.media)?;
+    /// Print labels in red only, leaving the black channel empty.
+    ///
+    /// Use it to stamp overlays such as "URGENT" or "FRAGILE" on pre-printed
+    /// stock. Needs `two_colors(true)` and red/black tape
+    /// (`Continuous62Red`), otherwise `Error::InvalidConfig` is returned
+    /// before anything is sent.
+    ///
+    /// # Arguments
+    /// * `images` - Iterator of pages holding the red dots
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, ContinuousType, Emulator, Media, Model, Printer};
+    /// let media = Media::Continuous(ContinuousType::Continuous62Red);
+    /// let emulator = Emulator::new(Model::QL820NWB, Some(media));
+    /// let config = Config::new(Model::QL820NWB, "emulator".to_string(), media)
+    ///     .two_colors(true);
+    /// let printer = Printer::with_transport(config, emulator.clone())?;
+    ///
+    /// printer.print_red(vec![vec![vec![0xFF; 90]; 300]].into_iter())?;
+    /// let page = &emulator.pages()[0];
+    /// assert_eq!(page.red.len(), 300);
+    /// assert!(page.black.iter().all(|row| row.iter().all(|&b| b == 0)));
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn print_red(&self, images: impl Iterator<Item = Matrix>) -> Result<(), Error> {
+        self.print_two_color(images.map(TwoColorMatrix::red_only))
+    }
 
-                info!("Starting two-color print job");
-                let alternating_images = images.map(|two_color| two_color.to_alternating_matrix());
-                self.print_label(alternating_images)?;
-                Ok(())
-            }
-            Err(err) => {
-                error!("Failed to read printer status: {:?}", err);
-                Err(err)
-            }
+    /// Print a template stored in the printer, filled with the data of
+    /// `template`, in P-touch Template mode.
+    ///
+    /// Only the commands filling the objects are sent, no raster rows. The
+    /// media and print settings of the template apply, not the ones of the
+    /// configuration. The next raster job switches the printer back to
+    /// raster mode.
+    ///
+    /// # Returns
+    /// * `Ok(())` - The template was printed
+    /// * `Err(Error::InvalidConfig)` - The model has no template mode, see
+    ///   `Model::supports_template()`, or `template` is invalid
+    /// * `Err(Error)` - The printer reported an error
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, ContinuousType, Emulator, Media, Model, Printer, Template};
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let emulator = Emulator::new(Model::QL820NWB, Some(media));
+    /// let printer = Printer::with_transport(Config::new_any(Model::QL820NWB, media), emulator.clone())?;
+    ///
+    /// let template = Template::new(2)
+    ///     .text("name", "Jane Doe")
+    ///     .barcode("id", "000123");
+    /// printer.print_template(&template)?;
+    /// assert_eq!(emulator.templates(), vec![template]);
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn print_template(&self, template: &Template) -> Result<(), Error> {
+        let model = self.config.model;
+        if !model.supports_template() {
+            return Err(Error::InvalidConfig(format!(
+                "{:?} has no P-touch Template mode",
+                model
+            )));
         }
+        let commands = template.encode().map_err(Error::InvalidConfig)?;
+
+        let _guard = self.lock();
+        let started = Instant::now();
+        let mut buf = self.reset();
+        buf.extend(commands);
+        let result = self
+            .write(buf)
+            .and_then(|()| self.wait_for_print_completion());
+        self.finish_job(&result, started);
+        result
     }
 
-    // Private helper methods
+    /// Print a diagnostic label.
+    ///
+    /// The label is sized for the configured media and shows the model,
+    /// serial number, media and resolution, a millimetre ruler to check the
+    /// alignment, density bars and a border around the printable area. The
+    /// firmware version is shown as well when the transport reports it, see
+    /// `DeviceInfo::firmware_version`.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{Config, Model, Media, ContinuousType, Printer};
+    /// let config = Config::new(Model::QL820NWB, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62));
+    /// let printer = Printer::new(config)?;
+    /// printer.print_test_page()?;
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn print_test_page(&self) -> Result<(), Error> {
+        let config = &self.config;
+        let firmware_version = self.transport.device_info().firmware_version;
+        let canvas = diagnostic::test_page(
+            config.model,
+            &config.serial,
+            firmware_version.as_deref(),
+            config.media,
+            config.resolution(),
+        );
+        self.print_canvas(&canvas)
+    }
 
-    fn open_device(
-        context: &mut Context,
-        pid: u16,
-        serial: String,
-    ) -> Result<(Device<Context>, DeviceDescriptor, DeviceHandle<Context>), Error> {
-        let devices = context.devices()?;
+    /// Print a built-in label in black, also with two-color tape installed.
+    fn print_canvas(&self, canvas: &Canvas) -> Result<(), Error> {
+        let matrix = canvas.to_matrix_dithered();
 
-        if devices.is_empty() {
-            warn!("Unable to enumerate USB devices");
-            return Err(Error::DeviceListNotReadable);
+        if self.config.two_colors {
+            let red = vec![vec![0x00; matrix[0].len()]; matrix.len()];
+            let page = TwoColorMatrix::new(matrix, red).map_err(Error::InvalidConfig)?;
+            self.print_two_color(std::iter::once(page))
+        } else {
+            self.print(std::iter::once(matrix))
         }
-        for device in devices.iter() {
-            let device_desc = match device.device_descriptor() {
-                Ok(d) => d,
-                Err(err) => {
-                    debug!("{:#?}", err);
-                    continue;
-                }
-            };
-            debug!(
-                "vender_id: {:x},  product_id: {:x}",
-                device_desc.vendor_id(),
-                device_desc.product_id()
+    }
+
+    /// Print marks to calibrate the print position.
+    ///
+    /// The label has lines along the edges of the printable area, a cross
+    /// through its center and scales with 0.5 mm steps around the center.
+    /// Fold the printed label in half both ways: the scale readings at the
+    /// folds are the offset to pass to `set_print_offset_mm()` with the sign
+    /// reversed. The current offset is applied, so print the page again to
+    /// verify the result.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{Config, Model, Media, DieCutType, Printer};
+    /// let config = Config::new(Model::QL800, "serial".to_string(),
+    ///                         Media::DieCut(DieCutType::DieCut29x90));
+    /// let mut printer = Printer::new(config)?;
+    /// printer.print_calibration_page()?;
+    ///
+    /// // The center was printed 0.5 mm too far right and 1 mm too late
+    /// printer.set_print_offset_mm(-0.5, -1.0);
+    /// printer.print_calibration_page()?;
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn print_calibration_page(&self) -> Result<(), Error> {
+        let canvas = diagnostic::calibration_page(self.config.media, self.config.resolution());
+        self.print_canvas(&canvas)
+    }
+
+    /// Print density patches and registration marks for red/black tape.
+    ///
+    /// The label has a row of black and a row of red patches from white to
+    /// full color, showing how gray levels render in each color. Use them to
+    /// choose the thresholds of `ColorThresholds` for the source images.
+    /// Below, a red cross is printed over black scales with 0.5 mm steps: the
+    /// readings where the red lines cross the scales are the offset to pass
+    /// to `set_red_offset_mm()` with the sign reversed.
+    ///
+    /// Needs `two_colors(true)` and `Continuous62Red` tape, otherwise
+    /// `Error::InvalidConfig` is returned.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{Config, Model, Media, ContinuousType, Printer};
+    /// let config = Config::new(Model::QL820NWB, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62Red))
+    ///     .two_colors(true);
+    /// let mut printer = Printer::new(config)?;
+    /// printer.print_two_color_calibration_page()?;
+    ///
+    /// // The red cross was printed 0.5 mm too far left
+    /// printer.set_red_offset_mm(0.5, 0.0);
+    /// printer.print_two_color_calibration_page()?;
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn print_two_color_calibration_page(&self) -> Result<(), Error> {
+        self.check_red_media()?;
+        let canvas =
+            diagnostic::two_color_calibration_page(self.config.media, self.config.resolution());
+        let page = TwoColorMatrix::new(
+            canvas.black().to_matrix_dithered(),
+            canvas.red().to_matrix_dithered(),
+        )
+        .map_err(Error::InvalidConfig)?;
+        self.print_two_color(std::iter::once(page))
+    }
+
+    /// Set the offset of the red color applied to all following two-color
+    /// jobs, see `Config::red_offset_mm()`.
+    pub fn set_red_offset_mm(&mut self, x: f32, y: f32) {
+        self.config.red_offset = (x, y);
+    }
+
+    /// Offset of the red color relative to black as `(x, y)` in millimetres.
+    pub fn red_offset_mm(&self) -> (f32, f32) {
+        self.config.red_offset
+    }
+
+    /// Set the print offset applied to all following jobs, see
+    /// `Config::print_offset_mm()`.
+    pub fn set_print_offset_mm(&mut self, x: f32, y: f32) {
+        self.config.offset = (x, y);
+        self.clear_encoding_cache();
+    }
+
+    /// Print offset applied to every job as `(x, y)` in millimetres.
+    ///
+    /// Store it along with the serial number to reuse the calibration of a
+    /// unit, see `print_calibration_page()`.
+    pub fn print_offset_mm(&self) -> (f32, f32) {
+        self.config.offset
+    }
+
+    /// Set the offset of die-cut labels applied to all following jobs, see
+    /// `Config::die_cut_offset_mm()`.
+    pub fn set_die_cut_offset_mm(&mut self, mm: f32) {
+        self.config.die_cut_offset = Some(mm);
+        self.clear_encoding_cache();
+    }
+
+    /// Offset of die-cut labels in millimetres, `None` for continuous media.
+    pub fn die_cut_offset_mm(&self) -> Option<f32> {
+        self.config.die_cut_offset()
+    }
+
+    /// Turn this printer into a cheap, cloneable handle.
+    ///
+    /// All clones share the same device, see `PrinterHandle`.
+    pub fn handle(self) -> PrinterHandle {
+        PrinterHandle {
+            printer: Arc::new(self),
+        }
+    }
+
+    /// Read the print settings of this printer.
+    ///
+    /// The power settings are the ones last set with `set_auto_power_off()`,
+    /// `set_power_on()` or `apply_settings()`, `None` until then.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{Config, Model, Media, ContinuousType, Printer};
+    /// # let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let reference = Printer::new(Config::new(Model::QL820NWB, "E8N117P02180".to_string(), media))?;
+    /// let mut other = Printer::new(Config::new(Model::QL820NWB, "E8N117P02181".to_string(), media))?;
+    ///
+    /// other.apply_settings(&reference.dump_settings())?;
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn dump_settings(&self) -> Settings {
+        let (auto_power_off, auto_power_on) = *self.power_settings();
+        Settings {
+            auto_power_off,
+            auto_power_on,
+            ..self.config.settings()
+        }
+    }
+
+    /// Apply print settings to this printer, used by all following jobs.
+    ///
+    /// The power settings that are set are sent to the printer right away.
+    ///
+    /// # Returns
+    /// * `Ok(())` - Settings applied
+    /// * `Err(Error::InvalidConfig)` - The settings are not valid for the media
+    /// * `Err(Error::PrinterError)` - The printer refused a power setting
+    pub fn apply_settings(&mut self, settings: &Settings) -> Result<(), Error> {
+        let config = self.config.clone().apply_settings(settings)?;
+        if let Some(minutes) = settings.auto_power_off {
+            self.set_auto_power_off(minutes)?;
+        }
+        if let Some(on) = settings.auto_power_on {
+            self.set_power_on(on)?;
+        }
+        self.config = config;
+        self.clear_encoding_cache();
+        Ok(())
+    }
+
+    /// Statistics of the labels printed with this printer.
+    ///
+    /// When `Config::statistics_file()` is set the numbers include previous
+    /// runs and are saved after every job.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{Config, Model, Media, ContinuousType, Printer};
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let config = Config::new(Model::QL800, "serial".to_string(), media)
+    ///     .statistics_file("ql800.stats");
+    /// let printer = Printer::new(config)?;
+    ///
+    /// let used = printer.statistics().media_usage(media).length_mm;
+    /// println!("{:.1} m of the 30.48 m roll left", 30.48 - used / 1000.0);
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn statistics(&self) -> Statistics {
+        match self.statistics.lock() {
+            Ok(stats) => stats.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Forget the tape used on `media`, e.g. after loading a new roll.
+    ///
+    /// Unlike `Statistics::reset_media()` on the copy returned by
+    /// `statistics()`, this resets the numbers of the printer, which
+    /// `media_state()` and `metrics()` report, and saves them to
+    /// `Config::statistics_file()` if set.
+    ///
+    /// # Returns
+    /// * `Ok(())` - The usage was reset
+    /// * `Err(Error::Io)` - The statistics file could not be written
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, ContinuousType, Emulator, Media, Model, Printer};
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let emulator = Emulator::new(Model::QL800, Some(media));
+    /// let printer = Printer::with_transport(Config::new_any(Model::QL800, media), emulator)?;
+    /// printer.print(vec![vec![vec![0xFF; 90]; 200]].into_iter())?;
+    /// assert_eq!(printer.statistics().media_usage(media).labels, 1);
+    ///
+    /// printer.reset_media_usage(media)?;
+    /// assert_eq!(printer.statistics().media_usage(media).labels, 0);
+    /// assert_eq!(printer.statistics().labels(), 1);
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn reset_media_usage(&self, media: Media) -> Result<(), Error> {
+        self.update_statistics(|stats| stats.reset_media(media));
+        match &self.config.statistics_file {
+            Some(path) => self
+                .statistics()
+                .save(path)
+                .map_err(|err| Error::Io(std::io::Error::other(err))),
+            None => Ok(()),
+        }
+    }
+
+    /// Media the jobs are printed on, see `detect_media()`.
+    pub fn media(&self) -> Media {
+        self.config.media
+    }
+
+    /// Serial number of the printer, resolved when it was opened if the
+    /// configuration did not name one, see `Config::new_any()`.
+    ///
+    /// Empty when the printer does not report its serial number.
+    pub fn serial(&self) -> &str {
+        &self.config.serial
+    }
+
+    /// Metrics of this printer in the Prometheus text format.
+    ///
+    /// Covers the jobs, labels and tape of `statistics()`, the failed jobs by
+    /// error, the state of the roll and a histogram of the job durations.
+    /// Every sample is labelled with the model and serial number, so the
+    /// output of several printers can be concatenated. Errors and durations
+    /// count the jobs since the printer was opened.
+    ///
+    /// # Example
+    /// Serve the metrics at `/metrics` for a Prometheus scraper:
+    /// ```rust,no_run
+    /// # use ql_label::{Config, Model, Media, ContinuousType, Printer};
+    /// use std::io::{Read, Write};
+    /// use std::net::TcpListener;
+    ///
+    /// # let config = Config::new(Model::QL800, "serial".to_string(),
+    /// #                         Media::Continuous(ContinuousType::Continuous62));
+    /// let printer = Printer::new(config)?;
+    /// let listener = TcpListener::bind("0.0.0.0:9100")?;
+    /// for stream in listener.incoming() {
+    ///     let mut stream = stream?;
+    ///     let mut request = [0u8; 1024];
+    ///     let _ = stream.read(&mut request)?;
+    ///     let body = printer.metrics();
+    ///     write!(
+    ///         stream,
+    ///         "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{}",
+    ///         body.len(),
+    ///         body
+    ///     )?;
+    /// }
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn metrics(&self) -> String {
+        let jobs = match self.job_metrics.lock() {
+            Ok(jobs) => jobs.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        metrics::render(
+            &format!("{:?}", self.config.model),
+            &self.config.serial,
+            &self.statistics(),
+            &jobs,
+            self.media_state(),
+        )
+    }
+
+    fn print_two_color_job(
+        &self,
+        images: impl Iterator<Item = TwoColorMatrix>,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "tracing")]
+        let _span = self.job_span("two_color").entered();
+        info!("Requesting printer status before two-color print job");
+
+        match self.query_status() {
+            Ok(status) => {
+                info!("Verifying correct media is installed");
+                let _ = self.wait_for_media(status, &mut |_| ControlFlow::Continue(()))?;
+
+                info!("Starting two-color print job");
+                let alternating_images = images
+                    .map(|two_color| self.config.align_red(two_color).to_alternating_matrix());
+                self.print_label(alternating_images, 1, &mut |_| ControlFlow::Continue(()))
+                    .map(|_| ())
+            }
+            Err(err) => {
+                error!("Failed to read printer status: {:?}", err);
+                Err(err)
+            }
+        }
+    }
+
+    // Private helper methods
+
+    /// Span of a print job of `kind`, enclosing its pages and transfers.
+    #[cfg(feature = "tracing")]
+    fn job_span(&self, kind: &'static str) -> tracing::Span {
+        tracing::info_span!(
+            "print_job",
+            kind,
+            model = ?self.config.model,
+            serial = %self.config.serial,
+            media = ?self.config.media,
+        )
+    }
+
+    /// Fail unless red/black tape is configured.
+    fn check_red_media(&self) -> Result<(), Error> {
+        if self.config.media != Media::Continuous(ContinuousType::Continuous62Red) {
+            return Err(Error::InvalidConfig(
+                "Red printing needs red/black tape".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn clear_encoding_cache(&mut self) {
+        self.encoding_cache
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    /// Check the media reported in `status`, waiting for the operator to
+    /// install the configured media if `Config::wait_for_media_change()` is
+    /// set. Breaks when `on_event` stopped the job while waiting.
+    fn wait_for_media(
+        &self,
+        status: Status,
+        on_event: &mut dyn FnMut(PrintEvent) -> ControlFlow<()>,
+    ) -> Result<ControlFlow<()>, Error> {
+        let installed = status.media;
+        let mut err = match status.check_media(self.config.media) {
+            Ok(()) => return Ok(ControlFlow::Continue(())),
+            Err(err) => err,
+        };
+        let timeout = match self.config.media_change_timeout {
+            Some(timeout) => timeout,
+            None => return Err(err),
+        };
+
+        warn!("{}, waiting for {:?}", err, self.config.media);
+        let expected = self.config.media;
+        let request = PrintEvent::MediaChangeRequested {
+            expected,
+            installed,
+        };
+        if on_event(request).is_break() {
+            return Ok(ControlFlow::Break(()));
+        }
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            std::thread::sleep(MEDIA_POLL_INTERVAL);
+            self.request_status()?;
+            let status = self.read_status()?;
+            match status.check_media(expected) {
+                Ok(()) => {
+                    info!("{:?} installed, resuming the job", expected);
+                    self.end_of_media.store(false, Ordering::Relaxed);
+                    return Ok(on_event(PrintEvent::MediaChanged));
+                }
+                Err(e) => err = e,
+            }
+        }
+        error!("Media was not changed within {:?}", timeout);
+        Err(err)
+    }
+
+    fn cancel_token(&self) -> MutexGuard<'_, Option<CancelToken>> {
+        self.cancel.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether the token of the running job has been cancelled.
+    fn cancelled(&self) -> bool {
+        self.cancel_token()
+            .as_ref()
+            .is_some_and(CancelToken::is_cancelled)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        match self.io_lock.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => {
+                // Another job panicked half way, flush whatever it left behind
+                self.needs_invalidate.store(true, Ordering::Relaxed);
+                poisoned.into_inner()
+            }
+        }
+    }
+
+    fn power_settings(&self) -> MutexGuard<'_, (Option<u8>, Option<bool>)> {
+        match self.power_settings.lock() {
+            Ok(settings) => settings,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn update_statistics(&self, update: impl FnOnce(&mut Statistics)) {
+        let mut stats = match self.statistics.lock() {
+            Ok(stats) => stats,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        update(&mut stats);
+    }
+
+    fn finish_job(&self, result: &Result<(), Error>, started: Instant) {
+        self.needs_invalidate
+            .store(result.is_err(), Ordering::Relaxed);
+        match self.job_metrics.lock() {
+            Ok(mut jobs) => jobs.record_job(started.elapsed(), result),
+            Err(poisoned) => poisoned.into_inner().record_job(started.elapsed(), result),
+        }
+        if result.is_err() {
+            self.update_statistics(|stats| stats.record_error());
+        }
+        if let Err(err) = result {
+            // Ready for the next job, e.g. a retry of this one
+            if err.is_transient() && self.config.reconnect.is_some() {
+                warn!("{}, reconnecting to the printer", err);
+                if let Err(err) = self.reopen() {
+                    warn!("Failed to reconnect: {}", err);
+                }
+            }
+        }
+        if let Some(path) = &self.config.statistics_file {
+            if let Err(err) = self.statistics().save(path) {
+                warn!("Failed to save statistics: {}", err);
+            }
+        }
+    }
+
+    /// Reset an opened printer, find its bulk endpoints and claim its
+    /// interface.
+    #[cfg(feature = "usb")]
+    fn claim_device(
+        mut device: Device<Context>,
+        device_desc: &DeviceDescriptor,
+        handle: DeviceHandle<Context>,
+    ) -> Result<UsbConnection, Error> {
+        handle.reset()?;
+
+        let endpoint_in =
+            Self::find_endpoint(&mut device, device_desc, Direction::In, TransferType::Bulk)
+                .ok_or(Error::MissingEndpoint)?;
+
+        let endpoint_out =
+            Self::find_endpoint(&mut device, device_desc, Direction::Out, TransferType::Bulk)
+                .ok_or(Error::MissingEndpoint)?;
+
+        // QL-800では`has_kernel_driver`が`true`となる
+        // QL-820NWBでは`has_kernel_driver`が`false`となる
+        // `has_kernel_driver`が`true`の場合に、カーネルドライバーをデタッチしないとエラーとなる
+        //
+        handle.set_auto_detach_kernel_driver(true)?;
+        let has_kernel_driver = match handle.kernel_driver_active(0) {
+            Ok(true) => {
+                handle.detach_kernel_driver(0).ok();
+                true
+            }
+            _ => false,
+        };
+        info!(" Kernel driver support is {}", has_kernel_driver);
+        let pid = device_desc.product_id();
+        match handle.set_active_configuration(1) {
+            Err(err) if driver_claimed(&err) => return Err(Error::DriverClaimedDevice(pid)),
+            result => result?,
+        }
+        match handle.claim_interface(0) {
+            Err(rusb::Error::Busy) => return Err(Error::PrinterInUse(None)),
+            Err(err) if driver_claimed(&err) => return Err(Error::DriverClaimedDevice(pid)),
+            result => result?,
+        }
+        handle.set_alternate_setting(0, 0)?;
+
+        Ok(UsbConnection {
+            handle,
+            endpoint_out,
+            endpoint_in,
+        })
+    }
+
+    /// Open and claim the printer with product id `pid` and `serial` again,
+    /// see `Transport::reconnect()`.
+    #[cfg(feature = "usb")]
+    pub(crate) fn reopen_device(pid: u16, serial: &str) -> Result<UsbConnection, Error> {
+        let mut context = Context::new()?;
+        let (device, device_desc, handle, _) =
+            Self::open_device(&mut context, pid, serial.to_string())?;
+        Self::claim_device(device, &device_desc, handle)
+    }
+
+    /// Open the printer with product id `pid` and `serial`, or the first one
+    /// when `serial` is empty. Returns the serial number of the device too.
+    #[cfg(feature = "usb")]
+    fn open_device(context: &mut Context, pid: u16, serial: String) -> Result<OpenedDevice, Error> {
+        let devices = context.devices()?;
+
+        if devices.is_empty() {
+            warn!("Unable to enumerate USB devices");
+            return Err(Error::DeviceListNotReadable);
+        }
+        let mut claimed = false;
+        for device in devices.iter() {
+            let device_desc = match device.device_descriptor() {
+                Ok(d) => d,
+                Err(err) => {
+                    debug!("{:#?}", err);
+                    continue;
+                }
+            };
+            debug!(
+                "vender_id: {:x},  product_id: {:x}",
+                device_desc.vendor_id(),
+                device_desc.product_id()
             );
             if device_desc.vendor_id() == VENDOR_ID && device_desc.product_id() == pid {
                 match device.open() {
                     Ok(handle) => {
-                        let timeout = Duration::from_secs(1);
-                        let languages = handle.read_languages(timeout)?;
-
-                        if languages.len() > 0 {
-                            let language = languages[0];
-                            match handle.read_serial_number_string(language, &device_desc, timeout)
-                            {
-                                Ok(s) => {
-                                    if s == serial {
-                                        info!("Connected to printer (serial: {})", serial);
-                                        return Ok((device, device_desc, handle));
-                                    } else {
-                                        continue;
-                                    }
-                                }
-                                Err(err) => {
-                                    debug!("Cannot read device serial number: {:?}", err);
-                                    continue;
-                                }
-                            }
+                        let found = Self::read_serial(&handle, &device_desc);
+                        if serial.is_empty() || found.as_ref() == Some(&serial) {
+                            let serial = found.unwrap_or_default();
+                            info!("Connected to printer (serial: {})", serial);
+                            return Ok((device, device_desc, handle, serial));
                         } else {
                             continue;
                         }
                     }
                     Err(err) => {
                         debug!("Unable to open USB device: {:?}", err);
+                        claimed |= driver_claimed(&err);
                         continue;
                     }
                 }
             }
         }
-        error!("No printer found with serial number: {}", serial);
+        if claimed {
+            // The device can't be opened to read its serial number either
+            warn!("Printer {:04x} is bound to the Brother driver", pid);
+            return Err(Error::DriverClaimedDevice(pid));
+        }
+        if serial.is_empty() {
+            error!("No printer found with product id: {:04x}", pid);
+        } else {
+            error!("No printer found with serial number: {}", serial);
+        }
         Err(Error::DeviceOffline)
     }
 
+    /// Serial number of an opened device, in its first language.
+    #[cfg(feature = "usb")]
+    fn read_serial(
+        handle: &DeviceHandle<Context>,
+        device_desc: &DeviceDescriptor,
+    ) -> Option<String> {
+        let timeout = Duration::from_secs(1);
+        let language = match handle.read_languages(timeout) {
+            Ok(languages) => *languages.first()?,
+            Err(err) => {
+                debug!("Cannot read device languages: {:?}", err);
+                return None;
+            }
+        };
+        match handle.read_serial_number_string(language, device_desc, timeout) {
+            Ok(serial) => Some(serial),
+            Err(err) => {
+                debug!("Cannot read device serial number: {:?}", err);
+                None
+            }
+        }
+    }
+
+    #[cfg(feature = "usb")]
     fn find_endpoint(
         device: &mut Device<Context>,
         device_desc: &DeviceDescriptor,
@@ -378,7 +1705,7 @@ impl Printer {
         let total_timeout_secs = base_timeout_secs as f64 + data_dependent_timeout;
 
         // 最小10秒、最大60秒の範囲でクランプ
-        let timeout_secs = total_timeout_secs.max(10.0).min(60.0);
+        let timeout_secs = total_timeout_secs.clamp(10.0, 60.0);
         let timeout = Duration::from_secs(timeout_secs as u64);
 
         debug!(
@@ -386,16 +1713,22 @@ impl Printer {
             timeout_secs,
             buf.len()
         );
-        let result = self
-            .handle
-            .write_bulk(self.endpoint_out.address, &buf, timeout);
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("bulk_transfer", bytes = buf.len()).entered();
+        #[cfg(feature = "tracing")]
+        let started = Instant::now();
+        let result = self.transport.write(&buf, timeout);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            written = result.as_ref().ok().copied(),
+            elapsed_us = started.elapsed().as_micros() as u64,
+            error = result.as_ref().err().map(tracing::field::display),
+            "bulk transfer finished"
+        );
         match result {
             Ok(n) => {
                 if n == buf.len() {
-                    debug!(
-                        "Successfully wrote {} bytes to endpoint {:#x}",
-                        n, self.endpoint_out.address
-                    );
+                    debug!("Successfully wrote {} bytes", n);
                     Ok(())
                 } else {
                     warn!(
@@ -406,7 +1739,120 @@ impl Printer {
                     Err(Error::InvalidResponse(n))
                 }
             }
-            Err(e) => Err(Error::UsbError(e)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Send a page, stopping as soon as the printer reports an error.
+    ///
+    /// A page can take seconds to transfer. The printer reports problems such
+    /// as an opened cover right away, so the page is written in chunks and
+    /// pending status messages are checked in between.
+    fn write_page(&self, buf: Vec<u8>) -> Result<(), Error> {
+        let mut chunks = buf.chunks(self.chunk_size()).peekable();
+        while let Some(chunk) = chunks.next() {
+            if let Err(err) = self.write(chunk.to_vec()) {
+                // A stalled transfer is usually caused by a hardware error
+                self.poll_error()?;
+                return Err(err);
+            }
+            // The printer starts printing with the last chunk, completion is
+            // handled by `wait_for_print_completion()`
+            if chunks.peek().is_some() {
+                self.check_chunk()?;
+                if self.cancelled() {
+                    return Err(Error::Cancelled);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Bytes written at once, see `Config::chunk_size()`.
+    fn chunk_size(&self) -> usize {
+        match self.config.chunk_size {
+            0 => usize::MAX,
+            bytes => bytes,
+        }
+    }
+
+    /// Check the status messages received after a chunk was written,
+    /// waiting while the printer reports a full buffer.
+    fn check_chunk(&self) -> Result<(), Error> {
+        match self.poll_error() {
+            Err(Error::PrinterError(PrinterError::BufferFull)) => self.wait_for_buffer(),
+            result => result,
+        }
+    }
+
+    /// Wait for the status message clearing a full buffer.
+    ///
+    /// No status is requested: the request would land in the middle of the
+    /// raster data, the printer reports the change by itself.
+    fn wait_for_buffer(&self) -> Result<(), Error> {
+        warn!("Printer buffer is full, waiting before the next chunk");
+        let started = Instant::now();
+        while started.elapsed() < BUFFER_FULL_TIMEOUT {
+            let status = match self.read_status_with_timeout(Duration::from_millis(500)) {
+                Ok(status) => status,
+                Err(Error::ReadStatusTimeout) => continue,
+                Err(err) => return Err(err),
+            };
+            if !status.errors.contains(PrinterErrors::BUFFER_FULL) {
+                if !status.errors.is_empty() {
+                    error!("Printer reported error while sending: {}", status.errors);
+                    return Err(Error::PrinterError(status.errors.primary()));
+                }
+                debug!("Printer buffer has room again");
+                return Ok(());
+            }
+        }
+        error!("Printer buffer stayed full for {:?}", BUFFER_FULL_TIMEOUT);
+        Err(Error::PrinterError(PrinterError::BufferFull))
+    }
+
+    /// Pass a received status to the subscribers, forgetting those that
+    /// dropped their receiver.
+    fn publish(&self, status: &Status) {
+        let mut subscribers = match self.subscribers.lock() {
+            Ok(subscribers) => subscribers,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if !subscribers.is_empty() {
+            let event = StatusEvent::from_status(status.clone());
+            subscribers.retain(|sender| sender.send(event.clone()).is_ok());
+        }
+    }
+
+    /// Check for an error status sent by the printer without waiting.
+    fn poll_error(&self) -> Result<(), Error> {
+        let mut buf: [u8; 32] = [0x00; 32];
+        match self.transport.read(&mut buf, Duration::from_millis(1)) {
+            Ok(32) => {
+                let status = Status::from_buf(buf);
+                debug!("Status received while sending: {:?}", status);
+                self.publish(&status);
+                if !status.errors.is_empty() || status.status_type == StatusType::Error {
+                    error!("Printer reported error while sending: {}", status.errors);
+                    return Err(Error::PrinterError(status.errors.primary()));
+                }
+                Ok(())
+            }
+            Ok(_) | Err(Error::ReadStatusTimeout) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Stop the current job after a hardware error.
+    ///
+    /// The invalidate bytes flush any half-sent raster data and ESC @ clears
+    /// the job, so the printer accepts a new job once the operator has fixed
+    /// the problem. Errors are only logged, the original error is reported.
+    fn abort(&self, err: &PrinterError) {
+        warn!("Aborting print job: {}", err);
+        self.needs_invalidate.store(true, Ordering::Relaxed);
+        if let Err(e) = self.invalidate() {
+            warn!("Failed to reset printer after error: {}", e);
         }
     }
 
@@ -418,12 +1864,8 @@ impl Printer {
         let mut buf: [u8; 32] = [0x00; 32];
         let mut counter = 0;
 
-        debug!("reading from endpoint_in {:#?}", self.endpoint_in);
         while counter < 100000 {
-            match self
-                .handle
-                .read_bulk(self.endpoint_in.address, &mut buf, timeout)
-            {
+            match self.transport.read(&mut buf, timeout) {
                 // TODO: Check the first 4bytes match to [0x80, 0x20, 0x42, 0x34]
                 // TODO: Check the error status
                 //
@@ -432,15 +1874,25 @@ impl Printer {
                     let status = Status::from_buf(buf);
                     debug!("Raw status code: {:X?}", buf);
                     debug!("Parsed Status struct: {:?}", status);
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(
+                        status_type = ?status.status_type,
+                        phase = ?status.phase,
+                        errors = %status.errors,
+                        error_bits = ?status.errors.bits(),
+                        media = ?status.media,
+                        "status received"
+                    );
+                    self.publish(&status);
                     return Ok(status);
                 }
                 Ok(x) => {
                     debug!("Waiting {counter} {x}");
                     std::thread::sleep(std::time::Duration::from_millis(50));
                 }
-                Err(e) => return Err(Error::UsbError(e)),
+                Err(e) => return Err(e),
             };
-            counter = counter + 1;
+            counter += 1;
         }
         Err(Error::ReadStatusTimeout)
     }
@@ -454,21 +1906,21 @@ impl Printer {
         loop {
             let status = self.read_status_with_timeout(Duration::from_millis(1000))?;
             debug!(
-                "Print completion check: status_type={:?}, phase={:?}, error={:?}",
-                status.status_type, status.phase, status.error
+                "Print completion check: status_type={:?}, phase={:?}, errors={}",
+                status.status_type, status.phase, status.errors
             );
 
             // エラー状態の即座検出
-            if !status.error.is_no_error() {
-                error!("Print operation failed: {:?}", status.error);
-                return Err(Error::PrinterError(status.error));
+            if !status.errors.is_empty() {
+                error!("Print operation failed: {}", status.errors);
+                return Err(Error::PrinterError(status.errors.primary()));
             }
 
             match (status.status_type, status.phase) {
                 // エラー状態の即座検出
                 (StatusType::Error, _) => {
                     error!("Printer reported error status");
-                    return Err(Error::PrinterError(status.error));
+                    return Err(Error::PrinterError(status.errors.primary()));
                 }
 
                 // 印刷完了 -> 受信待機への遷移を待つ
@@ -500,6 +1952,18 @@ impl Printer {
                     std::thread::sleep(Duration::from_millis(50));
                 }
 
+                // 冷却中は印刷が止まる
+                (StatusType::Notification, _) => match status.notification {
+                    Notification::CoolingStarted => {
+                        warn!("Print head is cooling down");
+                        self.cooling_count.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Notification::CoolingFinished => info!("Print head cooled down"),
+                    Notification::NotAvailable => {
+                        debug!("Unknown notification: {:#?}", status)
+                    }
+                },
+
                 // 予期しない状態
                 _ => {
                     debug!("Unexpected status during print completion: {:#?}", status);
@@ -520,240 +1984,321 @@ impl Printer {
     }
 
     fn initialize(&self) -> Vec<u8> {
-        let mut buf: Vec<u8> = Vec::new();
-        buf.append(&mut [0x00; 400].to_vec());
-        buf.append(&mut [0x1B, 0x40].to_vec());
-        buf
+        protocol::initialize()
     }
 
-    fn set_media(&self, buf: &mut std::vec::Vec<u8>, raster_count: u32) {
-        buf.extend_from_slice(&[0x1B, 0x69, 0x7A]); // ESC i z
+    /// Initialize command, without the invalidate bytes when they can be skipped.
+    fn reset(&self) -> Vec<u8> {
+        if self.skip_invalidate() {
+            protocol::RESET.to_vec()
+        } else {
+            self.initialize()
+        }
+    }
 
-        // n1: 有効フラグ (用紙種類+幅+長さ+ラスター数)
-        let valid_flags = 0x02 | 0x04 | 0x08 | 0x40;
-        buf.push(valid_flags);
+    fn skip_invalidate(&self) -> bool {
+        self.config.skip_invalidate && !self.needs_invalidate.load(Ordering::Relaxed)
+    }
 
-        // n2: 用紙種類 (長尺:0x0A, ダイカット:0x0C)
-        let media_type = match self.config.media {
-            Media::Continuous(_) => 0x0A,
-            Media::DieCut(_) => 0x0B,
+    fn print_label<S: RasterSource>(
+        &self,
+        images: impl Iterator<Item = S>,
+        copies: usize,
+        on_event: &mut dyn FnMut(PrintEvent) -> ControlFlow<()>,
+    ) -> Result<ControlFlow<()>, Error> {
+        let sender = PageSender {
+            printer: self,
+            copies,
+            on_event: &mut *on_event,
         };
-        buf.push(media_type);
-
-        // n3, n4: 用紙幅・長さ (mm)
-        let spec = self.config.media.spec();
-        buf.push(spec.width_mm());
-        buf.push(spec.length_mm());
+        let result = self.config.pipeline(images, sender);
+        self.handle_hardware_error(&result, on_event);
+        result
+    }
 
-        // n5-n8: ラスター数 (リトルエンディアン)
-        let raster_bytes = raster_count.to_le_bytes();
-        buf.extend_from_slice(&raster_bytes);
+    /// Stop the job after a hardware error reported by the printer.
+    fn handle_hardware_error<T>(
+        &self,
+        result: &Result<T, Error>,
+        on_event: &mut dyn FnMut(PrintEvent) -> ControlFlow<()>,
+    ) {
+        if let Err(Error::PrinterError(err)) = result {
+            if is_end_of_media(err) {
+                self.end_of_media.store(true, Ordering::Relaxed);
+                let _ = on_event(PrintEvent::EndOfMedia);
+            }
+            self.abort(err);
+        }
+    }
 
-        // n9: 先頭ページフラグ (0=先頭ページ)
-        buf.push(0x00);
+    /// Report a printed label and the state of the roll.
+    fn label_printed(
+        &self,
+        page: usize,
+        length_mm: f64,
+        on_event: &mut dyn FnMut(PrintEvent) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        self.update_statistics(|stats| stats.record_label(self.config.media, length_mm));
+        self.end_of_media.store(false, Ordering::Relaxed);
 
-        // n10: 固定値
-        buf.push(0x00);
+        on_event(PrintEvent::PagePrinted { page, length_mm })?;
+        match self.media_state() {
+            MediaState::Low { remaining_mm } => on_event(PrintEvent::MediaLow { remaining_mm }),
+            _ => ControlFlow::Continue(()),
+        }
     }
 
-    fn print_label(&self, images: impl Iterator<Item = Matrix>) -> Result<(), Error> {
-        let mut preamble: Vec<u8> = self.initialize();
-        preamble.append(&mut [0x1B, 0x69, 0x61, 0x01].to_vec()); // Set raster command mode
-        preamble.append(&mut [0x1B, 0x69, 0x21, 0x00].to_vec()); // Set auto status notificatoin mode
-                                                                 //
-                                                                 // Apply config values
-        match self.config.clone().build() {
-            Ok(mut buf) => preamble.append(&mut buf),
-            Err(err) => return Err(err),
-        }
+    /// Print `pages`, returning `ControlFlow::Break` if `on_event` stopped
+    /// the job before its last label.
+    ///
+    /// A stop requested while the segments of a long page are printed takes
+    /// effect once the whole page is printed.
+    fn send_pages<S: RasterSource>(
+        &self,
+        pages: impl Iterator<Item = Segmented<S>>,
+        copies: usize,
+        on_event: &mut dyn FnMut(PrintEvent) -> ControlFlow<()>,
+    ) -> Result<ControlFlow<()>, Error> {
+        // The preamble is built for every job: ESC @ clears all modes set by
+        // a previous job, so a Printer can run any number of jobs back-to-back.
+        let mut preamble: Vec<u8> = self.reset();
+        preamble.append(&mut self.config.job_commands()?);
 
-        // QL-800では圧縮モードがサポートされていないため、常に非圧縮とする
-        let use_compression = if matches!(self.config.model, Model::QL800) && self.config.compress {
+        if self.config.compress && !self.config.use_compression() {
             warn!("QL-800 does not support compression mode, using uncompressed mode instead");
-            false
-        } else {
-            self.config.compress
-        };
-        
-        if use_compression {
-            preamble.append(&mut [0x4D, 0x02].to_vec()); // Set to pack bits compression mode
-        } else {
-            preamble.append(&mut [0x4D, 0x00].to_vec()); // Set to no compression mode
         }
 
         debug!("{:?}", self.config);
 
-        let mut start_flag: bool = true;
-        let mut color = false;
+        let mut encoder = PageEncoder::new(&self.config).cache(&self.encoding_cache);
+        let mut iter = pages.peekable();
+        let mut page = 0;
+        // Tape used by the segments of the current page printed so far
+        let mut joined_mm = 0.0;
+        // Set when a segment was stopped, the page is finished first
+        let mut stop = false;
 
-        let mut iter = images.into_iter().peekable();
+        while let Some(image) = iter.next() {
+            // Identical pages following this one are printed as its copies
+            let mut count = copies;
+            while iter.peek().is_some_and(|next| image.same_page(next)) {
+                iter.next();
+                count += copies;
+            }
+            if count > copies {
+                debug!("Printing {} identical pages as copies", count / copies);
+            }
 
-        loop {
-            let mut buf: Vec<u8> = Vec::new();
-
-            match iter.next() {
-                Some(image) => {
-                    if start_flag {
-                        buf.append(&mut preamble);
-                    }
+            // Copies reuse the encoded raster lines, only the framing differs
+            let continues = image.continues();
+            let raster_count = image.raster_count();
+            encoder.continues(continues);
+            let rows = encoder.encode_rows(image)?;
+            let length_mm = rows.length_mm;
+            let last_image = iter.peek().is_none();
+            for copy in 0..count {
+                let last = last_image && copy + 1 == count;
+                let mut buf = std::mem::take(&mut preamble);
+                buf.append(&mut encoder.frame(&rows, last));
 
-                    // ESC i z 印刷情報司令
-                    let raster_count = if self.config.two_colors {
-                        (image.len() / 2) as u32
-                    } else {
-                        image.len() as u32
-                    };
-                    self.set_media(&mut buf, raster_count);
-                    if start_flag {
-                        buf.append(&mut [0x00, 0x00].to_vec());
-                        start_flag = false;
-                    } else {
-                        buf.append(&mut [0x01, 0x00].to_vec());
-                    }
+                let sent = PrintEvent::PageSent {
+                    page,
+                    rows: raster_count,
+                };
+                #[cfg(feature = "tracing")]
+                let _span = tracing::info_span!(
+                    "page",
+                    page,
+                    copy,
+                    rows = raster_count,
+                    bytes = buf.len(),
+                    last,
+                )
+                .entered();
+                if !last {
+                    self.write_page(buf)?;
+                    info!("Print command sent, waiting for completion...");
+                    stop |= on_event(sent).is_break();
 
-                    // Add raster line image data
-                    if self.config.two_colors {
-                        for mut row in image {
-                            if color {
-                                // Black raster line (color code 0x01)
-                                buf.append(&mut [0x77, 0x01, 90].to_vec());
-                                buf.append(&mut row);
-                                color = !color;
-                            } else {
-                                // Red raster line (color code 0x02)
-                                buf.append(&mut [0x77, 0x02, 90].to_vec());
-                                buf.append(&mut row);
-                                color = !color;
-                            }
-                        }
-                    } else {
-                        if use_compression {
-                            for row in image {
-                                let mut packed = Self::pack_bits(&row);
-                                let len = packed.len() as u8;
-                                buf.append(&mut [0x67, 0x00, len].to_vec());
-                                buf.append(&mut packed);
-                            }
-                        } else {
-                            for mut row in image {
-                                buf.append(&mut [0x67, 0x00, 90].to_vec());
-                                buf.append(&mut row);
-                            }
-                        }
+                    // 改善されたステータス待機（中間ページ）
+                    self.wait_for_print_completion()?;
+                    if continues {
+                        info!("Segment printed, the page continues");
+                        joined_mm += length_mm;
+                        continue;
                     }
-
-                    if iter.peek().is_some() {
-                        buf.push(0x0C); // FF : Print
-                        self.write(buf)?;
-                        info!("Print command sent, waiting for completion...");
-
-                        // 改善されたステータス待機（中間ページ）
-                        self.wait_for_print_completion()?;
-                        info!("Page printed successfully");
-                    } else {
-                        buf.push(0x1A); // Control-Z : Print then Eject
-                        self.write(buf)?;
-                        info!("Final print command sent, ejecting media...");
-
-                        // 改善されたステータス待機
-                        self.wait_for_print_completion()?;
-                        info!("Print job completed successfully");
-
+                    info!("Page printed successfully");
+                    let length_mm = std::mem::take(&mut joined_mm) + length_mm;
+                    let printed = self.label_printed(page, length_mm, on_event);
+                    if stop || printed.is_break() {
+                        info!("Print job stopped after page {}", page);
+                        // Drop the unfinished job, the printed labels are kept
                         self.invalidate()?;
+                        return Ok(ControlFlow::Break(()));
                     }
+                } else {
+                    self.write_page(buf)?;
+                    info!("Final print command sent, ejecting media...");
+                    let _ = on_event(sent);
+
+                    // 改善されたステータス待機
+                    self.wait_for_print_completion()?;
+                    info!("Print job completed successfully");
+                    let length_mm = std::mem::take(&mut joined_mm) + length_mm;
+                    let _ = self.label_printed(page, length_mm, on_event);
+                    self.finish_last_page()?;
                 }
-                None => {
-                    break;
-                }
+                page += 1;
             }
         }
-        Ok(())
+        Ok(ControlFlow::Continue(()))
     }
 
-    /// TIFF PackBits圧縮アルゴリズム（Brother QL仕様準拠）
-    ///
-    /// 仕様:
-    /// - 同一データ連続：個数-1を負数で指定 + データ1バイト
-    /// - 異なるデータ連続：個数-1を正数で指定 + 全データ
-    /// - 90バイト超過時は非圧縮として91バイト送信
-    fn pack_bits(data: &[u8]) -> Vec<u8> {
-        // 入力データが90バイト固定でない場合はそのまま返す
-        if data.len() != 90 {
-            return data.to_vec();
+    /// Feed and reset after the last page of a job has been printed.
+    fn finish_last_page(&self) -> Result<(), Error> {
+        if let Some(mm) = self.config.trailing_feed {
+            self.trailing_feed(mm)?;
         }
 
-        let mut packed = Vec::new();
-        let mut i = 0;
-
-        while i < data.len() {
-            // Run-length encoding (RLE)のチェック
-            let mut run_length = 1;
-            let run_value = data[i];
+        if !self.skip_invalidate() {
+            self.invalidate()?;
+        }
+        Ok(())
+    }
 
-            // 同じ値の連続をカウント（最大128個まで）
-            while i + run_length < data.len()
-                && run_length < 128
-                && data[i + run_length] == run_value
-            {
-                run_length += 1;
-            }
+    /// Stream a single page to the printer, encoding and writing one chunk
+    /// at a time, see `print_banner()`.
+    fn stream_banner<S: RasterSource>(
+        &self,
+        banner: S,
+        on_event: &mut dyn FnMut(PrintEvent) -> ControlFlow<()>,
+    ) -> Result<(), Error> {
+        let mut buf: Vec<u8> = self.reset();
+        buf.append(&mut self.config.job_commands()?);
 
-            // RLEが効果的な場合（2個以上の連続）
-            if run_length >= 2 {
-                // 負数で圧縮指示: -(count-1)
-                packed.push((-(run_length as i8 - 1)) as u8);
-                packed.push(run_value);
-                i += run_length;
-            } else {
-                // リテラル実行のチェック
-                let start_pos = i;
-                let mut literal_length = 1;
-
-                // リテラル実行の最適な長さを決定
-                while i + literal_length < data.len() && literal_length < 128 {
-                    // 次の位置で2個以上同じ値が続く場合は、ここでリテラル実行を終了
-                    if i + literal_length + 1 < data.len()
-                        && data[i + literal_length] == data[i + literal_length + 1]
-                    {
-                        break;
+        let total = banner.raster_count();
+        let mut encoder = PageEncoder::new(&self.config);
+        let segments = Segmented::split(banner, self.config.max_page_rows());
+        let count = segments.len();
+        let mut sent_rows = 0;
+        let mut length_mm = 0.0;
+        for (i, segment) in segments.into_iter().enumerate() {
+            let rows = segment.raster_count();
+            encoder.continues(segment.continues());
+            let last = i + 1 == count;
+            // Segments are streamed, their bytes are on the bulk transfers
+            #[cfg(feature = "tracing")]
+            let _span = tracing::info_span!("page", page = i, rows, last).entered();
+            let sent =
+                encoder.stream(segment, last, &mut buf, self.chunk_size(), |chunk, rows| {
+                    if let Err(err) = self.write(chunk) {
+                        // A stalled transfer is usually caused by a hardware error
+                        self.poll_error()?;
+                        return Err(err);
                     }
-                    literal_length += 1;
+                    self.check_chunk()?;
+                    let rows = sent_rows + rows;
+                    Ok(on_event(PrintEvent::RowsSent { rows, total }))
+                })?;
+            match sent {
+                Some(segment_mm) => length_mm += segment_mm,
+                None => {
+                    info!("Banner stopped, discarding the rows sent");
+                    return self.invalidate();
                 }
+            }
+
+            self.write(std::mem::take(&mut buf))?;
+            info!(
+                "Banner segment {} of {} sent, waiting for completion...",
+                i + 1,
+                count
+            );
+            sent_rows += rows;
+            let _ = on_event(PrintEvent::RowsSent {
+                rows: sent_rows,
+                total,
+            });
+            self.wait_for_print_completion()?;
+        }
+        let _ = self.label_printed(0, length_mm, on_event);
+        self.finish_last_page()
+    }
 
-                // リテラル実行: 正数で非圧縮指示
-                packed.push((literal_length - 1) as u8);
-                packed.extend_from_slice(&data[start_pos..start_pos + literal_length]);
-                i += literal_length;
+    /// Request and read the status, reconnecting once after a transient
+    /// error when `Config::reconnect()` is set.
+    fn query_status(&self) -> Result<Status, Error> {
+        match self.request_status().and_then(|()| self.read_status()) {
+            Err(err) if err.is_transient() && self.config.reconnect.is_some() => {
+                warn!("{}, reconnecting to the printer", err);
+                self.reopen()?;
+                self.request_status()?;
+                self.read_status()
             }
+            result => result,
         }
+    }
 
-        // 重要な最適化: 90バイト超過時は非圧縮として91バイト返す
-        if packed.len() > 90 {
-            warn!(
-                "Data compression ineffective, sending uncompressed ({} bytes)",
-                data.len()
-            );
-            let mut result = Vec::with_capacity(91);
-            result.push(89); // 90-1 = 89（90バイトの非圧縮指示）
-            result.extend_from_slice(data);
-            result
-        } else {
-            debug!(
-                "Compression reduced data from {} to {} bytes ({:.1}% reduction)",
-                data.len(),
-                packed.len(),
-                (1.0 - packed.len() as f64 / data.len() as f64) * 100.0
-            );
-            packed
+    /// Reconnect the transport with the attempts and backoff of
+    /// `Config::reconnect()`.
+    fn reopen(&self) -> Result<(), Error> {
+        let (attempts, backoff) = self.config.reconnect.unwrap_or((1, Duration::ZERO));
+        let mut delay = backoff;
+        let mut attempt = 1;
+        loop {
+            std::thread::sleep(delay);
+            match self.transport.reconnect() {
+                Ok(()) => {
+                    info!("Reconnected to the printer");
+                    // The printer lost the state of the interrupted job
+                    self.needs_invalidate.store(true, Ordering::Relaxed);
+                    return Ok(());
+                }
+                Err(err) if attempt >= attempts => return Err(err),
+                Err(err) => warn!("Reconnect attempt {} failed: {}", attempt, err),
+            }
+            attempt += 1;
+            delay *= 2;
         }
     }
 
     fn request_status(&self) -> Result<(), Error> {
-        let mut buf: Vec<u8> = self.initialize();
-        buf.append(&mut [0x1b, 0x69, 0x53].to_vec());
+        let mut buf: Vec<u8> = self.reset();
+        buf.extend_from_slice(&protocol::STATUS_REQUEST);
         self.write(buf)
     }
 
+    /// Eject extra tape by printing a blank page without cutting.
+    ///
+    /// The raster commands have no plain feed command, so the tape is moved
+    /// with an empty page. The page itself adds the feed margin on both ends.
+    fn trailing_feed(&self, mm: f32) -> Result<(), Error> {
+        if let Media::DieCut(_) = self.config.media {
+            warn!("Trailing feed is only supported on continuous media");
+            return Ok(());
+        }
+        let config = Config {
+            auto_cut: AutoCut::Disabled,
+            cut_at_end: false,
+            high_resolution: false,
+            two_colors: false,
+            compress: false,
+            ..self.config.clone()
+        };
+        // Never shorter than the minimum, see `Config::job_commands()`
+        let rows = mm_to_dots(mm, HEAD_DPI).saturating_sub(2 * config.feed as u32);
+
+        let mut buf = self.reset();
+        buf.append(&mut config.job_commands()?);
+        protocol::print_information(&mut buf, config.media, rows, true);
+        buf.extend_from_slice(&vec![protocol::ZERO_RASTER; rows as usize]);
+        buf.push(protocol::PRINT_AND_EJECT);
+
+        info!("Feeding {} rows of blank tape", rows);
+        self.write(buf)?;
+        self.wait_for_print_completion()
+    }
+
     fn invalidate(&self) -> Result<(), Error> {
         let buf: Vec<u8> = self.initialize();
         self.write(buf)
@@ -763,98 +2308,735 @@ impl Printer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::media::DieCutType;
 
     #[test]
-    fn test_pack_bits_compression() {
-        // テスト1: 効果的な圧縮（同一データ連続）
-        let all_zeros = vec![0u8; 90];
-        let compressed = Printer::pack_bits(&all_zeros);
-        println!(
-            "All zeros: {} -> {} bytes",
-            all_zeros.len(),
-            compressed.len()
-        );
-        assert!(compressed.len() < all_zeros.len(), "圧縮が効果的でない");
-
-        // テスト2: 非効果的な圧縮（ランダムデータ）
-        let random_data: Vec<u8> = (0..90).map(|i| (i * 37 + 17) as u8).collect();
-        let compressed_random = Printer::pack_bits(&random_data);
-        println!(
-            "Random data: {} -> {} bytes",
-            random_data.len(),
-            compressed_random.len()
-        );
+    fn test_printer_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Printer>();
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_config_json() {
+        let media = Media::DieCut(DieCutType::DieCut29x90);
+        let config = Config::new(Model::QL820NWB, "000G0Z000000".to_string(), media)
+            .disable_auto_cut()
+            .print_offset_mm(0.5, -0.25);
+
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["model"], "QL820NWB");
+        assert_eq!(json["media"], "DieCut29x90");
+        assert_eq!(json["auto_cut"], serde_json::Value::Null);
+
+        let decoded: Config = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), json);
+        let commands = config.job_commands().unwrap();
+        assert_eq!(decoded.job_commands().unwrap(), commands);
+
+        // brother_ql identifiers are accepted for the media
+        let media: Media = serde_json::from_str("\"62red\"").unwrap();
+        assert_eq!(media, Media::Continuous(ContinuousType::Continuous62Red));
+    }
+
+    #[test]
+    fn test_job_commands_repeatable() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let config = Config::new(Model::QL820NWB, "serial".to_string(), media).compress(true);
+
+        // 続けて印刷しても毎回同じ完全なコマンド列を送る
+        let first = config.job_commands().unwrap();
+        let second = config.job_commands().unwrap();
+        assert_eq!(first, second);
+        assert_eq!(&first[..4], &[0x1B, 0x69, 0x61, 0x01]);
+        assert_eq!(&first[first.len() - 2..], &[0x4D, 0x02]);
+
+        // QL-800は圧縮非対応
+        let config = Config::new(Model::QL800, "serial".to_string(), media).compress(true);
+        let commands = config.job_commands().unwrap();
+        assert_eq!(&commands[commands.len() - 2..], &[0x4D, 0x00]);
+    }
+
+    #[test]
+    fn test_trailing_feed() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let page = vec![vec![0xFF; 90]; 200];
+        let printer_with_feed = |mm| {
+            let emulator = crate::emulator::Emulator::new(Model::QL800, Some(media));
+            let config = Config::new(Model::QL800, "emulator".to_string(), media);
+            let config = config.trailing_feed_mm(mm);
+            let printer = Printer::with_transport(config, emulator.clone()).unwrap();
+            (printer, emulator)
+        };
+
+        // 25 mm is 295 dots, the blank page adds the feed on both ends
+        let (printer, emulator) = printer_with_feed(25.0);
+        printer.print(vec![page.clone()].into_iter()).unwrap();
+        let printed = emulator.pages();
+        assert_eq!(printed.len(), 2);
+        assert_eq!(printed[1].black.len(), 295 - 70);
+
+        // Shorter than the blank page can be
+        let (printer, emulator) = printer_with_feed(3.0);
+        let result = printer.print(vec![page].into_iter());
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
+        assert!(emulator.pages().is_empty());
+    }
+
+    #[test]
+    fn test_identical_pages() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let emulator = crate::emulator::Emulator::new(Model::QL800, Some(media));
+        let config = Config::new(Model::QL800, "emulator".to_string(), media);
+        let printer = Printer::with_transport(config, emulator.clone()).unwrap();
+
+        // Runs of identical pages print like any other pages
+        let a = vec![vec![0xFF; 90]; 100];
+        let b = vec![vec![0x0F; 90]; 120];
+        let pages = vec![a.clone(), a.clone(), a.clone(), b.clone(), a.clone()];
+        printer.print(pages.clone().into_iter()).unwrap();
 
-        // テスト3: 91バイト制限の確認
-        if compressed_random.len() > 90 {
-            println!("91バイト制限により非圧縮データが返される");
-            assert_eq!(compressed_random.len(), 91); // 89 + 90バイトの元データ
-            assert_eq!(compressed_random[0], 89); // 非圧縮指示
-        }
-
-        // テスト4: 混合パターン（部分的な圧縮効果）
-        let mut mixed_data = vec![0u8; 30];
-        mixed_data.extend(vec![255u8; 30]);
-        mixed_data.extend((0..30).map(|i| i as u8));
-        let compressed_mixed = Printer::pack_bits(&mixed_data);
-        println!(
-            "Mixed data: {} -> {} bytes",
-            mixed_data.len(),
-            compressed_mixed.len()
+        let printed = emulator.pages();
+        assert_eq!(printed.len(), 5);
+        for (page, expected) in printed.iter().zip(&pages) {
+            assert_eq!(&page.black, expected);
+        }
+        assert_eq!(
+            printed.iter().map(|page| page.ejected).collect::<Vec<_>>(),
+            vec![false, false, false, false, true]
         );
+        assert_eq!(printer.statistics().labels(), 5);
     }
 
     #[test]
-    fn test_pack_bits_edge_cases() {
-        // エッジケース1: 空のデータ
-        let empty_data = vec![];
-        let compressed_empty = Printer::pack_bits(&empty_data);
-        assert_eq!(compressed_empty, empty_data);
-
-        // エッジケース2: 90バイト以外のサイズ
-        let wrong_size = vec![42u8; 50];
-        let compressed_wrong = Printer::pack_bits(&wrong_size);
-        assert_eq!(compressed_wrong, wrong_size);
-
-        // エッジケース3: 単一バイトの繰り返し（最大圧縮）
-        let single_byte = vec![42u8; 90];
-        let compressed_single = Printer::pack_bits(&single_byte);
-        assert_eq!(compressed_single.len(), 2); // 長さ指示 + データ
-        assert_eq!(compressed_single[0], (-(90i8 - 1)) as u8); // -89
-        assert_eq!(compressed_single[1], 42);
+    fn test_zero_raster() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let emulator = crate::emulator::Emulator::new(Model::QL1100, Some(media));
+        let config = Config::new(Model::QL1100, "emulator".to_string(), media).compress(true);
+        let printer = Printer::with_transport(config, emulator.clone()).unwrap();
+
+        let mut page = vec![vec![0x00; 90]; 200];
+        page[100] = vec![0xFF; 90];
+        printer.print(vec![page].into_iter()).unwrap();
+
+        // Blank rows are a single byte each
+        let received = emulator.received();
+        let zero_rows = received
+            .iter()
+            .filter(|&&b| b == protocol::ZERO_RASTER)
+            .count();
+        assert_eq!(zero_rows, 199);
+        let job = protocol::decode(&received).unwrap();
+        let printed = &emulator.pages()[0].black;
+        assert_eq!(&job.pages[0], printed);
+        assert_eq!(printed.len(), 200);
+        assert_eq!(printed[0], vec![0x00; 162]);
+        assert_eq!(&printed[100][..90], &[0xFF; 90][..]);
     }
-}
 
-///
-/// Status received from the printer encoded to Rust friendly type.
-///
-#[derive(Debug)]
-#[allow(dead_code)]
-pub struct Status {
-    model: Model,
-    error: PrinterError,
-    media: Option<Media>,
-    mode: u8,
-    status_type: StatusType,
-    phase: Phase,
-    notification: Notification,
-    id: u8,
-}
+    #[test]
+    fn test_segmented_page() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let emulator = crate::emulator::Emulator::new(Model::QL800, Some(media));
+        let config = Config::new(Model::QL800, "emulator".to_string(), media);
+        let printer = Printer::with_transport(config, emulator.clone()).unwrap();
 
-impl Status {
-    fn from_buf(buf: [u8; 32]) -> Self {
-        Status {
-            model: Model::from_code(buf[4]),
-            error: PrinterError::from_buf(buf),
-            media: Media::from_buf(buf),
-            mode: buf[15],
-            status_type: StatusType::from_code(buf[18]),
-            phase: Phase::from_buf(buf),
-            notification: Notification::from_code(buf[22]),
-            id: buf[14],
+        // A page over 1 m is printed in two segments without a cut between
+        let max = Model::QL800.max_raster_lines() as usize;
+        let rows = max + 101;
+        let page = vec![vec![0x0F; 90]; rows];
+        printer.print(vec![page.clone()].into_iter()).unwrap();
+
+        let printed = emulator.pages();
+        assert_eq!(printed.len(), 2);
+        assert_eq!(printed[0].black.len(), rows / 2);
+        assert_eq!(printed[1].black.len(), rows / 2);
+        assert!(!printed[0].ejected && printed[1].ejected);
+
+        let received = emulator.received();
+        let modes: Vec<_> = (0..received.len())
+            .filter(|&i| received[i..].starts_with(&[0x1B, 0x69, 0x4D]))
+            .map(|i| received[i + 3])
+            .collect();
+        assert_eq!(modes, vec![0x40, 0x00, 0x40]);
+        assert_eq!(printer.statistics().labels(), 1);
+    }
+
+    #[test]
+    fn test_segmented_page_without_short_tail() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let emulator = crate::emulator::Emulator::new(Model::QL800, Some(media));
+        let config = Config::new(Model::QL800, "emulator".to_string(), media);
+        let printer = Printer::with_transport(config, emulator.clone()).unwrap();
+
+        // One row over the limit is not left on a segment of its own
+        let max = Model::QL800.max_raster_lines() as usize;
+        let rows = 2 * max + 1;
+        let page = vec![vec![0x0F; 90]; rows];
+        printer.print(vec![page].into_iter()).unwrap();
+
+        let lengths: Vec<_> = emulator.pages().iter().map(|p| p.black.len()).collect();
+        assert_eq!(lengths, vec![rows / 3 + 1, rows / 3, rows / 3]);
+        let min = MIN_CONTINUOUS_LENGTH_DOTS as usize;
+        assert!(lengths.iter().all(|&n| n >= min));
+    }
+
+    #[test]
+    fn test_stop_segmented_page() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let emulator = crate::emulator::Emulator::new(Model::QL800, Some(media));
+        let config = Config::new(Model::QL800, "emulator".to_string(), media);
+        let printer = Printer::with_transport(config, emulator.clone()).unwrap();
+
+        // Stopped on the first segment, the page is finished first
+        let max = Model::QL800.max_raster_lines() as usize;
+        let pages = vec![vec![vec![0x0F; 90]; max + 200], vec![vec![0x0F; 90]; 100]];
+        let mut events = 0;
+        printer
+            .print_with_progress(pages.into_iter(), |_| {
+                events += 1;
+                match events {
+                    1 => ControlFlow::Break(()),
+                    _ => ControlFlow::Continue(()),
+                }
+            })
+            .unwrap();
+        assert_eq!(emulator.pages().len(), 2);
+        assert_eq!(printer.statistics().labels(), 1);
+    }
+
+    #[test]
+    fn test_apply_power_settings() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let reference = Config::new(Model::QL800, "emulator".to_string(), media);
+        let emulator = crate::emulator::Emulator::new(Model::QL800, Some(media));
+        let mut printer = Printer::with_transport(reference.clone(), emulator.clone()).unwrap();
+        assert_eq!(printer.dump_settings(), reference.settings());
+
+        // Power settings are sent to the printer and dumped again
+        let settings = Settings {
+            auto_power_off: Some(20),
+            auto_power_on: Some(true),
+            ..reference.enable_auto_cut(2).settings()
+        };
+        printer.apply_settings(&settings).unwrap();
+        assert_eq!(emulator.power_settings(), (20, true));
+        assert_eq!(printer.dump_settings(), settings);
+
+        let invalid = Settings {
+            auto_power_off: Some(25),
+            ..settings.clone()
+        };
+        assert!(printer.apply_settings(&invalid).is_err());
+        assert_eq!(printer.dump_settings(), settings);
+    }
+
+    #[test]
+    fn test_test_page_firmware_version() {
+        // The firmware version adds a line when the transport reports it
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let length = |version| {
+            diagnostic::test_page(Model::QL800, "A1", version, media, Resolution::Normal)
+                .size_mm()
+                .1
+        };
+        assert!(length(Some("1.05")) > length(None));
+    }
+
+    #[test]
+    fn test_reset_media_usage() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let path = std::env::temp_dir().join(format!("ql-label-{}.stats", std::process::id()));
+        let config =
+            Config::new(Model::QL800, "emulator".to_string(), media).statistics_file(&path);
+        let emulator = crate::emulator::Emulator::new(Model::QL800, Some(media));
+        let printer = Printer::with_transport(config, emulator).unwrap();
+        printer
+            .print(vec![vec![vec![0xFF; 90]; 200]].into_iter())
+            .unwrap();
+
+        // The reset usage is saved right away, not with the next job
+        printer.reset_media_usage(media).unwrap();
+        let saved = Statistics::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved.media_usage(media).labels, 0);
+        assert_eq!(saved.labels(), 1);
+    }
+
+    #[test]
+    fn test_save_job_keeps_file_on_error() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let config = Config::new_any(Model::QL800, media);
+        let path = std::env::temp_dir().join(format!("ql-label-{}.prn", std::process::id()));
+        config
+            .save_job(vec![vec![vec![0xFF; 90]; 300]].into_iter(), &path)
+            .unwrap();
+
+        let mut page = vec![vec![0xFF; 90]; 300];
+        page[10] = vec![0xFF; 10];
+        let result = config.save_job(vec![page].into_iter(), &path);
+        assert!(matches!(result, Err(Error::InvalidRaster { row: 10, .. })));
+        assert!(!path.with_extension("tmp").exists());
+        let job = protocol::decode(&std::fs::read(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(job.pages[0].len(), 300);
+    }
+
+    #[test]
+    fn test_encode_job_segments() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let config = Config::new(Model::QL820NWB, "serial".to_string(), media);
+
+        // Saved jobs are split like printed ones
+        let page = vec![vec![0x0F; 90]; 12000];
+        let job = config.encode_job(vec![page].into_iter()).unwrap();
+        let pages = protocol::decode(&job).unwrap().pages;
+        let rows: Vec<_> = pages.iter().map(|page| page.len()).collect();
+        assert_eq!(rows, vec![6000, 6000]);
+
+        // And follow the blank page policy
+        let label = vec![vec![0xFF; 90]; 100];
+        let blank = vec![vec![0x00; 90]; 100];
+        let pages = vec![label.clone(), blank, label];
+        let config = config.blank_pages(BlankPages::Skip);
+        let job = config.encode_job(pages.clone().into_iter()).unwrap();
+        assert_eq!(protocol::decode(&job).unwrap().pages.len(), 2);
+        let config = config.blank_pages(BlankPages::Fail);
+        let result = config.encode_job(pages.into_iter());
+        assert!(matches!(result, Err(Error::BlankPage { page: 1 })));
+    }
+
+    #[test]
+    fn test_new_document() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let emulator = crate::emulator::Emulator::new(Model::QL800, Some(media));
+        let config = Config::new(Model::QL800, "emulator".to_string(), media);
+        let printer = Printer::with_transport(config, emulator.clone()).unwrap();
+
+        let label = vec![vec![0xFF; 90]; 200];
+        let pages = vec![
+            Page::new(label.clone()),
+            Page::new(label.clone()),
+            Page::new(label.clone()).new_document(),
+            Page::new(label),
+        ];
+        printer.print(pages.into_iter()).unwrap();
+
+        // n9 of each print information, 0 for a starting page
+        let received = emulator.received();
+        let flags: Vec<_> = (0..received.len())
+            .filter(|&i| received[i..].starts_with(&[0x1B, 0x69, 0x7A]))
+            .map(|i| received[i + 11])
+            .collect();
+        assert_eq!(flags, vec![0x00, 0x01, 0x00, 0x01]);
+        let starting: Vec<_> = emulator
+            .pages()
+            .iter()
+            .map(|page| page.starting_page)
+            .collect();
+        assert_eq!(starting, vec![true, false, true, false]);
+    }
+
+    #[test]
+    fn test_new_document_buffered() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let label = vec![vec![0xFF; 90]; 200];
+        let pages = || {
+            vec![
+                Page::new(label.clone()),
+                Page::new(label.clone()).new_document(),
+            ]
+            .into_iter()
+        };
+
+        // Pages buffered for trimming or blank page detection still start
+        // their document
+        let configs = [
+            Config::new_any(Model::QL800, media).enable_auto_trim(10),
+            Config::new_any(Model::QL800, media).blank_pages(BlankPages::Skip),
+            Config::new_any(Model::QL800, media).blank_pages(BlankPages::Fail),
+        ];
+        for config in configs {
+            let emulator = crate::emulator::Emulator::new(Model::QL800, Some(media));
+            let printer = Printer::with_transport(config, emulator.clone()).unwrap();
+            printer.print(pages()).unwrap();
+
+            let received = emulator.received();
+            let flags: Vec<_> = (0..received.len())
+                .filter(|&i| received[i..].starts_with(&[0x1B, 0x69, 0x7A]))
+                .map(|i| received[i + 11])
+                .collect();
+            assert_eq!(flags, vec![0x00, 0x00]);
+        }
+    }
+
+    // Emulator that is unplugged until reconnected
+    struct Unplugged {
+        emulator: crate::emulator::Emulator,
+        reconnects: Arc<AtomicU64>,
+    }
+
+    impl Transport for Unplugged {
+        fn write(&self, buf: &[u8], timeout: Duration) -> Result<usize, Error> {
+            if self.reconnects.load(Ordering::Relaxed) == 0 {
+                return Err(Error::DeviceOffline);
+            }
+            self.emulator.write(buf, timeout)
+        }
+
+        fn read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, Error> {
+            self.emulator.read(buf, timeout)
+        }
+
+        fn reconnect(&self) -> Result<(), Error> {
+            self.reconnects.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    /// Emulator reporting a full buffer after the first chunk of a page.
+    struct Congested {
+        emulator: crate::emulator::Emulator,
+        writes: AtomicU64,
+        replies: Mutex<std::collections::VecDeque<[u8; 32]>>,
+    }
+
+    impl Transport for Congested {
+        fn write(&self, buf: &[u8], timeout: Duration) -> Result<usize, Error> {
+            // The job commands, then the first chunk of the page
+            if self.writes.fetch_add(1, Ordering::Relaxed) == 1 {
+                let mut status = [0x00; 32];
+                status[..5].copy_from_slice(&[0x80, 0x20, 0x42, 0x34, 0x38]);
+                status[9] = 0x02;
+                status[18] = 0x02;
+                let mut cleared = status;
+                cleared[9] = 0x00;
+                cleared[18] = 0x06;
+                self.replies.lock().unwrap().extend([status, cleared]);
+            }
+            self.emulator.write(buf, timeout)
+        }
+
+        fn read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, Error> {
+            match self.replies.lock().unwrap().pop_front() {
+                Some(status) => {
+                    buf.copy_from_slice(&status);
+                    Ok(32)
+                }
+                None => self.emulator.read(buf, timeout),
+            }
+        }
+    }
+
+    #[test]
+    fn test_buffer_full() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let emulator = crate::emulator::Emulator::new(Model::QL800, Some(media));
+        let transport = Congested {
+            emulator: emulator.clone(),
+            writes: AtomicU64::new(0),
+            replies: Mutex::new(Default::default()),
+        };
+        let config = Config::new(Model::QL800, "emulator".to_string(), media).chunk_size(1024);
+        let printer = Printer::with_transport(config, transport).unwrap();
+
+        // The page continues once the printer has room again
+        printer
+            .print(vec![vec![vec![0xAA; 90]; 100]].into_iter())
+            .unwrap();
+        assert_eq!(emulator.pages()[0].black.len(), 100);
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let emulator = crate::emulator::Emulator::new(Model::QL800, Some(media));
+        let reconnects = Arc::new(AtomicU64::new(0));
+        let transport = Unplugged {
+            emulator: emulator.clone(),
+            reconnects: reconnects.clone(),
+        };
+        let config = Config::new(Model::QL800, "emulator".to_string(), media)
+            .reconnect(3, Duration::from_millis(1));
+        let printer = Printer::with_transport(config, transport).unwrap();
+
+        // The status request fails, the job starts again once reconnected
+        printer
+            .print(std::iter::once(vec![vec![0xFF; 90]; 100]))
+            .unwrap();
+        assert_eq!(reconnects.load(Ordering::Relaxed), 1);
+        assert_eq!(emulator.pages().len(), 1);
+    }
+
+    #[test]
+    fn test_cancel_while_sending() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let emulator = crate::emulator::Emulator::new(Model::QL800, Some(media));
+        let config = Config::new(Model::QL800, "emulator".to_string(), media);
+        let printer = Printer::with_transport(config, emulator.clone()).unwrap();
+
+        // Cancelled while the page is produced, it takes several chunks
+        let cancel = CancelToken::new();
+        let page = std::iter::once_with(|| {
+            cancel.cancel();
+            vec![vec![0xFF; 90]; 1000]
+        });
+        let result = printer.print_with_cancel(page, &cancel);
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert!(emulator.pages().is_empty());
+        assert!(emulator.received().ends_with(&protocol::initialize()));
+    }
+
+    #[test]
+    fn test_cancel_after_last_page() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let emulator = crate::emulator::Emulator::new(Model::QL800, Some(media));
+        let config = Config::new(Model::QL800, "emulator".to_string(), media);
+        let printer = Printer::with_transport(config, emulator.clone()).unwrap();
+
+        // Cancelled once the last page is on its way, the job still completes
+        let cancel = CancelToken::new();
+        let pages = (0..2).map(|n| vec![vec![0xFF; 90]; 100 + n]);
+        let pages = pages.chain(std::iter::from_fn(|| {
+            cancel.cancel();
+            None
+        }));
+        printer.print_with_cancel(pages, &cancel).unwrap();
+        assert_eq!(emulator.pages().len(), 2);
+        assert!(emulator.pages()[1].ejected);
+    }
+
+    #[test]
+    fn test_page_progress() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let emulator = crate::emulator::Emulator::new(Model::QL800, Some(media));
+        let config = Config::new(Model::QL800, "emulator".to_string(), media);
+        let printer = Printer::with_transport(config, emulator.clone()).unwrap();
+
+        let pages = (1..=3).map(|n| vec![vec![0xFF; 90]; 100 * n]);
+        let mut events = Vec::new();
+        printer
+            .print_with_progress(pages, |event| {
+                events.push(event);
+                match event {
+                    // Stops once the page is printed
+                    PrintEvent::PageSent { page: 1, .. } => ControlFlow::Break(()),
+                    _ => ControlFlow::Continue(()),
+                }
+            })
+            .unwrap();
+
+        let sent: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                PrintEvent::PageSent { page, rows } => Some((*page, *rows)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(sent, vec![(0, 100), (1, 200)]);
+        assert!(matches!(
+            events.last(),
+            Some(PrintEvent::PagePrinted { page: 1, .. })
+        ));
+        assert_eq!(emulator.pages().len(), 2);
+    }
+
+    #[test]
+    fn test_wide_printer() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let emulator = crate::emulator::Emulator::new(Model::QL1100, Some(media));
+        let config = Config::new(Model::QL1100, "emulator".to_string(), media).compress(true);
+        let printer = Printer::with_transport(config, emulator.clone()).unwrap();
+
+        printer
+            .print(std::iter::once(vec![vec![0xFF; 162]; 100]))
+            .unwrap();
+        // Rows for normal-width media are padded on the left of the head
+        printer
+            .print(std::iter::once(vec![vec![0xFF; 90]; 100]))
+            .unwrap();
+        let pages = emulator.pages();
+        assert_eq!(pages[0].black[0], vec![0xFF; 162]);
+        assert_eq!(pages[1].black[0][..90], [0xFF; 90]);
+        assert_eq!(pages[1].black[0][90..], [0x00; 72]);
+
+        let mut page = vec![vec![0xFF; 162]; 100];
+        page[5] = vec![0xFF; 100];
+        let result = printer.print(std::iter::once(page));
+        assert!(matches!(
+            result,
+            Err(Error::InvalidRaster {
+                row: 5,
+                expected: 162,
+                actual: 100
+            })
+        ));
+        assert_eq!(emulator.pages().len(), 2);
+    }
+
+    #[cfg(feature = "usb")]
+    #[test]
+    fn test_driver_claimed() {
+        assert!(claimed_on(&rusb::Error::NotSupported, true));
+        // A device unplugged while opening is not claimed by a driver
+        assert!(!claimed_on(&rusb::Error::NotFound, true));
+        assert!(!claimed_on(&rusb::Error::Access, true));
+        assert!(!claimed_on(&rusb::Error::NotSupported, false));
+    }
+
+    #[test]
+    fn test_wide_media() {
+        let media = Media::DieCut(DieCutType::DieCut102x152);
+        match Config::builder(Model::QL800, media).build() {
+            Err(Error::ConfigConflicts(conflicts)) => assert_eq!(
+                conflicts,
+                [ConfigConflict::WideMedia {
+                    model: Model::QL800,
+                    media
+                }]
+            ),
+            result => panic!("expected conflicts, got {:?}", result),
+        }
+
+        let emulator = crate::emulator::Emulator::new(Model::QL1100, Some(media));
+        let config = Config::builder(Model::QL1100, media)
+            .serial("emulator")
+            .build()
+            .unwrap();
+        let printer = Printer::with_transport(config, emulator.clone()).unwrap();
+        let mut canvas = crate::Canvas::new(media, crate::Resolution::Normal);
+        canvas.fill_rect(0.0, 0.0, 10.0, 10.0);
+        printer.print(std::iter::once(canvas.to_matrix())).unwrap();
+        let pages = emulator.pages();
+        assert_eq!(pages[0].black.len(), 1809);
+        assert!(pages[0].black.iter().all(|row| row.len() == 162));
+    }
+
+    #[test]
+    fn test_die_cut_raster_count() {
+        let media = Media::DieCut(DieCutType::DieCut29x90);
+        let emulator = crate::emulator::Emulator::new(Model::QL820NWB, Some(media));
+        let config = Config::new(Model::QL820NWB, "emulator".to_string(), media);
+        let printer = Printer::with_transport(config, emulator.clone()).unwrap();
+
+        printer
+            .print(std::iter::once(vec![vec![0xFF; 90]; 1061]))
+            .unwrap();
+        let result = printer.print(std::iter::once(vec![vec![0xFF; 90]; 1062]));
+        assert!(matches!(
+            result,
+            Err(Error::InvalidRasterCount {
+                count: 1062,
+                max: 1061
+            })
+        ));
+        assert_eq!(emulator.pages().len(), 1);
+    }
+
+    #[test]
+    fn test_red_offset() {
+        let media = Media::Continuous(ContinuousType::Continuous62Red);
+        let emulator = crate::emulator::Emulator::new(Model::QL820NWB, Some(media));
+        let config = Config::new(Model::QL820NWB, "emulator".to_string(), media)
+            .two_colors(true)
+            .red_offset_mm(0.0, 1.0);
+        let mut printer = Printer::with_transport(config, emulator.clone()).unwrap();
+
+        printer.print_two_color_calibration_page().unwrap();
+        let page = &emulator.pages()[0];
+        assert!(page.black.iter().any(|row| row.iter().any(|&b| b != 0)));
+        assert!(page.red.iter().any(|row| row.iter().any(|&b| b != 0)));
+
+        // Only the red rows move, 1 mm is 12 rows
+        let mut red = vec![vec![0x00; 90]; 200];
+        red[0] = vec![0xFF; 90];
+        let black = red.clone();
+        let two_color = TwoColorMatrix::new(black, red).unwrap();
+        printer.print_two_color(std::iter::once(two_color)).unwrap();
+        let page = &emulator.pages()[1];
+        assert_eq!(page.black[0], vec![0xFF; 90]);
+        assert_eq!(page.red[12], vec![0xFF; 90]);
+        assert_eq!(page.red.len(), 200);
+
+        printer.set_red_offset_mm(0.0, 0.0);
+        assert_eq!(printer.red_offset_mm(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_die_cut_offset() {
+        let media = Media::DieCut(DieCutType::DieCut29x90);
+        let emulator = crate::emulator::Emulator::new(Model::QL800, Some(media));
+        let config = Config::new(Model::QL800, "emulator".to_string(), media);
+        let mut printer = Printer::with_transport(config, emulator.clone()).unwrap();
+
+        let mut page = vec![vec![0x00; 90]; 1061];
+        page[0] = vec![0xFF; 90];
+        printer.print(vec![page.clone()].into_iter()).unwrap();
+
+        // 0.5 mm later than the nominal 3 mm offset is 6 rows
+        printer.set_die_cut_offset_mm(3.5);
+        assert_eq!(printer.die_cut_offset_mm(), Some(3.5));
+        printer.print(vec![page].into_iter()).unwrap();
+
+        let printed = emulator.pages();
+        assert_eq!(printed[0].black.len(), 1061);
+        assert_eq!(printed[1].black.len(), 1061);
+        assert_eq!(printed[0].black[0], vec![0xFF; 90]);
+        assert_eq!(printed[1].black[6], vec![0xFF; 90]);
+        assert!(printed[1].black[..6]
+            .iter()
+            .all(|row| row.iter().all(|&b| b == 0)));
+    }
+
+    // Page whose content hash collides with every other
+    struct Colliding(Matrix);
+
+    impl RasterSource for Colliding {
+        type Rows = std::vec::IntoIter<Vec<u8>>;
+
+        fn raster_count(&self) -> u32 {
+            self.0.len() as u32
+        }
+
+        fn into_rows(self) -> Self::Rows {
+            self.0.into_iter()
+        }
+
+        fn content_hash(&self) -> Option<u64> {
+            Some(0)
+        }
+    }
+
+    #[test]
+    fn test_encoding_cache_collision() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let emulator = crate::emulator::Emulator::new(Model::QL800, Some(media));
+        let config = Config::new(Model::QL800, "emulator".to_string(), media).encoding_cache(4);
+        let printer = Printer::with_transport(config, emulator.clone()).unwrap();
+
+        // Pages with the same hash are not mistaken for each other
+        let a = vec![vec![0xFF; 90]; 100];
+        let b = vec![vec![0x0F; 90]; 100];
+        for page in [&a, &b, &a] {
+            printer
+                .print(std::iter::once(Colliding(page.clone())))
+                .unwrap();
         }
+        let printed: Vec<_> = emulator
+            .pages()
+            .into_iter()
+            .map(|page| page.black)
+            .collect();
+        assert_eq!(printed, vec![a.clone(), b, a]);
+
+        // Nor are their rows left unchecked
+        let short = vec![vec![0xFF; 10]; 100];
+        let result = printer.print(std::iter::once(Colliding(short)));
+        assert!(matches!(result, Err(Error::InvalidRaster { .. })));
     }
+}
 
+impl Status {
     pub fn check_media(self, expected_media: Media) -> Result<(), Error> {
         match self.media {
             Some(actual_media) => {
@@ -872,80 +3054,222 @@ impl Status {
     }
 }
 
-// StatusType
-
-#[derive(Debug, PartialEq, Clone, Copy)]
-enum StatusType {
-    ReplyToRequest,
-    Completed,
-    Error,
-    Offline,
-    Notification,
-    PhaseChange,
-    Unknown,
+fn is_end_of_media(error: &PrinterError) -> bool {
+    matches!(error, PrinterError::EndOfMedia | PrinterError::NoMedia)
 }
 
-impl StatusType {
-    fn from_code(code: u8) -> StatusType {
-        match code {
-            0x00 => Self::ReplyToRequest,
-            0x01 => Self::Completed,
-            0x02 => Self::Error,
-            0x04 => Self::Offline,
-            0x05 => Self::Notification,
-            0x06 => Self::PhaseChange,
-            _ => Self::Unknown,
-        }
-    }
+/// Whether libusb failed because another driver than WinUSB owns the device.
+///
+/// libusb reports the Windows printer class driver (usbprint.sys) as not
+/// supported; on other systems it detaches kernel drivers itself.
+#[cfg(feature = "usb")]
+fn driver_claimed(err: &rusb::Error) -> bool {
+    claimed_on(err, cfg!(windows))
 }
-// Phase
-
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub enum Phase {
-    Receiving,
-    Printing,
-    Waiting(u16),
-    // Printing(u16),
+
+/// `driver_claimed()` on Windows or, with `windows` false, elsewhere.
+#[cfg(feature = "usb")]
+fn claimed_on(err: &rusb::Error, windows: bool) -> bool {
+    windows && matches!(err, rusb::Error::NotSupported)
 }
 
-impl Phase {
-    fn from_buf(buf: [u8; 32]) -> Self {
-        match buf[19] {
-            0x00 => Self::Receiving,
-            0x01 => Self::Printing,
-            _ => Self::Waiting(0),
-        }
+/// Span of opening the printer of `config` through `transport`.
+#[cfg(feature = "tracing")]
+fn open_span(config: &Config, transport: &'static str) -> tracing::Span {
+    tracing::info_span!(
+        "open",
+        transport,
+        model = ?config.model,
+        serial = %config.serial,
+        media = ?config.media,
+    )
+}
+
+/// A Brother USB device found by `Printer::enumerate()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredPrinter {
+    /// Model with this USB product id, `None` for other Brother devices.
+    pub model: Option<Model>,
+    /// Serial number, `None` when the device cannot be opened.
+    pub serial: Option<String>,
+    /// USB product id.
+    pub product_id: u16,
+    /// Number of the USB bus the device is attached to.
+    pub bus: u8,
+    /// Address of the device on its bus.
+    pub address: u8,
+}
+
+impl DiscoveredPrinter {
+    /// Configuration connecting to this printer with `media` installed,
+    /// `None` when its model or serial number is unknown.
+    pub fn config(&self, media: Media) -> Option<Config> {
+        Some(Config::new(self.model?, self.serial.clone()?, media))
     }
 }
 
-// Notification
+/// Identification of a printer, see `Printer::device_info()`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DeviceInfo {
+    /// Model reported in the status of the printer.
+    pub model: Option<Model>,
+    /// USB vendor id.
+    pub vendor_id: Option<u16>,
+    /// USB product id.
+    pub product_id: Option<u16>,
+    /// Manufacturer string of the USB device, e.g. "Brother".
+    pub manufacturer: Option<String>,
+    /// Product string of the USB device, e.g. "QL-820NWB".
+    pub product: Option<String>,
+    /// Serial number string of the USB device.
+    pub serial: Option<String>,
+    /// Firmware version from the device release number, e.g. "1.05".
+    pub firmware_version: Option<String>,
+}
+
+/// State of the installed roll, see `Printer::media_state()`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MediaState {
+    /// Enough tape is left, or no warning is configured.
+    Ok,
+    /// Less tape than the configured threshold is left.
+    Low { remaining_mm: f64 },
+    /// The printer reported the end of the roll.
+    Empty,
+}
+
+/// What to do with pages without any black dots, see
+/// `Config::blank_pages()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BlankPages {
+    /// Feed and cut blank labels like any other page.
+    #[default]
+    Print,
+    /// Leave blank pages out of the job.
+    Skip,
+    /// Stop the job before the first blank page with `Error::BlankPage`.
+    Fail,
+}
+
+/// Progress of a print job, see `Printer::print_with_progress()`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrintEvent {
+    /// A label has been printed. `page` counts from 0.
+    PagePrinted { page: usize, length_mm: f64 },
+    /// Sent after a printed label while the roll is low.
+    MediaLow { remaining_mm: f64 },
+    /// The roll ran out, the job fails with `PrinterError::EndOfMedia`.
+    EndOfMedia,
+    /// Sent before the first label when `expected` media is not installed
+    /// and `Config::wait_for_media_change()` is set. The printer waits for
+    /// the operator to install it, returning `ControlFlow::Break(())` stops
+    /// the job instead.
+    MediaChangeRequested {
+        expected: Media,
+        installed: Option<Media>,
+    },
+    /// The requested media was installed, the job proceeds.
+    MediaChanged,
+    /// `rows` of the `total` rows of a banner were sent, see
+    /// `Printer::print_banner()`.
+    RowsSent { rows: u32, total: u32 },
+    /// The `rows` raster lines of a page were sent and are being printed,
+    /// `PagePrinted` follows once the printer has finished. Pages longer
+    /// than `Config::max_page_rows()` report every segment.
+    PageSent { page: usize, rows: u32 },
+}
+
+/// Handle to cancel a print job from another thread, see
+/// `Printer::print_with_cancel()`.
+///
+/// Clones share the same state. A token stays cancelled, use a new one for
+/// the next job.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the job printed with this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether `cancel()` has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
 
-#[derive(Debug)]
-enum Notification {
-    NotAvailable,
-    CoolingStarted,
-    CoolingFinished,
+/// Status message received from the printer, see
+/// `Printer::subscribe_events()`.
+#[derive(Debug, Clone)]
+pub enum StatusEvent {
+    /// Reply to a status request, e.g. before every job.
+    Reply(Status),
+    /// A page has been printed.
+    PageCompleted(Status),
+    /// The printer switched between receiving and printing, see
+    /// `Status::phase()`.
+    PhaseChanged(Status),
+    /// The print head overheated, printing pauses.
+    CoolingStarted(Status),
+    /// The print head cooled down, printing resumes.
+    CoolingFinished(Status),
+    /// The printer reported an error, see `Status::error()`.
+    Error(Status),
+    /// Any other message, e.g. the printer turned off.
+    Other(Status),
 }
 
-impl Notification {
-    fn from_code(code: u8) -> Self {
-        match code {
-            0x03 => Self::CoolingStarted,
-            0x04 => Self::CoolingFinished,
-            _ => Self::NotAvailable,
+impl StatusEvent {
+    /// Classify a status message by its type and notification.
+    pub fn from_status(status: Status) -> Self {
+        if !status.errors.is_empty() {
+            return StatusEvent::Error(status);
+        }
+        match (status.status_type, status.notification) {
+            (StatusType::ReplyToRequest, _) => StatusEvent::Reply(status),
+            (StatusType::Completed, _) => StatusEvent::PageCompleted(status),
+            (StatusType::PhaseChange, _) => StatusEvent::PhaseChanged(status),
+            (StatusType::Notification, Notification::CoolingStarted) => {
+                StatusEvent::CoolingStarted(status)
+            }
+            (StatusType::Notification, Notification::CoolingFinished) => {
+                StatusEvent::CoolingFinished(status)
+            }
+            (StatusType::Error, _) => StatusEvent::Error(status),
+            _ => StatusEvent::Other(status),
+        }
+    }
+
+    /// The status message of the event.
+    pub fn status(&self) -> &Status {
+        match self {
+            StatusEvent::Reply(status)
+            | StatusEvent::PageCompleted(status)
+            | StatusEvent::PhaseChanged(status)
+            | StatusEvent::CoolingStarted(status)
+            | StatusEvent::CoolingFinished(status)
+            | StatusEvent::Error(status)
+            | StatusEvent::Other(status) => status,
         }
     }
 }
 
 /// Config
 ///
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum AutoCut {
     Enabled(u8),
     Disabled,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Config {
     model: Model,
     serial: String,
@@ -956,6 +3280,22 @@ pub struct Config {
     high_resolution: bool,
     feed: u16,
     compress: bool,
+    skip_invalidate: bool,
+    auto_trim: Option<u32>,
+    blank_pages: BlankPages,
+    encoding_cache: usize,
+    chunk_size: usize,
+    statistics_file: Option<PathBuf>,
+    trailing_feed: Option<f32>,
+    lock_dir: Option<PathBuf>,
+    low_media: Option<(f64, f64)>,
+    media_change_timeout: Option<Duration>,
+    reconnect: Option<(u32, Duration)>,
+    detect_media: bool,
+    offset: (f32, f32),
+    red_offset: (f32, f32),
+    die_cut_offset: Option<f32>,
+    extra_expanded_mode: ExpandedMode,
 }
 
 impl Config {
@@ -966,7 +3306,7 @@ impl Config {
     /// # Example
     ///
     /// ```rust,no_run
-    /// use ptouch::{Config, ContinuousType, Media, Model};
+    /// use ql_label::{Config, ContinuousType, Media, Model};
     /// 
     /// let media = Media::Continuous(ContinuousType::Continuous29);
     /// let model = Model::QL800;
@@ -984,17 +3324,122 @@ impl Config {
             high_resolution: false,
             feed: media.get_default_feed_dots(),
             compress: false,
+            skip_invalidate: false,
+            auto_trim: None,
+            blank_pages: BlankPages::Print,
+            encoding_cache: 0,
+            chunk_size: WRITE_CHUNK_SIZE,
+            statistics_file: None,
+            trailing_feed: None,
+            lock_dir: None,
+            low_media: None,
+            media_change_timeout: None,
+            reconnect: None,
+            detect_media: false,
+            offset: (0.0, 0.0),
+            red_offset: (0.0, 0.0),
+            die_cut_offset: None,
+            extra_expanded_mode: ExpandedMode::empty(),
         }
     }
 
-    /// Enable auto cut after printing specified number of labels.
+    /// Configuration connecting to the first printer of `model` found,
+    /// for setups with a single printer.
     ///
-    /// # Arguments
+    /// The serial number is resolved by `Printer::new()`, see
+    /// `Printer::serial()`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use ql_label::{Config, ContinuousType, Media, Model, Printer};
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let printer = Printer::new(Config::new_any(Model::QL800, media))?;
+    /// println!("Connected to {}", printer.serial());
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn new_any(model: Model, media: Media) -> Config {
+        Config::new(model, String::new(), media)
+    }
+
+    /// Preset for shipping labels on DK-11202 (62 x 100 mm) die-cut labels.
+    ///
+    /// Every label is cut. For 4 x 6 inch labels on the wide QL-1100
+    /// series see `shipping_label_4x6()`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, Model};
+    /// let config = Config::shipping_label_62x100(Model::QL820NWB, "serial".to_string());
+    /// assert_eq!(config.settings().auto_cut, Some(1));
+    /// ```
+    pub fn shipping_label_62x100(model: Model, serial: String) -> Config {
+        Config::new(model, serial, Media::DieCut(DieCutType::DieCut62x100)).enable_auto_cut(1)
+    }
+
+    /// Preset for 4 x 6 inch shipping labels on DK-11241 (102 x 152 mm)
+    /// die-cut labels, for the wide QL-1100 series.
+    ///
+    /// Every label is cut. Normal-width models cannot print the labels, see
+    /// `Config::builder()`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, DieCutType, Media, Model};
+    /// let config = Config::shipping_label_4x6(Model::QL1100, "serial".to_string());
+    /// assert_eq!(config.media(), Media::DieCut(DieCutType::DieCut102x152));
+    /// assert_eq!(config.settings().auto_cut, Some(1));
+    /// assert!(config.validate().is_ok());
+    /// assert!(Config::shipping_label_4x6(Model::QL800, "serial".to_string())
+    ///     .validate()
+    ///     .is_err());
+    /// ```
+    pub fn shipping_label_4x6(model: Model, serial: String) -> Config {
+        Config::new(model, serial, Media::DieCut(DieCutType::DieCut102x152)).enable_auto_cut(1)
+    }
+
+    /// Preset for address labels on DK-11201 (29 x 90 mm) die-cut labels.
+    ///
+    /// Labels of a job stay on one strip that is cut after the last label,
+    /// which is convenient for mailing lists.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, Model};
+    /// let config = Config::address_label(Model::QL800, "serial".to_string());
+    /// assert_eq!(config.settings().auto_cut, None);
+    /// assert!(config.settings().cut_at_end);
+    /// ```
+    pub fn address_label(model: Model, serial: String) -> Config {
+        Config::new(model, serial, Media::DieCut(DieCutType::DieCut29x90))
+            .disable_auto_cut()
+            .cut_at_end(true)
+    }
+
+    /// Preset for name badges on DK-11234 (60 x 86 mm) die-cut labels.
+    ///
+    /// Printed in high resolution for crisp names, every badge is cut.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, Model, Resolution};
+    /// let config = Config::name_badge(Model::QL820NWB, "serial".to_string());
+    /// assert_eq!(config.resolution(), Resolution::High);
+    /// ```
+    pub fn name_badge(model: Model, serial: String) -> Config {
+        Config::new(model, serial, Media::DieCut(DieCutType::DieCut60x86))
+            .enable_auto_cut(1)
+            .high_resolution(true)
+    }
+
+    /// Enable auto cut after printing specified number of labels.
+    ///
+    /// # Arguments
     /// * `size` - Number of labels to print before auto-cutting (1-255)
     ///
     /// # Example
     /// ```rust,no_run
-    /// # use ptouch::{Config, Model, Media, ContinuousType};
+    /// # use ql_label::{Config, Model, Media, ContinuousType};
     /// let config = Config::new(Model::QL820NWB, "serial".to_string(), 
     ///                         Media::Continuous(ContinuousType::Continuous62))
     ///     .enable_auto_cut(3); // Cut after every 3 labels
@@ -1012,7 +3457,7 @@ impl Config {
     ///
     /// # Example
     /// ```rust,no_run
-    /// # use ptouch::{Config, Model, Media, ContinuousType};
+    /// # use ql_label::{Config, Model, Media, ContinuousType};
     /// let config = Config::new(Model::QL820NWB, "serial".to_string(), 
     ///                         Media::Continuous(ContinuousType::Continuous62))
     ///     .disable_auto_cut();
@@ -1031,7 +3476,7 @@ impl Config {
     ///
     /// # Example
     /// ```rust,no_run
-    /// # use ptouch::{Config, Model, Media, ContinuousType};
+    /// # use ql_label::{Config, Model, Media, ContinuousType};
     /// let config = Config::new(Model::QL820NWB, "serial".to_string(), 
     ///                         Media::Continuous(ContinuousType::Continuous62))
     ///     .cut_at_end(true); // Cut at the end of job
@@ -1053,7 +3498,7 @@ impl Config {
     ///
     /// # Example
     /// ```rust,no_run
-    /// # use ptouch::{Config, Model, Media, ContinuousType};
+    /// # use ql_label::{Config, Model, Media, ContinuousType};
     /// let config = Config::new(Model::QL820NWB, "serial".to_string(), 
     ///                         Media::Continuous(ContinuousType::Continuous62))
     ///     .high_resolution(true); // Enable 600 DPI
@@ -1075,7 +3520,7 @@ impl Config {
     ///
     /// # Example
     /// ```rust,no_run
-    /// # use ptouch::{Config, Model, Media, ContinuousType};
+    /// # use ql_label::{Config, Model, Media, ContinuousType};
     /// let config = Config::new(Model::QL820NWB, "serial".to_string(), 
     ///                         Media::Continuous(ContinuousType::Continuous62))
     ///     .set_feed_in_dots(150); // Set feed to 150 dots
@@ -1094,7 +3539,7 @@ impl Config {
     ///
     /// # Example
     /// ```rust,no_run
-    /// # use ptouch::{Config, Model, Media, ContinuousType};
+    /// # use ql_label::{Config, Model, Media, ContinuousType};
     /// let config = Config::new(Model::QL820NWB, "serial".to_string(), 
     ///                         Media::Continuous(ContinuousType::Continuous62Red))
     ///     .two_colors(true); // Enable red and black printing
@@ -1113,7 +3558,7 @@ impl Config {
     ///
     /// # Example
     /// ```rust,no_run
-    /// # use ptouch::{Config, Model, Media, ContinuousType};
+    /// # use ql_label::{Config, Model, Media, ContinuousType};
     /// let config = Config::new(Model::QL820NWB, "serial".to_string(), 
     ///                         Media::Continuous(ContinuousType::Continuous62))
     ///     .compress(true); // Enable compression
@@ -1125,55 +3570,1483 @@ impl Config {
         }
     }
 
-    fn build(self) -> Result<Vec<u8>, Error> {
-        let mut buf: Vec<u8> = Vec::new();
+    /// Skip the 400 byte invalidate command while the printer is known to be idle.
+    ///
+    /// Every job normally starts and ends with 400 zero bytes to flush any
+    /// half received command. When enabled, they are only sent for the first
+    /// job and after a job failed, which saves time on slow links.
+    /// `Printer::cancel()` always sends them.
+    ///
+    /// # Arguments
+    /// * `flag` - `true` to skip the invalidate command when possible
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{Config, Model, Media, ContinuousType};
+    /// let config = Config::new(Model::QL820NWB, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62))
+    ///     .skip_invalidate(true);
+    /// ```
+    pub fn skip_invalidate(self, flag: bool) -> Self {
+        Config {
+            skip_invalidate: flag,
+            ..self
+        }
+    }
+
+    /// Trim blank rows from the start and end of each label before printing.
+    ///
+    /// Only applies to continuous media, die-cut labels always keep their length.
+    /// Labels shorter than `min_length` rows after trimming are padded with
+    /// blank rows at the end.
+    ///
+    /// # Arguments
+    /// * `min_length` - Minimum label length in rows (dots)
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{Config, Model, Media, ContinuousType};
+    /// let config = Config::new(Model::QL820NWB, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62))
+    ///     .enable_auto_trim(150); // Never shorter than 150 rows
+    /// ```
+    pub fn enable_auto_trim(self, min_length: u32) -> Self {
+        Config {
+            auto_trim: Some(min_length),
+            ..self
+        }
+    }
+
+    /// Disable trimming of blank rows. This is the default.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{Config, Model, Media, ContinuousType};
+    /// let config = Config::new(Model::QL820NWB, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62))
+    ///     .disable_auto_trim();
+    /// ```
+    pub fn disable_auto_trim(self) -> Self {
+        Config {
+            auto_trim: None,
+            ..self
+        }
+    }
+
+    /// Skip pages without any black dots, or fail the job on them, instead
+    /// of feeding and cutting empty labels.
+    ///
+    /// Blank pages usually come from templating mistakes such as a missing
+    /// field. Pages are checked as they are sent, so with `BlankPages::Fail`
+    /// the pages before the blank one are printed and the job ends with
+    /// `Error::BlankPage`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{BlankPages, Config, ContinuousType, Emulator, Error, Media, Model, Printer};
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let emulator = Emulator::new(Model::QL800, Some(media));
+    /// let config = Config::new(Model::QL800, "emulator".to_string(), media)
+    ///     .blank_pages(BlankPages::Fail);
+    /// let printer = Printer::with_transport(config, emulator.clone())?;
+    ///
+    /// let pages = vec![vec![vec![0xFF; 90]; 100], vec![vec![0x00; 90]; 100]];
+    /// let result = printer.print(pages.into_iter());
+    /// assert!(matches!(result, Err(Error::BlankPage { page: 1 })));
+    /// assert_eq!(emulator.pages().len(), 1);
+    /// # Ok::<(), Error>(())
+    /// ```
+    pub fn blank_pages(self, policy: BlankPages) -> Self {
+        Config {
+            blank_pages: policy,
+            ..self
+        }
+    }
+
+    /// Keep the raster commands of the last `pages` distinct pages printed,
+    /// so reprinting one of them skips encoding it again.
+    ///
+    /// Meant for kiosks reprinting the same few labels. Pages are looked up
+    /// by `RasterSource::content_hash()`, which `Matrix` and `Page<Matrix>`
+    /// implement. The cache is cleared when the print offset or settings of
+    /// the printer change. 0, the default, disables it.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, ContinuousType, Emulator, Media, Model, Printer};
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let emulator = Emulator::new(Model::QL820NWB, Some(media));
+    /// let config = Config::new(Model::QL820NWB, "emulator".to_string(), media)
+    ///     .compress(true)
+    ///     .encoding_cache(16);
+    /// let printer = Printer::with_transport(config, emulator.clone())?;
+    ///
+    /// let label = vec![vec![0xAA; 90]; 300];
+    /// printer.print(vec![label.clone()].into_iter())?;
+    /// // Sent from the cache
+    /// printer.print(vec![label.clone()].into_iter())?;
+    /// assert_eq!(emulator.pages()[1].black, label);
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn encoding_cache(self, pages: usize) -> Self {
+        Config {
+            encoding_cache: pages,
+            ..self
+        }
+    }
+
+    /// Write the raster data of a page in bulk transfers of `bytes`, 16 KiB
+    /// by default.
+    ///
+    /// Status messages received between two chunks are checked, so a job
+    /// stops as soon as the printer reports an error. When its buffer is
+    /// full, the next chunk waits up to 5 seconds for the printer to catch
+    /// up. Smaller chunks avoid USB timeouts on long high resolution labels;
+    /// 0 writes every page in a single transfer.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, ContinuousType, Emulator, Media, Model, Printer};
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let emulator = Emulator::new(Model::QL820NWB, Some(media));
+    /// let config = Config::new(Model::QL820NWB, "emulator".to_string(), media)
+    ///     .high_resolution(true)
+    ///     .chunk_size(4096);
+    /// let printer = Printer::with_transport(config, emulator.clone())?;
+    ///
+    /// printer.print(vec![vec![vec![0xAA; 90]; 2000]].into_iter())?;
+    /// assert_eq!(emulator.pages()[0].black.len(), 2000);
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn chunk_size(self, bytes: usize) -> Self {
+        Config {
+            chunk_size: bytes,
+            ..self
+        }
+    }
+
+    /// Eject extra tape after the last page of every job.
+    ///
+    /// Useful for label dispensers that need the cut label pushed out a bit
+    /// further. The tape is fed by printing a blank page without cutting, so
+    /// the shortest feed is the minimum label length plus the feed margins
+    /// (about 19mm with the default margin). Jobs with a shorter feed fail
+    /// with `Error::InvalidConfig` before anything is printed. Only continuous
+    /// media is supported.
+    ///
+    /// # Arguments
+    /// * `mm` - Length of tape to eject in millimetres
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{Config, Model, Media, ContinuousType};
+    /// let config = Config::new(Model::QL820NWB, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62))
+    ///     .trailing_feed_mm(25.0);
+    /// ```
+    pub fn trailing_feed_mm(self, mm: f32) -> Self {
+        Config {
+            trailing_feed: Some(mm),
+            ..self
+        }
+    }
+
+    /// Keep print statistics in a file across runs.
+    ///
+    /// The statistics are loaded when the printer is opened and saved after
+    /// every print job, see `Printer::statistics()`.
+    ///
+    /// # Arguments
+    /// * `path` - File holding the statistics, created if missing
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{Config, Model, Media, ContinuousType};
+    /// let config = Config::new(Model::QL820NWB, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62))
+    ///     .statistics_file("/var/lib/labels/ql820.stats");
+    /// ```
+    pub fn statistics_file<P: Into<PathBuf>>(self, path: P) -> Self {
+        Config {
+            statistics_file: Some(path.into()),
+            ..self
+        }
+    }
+
+    /// Lock the printer against use by other processes.
+    ///
+    /// `Printer::new()` takes an exclusive lock on a file named after the
+    /// serial number in `dir` and fails with `Error::PrinterInUse` and the
+    /// PID of the holder while another process has the printer open. The
+    /// lock is released when the `Printer` is dropped. All processes sharing
+    /// a printer have to use the same directory.
+    ///
+    /// # Arguments
+    /// * `dir` - Existing directory for the lock files, e.g. `/run/lock`
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{Config, Model, Media, ContinuousType, Error, Printer};
+    /// let config = Config::new(Model::QL820NWB, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62))
+    ///     .lock_dir("/run/lock");
+    ///
+    /// match Printer::new(config) {
+    ///     Err(Error::PrinterInUse(pid)) => eprintln!("busy: {:?}", pid),
+    ///     _ => {}
+    /// }
+    /// ```
+    pub fn lock_dir<P: Into<PathBuf>>(self, dir: P) -> Self {
+        Config {
+            lock_dir: Some(dir.into()),
+            ..self
+        }
+    }
+
+    /// Warn before the roll runs out.
+    ///
+    /// The printer only reports the end of the roll once it is reached, so
+    /// the remaining tape is estimated from the statistics of the printer.
+    /// Combine it with `statistics_file()` to keep counting across runs and
+    /// call `Printer::reset_media_usage()` after loading a new roll.
+    ///
+    /// # Arguments
+    /// * `roll_length_mm` - Tape length of a full roll, e.g. 30480 for DK-22205.
+    ///   For die-cut rolls use the label count times the label length
+    /// * `threshold_mm` - `MediaState::Low` is reported below this length
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{Config, Model, Media, ContinuousType};
+    /// let config = Config::new(Model::QL820NWB, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62))
+    ///     .low_media_warning(30_480.0, 1_000.0);
+    /// ```
+    pub fn low_media_warning(self, roll_length_mm: f64, threshold_mm: f64) -> Self {
+        Config {
+            low_media: Some((roll_length_mm, threshold_mm)),
+            ..self
+        }
+    }
+
+    /// Wait for the operator to change the roll instead of failing a job with
+    /// `Error::MediaMismatch` or `Error::NoMediaInstalled`.
+    ///
+    /// `Printer::print_with_progress()` reports `PrintEvent::MediaChangeRequested`
+    /// and polls the printer status until the configured media is installed,
+    /// then reports `PrintEvent::MediaChanged` and prints the job. The job
+    /// fails with the original error if the media is not changed within
+    /// `timeout`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::ops::ControlFlow;
+    /// # use std::time::Duration;
+    /// # use ql_label::{Config, ContinuousType, DieCutType, Emulator, Media, Model, PrintEvent, Printer};
+    /// let media = Media::DieCut(DieCutType::DieCut29x90);
+    /// let emulator = Emulator::new(Model::QL800, Some(Media::Continuous(ContinuousType::Continuous62)));
+    /// let config = Config::new(Model::QL800, "emulator".to_string(), media)
+    ///     .wait_for_media_change(Duration::from_secs(60));
+    /// let printer = Printer::with_transport(config, emulator.clone())?;
+    ///
+    /// let pages = vec![vec![vec![0xFF; 90]; 991]].into_iter();
+    /// printer.print_with_progress(pages, |event| {
+    ///     if let PrintEvent::MediaChangeRequested { expected, .. } = event {
+    ///         println!("Please install {:?}", expected);
+    ///         // The operator swaps the roll
+    ///         emulator.set_media(Some(expected));
+    ///     }
+    ///     ControlFlow::Continue(())
+    /// })?;
+    /// assert_eq!(emulator.pages().len(), 1);
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn wait_for_media_change(self, timeout: Duration) -> Self {
+        Config {
+            media_change_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Open the printer again when a transfer fails with a transient error,
+    /// see `Error::is_transient()`, e.g. after the cable was bumped or the
+    /// printer was power-cycled.
+    ///
+    /// Up to `attempts` tries are made, waiting `backoff` before the first
+    /// one and twice as long before every further one. A job that cannot
+    /// send its first page reconnects and starts again. A job failing half
+    /// way returns its error, the printer is reopened for the next job;
+    /// `JobQueue::set_retry()` resumes such jobs after the last printed
+    /// label.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # use ql_label::{Config, ContinuousType, Media, Model, Printer};
+    /// let config = Config::new(Model::QL800, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62))
+    ///     .reconnect(5, Duration::from_millis(500));
+    /// let printer = Printer::new(config)?;
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn reconnect(self, attempts: u32, backoff: Duration) -> Self {
+        Config {
+            reconnect: Some((attempts, backoff)),
+            ..self
+        }
+    }
+
+    /// Print on the media installed in the printer instead of the media of
+    /// the configuration.
+    ///
+    /// `Printer::new()` asks the printer for its media when it is opened and
+    /// fails with `Error::NoMediaInstalled` if there is none. The feed is
+    /// reset to the default of the detected media unless it is valid for
+    /// it. Use `Printer::detect_media()` again after changing the roll.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, ContinuousType, DieCutType, Emulator, Media, Model, Printer};
+    /// let installed = Media::DieCut(DieCutType::DieCut29x90);
+    /// let emulator = Emulator::new(Model::QL800, Some(installed));
+    ///
+    /// let config = Config::new(Model::QL800, "emulator".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62))
+    ///     .media_auto();
+    /// let printer = Printer::with_transport(config, emulator)?;
+    /// assert_eq!(printer.media(), installed);
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn media_auto(self) -> Self {
+        Config {
+            detect_media: true,
+            ..self
+        }
+    }
+
+    /// Move everything printed by this printer to compensate for mechanical
+    /// differences between units.
+    ///
+    /// Use `Printer::print_calibration_page()` to measure the offset. The
+    /// length of each page is kept, so content moved past an edge is cut off.
+    ///
+    /// # Arguments
+    /// * `x` - Millimetres away from the left edge of the tape, negative values move left
+    /// * `y` - Millimetres towards the end of the label, negative values move back
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{Config, Model, Media, DieCutType};
+    /// let config = Config::new(Model::QL800, "serial".to_string(),
+    ///                         Media::DieCut(DieCutType::DieCut29x90))
+    ///     .print_offset_mm(-0.5, 1.0);
+    /// ```
+    pub fn print_offset_mm(self, x: f32, y: f32) -> Self {
+        Config {
+            offset: (x, y),
+            ..self
+        }
+    }
+
+    /// Move the red color of two-color labels relative to black, to align
+    /// the two colors of a unit.
+    ///
+    /// Use `Printer::print_two_color_calibration_page()` to measure the
+    /// offset. Like `print_offset_mm()`, the length of each page is kept.
+    ///
+    /// # Arguments
+    /// * `x` - Millimetres away from the left edge of the tape, negative values move left
+    /// * `y` - Millimetres towards the end of the label, negative values move back
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, Model, Media, ContinuousType};
+    /// let config = Config::new(Model::QL820NWB, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62Red))
+    ///     .two_colors(true)
+    ///     .red_offset_mm(0.5, -0.25);
+    /// assert_eq!(config.red_offset(), (0.5, -0.25));
+    /// ```
+    pub fn red_offset_mm(self, x: f32, y: f32) -> Self {
+        Config {
+            red_offset: (x, y),
+            ..self
+        }
+    }
+
+    /// Override the offset of die-cut labels from the media specification,
+    /// see `MediaSpec::offset_mm()`.
+    ///
+    /// The firmware positions the first raster line at the nominal offset
+    /// from the leading edge of each label. When a roll or a unit differs,
+    /// pages are moved along the tape by the difference, keeping the raster
+    /// count sent in the print information equal to the label length.
+    /// Continuous media ignore the offset.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, Model, Media, DieCutType};
+    /// let config = Config::new(Model::QL800, "serial".to_string(),
+    ///                         Media::DieCut(DieCutType::DieCut29x90));
+    /// assert_eq!(config.die_cut_offset(), Some(3.0));
+    ///
+    /// // Content lands 0.5 mm too early on this roll
+    /// let config = config.die_cut_offset_mm(3.5);
+    /// assert_eq!(config.die_cut_offset(), Some(3.5));
+    /// ```
+    pub fn die_cut_offset_mm(self, mm: f32) -> Self {
+        Config {
+            die_cut_offset: Some(mm),
+            ..self
+        }
+    }
+
+    /// Set additional bits of the expanded mode byte.
+    ///
+    /// The bits are combined with the ones derived from the other options,
+    /// see `ExpandedMode`. Undocumented bits may have no effect or unexpected
+    /// effects depending on the model and firmware.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{Config, ContinuousType, ExpandedMode, Media, Model};
+    /// let config = Config::new(Model::QL820NWB, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62))
+    ///     .extra_expanded_mode(ExpandedMode::RESERVED_2);
+    /// ```
+    pub fn extra_expanded_mode(self, flags: ExpandedMode) -> Self {
+        Config {
+            extra_expanded_mode: flags,
+            ..self
+        }
+    }
+
+    /// Expanded mode byte sent with every job.
+    pub fn expanded_mode(&self) -> ExpandedMode {
+        protocol::expanded_mode(&self.settings(), self.extra_expanded_mode)
+    }
+
+    /// Printer model of this configuration.
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// Serial number of the printer, empty for `Config::new_any()`.
+    pub fn serial(&self) -> &str {
+        &self.serial
+    }
+
+    /// Media installed in the printer.
+    pub fn media(&self) -> Media {
+        self.media
+    }
+
+    /// Print offset as `(x, y)` in millimetres, see `print_offset_mm()`.
+    pub fn offset_mm(&self) -> (f32, f32) {
+        self.offset
+    }
+
+    /// Offset of the red color as `(x, y)` in millimetres, see
+    /// `red_offset_mm()`.
+    pub fn red_offset(&self) -> (f32, f32) {
+        self.red_offset
+    }
+
+    /// Offset of die-cut labels in millimetres, the override set with
+    /// `die_cut_offset_mm()` or the one of the media specification. `None`
+    /// for continuous media.
+    pub fn die_cut_offset(&self) -> Option<f32> {
+        self.media
+            .spec()
+            .offset_mm()
+            .map(|nominal| self.die_cut_offset.unwrap_or(nominal))
+    }
 
-        // Set feeding values in dots
-        {
-            match self.media.check_feed_value(self.feed) {
-                Ok(feed) => {
-                    buf.append(&mut [0x1B, 0x69, 0x64].to_vec());
-                    buf.append(&mut feed.to_vec());
+    /// Resolution mode selected by this configuration.
+    ///
+    /// Use it to convert physical sizes to dots for this configuration.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, Model, Media, ContinuousType, Resolution};
+    /// let config = Config::new(Model::QL820NWB, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62))
+    ///     .high_resolution(true);
+    /// assert_eq!(config.resolution(), Resolution::High);
+    /// assert_eq!(config.resolution().dots_along(10.0), 236);
+    /// ```
+    pub fn resolution(&self) -> Resolution {
+        if self.high_resolution {
+            Resolution::High
+        } else {
+            Resolution::Normal
+        }
+    }
+
+    /// Print settings of this configuration, see `Settings`. The power
+    /// settings belong to the printer and are `None`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, Model, Media, ContinuousType};
+    /// let config = Config::new(Model::QL820NWB, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62))
+    ///     .enable_auto_cut(3);
+    /// assert_eq!(config.settings().auto_cut, Some(3));
+    /// ```
+    pub fn settings(&self) -> Settings {
+        Settings {
+            auto_cut: match self.auto_cut {
+                AutoCut::Enabled(n) => Some(n),
+                AutoCut::Disabled => None,
+            },
+            cut_at_end: self.cut_at_end,
+            high_resolution: self.high_resolution,
+            two_colors: self.two_colors,
+            feed_dots: self.feed,
+            compress: self.compress,
+            auto_power_off: None,
+            auto_power_on: None,
+        }
+    }
+
+    /// Replace the print settings of this configuration.
+    ///
+    /// Model, serial number and media are kept, so settings dumped from one
+    /// printer can be applied to the configuration of another one. The
+    /// power settings are ignored, `Printer::apply_settings()` sends them.
+    ///
+    /// # Returns
+    /// * `Ok(Config)` - Configuration with the new settings
+    /// * `Err(Error::InvalidConfig)` - The feed amount is not valid for the media
+    pub fn apply_settings(self, settings: &Settings) -> Result<Self, Error> {
+        self.media
+            .check_feed_value(settings.feed_dots)
+            .map_err(Error::InvalidConfig)?;
+        Ok(Config {
+            auto_cut: match settings.auto_cut {
+                Some(n) => AutoCut::Enabled(n),
+                None => AutoCut::Disabled,
+            },
+            cut_at_end: settings.cut_at_end,
+            high_resolution: settings.high_resolution,
+            two_colors: settings.two_colors,
+            feed: settings.feed_dots,
+            compress: settings.compress,
+            ..self
+        })
+    }
+
+    /// Encode `images` into a complete job without a printer.
+    ///
+    /// The job starts with the invalidate and initialize commands and ends
+    /// with Control-Z, like the `.bin` files of brother_ql. Save it to hand
+    /// it to `brother_ql_send` or copy it to the printer device directly
+    /// (`cat label.bin > /dev/usb/lp0`). Pages are encoded as in `print()`:
+    /// trimmed with `enable_auto_trim()`, checked with `blank_pages()` and
+    /// split into segments when longer than the printer accepts. Pass
+    /// `TwoColorMatrix::to_alternating_matrix()` for two-color pages.
+    ///
+    /// # Returns
+    /// * `Ok(Vec<u8>)` - Raster commands of the job
+    /// * `Err(Error::InvalidConfig)` - The feed amount is not valid for the media
+    /// * `Err(Error::BlankPage)` - A page is blank with `BlankPages::Fail`
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{protocol, Config, Model, Media, ContinuousType};
+    /// let config = Config::new(Model::QL820NWB, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62));
+    /// let job = config.encode_job(vec![vec![vec![0xFF; 90]; 300]].into_iter())?;
+    /// assert_eq!(protocol::decode(&job)?.pages.len(), 1);
+    ///
+    /// std::fs::write(std::env::temp_dir().join("label.bin"), job)?;
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn encode_job<S: RasterSource>(
+        &self,
+        images: impl Iterator<Item = S>,
+    ) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::new();
+        self.write_job(images, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Encode `images` like `encode_job()` and write the job to `writer`
+    /// page by page, so long jobs are never held in memory at once.
+    ///
+    /// Write to the standard input of `lp -o raw` to spool the job through
+    /// CUPS, or to a network socket of a print server.
+    ///
+    /// # Returns
+    /// * `Ok(())` - The whole job was written
+    /// * `Err(Error::InvalidConfig)` - The feed amount is not valid for the
+    ///   media, nothing was written
+    /// * `Err(Error::BlankPage)` - A page is blank with `BlankPages::Fail`,
+    ///   the job written so far ends with the page before it
+    /// * `Err(Error::Io)` - Writing failed
+    pub fn write_job<S: RasterSource, W: Write>(
+        &self,
+        images: impl Iterator<Item = S>,
+        mut writer: W,
+    ) -> Result<(), Error> {
+        let commands = self.job_commands()?;
+        writer.write_all(&protocol::initialize())?;
+        writer.write_all(&commands)?;
+        self.pipeline(
+            images,
+            PageWriter {
+                config: self,
+                writer,
+            },
+        )
+    }
+
+    /// Pass `images` through the steps every job takes before its pages are
+    /// encoded, printed or written: trimming blank rows with
+    /// `enable_auto_trim()`, the `BlankPages` policy and splitting pages
+    /// longer than `max_page_rows()` into segments. `sink` gets the pages
+    /// as they are sent.
+    fn pipeline<S: RasterSource, K: PageSink>(
+        &self,
+        images: impl Iterator<Item = S>,
+        sink: K,
+    ) -> Result<K::Output, Error> {
+        match (self.media, self.auto_trim) {
+            (Media::Continuous(_), Some(min_length)) => {
+                // Trimming needs the whole page in memory
+                let pages = images.map(|image| trim_page(image, min_length));
+                self.filter_blank_pages(pages, sink)
+            }
+            _ => self.filter_blank_pages(images, sink),
+        }
+    }
+
+    /// Apply the `BlankPages` policy and split long pages, see `pipeline()`.
+    fn filter_blank_pages<S: RasterSource, K: PageSink>(
+        &self,
+        images: impl Iterator<Item = S>,
+        sink: K,
+    ) -> Result<K::Output, Error> {
+        let max_rows = self.max_page_rows();
+        match self.blank_pages {
+            BlankPages::Print => {
+                sink.send(images.flat_map(|image| Segmented::split(image, max_rows)))
+            }
+            policy => {
+                // Detecting blank pages needs the whole page in memory
+                let mut blank_page = None;
+                let pages = images
+                    .map(buffer_page)
+                    .enumerate()
+                    .take_while(|(page, (_, blank))| {
+                        if *blank && policy == BlankPages::Fail {
+                            blank_page = Some(*page);
+                        }
+                        blank_page.is_none()
+                    })
+                    .filter(|(page, (_, blank))| {
+                        if *blank {
+                            warn!("Skipping blank page {}", page);
+                        }
+                        !blank
+                    })
+                    .flat_map(|(_, (page, _))| Segmented::split(page, max_rows));
+                let result = sink.send(pages);
+                match (result, blank_page) {
+                    (Ok(_), Some(page)) => Err(Error::BlankPage { page }),
+                    (result, _) => result,
                 }
-                Err(msg) => return Err(Error::InvalidConfig(msg)),
             }
         }
-        // Set auto cut settings
-        {
-            let mut various_mode: u8 = 0b0000_0000;
-            let mut auto_cut_num: u8 = 1;
+    }
+
+    /// Encode `images` and save the job to the file at `path`, e.g. a
+    /// `.prn` file to archive or to print later with `lp -o raw`.
+    ///
+    /// The job is written to a temporary file next to `path` and renamed
+    /// into place, so a job that fails to encode leaves `path` as it was.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{protocol, Config, ContinuousType, Media, Model};
+    /// let config = Config::new_any(Model::QL800, Media::Continuous(ContinuousType::Continuous62));
+    /// let path = std::env::temp_dir().join("label.prn");
+    /// config.save_job(vec![vec![vec![0xFF; 90]; 300]].into_iter(), &path)?;
+    ///
+    /// let job = protocol::decode(&std::fs::read(&path)?)?;
+    /// assert_eq!(job.pages[0].len(), 300);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn save_job<S: RasterSource, P: AsRef<Path>>(
+        &self,
+        images: impl Iterator<Item = S>,
+        path: P,
+    ) -> Result<(), Error> {
+        let path = path.as_ref();
+        let temp = path.with_extension("tmp");
+        let file = std::fs::File::create(&temp)?;
+        let result = self
+            .write_job(images, BufWriter::new(file))
+            .and_then(|()| Ok(std::fs::rename(&temp, path)?));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temp);
+        }
+        result
+    }
+
+    /// Whether raster data is sent compressed.
+    fn use_compression(&self) -> bool {
+        // QL-800では圧縮モードがサポートされていないため、常に非圧縮とする
+        self.compress && !matches!(self.model, Model::QL800)
+    }
 
-            if let AutoCut::Enabled(n) = self.auto_cut {
-                various_mode = various_mode | 0b0100_0000;
-                auto_cut_num = n;
+    /// Commands sent at the start of every job after the initialize command.
+    fn job_commands(&self) -> Result<Vec<u8>, Error> {
+        if let (Media::Continuous(_), Some(mm)) = (self.media, self.trailing_feed) {
+            let min = MIN_CONTINUOUS_LENGTH_DOTS + 2 * self.feed as u32;
+            if mm_to_dots(mm, HEAD_DPI) < min {
+                return Err(Error::InvalidConfig(format!(
+                    "Trailing feed of {}mm is shorter than the minimum of {:.1}mm",
+                    mm,
+                    min as f32 * 25.4 / HEAD_DPI as f32
+                )));
             }
+        }
+        let settings = Settings {
+            compress: self.use_compression(),
+            ..self.settings()
+        };
+        protocol::job_commands(self.media, &settings, self.extra_expanded_mode)
+            .map_err(Error::InvalidConfig)
+    }
 
-            debug!("Auto-cut mode configured: {:#04x}", various_mode);
-            debug!("Auto-cut frequency: {} pages", auto_cut_num);
+    /// Move the rows of a page by the calibrated print offset.
+    fn apply_offset<S: RasterSource>(&self, image: S) -> OffsetRows<S::Rows> {
+        let (x, y) = self.offset;
+        // The firmware already skips the nominal offset of die-cut labels
+        let nominal = self.media.spec().offset_mm();
+        let y = match (self.die_cut_offset(), nominal) {
+            (Some(offset), Some(nominal)) => y + offset - nominal,
+            _ => y,
+        };
+        let x = mm_to_dots(x.abs(), HEAD_DPI) as i32 * x.signum() as i32;
+        let y = self.resolution().dots_along(y.abs()) as i32 * y.signum() as i32;
+        // Two-color pages send a black and a red row for every raster line
+        let y = if self.two_colors { y * 2 } else { y };
+
+        let count = image.raster_count();
+        OffsetRows::new(image.into_rows(), count, x, y)
+    }
 
-            buf.append(&mut [0x1B, 0x69, 0x4D, various_mode].to_vec()); // ESC i M : Set various mode
-            buf.append(&mut [0x1B, 0x69, 0x41, auto_cut_num].to_vec()); // ESC i A : Set auto cut number
+    /// Move the red rows of a two-color page by the calibrated red offset.
+    fn align_red(&self, image: TwoColorMatrix) -> TwoColorMatrix {
+        let (x, y) = self.red_offset;
+        if x == 0.0 && y == 0.0 {
+            return image;
+        }
+        let x = mm_to_dots(x.abs(), HEAD_DPI) as i32 * x.signum() as i32;
+        let y = self.resolution().dots_along(y.abs()) as i32 * y.signum() as i32;
+        let count = image.red.len() as u32;
+        TwoColorMatrix {
+            red: OffsetRows::new(image.red.into_iter(), count, x, y).collect(),
+            ..image
         }
-        // Set expanded mode
-        {
-            let mut expanded_mode: u8 = 0b00000000;
+    }
 
-            if self.two_colors {
-                expanded_mode = expanded_mode | 0b0000_0001;
+    /// Most raster lines the printer accepts in one page, counting the red
+    /// and black lines of two-color pages. `None` for die-cut labels, which
+    /// have a fixed length.
+    ///
+    /// Longer pages are printed as consecutive segments without cutting the
+    /// tape in between. Each segment is fed with the page's margin, so the
+    /// joins show a gap of twice the feed.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, ContinuousType, Media, Model};
+    /// let config = Config::new(Model::QL820NWB, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62));
+    /// // 1 m at 300 DPI
+    /// assert_eq!(config.max_page_rows(), Some(11811));
+    /// assert_eq!(config.high_resolution(true).max_page_rows(), Some(23622));
+    /// ```
+    pub fn max_page_rows(&self) -> Option<u32> {
+        match self.media {
+            Media::DieCut(_) => None,
+            Media::Continuous(_) => {
+                let dpi = self.resolution().vertical_dpi();
+                let rows = self.model.max_raster_lines() * dpi / HEAD_DPI;
+                Some(if self.two_colors { rows * 2 } else { rows })
             }
+        }
+    }
 
-            if self.cut_at_end {
-                expanded_mode = expanded_mode | 0b0000_1000;
-            };
+    /// The configuration for printing on `media`, with its default feed
+    /// unless the feed is valid for it.
+    fn with_media(self, media: Media) -> Self {
+        let feed = match media.check_feed_value(self.feed) {
+            Ok(_) => self.feed,
+            Err(_) => media.get_default_feed_dots(),
+        };
+        Config {
+            media,
+            feed,
+            ..self
+        }
+    }
 
-            if self.high_resolution {
-                expanded_mode = expanded_mode | 0b0100_0000;
+    /// Check the options against the capabilities of the model and the
+    /// media, see `ConfigBuilder`.
+    ///
+    /// # Returns
+    /// * `Ok(())` - The printer can print with this configuration
+    /// * `Err(Error::ConfigConflicts)` - Every conflicting option
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut conflicts = Vec::new();
+        let model = self.model;
+        let red_media = self.media == Media::Continuous(ContinuousType::Continuous62Red);
+        if self.two_colors && !model.supports_two_color() {
+            conflicts.push(ConfigConflict::TwoColors(model));
+        } else if red_media && !model.supports_two_color() {
+            conflicts.push(ConfigConflict::RedMedia(model));
+        }
+        if self.two_colors && !red_media {
+            conflicts.push(ConfigConflict::TwoColorsMedia(self.media));
+        }
+        if self.high_resolution && !model.supports_high_resolution() {
+            conflicts.push(ConfigConflict::HighResolution(model));
+        }
+        if self.high_resolution && self.two_colors {
+            conflicts.push(ConfigConflict::HighResolutionTwoColors);
+        }
+        let spec = self.media.spec();
+        if spec.left_margin_dots() + spec.effective_dots() > model.pins() {
+            conflicts.push(ConfigConflict::WideMedia {
+                model,
+                media: self.media,
+            });
+        }
+        if self.media.check_feed_value(self.feed).is_err() {
+            conflicts.push(ConfigConflict::Feed {
+                feed: self.feed,
+                media: self.media,
+            });
+        }
+        if conflicts.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::ConfigConflicts(conflicts))
+        }
+    }
+
+    /// Fail pages with more raster lines than their die-cut label holds.
+    fn check_raster_count(&self, count: u32) -> Result<(), Error> {
+        if let Media::Continuous(_) = self.media {
+            return Ok(());
+        }
+        let dpi = self.resolution().vertical_dpi();
+        let rows = self.media.spec().length_dots() * dpi / HEAD_DPI;
+        let max = if self.two_colors { rows * 2 } else { rows };
+        if count > max {
+            return Err(Error::InvalidRasterCount { count, max });
+        }
+        Ok(())
+    }
+
+    /// Tape consumed by a label of `raster_count` rows in millimetres.
+    fn label_length_mm(&self, raster_count: u32, feed: u16) -> f64 {
+        match self.media {
+            Media::DieCut(_) => self.media.spec().length_mm() as f64,
+            Media::Continuous(_) => {
+                // The feed margin is added before and after the printed area
+                let dpi = self.resolution().vertical_dpi() as f64;
+                (raster_count as f64 / dpi + 2.0 * feed as f64 / 300.0) * 25.4
             }
+        }
+    }
+}
+
+/// Builder of a `Config` checked against the capabilities of the model and
+/// the media.
+///
+/// `Config` accepts any combination of options and only the printer
+/// rejects the job. `build()` finds the conflicts before anything is sent,
+/// like two colors on a model printing black only or 600 DPI on an older
+/// model. Options without constraints are set on the built `Config`.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{Config, ConfigConflict, ContinuousType, Error, Media, Model};
+/// let red = Media::Continuous(ContinuousType::Continuous62Red);
+/// let config = Config::builder(Model::QL820NWB, red)
+///     .serial("000G0Z000000")
+///     .two_colors(true)
+///     .build()?
+///     .red_offset_mm(0.0, 0.5);
+/// assert_eq!(config.settings().two_colors, true);
+///
+/// let result = Config::builder(Model::QL720NW, red)
+///     .two_colors(true)
+///     .high_resolution(true)
+///     .build();
+/// match result {
+///     Err(Error::ConfigConflicts(conflicts)) => assert_eq!(
+///         conflicts,
+///         [
+///             ConfigConflict::TwoColors(Model::QL720NW),
+///             ConfigConflict::HighResolutionTwoColors,
+///         ]
+///     ),
+///     _ => panic!("expected conflicts"),
+/// }
+/// # Ok::<(), ql_label::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConfigBuilder {
+    config: Config,
+}
 
-            debug!("Print mode settings: {:#04x}", expanded_mode);
+impl Config {
+    /// Start a checked configuration for `model` and `media`, connecting to
+    /// the first printer of the model unless `ConfigBuilder::serial()` is
+    /// set.
+    pub fn builder(model: Model, media: Media) -> ConfigBuilder {
+        ConfigBuilder {
+            config: Config::new_any(model, media),
+        }
+    }
+}
 
-            buf.append(&mut [0x1B, 0x69, 0x4B, expanded_mode].to_vec()); // ESC i K : Set expanded mode
+impl ConfigBuilder {
+    /// Serial number of the printer, see `Config::new()`.
+    pub fn serial<S: Into<String>>(self, serial: S) -> Self {
+        ConfigBuilder {
+            config: Config {
+                serial: serial.into(),
+                ..self.config
+            },
+        }
+    }
+
+    /// Print black and red, see `Config::two_colors()`.
+    pub fn two_colors(self, two_colors: bool) -> Self {
+        ConfigBuilder {
+            config: self.config.two_colors(two_colors),
         }
-        Ok(buf)
     }
-}
\ No newline at end of file
+
+    /// Print 600 DPI along the tape, see `Config::high_resolution()`.
+    pub fn high_resolution(self, high: bool) -> Self {
+        ConfigBuilder {
+            config: self.config.high_resolution(high),
+        }
+    }
+
+    /// Feed amount in dots, see `Config::set_feed_in_dots()`.
+    pub fn feed_dots(self, feed: u16) -> Self {
+        ConfigBuilder {
+            config: self.config.set_feed_in_dots(feed),
+        }
+    }
+
+    /// Compress the raster lines, see `Config::compress()`. Models without
+    /// compression send them uncompressed.
+    pub fn compress(self, flag: bool) -> Self {
+        ConfigBuilder {
+            config: self.config.compress(flag),
+        }
+    }
+
+    /// Check the options and return the configuration.
+    ///
+    /// # Returns
+    /// * `Ok(Config)` - The printer can print with the options
+    /// * `Err(Error::ConfigConflicts)` - Every conflicting option, see
+    ///   `ConfigConflict`
+    pub fn build(self) -> Result<Config, Error> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// Serialized form of a `Config`: the printer, the media, the settings sent
+/// with every job, the calibrated offsets and every other option. Options
+/// left at their default are omitted, so profiles stay short.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ConfigRecord {
+    model: Model,
+    serial: String,
+    media: Media,
+    #[serde(flatten)]
+    settings: Settings,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    offset_x_mm: f32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    offset_y_mm: f32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    red_offset_x_mm: f32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    red_offset_y_mm: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    die_cut_offset_mm: Option<f32>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    skip_invalidate: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    auto_trim: Option<u32>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    blank_pages: BlankPages,
+    #[serde(default, skip_serializing_if = "is_default")]
+    encoding_cache: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    chunk_size: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    statistics_file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trailing_feed_mm: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lock_dir: Option<PathBuf>,
+    /// Roll length and warning threshold in millimetres.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    low_media_mm: Option<(f64, f64)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    media_change_timeout_secs: Option<f64>,
+    /// Attempts and backoff in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reconnect: Option<(u32, f64)>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    detect_media: bool,
+    #[serde(default, skip_serializing_if = "is_default")]
+    extra_expanded_mode: u8,
+}
+
+#[cfg(feature = "serde")]
+fn is_zero(value: &f32) -> bool {
+    *value == 0.0
+}
+
+#[cfg(feature = "serde")]
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Config {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (offset_x_mm, offset_y_mm) = self.offset;
+        let (red_offset_x_mm, red_offset_y_mm) = self.red_offset;
+        ConfigRecord {
+            model: self.model,
+            serial: self.serial.clone(),
+            media: self.media,
+            settings: self.settings(),
+            offset_x_mm,
+            offset_y_mm,
+            red_offset_x_mm,
+            red_offset_y_mm,
+            die_cut_offset_mm: self.die_cut_offset,
+            skip_invalidate: self.skip_invalidate,
+            auto_trim: self.auto_trim,
+            blank_pages: self.blank_pages,
+            encoding_cache: self.encoding_cache,
+            chunk_size: Some(self.chunk_size).filter(|&bytes| bytes != WRITE_CHUNK_SIZE),
+            statistics_file: self.statistics_file.clone(),
+            trailing_feed_mm: self.trailing_feed,
+            lock_dir: self.lock_dir.clone(),
+            low_media_mm: self.low_media,
+            media_change_timeout_secs: self.media_change_timeout.map(|t| t.as_secs_f64()),
+            reconnect: self
+                .reconnect
+                .map(|(attempts, backoff)| (attempts, backoff.as_secs_f64())),
+            detect_media: self.detect_media,
+            extra_expanded_mode: self.extra_expanded_mode.bits(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Config {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let record = ConfigRecord::deserialize(deserializer)?;
+        let seconds = |secs: f64| {
+            Duration::try_from_secs_f64(secs).map_err(|_| {
+                serde::de::Error::custom(format!("Invalid number of seconds: {}", secs))
+            })
+        };
+        let config = Config::new(record.model, record.serial, record.media)
+            .apply_settings(&record.settings)
+            .map_err(|err| match err {
+                Error::InvalidConfig(message) => serde::de::Error::custom(message),
+                err => serde::de::Error::custom(err),
+            })?;
+        Ok(Config {
+            offset: (record.offset_x_mm, record.offset_y_mm),
+            red_offset: (record.red_offset_x_mm, record.red_offset_y_mm),
+            die_cut_offset: record.die_cut_offset_mm,
+            skip_invalidate: record.skip_invalidate,
+            auto_trim: record.auto_trim,
+            blank_pages: record.blank_pages,
+            encoding_cache: record.encoding_cache,
+            chunk_size: record.chunk_size.unwrap_or(WRITE_CHUNK_SIZE),
+            statistics_file: record.statistics_file,
+            trailing_feed: record.trailing_feed_mm,
+            lock_dir: record.lock_dir,
+            low_media: record.low_media_mm,
+            media_change_timeout: record.media_change_timeout_secs.map(seconds).transpose()?,
+            reconnect: match record.reconnect {
+                Some((attempts, backoff)) => Some((attempts, seconds(backoff)?)),
+                None => None,
+            },
+            detect_media: record.detect_media,
+            extra_expanded_mode: ExpandedMode::from_bits_truncate(record.extra_expanded_mode),
+            ..config
+        })
+    }
+}
+
+/// Last step of `Config::pipeline()`, receiving the pages of a job.
+trait PageSink {
+    type Output;
+
+    fn send<S: RasterSource>(
+        self,
+        pages: impl Iterator<Item = Segmented<S>>,
+    ) -> Result<Self::Output, Error>;
+}
+
+/// Sends the pages of a job to the printer, see `Printer::send_pages()`.
+struct PageSender<'a> {
+    printer: &'a Printer,
+    copies: usize,
+    on_event: &'a mut dyn FnMut(PrintEvent) -> ControlFlow<()>,
+}
+
+impl PageSink for PageSender<'_> {
+    type Output = ControlFlow<()>;
+
+    fn send<S: RasterSource>(
+        self,
+        pages: impl Iterator<Item = Segmented<S>>,
+    ) -> Result<ControlFlow<()>, Error> {
+        self.printer.send_pages(pages, self.copies, self.on_event)
+    }
+}
+
+/// Writes the encoded pages of a job, see `Config::write_job()`.
+struct PageWriter<'a, W> {
+    config: &'a Config,
+    writer: W,
+}
+
+impl<W: Write> PageSink for PageWriter<'_, W> {
+    type Output = ();
+
+    fn send<S: RasterSource>(
+        mut self,
+        pages: impl Iterator<Item = Segmented<S>>,
+    ) -> Result<(), Error> {
+        let mut encoder = PageEncoder::new(self.config);
+        let mut pages = pages.peekable();
+        while let Some(page) = pages.next() {
+            let last = pages.peek().is_none();
+            encoder.continues(page.continues());
+            let (buf, _) = encoder.encode(page, last)?;
+            self.writer.write_all(&buf)?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Page commands of a job, from the print information to the print command.
+struct PageEncoder<'a> {
+    config: &'a Config,
+    compress: bool,
+    first: bool,
+    // Black and red raster lines alternate, set when the next one is red
+    color: bool,
+    // Set once a page overrides the feed, later pages restore the configured one
+    feed_overridden: bool,
+    cache: Option<&'a Mutex<EncodingCache>>,
+    // The next page is continued by the following one, see `continues()`
+    continues: bool,
+    // Set while the cutter is disabled for segments of a long page
+    cut_suppressed: bool,
+    // The next page starts a new document, see `starts_document()`
+    document_start: bool,
+}
+
+impl<'a> PageEncoder<'a> {
+    fn new(config: &'a Config) -> Self {
+        PageEncoder {
+            config,
+            compress: config.use_compression(),
+            first: true,
+            color: false,
+            feed_overridden: false,
+            cache: None,
+            continues: false,
+            cut_suppressed: false,
+            document_start: false,
+        }
+    }
+
+    /// Whether the next page is a segment continued by the following page,
+    /// so the tape must not be cut after it.
+    fn continues(&mut self, continues: bool) {
+        self.continues = continues;
+    }
+
+    /// Reuse the raster commands of pages found in `cache`.
+    fn cache(self, cache: &'a Mutex<EncodingCache>) -> Self {
+        PageEncoder {
+            cache: Some(cache),
+            ..self
+        }
+    }
+
+    /// Encode the next page, returning its commands and the tape it uses in
+    /// millimetres. `last` pages are ejected.
+    fn encode<S: RasterSource>(&mut self, image: S, last: bool) -> Result<(Vec<u8>, f64), Error> {
+        let rows = self.encode_rows(image)?;
+        Ok((self.frame(&rows, last), rows.length_mm))
+    }
+
+    /// Encode the print information and raster lines of a page, to be framed
+    /// by `frame()` once for every copy.
+    fn encode_rows<S: RasterSource>(&mut self, image: S) -> Result<EncodedRows, Error> {
+        let (header, raster_count, length_mm) = self.header(&image)?;
+
+        let (cache, hash) = match (self.cache, image.content_hash()) {
+            (Some(cache), Some(hash)) => (cache, hash),
+            _ => {
+                // Add raster line image data
+                let mut rows = Vec::new();
+                for (row, line) in self.config.apply_offset(image).enumerate() {
+                    self.push_line(&mut rows, row, &line)?;
+                }
+                return Ok(EncodedRows {
+                    header,
+                    raster_count,
+                    rows,
+                    length_mm,
+                });
+            }
+        };
+
+        // The red and black lines of two-color pages alternate across pages,
+        // and the same lines are encoded differently for wider heads
+        let mut hasher = DefaultHasher::new();
+        (hash, self.color, self.config.model.row_bytes()).hash(&mut hasher);
+        let key = hasher.finish();
+        let odd = image.raster_count() % 2 == 1;
+        let lines: Matrix = self.config.apply_offset(image).collect();
+        let cached = cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(key, &lines);
+        if let Some(rows) = cached {
+            debug!("Reusing the encoded rows of page {:016x}", hash);
+            if self.config.two_colors && odd {
+                self.color = !self.color;
+            }
+            return Ok(EncodedRows {
+                header,
+                raster_count,
+                rows,
+                length_mm,
+            });
+        }
+
+        let mut rows = Vec::new();
+        for (row, line) in lines.iter().enumerate() {
+            self.push_line(&mut rows, row, line)?;
+        }
+        let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache.insert(key, lines, rows.clone());
+
+        Ok(EncodedRows {
+            header,
+            raster_count,
+            rows,
+            length_mm,
+        })
+    }
+
+    /// Commands printing an encoded page. `last` pages are ejected.
+    fn frame(&mut self, page: &EncodedRows, last: bool) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(page.header.len() + page.rows.len() + 14);
+        self.push_print_information(&mut buf, page.raster_count);
+        buf.extend_from_slice(&page.header);
+        buf.extend_from_slice(&page.rows);
+        buf.push(terminator(last));
+        buf
+    }
+
+    /// Encode the next page into `buf`, handing it to `flush` whenever it
+    /// holds `chunk_size` bytes or more, so only one chunk of the page is in
+    /// memory. `flush` also gets the number of rows encoded so far and may
+    /// stop the page, which returns `None`. Otherwise returns the tape the
+    /// page uses in millimetres, the commands following the last flush are
+    /// left in `buf`.
+    fn stream<S: RasterSource>(
+        &mut self,
+        image: S,
+        last: bool,
+        buf: &mut Vec<u8>,
+        chunk_size: usize,
+        mut flush: impl FnMut(Vec<u8>, u32) -> Result<ControlFlow<()>, Error>,
+    ) -> Result<Option<f64>, Error> {
+        let (header, raster_count, length_mm) = self.header(&image)?;
+        self.push_print_information(buf, raster_count);
+        buf.extend_from_slice(&header);
+        for (row, line) in self.config.apply_offset(image).enumerate() {
+            self.push_line(buf, row, &line)?;
+            if buf.len() >= chunk_size && flush(std::mem::take(buf), row as u32 + 1)?.is_break() {
+                return Ok(None);
+            }
+        }
+        buf.push(terminator(last));
+        Ok(Some(length_mm))
+    }
+
+    /// Cutter and margin commands following the print information of
+    /// `image`, its raster count and the tape it uses in millimetres.
+    fn header<S: RasterSource>(&mut self, image: &S) -> Result<(Vec<u8>, u32, f64), Error> {
+        let config = self.config;
+        let mut header = Vec::new();
+
+        let raster_count = if config.two_colors {
+            image.raster_count() / 2
+        } else {
+            image.raster_count()
+        };
+        config.check_raster_count(image.raster_count())?;
+        if self.continues != self.cut_suppressed {
+            let auto_cut = if self.continues {
+                None
+            } else {
+                config.settings().auto_cut
+            };
+            protocol::set_various_mode(&mut header, auto_cut);
+            self.cut_suppressed = self.continues;
+        }
+        // Only the first copy of a page starting a document is flagged
+        self.document_start = image.starts_document();
+        let feed = image.feed_dots().unwrap_or(config.feed);
+        let length_mm = config.label_length_mm(raster_count, feed);
+        if image.feed_dots().is_some() || self.feed_overridden {
+            protocol::set_margin(&mut header, config.media, feed).map_err(Error::InvalidConfig)?;
+            self.feed_overridden = image.feed_dots().is_some();
+        }
+        Ok((header, raster_count, length_mm))
+    }
+
+    /// Append the print information (ESC i z) of the next page, flagged as
+    /// the starting page for the first page of the job or of a document.
+    fn push_print_information(&mut self, buf: &mut Vec<u8>, raster_count: u32) {
+        let starting_page = self.first || self.document_start;
+        protocol::print_information(buf, self.config.media, raster_count, starting_page);
+        self.first = false;
+        self.document_start = false;
+    }
+
+    fn push_line(&mut self, buf: &mut Vec<u8>, row: usize, line: &[u8]) -> Result<(), Error> {
+        let expected = self.config.model.row_bytes();
+        let normal = Model::QL800.row_bytes();
+        let padded;
+        let line = match line.len() {
+            width if width == expected => line,
+            // Rows for normal-width media start at the right edge of a wide head
+            width if width == normal && expected > normal => {
+                padded = [line, &vec![0x00; expected - width]].concat();
+                &padded
+            }
+            actual => {
+                return Err(Error::InvalidRaster {
+                    row,
+                    expected,
+                    actual,
+                })
+            }
+        };
+        if self.config.two_colors {
+            protocol::two_color_row(buf, line, self.color);
+            self.color = !self.color;
+        } else if line.iter().all(|&b| b == 0x00) {
+            buf.push(protocol::ZERO_RASTER);
+        } else {
+            protocol::raster_row(buf, line, self.compress);
+        }
+        Ok(())
+    }
+}
+
+/// Command ending a page, `last` pages are ejected.
+fn terminator(last: bool) -> u8 {
+    if last {
+        protocol::PRINT_AND_EJECT
+    } else {
+        protocol::PRINT
+    }
+}
+
+/// A page encoded by `PageEncoder::encode_rows()`.
+struct EncodedRows {
+    // Cutter and margin commands following the print information
+    header: Vec<u8>,
+    // Raster count of the print information
+    raster_count: u32,
+    // Raster commands
+    rows: Vec<u8>,
+    length_mm: f64,
+}
+
+/// `image` held in memory, and whether it is blank.
+fn buffer_page<S: RasterSource>(image: S) -> (Page<Matrix>, bool) {
+    let (feed, new_document) = (image.feed_dots(), image.starts_document());
+    let rows: Matrix = image.into_rows().collect();
+    let blank = rows.iter().all(|row| row.iter().all(|&b| b == 0));
+    (rebuild_page(rows, feed, new_document), blank)
+}
+
+/// `image` with blank rows trimmed, see `Config::enable_auto_trim()`.
+fn trim_page<S: RasterSource>(image: S, min_length: u32) -> Page<Matrix> {
+    let (feed, new_document) = (image.feed_dots(), image.starts_document());
+    let rows = image.into_rows().collect();
+    let rows = trim_blank_rows(rows, min_length as usize);
+    rebuild_page(rows, feed, new_document)
+}
+
+/// Page of buffered `rows` keeping the feed and document start of its source.
+fn rebuild_page(rows: Matrix, feed: Option<u16>, new_document: bool) -> Page<Matrix> {
+    let page = match feed {
+        Some(feed) => Page::new(rows).set_feed_in_dots(feed),
+        None => Page::new(rows),
+    };
+    if new_document {
+        page.new_document()
+    } else {
+        page
+    }
+}
+
+/// Cloneable handle to a shared `Printer`.
+///
+/// Created with `Printer::handle()`. Dereferences to `Printer`, so all
+/// printing and status methods are available directly. Jobs sent through
+/// different clones are serialized by the printer's internal lock.
+#[derive(Clone)]
+pub struct PrinterHandle {
+    printer: Arc<Printer>,
+}
+
+impl Deref for PrinterHandle {
+    type Target = Printer;
+
+    fn deref(&self) -> &Printer {
+        &self.printer
+    }
+}
@@ -0,0 +1,171 @@
+//! Named printer profiles stored in a TOML file.
+//!
+//! Enabled with the `profiles` feature. A profile keeps a whole `Config`
+//! under a short name, so applications can print to `"warehouse-2"` instead
+//! of repeating the model, serial number, media and options. Options left at
+//! their default are not written. The default store is
+//! `~/.config/ql-label/profiles.toml`:
+//!
+//! ```toml
+//! [warehouse-2]
+//! model = "QL820NWB"
+//! serial = "000G0Z000000"
+//! media = "Continuous62"
+//! auto_cut = 1
+//! cut_at_end = true
+//! high_resolution = false
+//! two_colors = false
+//! feed_dots = 35
+//! compress = false
+//! blank_pages = "Skip"
+//! media_change_timeout_secs = 120.0
+//! ```
+//!
+//! # Example
+//! ```rust,no_run
+//! use ql_label::{Config, ContinuousType, Media, Model, Printer, Profiles};
+//!
+//! let mut profiles = Profiles::open_default()?;
+//! let media = Media::Continuous(ContinuousType::Continuous62);
+//! profiles.insert("warehouse-2", &Config::new(Model::QL820NWB, "000G0Z000000".to_string(), media));
+//! profiles.save()?;
+//!
+//! // Later, possibly in another program
+//! let printer = Printer::new(Config::from_profile("warehouse-2")?);
+//! # Ok::<(), String>(())
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::printer::Config;
+
+/// Store of named printer configurations.
+pub struct Profiles {
+    path: PathBuf,
+    profiles: BTreeMap<String, Config>,
+}
+
+impl Profiles {
+    /// Location of the default store, `profiles.toml` in the `ql-label`
+    /// directory of the user's configuration directory.
+    ///
+    /// Uses `$XDG_CONFIG_HOME` or `~/.config`, and `%APPDATA%` on Windows.
+    /// Returns `None` when none of them is set.
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))?;
+        Some(dir.join("ql-label").join("profiles.toml"))
+    }
+
+    /// Open the default store, see `default_path()`.
+    pub fn open_default() -> Result<Self, String> {
+        let path = Self::default_path()
+            .ok_or_else(|| "Can't locate the configuration directory".to_string())?;
+        Self::open(path)
+    }
+
+    /// Open a store. A missing file yields an empty store.
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<Self, String> {
+        let path = path.into();
+        let profiles = match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text)
+                .map_err(|e| format!("Invalid profiles in {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        Ok(Profiles { path, profiles })
+    }
+
+    /// Write the store back to its file, creating the directory if needed.
+    pub fn save(&self) -> Result<(), String> {
+        let text = toml::to_string(&self.profiles)
+            .map_err(|e| format!("Failed to encode profiles: {}", e))?;
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        std::fs::write(&self.path, text)
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+
+    /// Names of all profiles in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    /// Configuration stored under `name`.
+    pub fn get(&self, name: &str) -> Result<Config, String> {
+        self.profiles
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("Unknown printer profile: {}", name))
+    }
+
+    /// Store `config` under `name`, replacing an existing profile.
+    pub fn insert(&mut self, name: &str, config: &Config) {
+        self.profiles.insert(name.to_string(), config.clone());
+    }
+
+    /// Remove the profile `name`, returns `false` if it did not exist.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.profiles.remove(name).is_some()
+    }
+}
+
+impl Config {
+    /// Load the configuration saved under `name` in the default profile store.
+    ///
+    /// See `Profiles` to manage the store.
+    pub fn from_profile(name: &str) -> Result<Config, String> {
+        Profiles::open_default()?.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::{DieCutType, Media};
+    use crate::model::Model;
+    use crate::printer::BlankPages;
+    use std::time::Duration;
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("ql-label-profiles-{}", std::process::id()));
+        let path = dir.join("profiles.toml");
+        let media = Media::DieCut(DieCutType::DieCut29x90);
+        let config = Config::new(Model::QL820NWB, "000G0Z000000".to_string(), media)
+            .enable_auto_cut(2)
+            .compress(true)
+            .print_offset_mm(-0.5, 1.0)
+            .die_cut_offset_mm(2.5)
+            .enable_auto_trim(100)
+            .blank_pages(BlankPages::Skip)
+            .encoding_cache(4)
+            .statistics_file("ql820.stats")
+            .wait_for_media_change(Duration::from_secs(90))
+            .reconnect(3, Duration::from_millis(500))
+            .media_auto();
+
+        let mut profiles = Profiles::open(&path).unwrap();
+        profiles.insert("warehouse-2", &config);
+        profiles.insert("plain", &Config::new_any(Model::QL800, media));
+        profiles.save().unwrap();
+
+        let profiles = Profiles::open(&path).unwrap();
+        assert_eq!(
+            profiles.names().collect::<Vec<_>>(),
+            ["plain", "warehouse-2"]
+        );
+        assert_eq!(profiles.get("warehouse-2").unwrap(), config);
+        assert_eq!(
+            profiles.get("plain").unwrap(),
+            Config::new_any(Model::QL800, media)
+        );
+        assert!(profiles.get("missing").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,1529 @@
+//! Brother QL raster protocol without any I/O.
+//!
+//! This module only needs `core` and `alloc`, so it also builds with
+//! `default-features = false` for hosts without an operating system, e.g. a
+//! microcontroller driving a printer as a USB host. It encodes the commands of
+//! a print job and parses the status responses; sending the bytes is left to
+//! the caller. `Printer` uses the same functions on top of `rusb`.
+//!
+//! A job is `initialize()`, `job_commands()` and then for every page
+//! `print_information()`, the raster rows and `PRINT`, or
+//! `PRINT_AND_EJECT` after the last page, which `page()` appends in one go.
+//! `set_margin()` and `set_various_mode()` change the feed and the cutter
+//! between pages.
+//!
+//! # Example
+//! ```rust
+//! # use ql_label::protocol::{self, ExpandedMode, Settings};
+//! # use ql_label::{ContinuousType, Media};
+//! let media = Media::Continuous(ContinuousType::Continuous62);
+//! let settings = Settings {
+//!     auto_cut: Some(1),
+//!     cut_at_end: true,
+//!     high_resolution: false,
+//!     two_colors: false,
+//!     feed_dots: 35,
+//!     compress: true,
+//!     auto_power_off: None,
+//!     auto_power_on: None,
+//! };
+//!
+//! let mut job = protocol::initialize();
+//! job.extend(protocol::job_commands(media, &settings, ExpandedMode::empty())?);
+//! protocol::print_information(&mut job, media, 1, true);
+//! protocol::raster_row(&mut job, &[0xFF; 90], settings.compress);
+//! job.push(protocol::PRINT_AND_EJECT);
+//!
+//! // One compressed row: a run of 90 0xFF bytes
+//! assert_eq!(&job[job.len() - 6..], &[0x67, 0x00, 0x02, 0xA7, 0xFF, 0x1A]);
+//! # Ok::<(), String>(())
+//! ```
+
+use alloc::{format, string::String, vec, vec::Vec};
+use bitflags::bitflags;
+use core::fmt::{self, Write};
+use log::{debug, warn};
+
+use crate::media::{ContinuousType, Media};
+use crate::model::Model;
+
+/// ESC @ : Initialize, clears all modes set by a previous job.
+pub const RESET: [u8; 2] = [0x1B, 0x40];
+/// ESC i S : Request a status response.
+pub const STATUS_REQUEST: [u8; 3] = [0x1B, 0x69, 0x53];
+/// FF : Print the page.
+pub const PRINT: u8 = 0x0C;
+/// Control-Z : Print the last page of a job and eject it.
+pub const PRINT_AND_EJECT: u8 = 0x1A;
+/// Z : Raster line without any black dot, one byte instead of a whole row.
+/// Black and white pages only, two-color pages send every line with `w`.
+pub const ZERO_RASTER: u8 = 0x5A;
+// Printer settings of ESC i U
+pub(crate) const AUTO_POWER_OFF: u8 = 0x41;
+pub(crate) const AUTO_POWER_ON: u8 = 0x70;
+
+/// Invalidate bytes followed by the initialize command.
+///
+/// The 400 zero bytes bring the printer back to a known state when a previous
+/// job was interrupted.
+pub fn initialize() -> Vec<u8> {
+    let mut buf = vec![0x00; 400];
+    buf.extend_from_slice(&RESET);
+    buf
+}
+
+/// Commands sent at the start of every job after `initialize()`.
+///
+/// Selects raster mode and sets the feed, the auto cutter, the expanded mode
+/// and the compression from `settings`. `extra` bits are added to the
+/// expanded mode, see `expanded_mode()`.
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` - Encoded commands
+/// * `Err(String)` - The feed amount is not valid for the media
+pub fn job_commands(
+    media: Media,
+    settings: &Settings,
+    extra: ExpandedMode,
+) -> Result<Vec<u8>, String> {
+    let mut buf: Vec<u8> = Vec::new();
+    buf.extend_from_slice(&[0x1B, 0x69, 0x61, 0x01]); // Set raster command mode
+    buf.extend_from_slice(&[0x1B, 0x69, 0x21, 0x00]); // Set auto status notificatoin mode
+
+    // Set feeding values in dots
+    set_margin(&mut buf, media, settings.feed_dots)?;
+
+    // Set auto cut settings
+    let auto_cut_num = settings.auto_cut.unwrap_or(1);
+    let various_mode = various_mode(settings.auto_cut);
+    debug!("Auto-cut mode configured: {:#04x}", various_mode);
+    debug!("Auto-cut frequency: {} pages", auto_cut_num);
+    set_various_mode(&mut buf, settings.auto_cut);
+    buf.extend_from_slice(&[0x1B, 0x69, 0x41, auto_cut_num]); // ESC i A : Set auto cut number
+
+    // Set expanded mode
+    let expanded_mode = expanded_mode(settings, extra).bits();
+    debug!("Print mode settings: {:#04x}", expanded_mode);
+    buf.extend_from_slice(&[0x1B, 0x69, 0x4B, expanded_mode]); // ESC i K : Set expanded mode
+
+    if settings.compress {
+        buf.extend_from_slice(&[0x4D, 0x02]); // Set to pack bits compression mode
+    } else {
+        buf.extend_from_slice(&[0x4D, 0x00]); // Set to no compression mode
+    }
+    Ok(buf)
+}
+
+/// Various mode byte (ESC i M) cutting every `auto_cut` labels, `None`
+/// disables the cutter.
+pub fn various_mode(auto_cut: Option<u8>) -> u8 {
+    match auto_cut {
+        Some(_) => 0b0100_0000,
+        None => 0b0000_0000,
+    }
+}
+
+/// Append the various mode command (ESC i M), see `various_mode()`.
+///
+/// Sent again before a page to suppress the cut after it, e.g. between the
+/// labels of a document printed on continuous tape.
+pub fn set_various_mode(buf: &mut Vec<u8>, auto_cut: Option<u8>) {
+    buf.extend_from_slice(&[0x1B, 0x69, 0x4D, various_mode(auto_cut)]);
+}
+
+/// Append the margin command (ESC i d) feeding `dots` before and after a
+/// page.
+///
+/// # Returns
+/// * `Ok(())` - The command was appended
+/// * `Err(String)` - The feed amount is not valid for the media
+pub fn set_margin(buf: &mut Vec<u8>, media: Media, dots: u16) -> Result<(), String> {
+    let feed = media.check_feed_value(dots)?;
+    buf.extend_from_slice(&[0x1B, 0x69, 0x64]);
+    buf.extend_from_slice(&feed);
+    Ok(())
+}
+
+/// Append the printer setting command (ESC i U A) turning the printer off
+/// after `minutes` without a job, 0 never turns it off.
+///
+/// The QL-800 and QL-1100 series keep the setting after being turned off.
+///
+/// # Returns
+/// * `Ok(())` - The command was appended
+/// * `Err(String)` - `minutes` is not 0 to 60 in steps of 10
+///
+/// # Example
+/// ```rust
+/// # use ql_label::protocol;
+/// let mut buf = Vec::new();
+/// protocol::set_auto_power_off(&mut buf, 30).unwrap();
+/// assert_eq!(buf, [0x1B, 0x69, 0x55, 0x41, 0x00, 0x03]);
+/// assert!(protocol::set_auto_power_off(&mut buf, 15).is_err());
+/// ```
+pub fn set_auto_power_off(buf: &mut Vec<u8>, minutes: u8) -> Result<(), String> {
+    if minutes > 60 || !minutes.is_multiple_of(10) {
+        return Err(format!(
+            "Auto power-off of {} minutes, use 0 to 60 in steps of 10",
+            minutes
+        ));
+    }
+    buf.extend_from_slice(&[0x1B, 0x69, 0x55, AUTO_POWER_OFF, 0x00, minutes / 10]);
+    Ok(())
+}
+
+/// Append the printer setting command (ESC i U p) turning the printer on
+/// when it is plugged in, if `on` is set.
+pub fn set_auto_power_on(buf: &mut Vec<u8>, on: bool) {
+    buf.extend_from_slice(&[0x1B, 0x69, 0x55, AUTO_POWER_ON, 0x00, on as u8]);
+}
+
+/// Expanded mode byte for `settings` with the `extra` bits added.
+pub fn expanded_mode(settings: &Settings, extra: ExpandedMode) -> ExpandedMode {
+    let mut mode = extra;
+    mode.set(ExpandedMode::TWO_COLORS, settings.two_colors);
+    mode.set(ExpandedMode::CUT_AT_END, settings.cut_at_end);
+    mode.set(ExpandedMode::HIGH_RESOLUTION, settings.high_resolution);
+    mode
+}
+
+/// Append the print information command (ESC i z) of a page.
+///
+/// `raster_count` is the number of raster lines of the page, counting a
+/// black and red pair of a two-color page once. `starting_page` flags the
+/// first page of a job or of a new document within it, the other pages
+/// follow the page before them.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::protocol;
+/// # use ql_label::{ContinuousType, Media};
+/// let media = Media::Continuous(ContinuousType::Continuous62);
+/// let mut buf = Vec::new();
+/// protocol::print_information(&mut buf, media, 100, false);
+/// assert_eq!(buf.len(), 13);
+/// // n9: 1 for the pages following the starting page
+/// assert_eq!(buf[11], 0x01);
+/// ```
+pub fn print_information(buf: &mut Vec<u8>, media: Media, raster_count: u32, starting_page: bool) {
+    buf.extend_from_slice(&[0x1B, 0x69, 0x7A]); // ESC i z
+
+    // n1: 有効フラグ (用紙種類+幅+長さ+ラスター数)
+    let valid_flags = 0x02 | 0x04 | 0x08 | 0x40;
+    buf.push(valid_flags);
+
+    // n2: 用紙種類 (長尺:0x0A, ダイカット:0x0C)
+    let media_type = match media {
+        Media::Continuous(_) => 0x0A,
+        Media::DieCut(_) => 0x0B,
+    };
+    buf.push(media_type);
+
+    // n3, n4: 用紙幅・長さ (mm)
+    let spec = media.spec();
+    buf.push(spec.width_mm());
+    buf.push(spec.length_mm());
+
+    // n5-n8: ラスター数 (リトルエンディアン)
+    buf.extend_from_slice(&raster_count.to_le_bytes());
+
+    // n9: 先頭ページフラグ (0=先頭ページ, 1=その他のページ)
+    buf.push(if starting_page { 0x00 } else { 0x01 });
+
+    // n10: 固定値
+    buf.push(0x00);
+}
+
+/// Append a raster line (g), packed with `pack_bits()` when `compress` is
+/// set.
+///
+/// `row` holds a byte for every 8 pins of the print head, see
+/// `Model::row_bytes()`: 90 bytes, or 162 on wide printers.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::protocol;
+/// let mut buf = Vec::new();
+/// protocol::raster_row(&mut buf, &[0x00; 162], false);
+/// assert_eq!(&buf[..3], &[0x67, 0x00, 162]);
+/// ```
+pub fn raster_row(buf: &mut Vec<u8>, row: &[u8], compress: bool) {
+    if compress {
+        let packed = pack_bits(row);
+        buf.extend_from_slice(&[0x67, 0x00, packed.len() as u8]);
+        buf.extend_from_slice(&packed);
+    } else {
+        buf.extend_from_slice(&[0x67, 0x00, row.len() as u8]);
+        buf.extend_from_slice(row);
+    }
+}
+
+/// Append a raster line of a two-color page (w), black or `red`.
+pub fn two_color_row(buf: &mut Vec<u8>, row: &[u8], red: bool) {
+    let color = if red { 0x02 } else { 0x01 };
+    buf.extend_from_slice(&[0x77, color, row.len() as u8]);
+    buf.extend_from_slice(row);
+}
+
+/// Append a complete black and white page: the print information, the raster
+/// rows and `PRINT`, or `PRINT_AND_EJECT` for the `last` page of a job.
+/// `starting_page` is set for the first page, see `print_information()`.
+///
+/// This is what `Printer` sends for every page, so a job for another
+/// channel, such as a CUPS raw queue or a file, is `initialize()`,
+/// `job_commands()` and one `page()` per label.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::protocol;
+/// # use ql_label::{DieCutType, Media};
+/// let media = Media::DieCut(DieCutType::DieCut29x90);
+/// let rows = vec![vec![0xFF; 90]; 2];
+///
+/// let mut job = Vec::new();
+/// protocol::page(&mut job, media, &rows, false, true, true);
+/// assert_eq!(protocol::decode(&job)?.pages, vec![rows]);
+/// # Ok::<(), String>(())
+/// ```
+pub fn page(
+    buf: &mut Vec<u8>,
+    media: Media,
+    rows: &[Vec<u8>],
+    compress: bool,
+    starting_page: bool,
+    last: bool,
+) {
+    print_information(buf, media, rows.len() as u32, starting_page);
+    for row in rows {
+        raster_row(buf, row, compress);
+    }
+    buf.push(if last { PRINT_AND_EJECT } else { PRINT });
+}
+
+/// Print job decoded by `decode()`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Job {
+    /// Media of the first page, `None` when the job has no print information
+    /// or the media is unknown.
+    pub media: Option<Media>,
+    /// Settings of the job. The feed is the one of the job commands, pages
+    /// may override it.
+    pub settings: Settings,
+    /// Expanded mode byte as sent, including undocumented bits.
+    pub expanded_mode: ExpandedMode,
+    /// Raster lines of every printed page, uncompressed. Two-color pages
+    /// keep the alternating black and red lines.
+    pub pages: Vec<crate::Matrix>,
+}
+
+/// Decode a raster job, e.g. a `.prn` file or a capture of another driver,
+/// back into its settings and pages.
+///
+/// Rows of a page that is never printed (FF or Control-Z) are dropped.
+///
+/// # Returns
+/// * `Ok(Job)` - Decoded job
+/// * `Err(String)` - The job ends in the middle of a command or holds a
+///   broken raster line
+///
+/// # Example
+/// ```rust
+/// # use ql_label::protocol::{self, ExpandedMode, Settings};
+/// # use ql_label::{ContinuousType, Media};
+/// let media = Media::Continuous(ContinuousType::Continuous29);
+/// let settings = Settings {
+///     auto_cut: None,
+///     cut_at_end: true,
+///     high_resolution: false,
+///     two_colors: false,
+///     feed_dots: 35,
+///     compress: true,
+///     auto_power_off: None,
+///     auto_power_on: None,
+/// };
+///
+/// let mut job = protocol::initialize();
+/// job.extend(protocol::job_commands(media, &settings, ExpandedMode::empty())?);
+/// protocol::print_information(&mut job, media, 2, true);
+/// protocol::raster_row(&mut job, &[0x0F; 90], true);
+/// protocol::raster_row(&mut job, &[0xF0; 90], true);
+/// job.push(protocol::PRINT_AND_EJECT);
+///
+/// let decoded = protocol::decode(&job)?;
+/// assert_eq!(decoded.media, Some(media));
+/// assert_eq!(decoded.settings, settings);
+/// assert_eq!(decoded.pages, vec![vec![vec![0x0F; 90], vec![0xF0; 90]]]);
+/// # Ok::<(), String>(())
+/// ```
+pub fn decode(buf: &[u8]) -> Result<Job, String> {
+    let mut settings = Settings {
+        auto_cut: None,
+        cut_at_end: false,
+        high_resolution: false,
+        two_colors: false,
+        feed_dots: 0,
+        compress: false,
+        auto_power_off: None,
+        auto_power_on: None,
+    };
+    let mut expanded_mode = ExpandedMode::empty();
+    let mut auto_cut = false;
+    let mut auto_cut_number = 1;
+    let mut feed = None;
+    let mut information = None;
+    let mut page = Vec::new();
+    let mut pages = Vec::new();
+
+    let mut pos = 0;
+    while pos < buf.len() {
+        let (command, len) = Command::parse(&buf[pos..])
+            .ok_or_else(|| format!("Job ends in the middle of a command at byte {}", pos))?;
+        match command {
+            Command::Initialize => page.clear(),
+            Command::Margin(dots) => {
+                feed.get_or_insert(dots);
+            }
+            Command::VariousMode(mode) => auto_cut = mode & 0b0100_0000 != 0,
+            Command::AutoCutNumber(n) => auto_cut_number = n,
+            Command::ExpandedMode(mode) => expanded_mode = ExpandedMode::from_bits_truncate(mode),
+            Command::Compression(mode) => settings.compress = mode == 0x02,
+            Command::PrinterSetting {
+                setting: AUTO_POWER_OFF,
+                value,
+            } => settings.auto_power_off = Some(value.saturating_mul(10)),
+            Command::PrinterSetting {
+                setting: AUTO_POWER_ON,
+                value,
+            } => settings.auto_power_on = Some(value != 0),
+            Command::PrintInformation {
+                media_type,
+                width_mm,
+                length_mm,
+                ..
+            } => {
+                information.get_or_insert((media_type, width_mm, length_mm));
+            }
+            Command::Raster(data) if settings.compress => {
+                page.push(unpack_bits(&data).map_err(|e| format!("{} at byte {}", e, pos))?)
+            }
+            Command::Raster(data) | Command::TwoColorRaster { data, .. } => page.push(data),
+            // Sized once the width of the other rows of the page is known
+            Command::ZeroRaster => page.push(Vec::new()),
+            Command::Print | Command::PrintAndEject => {
+                let width = page
+                    .iter()
+                    .map(Vec::len)
+                    .max()
+                    .filter(|&n| n > 0)
+                    .unwrap_or(90);
+                for row in page.iter_mut().filter(|row| row.is_empty()) {
+                    *row = vec![0x00; width];
+                }
+                pages.push(core::mem::take(&mut page));
+            }
+            _ => {}
+        }
+        pos += len;
+    }
+
+    settings.auto_cut = if auto_cut {
+        Some(auto_cut_number)
+    } else {
+        None
+    };
+    settings.cut_at_end = expanded_mode.contains(ExpandedMode::CUT_AT_END);
+    settings.high_resolution = expanded_mode.contains(ExpandedMode::HIGH_RESOLUTION);
+    settings.two_colors = expanded_mode.contains(ExpandedMode::TWO_COLORS);
+    settings.feed_dots = feed.unwrap_or(0);
+
+    let media = information.and_then(|(media_type, width_mm, length_mm)| {
+        Media::ALL.iter().copied().find(|media| {
+            let spec = media.spec();
+            let (kind, length) = match media {
+                Media::Continuous(_) => (0x0A, length_mm),
+                Media::DieCut(_) => (0x0B, spec.length_mm()),
+            };
+            // Red and black tape can only be told apart by the two-color mode
+            let red = *media == Media::Continuous(ContinuousType::Continuous62Red);
+            kind == media_type
+                && spec.width_mm() == width_mm
+                && length == length_mm
+                && (media_type != 0x0A || width_mm != 62 || red == settings.two_colors)
+        })
+    });
+
+    Ok(Job {
+        media,
+        settings,
+        expanded_mode,
+        pages,
+    })
+}
+
+/// Human readable listing of a raster job, one command per line.
+///
+/// Every line starts with the byte offset of the command. Runs of raster
+/// lines are collapsed into a single line with their count, so a listing
+/// of a long job stays short.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::protocol;
+/// # use ql_label::{ContinuousType, Media};
+/// let media = Media::Continuous(ContinuousType::Continuous62);
+/// let mut job = protocol::initialize();
+/// protocol::print_information(&mut job, media, 2, true);
+/// protocol::raster_row(&mut job, &[0x00; 90], false);
+/// protocol::raster_row(&mut job, &[0xFF; 90], false);
+/// job.push(protocol::PRINT_AND_EJECT);
+///
+/// let listing = protocol::disassemble(&job);
+/// assert_eq!(
+///     listing.lines().collect::<Vec<_>>(),
+///     [
+///         "0000  00 x 400      Invalidate",
+///         "0190  ESC @         Initialize",
+///         "0192  ESC i z       Print information: continuous 62 mm, 2 raster lines, \
+///          valid flags 0x4e, starting page 0",
+///         "019f  g x 2         2 raster lines, 186 bytes",
+///         "0259  ^Z            Print and eject",
+///     ]
+/// );
+/// ```
+pub fn disassemble(buf: &[u8]) -> String {
+    let mut listing = String::new();
+    // Offset, count and size of the raster lines not listed yet
+    let mut rasters: Option<(usize, usize, usize, &str)> = None;
+    let mut pos = 0;
+
+    while pos < buf.len() {
+        let (command, len) = match Command::parse(&buf[pos..]) {
+            Some(parsed) => parsed,
+            None => {
+                flush_rasters(&mut listing, &mut rasters);
+                let _ = writeln!(
+                    listing,
+                    "{:04x}  {:<14}Truncated command, {} bytes",
+                    pos,
+                    "...",
+                    buf.len() - pos
+                );
+                break;
+            }
+        };
+        let raster = match command {
+            Command::Raster(_) => Some("g"),
+            Command::TwoColorRaster { .. } => Some("w"),
+            Command::ZeroRaster => Some("Z"),
+            _ => None,
+        };
+        match (raster, &mut rasters) {
+            (Some(code), Some((_, count, size, current))) if *current == code => {
+                *count += 1;
+                *size += len;
+            }
+            (Some(code), _) => {
+                flush_rasters(&mut listing, &mut rasters);
+                rasters = Some((pos, 1, len, code));
+            }
+            (None, _) => {
+                flush_rasters(&mut listing, &mut rasters);
+                let (mnemonic, description) = describe(&command);
+                let _ = writeln!(listing, "{:04x}  {:<14}{}", pos, mnemonic, description);
+            }
+        }
+        pos += len;
+    }
+    flush_rasters(&mut listing, &mut rasters);
+    listing
+}
+
+fn flush_rasters(listing: &mut String, rasters: &mut Option<(usize, usize, usize, &str)>) {
+    if let Some((pos, count, size, code)) = rasters.take() {
+        let kind = match code {
+            "w" => "two-color raster lines",
+            "Z" => "blank raster lines",
+            _ => "raster lines",
+        };
+        let mnemonic = format!("{} x {}", code, count);
+        let _ = writeln!(
+            listing,
+            "{:04x}  {:<14}{} {}, {} bytes",
+            pos, mnemonic, count, kind, size
+        );
+    }
+}
+
+/// Mnemonic and description of a command other than a raster line.
+fn describe(command: &Command) -> (String, String) {
+    let esc_i = |code: &str, n: u8| format!("ESC i {} {:02x}", code, n);
+    match command {
+        Command::Invalidate(n) => (format!("00 x {}", n), "Invalidate".into()),
+        Command::Initialize => ("ESC @".into(), "Initialize".into()),
+        Command::StatusRequest => ("ESC i S".into(), "Status request".into()),
+        Command::SwitchMode(mode) => (
+            esc_i("a", *mode),
+            match mode {
+                0x01 => "Switch to raster mode".into(),
+                _ => format!("Switch to mode {}", mode),
+            },
+        ),
+        Command::StatusNotification(n) => (
+            esc_i("!", *n),
+            match n {
+                0x00 => "Automatic status notification on".into(),
+                _ => "Automatic status notification off".into(),
+            },
+        ),
+        Command::Margin(dots) => (
+            format!("ESC i d {:02x} {:02x}", dots & 0xFF, dots >> 8),
+            format!("Feed {} dots", dots),
+        ),
+        Command::VariousMode(mode) => (
+            esc_i("M", *mode),
+            format!(
+                "Various mode: auto cut {}",
+                if mode & 0b0100_0000 != 0 { "on" } else { "off" }
+            ),
+        ),
+        Command::AutoCutNumber(n) => (esc_i("A", *n), format!("Cut every {} labels", n)),
+        Command::ExpandedMode(mode) => (
+            esc_i("K", *mode),
+            format!(
+                "Expanded mode: {:?}",
+                ExpandedMode::from_bits_truncate(*mode)
+            ),
+        ),
+        Command::PrinterSetting { setting, value } => (
+            format!("ESC i U {:02x} 00 {:02x}", setting, value),
+            match *setting {
+                AUTO_POWER_OFF if *value == 0 => "Auto power-off disabled".into(),
+                AUTO_POWER_OFF => format!("Auto power-off after {} minutes", *value as u32 * 10),
+                AUTO_POWER_ON => {
+                    format!("Auto power-on {}", if *value != 0 { "on" } else { "off" })
+                }
+                _ => format!("Printer setting {:02x}: {}", setting, value),
+            },
+        ),
+        Command::PrintInformation {
+            valid_flags,
+            media_type,
+            width_mm,
+            length_mm,
+            raster_count,
+            starting_page,
+        } => {
+            let media = match media_type {
+                0x0A => format!("continuous {} mm", width_mm),
+                0x0B => format!("die-cut {}x{} mm", width_mm, length_mm),
+                _ => format!(
+                    "media type {:#04x} {}x{} mm",
+                    media_type, width_mm, length_mm
+                ),
+            };
+            (
+                "ESC i z".into(),
+                format!(
+                    "Print information: {}, {} raster lines, valid flags {:#04x}, starting page {}",
+                    media, raster_count, valid_flags, starting_page
+                ),
+            )
+        }
+        Command::Compression(mode) => (
+            format!("M {:02x}", mode),
+            match mode {
+                0x02 => "Compression: PackBits".into(),
+                _ => "Compression: none".into(),
+            },
+        ),
+        Command::Print => ("FF".into(), "Print".into()),
+        Command::PrintAndEject => ("^Z".into(), "Print and eject".into()),
+        Command::Unknown(b) => (format!("{:02x}", b), "Unknown".into()),
+        Command::Raster(_) | Command::TwoColorRaster { .. } | Command::ZeroRaster => {
+            (String::new(), String::new())
+        }
+    }
+}
+
+/// TIFF PackBits圧縮アルゴリズム（Brother QL仕様準拠）
+///
+/// 仕様:
+/// - 同一データ連続：個数-1を負数で指定 + データ1バイト
+/// - 異なるデータ連続：個数-1を正数で指定 + 全データ
+/// - 元のサイズ超過時は非圧縮のリテラル実行として送信
+/// - ラスタ1行のサイズ（90バイト、ワイド機は162バイト）以外はそのまま返す
+pub fn pack_bits(data: &[u8]) -> Vec<u8> {
+    // 入力データがラスタ1行のサイズでない場合はそのまま返す
+    let normal = (crate::NORMAL_PRINTER_WIDTH / 8) as usize;
+    let wide = (crate::WIDE_PRINTER_WIDTH / 8) as usize;
+    if data.len() != normal && data.len() != wide {
+        return data.to_vec();
+    }
+
+    let mut packed = Vec::new();
+    let mut i = 0;
+
+    while i < data.len() {
+        // Run-length encoding (RLE)のチェック
+        let mut run_length = 1;
+        let run_value = data[i];
+
+        // 同じ値の連続をカウント（最大128個まで）
+        while i + run_length < data.len() && run_length < 128 && data[i + run_length] == run_value {
+            run_length += 1;
+        }
+
+        // RLEが効果的な場合（2個以上の連続）
+        if run_length >= 2 {
+            // 負数で圧縮指示: -(count-1)
+            packed.push((1 - run_length as i16) as u8);
+            packed.push(run_value);
+            i += run_length;
+        } else {
+            // リテラル実行のチェック
+            let start_pos = i;
+            let mut literal_length = 1;
+
+            // リテラル実行の最適な長さを決定
+            while i + literal_length < data.len() && literal_length < 128 {
+                // 次の位置で2個以上同じ値が続く場合は、ここでリテラル実行を終了
+                if i + literal_length + 1 < data.len()
+                    && data[i + literal_length] == data[i + literal_length + 1]
+                {
+                    break;
+                }
+                literal_length += 1;
+            }
+
+            // リテラル実行: 正数で非圧縮指示
+            packed.push((literal_length - 1) as u8);
+            packed.extend_from_slice(&data[start_pos..start_pos + literal_length]);
+            i += literal_length;
+        }
+    }
+
+    // 重要な最適化: 元のサイズ超過時は非圧縮として返す
+    // （90バイトなら 89 + 元データの91バイト、リテラル実行は最大128バイト）
+    if packed.len() > data.len() {
+        warn!(
+            "Data compression ineffective, sending uncompressed ({} bytes)",
+            data.len()
+        );
+        let mut result = Vec::with_capacity(data.len() + 2);
+        for literal in data.chunks(128) {
+            result.push((literal.len() - 1) as u8);
+            result.extend_from_slice(literal);
+        }
+        result
+    } else {
+        debug!(
+            "Compression reduced data from {} to {} bytes ({:.1}% reduction)",
+            data.len(),
+            packed.len(),
+            (1.0 - packed.len() as f64 / data.len() as f64) * 100.0
+        );
+        packed
+    }
+}
+
+/// Expand a raster line packed with `pack_bits()`.
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` - Unpacked raster line
+/// * `Err(String)` - A run is cut off
+pub(crate) fn unpack_bits(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut unpacked = Vec::new();
+    let mut i = 0;
+
+    while i < data.len() {
+        let n = data[i] as i8;
+        i += 1;
+        if n < 0 {
+            let value = *data.get(i).ok_or("PackBits run is cut off")?;
+            unpacked.extend(core::iter::repeat_n(value, 1 + n.unsigned_abs() as usize));
+            i += 1;
+        } else {
+            let end = i + n as usize + 1;
+            let literal = data.get(i..end).ok_or("PackBits literal is cut off")?;
+            unpacked.extend_from_slice(literal);
+            i = end;
+        }
+    }
+    Ok(unpacked)
+}
+
+/// A single command of a raster job.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Command {
+    /// Run of zero bytes sent to flush a half-received job.
+    Invalidate(usize),
+    /// ESC @
+    Initialize,
+    /// ESC i S
+    StatusRequest,
+    /// ESC i a : Switch the command mode, `1` is raster mode.
+    SwitchMode(u8),
+    /// ESC i ! : Automatic status notification, `0` enables it.
+    StatusNotification(u8),
+    /// ESC i d : Feed amount in dots.
+    Margin(u16),
+    /// ESC i M : Various mode, bit 6 enables the auto cutter.
+    VariousMode(u8),
+    /// ESC i A : Cut after this many labels.
+    AutoCutNumber(u8),
+    /// ESC i K
+    ExpandedMode(u8),
+    /// ESC i U : Printer setting kept by the printer, e.g. the auto
+    /// power-off time.
+    PrinterSetting { setting: u8, value: u8 },
+    /// ESC i z : Print information of the following page.
+    PrintInformation {
+        valid_flags: u8,
+        media_type: u8,
+        width_mm: u8,
+        length_mm: u8,
+        raster_count: u32,
+        starting_page: u8,
+    },
+    /// M : Compression mode, `2` selects PackBits.
+    Compression(u8),
+    /// g : Raster line, packed when compression is enabled.
+    Raster(Vec<u8>),
+    /// w : Raster line of a two-color page, color `1` is black and `2` red.
+    TwoColorRaster { color: u8, data: Vec<u8> },
+    /// Z : Blank raster line.
+    ZeroRaster,
+    /// FF
+    Print,
+    /// Control-Z
+    PrintAndEject,
+    /// Byte not starting any known command.
+    Unknown(u8),
+}
+
+impl Command {
+    /// Parse the command at the start of `buf`.
+    ///
+    /// Returns the command and the number of bytes it takes, or `None` when
+    /// `buf` ends in the middle of a command.
+    pub(crate) fn parse(buf: &[u8]) -> Option<(Command, usize)> {
+        let command = match *buf.first()? {
+            0x00 => {
+                let n = buf.iter().take_while(|&&b| b == 0x00).count();
+                (Command::Invalidate(n), n)
+            }
+            0x0C => (Command::Print, 1),
+            0x1A => (Command::PrintAndEject, 1),
+            ZERO_RASTER => (Command::ZeroRaster, 1),
+            0x4D => (Command::Compression(*buf.get(1)?), 2),
+            0x67 => {
+                let n = *buf.get(2)? as usize;
+                (Command::Raster(buf.get(3..3 + n)?.to_vec()), 3 + n)
+            }
+            0x77 => {
+                let (color, n) = (*buf.get(1)?, *buf.get(2)? as usize);
+                let data = buf.get(3..3 + n)?.to_vec();
+                (Command::TwoColorRaster { color, data }, 3 + n)
+            }
+            0x1B => match *buf.get(1)? {
+                0x40 => (Command::Initialize, 2),
+                0x69 => match *buf.get(2)? {
+                    0x53 => (Command::StatusRequest, 3),
+                    0x61 => (Command::SwitchMode(*buf.get(3)?), 4),
+                    0x21 => (Command::StatusNotification(*buf.get(3)?), 4),
+                    0x64 => {
+                        let n = buf.get(3..5)?;
+                        (Command::Margin(u16::from_le_bytes([n[0], n[1]])), 5)
+                    }
+                    0x4D => (Command::VariousMode(*buf.get(3)?), 4),
+                    0x41 => (Command::AutoCutNumber(*buf.get(3)?), 4),
+                    0x4B => (Command::ExpandedMode(*buf.get(3)?), 4),
+                    0x55 => {
+                        let n = buf.get(3..6)?;
+                        let (setting, value) = (n[0], n[2]);
+                        (Command::PrinterSetting { setting, value }, 6)
+                    }
+                    0x7A => {
+                        let n = buf.get(3..13)?;
+                        let command = Command::PrintInformation {
+                            valid_flags: n[0],
+                            media_type: n[1],
+                            width_mm: n[2],
+                            length_mm: n[3],
+                            raster_count: u32::from_le_bytes([n[4], n[5], n[6], n[7]]),
+                            starting_page: n[8],
+                        };
+                        (command, 13)
+                    }
+                    _ => (Command::Unknown(0x1B), 1),
+                },
+                _ => (Command::Unknown(0x1B), 1),
+            },
+            b => (Command::Unknown(b), 1),
+        };
+        Some(command)
+    }
+}
+
+bitflags! {
+    /// Bits of the expanded mode byte sent with `ESC i K`.
+    ///
+    /// `Config` sets the documented bits from `two_colors()`, `cut_at_end()`
+    /// and `high_resolution()`. The remaining bits are marked "not used" in
+    /// the raster command reference; set them with
+    /// `Config::extra_expanded_mode()` to experiment with firmware specific
+    /// behaviour.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, ContinuousType, ExpandedMode, Media, Model};
+    /// let config = Config::new(Model::QL820NWB, "serial".to_string(),
+    ///                         Media::Continuous(ContinuousType::Continuous62))
+    ///     .high_resolution(true);
+    ///
+    /// let mode = config.expanded_mode();
+    /// assert_eq!(mode, ExpandedMode::CUT_AT_END | ExpandedMode::HIGH_RESOLUTION);
+    /// assert_eq!(mode.bits(), 0x48);
+    /// ```
+    pub struct ExpandedMode: u8 {
+        /// Print in black and red (QL-800 series).
+        const TWO_COLORS = 0b0000_0001;
+        /// Not used.
+        const RESERVED_1 = 0b0000_0010;
+        /// Not used.
+        const RESERVED_2 = 0b0000_0100;
+        /// Cut after the last label of a job.
+        const CUT_AT_END = 0b0000_1000;
+        /// Not used.
+        const RESERVED_4 = 0b0001_0000;
+        /// Not used.
+        const RESERVED_5 = 0b0010_0000;
+        /// Print at 300 x 600 DPI.
+        const HIGH_RESOLUTION = 0b0100_0000;
+        /// Not used.
+        const RESERVED_7 = 0b1000_0000;
+    }
+}
+
+/// Print settings shared by all jobs of a printer.
+///
+/// These are the values sent with every print job (ESC i d, ESC i M,
+/// ESC i A and ESC i K), and the power settings stored by the printer
+/// itself (ESC i U). Dump them from a configured printer with
+/// `Printer::dump_settings()` and apply them to others with
+/// `Printer::apply_settings()` to make a fleet of printers behave
+/// identically. With the `json` or `profiles` feature the struct can be
+/// serialized with serde.
+///
+/// The printer can't report its power settings, they are `None` unless
+/// they were set through the same `Printer`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Settings {
+    /// Cut after this many labels, `None` to disable the auto cutter.
+    pub auto_cut: Option<u8>,
+    /// Cut after the last label of a job.
+    pub cut_at_end: bool,
+    /// Print at 300 x 600 DPI.
+    pub high_resolution: bool,
+    /// Print in black and red.
+    pub two_colors: bool,
+    /// Feed amount (margin) in dots.
+    pub feed_dots: u16,
+    /// Compress raster data with PackBits.
+    pub compress: bool,
+    /// Minutes without a job before the printer turns itself off, 0 keeps
+    /// it on. `None` leaves the setting of the printer unchanged.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub auto_power_off: Option<u8>,
+    /// Turn the printer on when it is plugged in. `None` leaves the setting
+    /// of the printer unchanged.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub auto_power_on: Option<bool>,
+}
+
+///
+/// Status received from the printer encoded to Rust friendly type.
+///
+/// The 32-byte message doesn't report a battery level or the remaining
+/// tape; `Printer::media_state()` estimates the latter from the printed
+/// length. Bytes specific to a model can be read from `raw()`.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::protocol::{Notification, StatusType};
+/// # use ql_label::{ContinuousType, Media, Model, Status};
+/// let mut buf = [0x00; 32];
+/// buf[..5].copy_from_slice(&[0x80, 0x20, 0x42, 0x34, 0x41]);
+/// buf[10] = 62;
+/// buf[11] = 0x0A;
+/// buf[15] = 0x40;
+/// buf[18] = 0x05;
+/// buf[22] = 0x03;
+/// buf[25] = 0x81;
+///
+/// let status = Status::from_buf(buf);
+/// assert_eq!(status.model(), Model::QL820NWB);
+/// assert_eq!(status.media(), Some(Media::Continuous(ContinuousType::Continuous62Red)));
+/// assert_eq!(status.media_size_mm(), (62, 0));
+/// assert!(status.auto_cut());
+/// assert_eq!(status.status_type(), StatusType::Notification);
+/// assert_eq!(status.notification(), Notification::CoolingStarted);
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[allow(dead_code)]
+pub struct Status {
+    pub(crate) model: Model,
+    pub(crate) errors: PrinterErrors,
+    pub(crate) media: Option<Media>,
+    pub(crate) mode: u8,
+    pub(crate) status_type: StatusType,
+    pub(crate) phase: Phase,
+    pub(crate) notification: Notification,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) id: u8,
+    pub(crate) raw: [u8; 32],
+}
+
+impl Status {
+    /// Parse the 32-byte status response of the printer.
+    pub fn from_buf(buf: [u8; 32]) -> Self {
+        Status {
+            model: Model::from_code(buf[4]),
+            errors: PrinterErrors::from_buf(buf),
+            media: Media::from_buf(buf),
+            mode: buf[15],
+            status_type: StatusType::from_code(buf[18]),
+            phase: Phase::from_buf(buf),
+            notification: Notification::from_code(buf[22]),
+            id: buf[14],
+            raw: buf,
+        }
+    }
+
+    /// Printer model reported in the status.
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// Every error reported by the printer.
+    pub fn errors(&self) -> PrinterErrors {
+        self.errors
+    }
+
+    /// Installed media, `None` when missing or not recognized.
+    pub fn media(&self) -> Option<Media> {
+        self.media
+    }
+
+    /// Phase of the printer, receiving or printing.
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// What the message reports, e.g. the reply to a status request or the
+    /// completion of a page.
+    pub fn status_type(&self) -> StatusType {
+        self.status_type
+    }
+
+    /// Notification carried by a `StatusType::Notification` message.
+    pub fn notification(&self) -> Notification {
+        self.notification
+    }
+
+    /// Various mode byte the printer is set to, see `various_mode()`.
+    pub fn mode(&self) -> u8 {
+        self.mode
+    }
+
+    /// Whether the auto cutter is enabled in the current mode.
+    pub fn auto_cut(&self) -> bool {
+        self.mode & various_mode(Some(1)) != 0
+    }
+
+    /// Width and length of the installed media in millimetres as reported,
+    /// also for media `media()` doesn't recognize. The length is 0 for
+    /// continuous tape.
+    pub fn media_size_mm(&self) -> (u8, u8) {
+        (self.raw[10], self.raw[17])
+    }
+
+    /// The status message as received.
+    pub fn raw(&self) -> &[u8; 32] {
+        &self.raw
+    }
+}
+
+// StatusType
+
+/// Kind of a status message.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StatusType {
+    /// Reply to `STATUS_REQUEST`.
+    ReplyToRequest,
+    /// A page was printed.
+    Completed,
+    /// An error occurred, see `Status::error()`.
+    Error,
+    /// The printer turned off.
+    Offline,
+    /// See `Status::notification()`.
+    Notification,
+    /// The printer switched between receiving and printing.
+    PhaseChange,
+    /// Any other value.
+    Unknown,
+}
+
+impl StatusType {
+    fn from_code(code: u8) -> StatusType {
+        match code {
+            0x00 => Self::ReplyToRequest,
+            0x01 => Self::Completed,
+            0x02 => Self::Error,
+            0x04 => Self::Offline,
+            0x05 => Self::Notification,
+            0x06 => Self::PhaseChange,
+            _ => Self::Unknown,
+        }
+    }
+}
+// Phase
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Phase {
+    Receiving,
+    Printing,
+    Waiting(u16),
+    // Printing(u16),
+}
+
+impl Phase {
+    fn from_buf(buf: [u8; 32]) -> Self {
+        match buf[19] {
+            0x00 => Self::Receiving,
+            0x01 => Self::Printing,
+            _ => Self::Waiting(0),
+        }
+    }
+}
+
+// Notification
+
+/// Notification sent by the printer on its own.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Notification {
+    /// No notification.
+    NotAvailable,
+    /// The print head overheated, printing pauses.
+    CoolingStarted,
+    /// The print head cooled down, printing resumes.
+    CoolingFinished,
+}
+
+impl Notification {
+    fn from_code(code: u8) -> Self {
+        match code {
+            0x03 => Self::CoolingStarted,
+            0x04 => Self::CoolingFinished,
+            _ => Self::NotAvailable,
+        }
+    }
+}
+
+/// Hardware-specific errors reported by the printer.
+///
+/// These errors are parsed from the printer's status response and indicate
+/// physical problems with the device that need user intervention.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PrinterError {
+    // Following errors are read from printer status
+    NoMedia,
+
+    EndOfMedia,
+
+    CutterJam,
+
+    PrinterInUse,
+
+    PrinterOffline,
+
+    InvalidMedia,
+
+    BufferFull,
+
+    CommunicationError,
+
+    CoverOpen,
+
+    FeedMediaFail,
+
+    SystemError,
+
+    UnknownError((u8, u8)),
+}
+
+impl PrinterError {
+    /// Parse printer error from 32-byte status buffer.
+    ///
+    /// Analyzes bytes 8 and 9 of the printer status response to determine
+    /// the specific error condition reported by the hardware.
+    ///
+    /// # Arguments
+    /// * `buf` - 32-byte status response from printer
+    ///
+    /// # Returns
+    /// The first error reported, see `PrinterErrors::primary()`, or
+    /// `UnknownError((0, 0))` if no error
+    pub fn from_buf(buf: [u8; 32]) -> Self {
+        PrinterErrors::from_buf(buf).primary()
+    }
+
+    /// Stable numeric code of this error, in the range 100-199.
+    ///
+    /// See `Error::code()`.
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::NoMedia => 101,
+            Self::EndOfMedia => 102,
+            Self::CutterJam => 103,
+            Self::PrinterInUse => 104,
+            Self::PrinterOffline => 105,
+            Self::InvalidMedia => 106,
+            Self::BufferFull => 107,
+            Self::CommunicationError => 108,
+            Self::CoverOpen => 109,
+            Self::FeedMediaFail => 110,
+            Self::SystemError => 111,
+            Self::UnknownError(_) => 199,
+        }
+    }
+
+    /// Printer error with the numeric `code`.
+    ///
+    /// Returns `None` for unknown codes and for `UnknownError`, whose raw
+    /// status bytes can't be restored from the code.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::PrinterError;
+    /// assert!(matches!(PrinterError::from_code(109), Some(PrinterError::CoverOpen)));
+    /// assert!(PrinterError::from_code(199).is_none());
+    /// ```
+    pub fn from_code(code: u16) -> Option<Self> {
+        match code {
+            101 => Some(Self::NoMedia),
+            102 => Some(Self::EndOfMedia),
+            103 => Some(Self::CutterJam),
+            104 => Some(Self::PrinterInUse),
+            105 => Some(Self::PrinterOffline),
+            106 => Some(Self::InvalidMedia),
+            107 => Some(Self::BufferFull),
+            108 => Some(Self::CommunicationError),
+            109 => Some(Self::CoverOpen),
+            110 => Some(Self::FeedMediaFail),
+            111 => Some(Self::SystemError),
+            _ => None,
+        }
+    }
+
+    /// Error bytes 8 and 9 of a status message reporting this error.
+    pub(crate) fn status_bytes(self) -> (u8, u8) {
+        match self {
+            Self::NoMedia => (0b0000_0001, 0),
+            Self::EndOfMedia => (0b0000_0010, 0),
+            Self::CutterJam => (0b0000_0100, 0),
+            Self::PrinterInUse => (0b0001_0000, 0),
+            Self::PrinterOffline => (0b0010_0000, 0),
+            Self::InvalidMedia => (0, 0b0000_0001),
+            Self::BufferFull => (0, 0b0000_0010),
+            Self::CommunicationError => (0, 0b0000_0100),
+            Self::CoverOpen => (0, 0b0001_0000),
+            Self::FeedMediaFail => (0, 0b0100_0000),
+            Self::SystemError => (0, 0b1000_0000),
+            Self::UnknownError(bytes) => bytes,
+        }
+    }
+
+    /// Check if this represents a "no error" state.
+    ///
+    /// Returns `true` if the printer is reporting no error condition.
+    /// Used to distinguish between actual errors and normal status.
+    ///
+    /// # Returns
+    /// `true` if no error is present, `false` otherwise
+    pub fn is_no_error(&self) -> bool {
+        matches!(self, Self::UnknownError((0, 0)))
+    }
+}
+
+impl fmt::Display for PrinterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NoMedia => "No media is installed",
+            Self::EndOfMedia => "End of media",
+            Self::CutterJam => "Cutter jam",
+            Self::PrinterInUse => "Printer is in use",
+            Self::PrinterOffline => "Printer if offline",
+            Self::InvalidMedia => "Installed media is not match",
+            Self::BufferFull => "Expansion buffer is full",
+            Self::CommunicationError => "Communication error",
+            Self::CoverOpen => "Cover is open",
+            Self::FeedMediaFail => "Media can not be fed",
+            Self::SystemError => "System error",
+            Self::UnknownError(_) => "Unknown error",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PrinterError {}
+
+bitflags! {
+    /// Every error condition of a status message.
+    ///
+    /// The printer sets a bit for each condition in bytes 8 (low byte) and
+    /// 9 (high byte) of its status, so an open cover can be reported
+    /// together with the end of the media. `PrinterError::from_buf()` only
+    /// returns the first of them.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{PrinterError, PrinterErrors};
+    /// let mut buf = [0x00; 32];
+    /// buf[8] = 0b0000_0010;
+    /// buf[9] = 0b0001_0000;
+    ///
+    /// let errors = PrinterErrors::from_buf(buf);
+    /// assert_eq!(errors, PrinterErrors::END_OF_MEDIA | PrinterErrors::COVER_OPEN);
+    /// assert_eq!(errors.bits(), 0x1002);
+    /// assert_eq!(
+    ///     errors.iter().collect::<Vec<_>>(),
+    ///     [PrinterError::EndOfMedia, PrinterError::CoverOpen]
+    /// );
+    /// assert_eq!(errors.primary(), PrinterError::EndOfMedia);
+    /// assert_eq!(errors.to_string(), "End of media, Cover is open");
+    /// assert!(PrinterErrors::from_buf([0x00; 32]).is_empty());
+    /// ```
+    #[derive(Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct PrinterErrors: u16 {
+        /// No media is installed.
+        const NO_MEDIA = 0x0001;
+        /// End of media (die-cut labels only).
+        const END_OF_MEDIA = 0x0002;
+        /// Cutter jam.
+        const CUTTER_JAM = 0x0004;
+        /// Not used.
+        const RESERVED_3 = 0x0008;
+        /// The printer is in use.
+        const PRINTER_IN_USE = 0x0010;
+        /// The printer was turned off.
+        const PRINTER_OFFLINE = 0x0020;
+        /// Not used.
+        const RESERVED_6 = 0x0040;
+        /// Not used.
+        const RESERVED_7 = 0x0080;
+        /// The installed media does not match the job.
+        const INVALID_MEDIA = 0x0100;
+        /// Expansion buffer full.
+        const BUFFER_FULL = 0x0200;
+        /// Transmission error.
+        const COMMUNICATION_ERROR = 0x0400;
+        /// Not used.
+        const RESERVED_11 = 0x0800;
+        /// The cover is open.
+        const COVER_OPEN = 0x1000;
+        /// Not used.
+        const RESERVED_13 = 0x2000;
+        /// The media can't be fed.
+        const FEED_MEDIA_FAIL = 0x4000;
+        /// System error.
+        const SYSTEM_ERROR = 0x8000;
+    }
+}
+
+impl PrinterErrors {
+    /// Error conditions in the order of their bits.
+    const ALL: [PrinterError; 11] = [
+        PrinterError::NoMedia,
+        PrinterError::EndOfMedia,
+        PrinterError::CutterJam,
+        PrinterError::PrinterInUse,
+        PrinterError::PrinterOffline,
+        PrinterError::InvalidMedia,
+        PrinterError::BufferFull,
+        PrinterError::CommunicationError,
+        PrinterError::CoverOpen,
+        PrinterError::FeedMediaFail,
+        PrinterError::SystemError,
+    ];
+
+    /// Parse error bytes 8 and 9 of a 32-byte status message.
+    pub fn from_buf(buf: [u8; 32]) -> Self {
+        Self::from_bits_truncate(u16::from_le_bytes([buf[8], buf[9]]))
+    }
+
+    /// The reported conditions in the order of their bits. Bits without a
+    /// documented meaning are returned last as one `UnknownError`.
+    pub fn iter(&self) -> impl Iterator<Item = PrinterError> {
+        let errors = *self;
+        let unknown = Self::ALL
+            .iter()
+            .fold(errors, |unknown, &error| unknown - error.into());
+        let [err_1, err_2] = unknown.bits().to_le_bytes();
+        Self::ALL
+            .iter()
+            .copied()
+            .filter(move |&error| errors.contains(error.into()))
+            .chain((!unknown.is_empty()).then_some(PrinterError::UnknownError((err_1, err_2))))
+    }
+
+    /// The first reported condition, or `UnknownError((0, 0))` if there is
+    /// none, like `PrinterError::from_buf()`.
+    pub fn primary(&self) -> PrinterError {
+        self.iter()
+            .next()
+            .unwrap_or(PrinterError::UnknownError((0, 0)))
+    }
+}
+
+impl From<PrinterError> for PrinterErrors {
+    fn from(error: PrinterError) -> Self {
+        let (err_1, err_2) = error.status_bytes();
+        Self::from_bits_truncate(u16::from_le_bytes([err_1, err_2]))
+    }
+}
+
+impl fmt::Display for PrinterErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("No error");
+        }
+        for (i, error) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_bits_compression() {
+        // テスト1: 効果的な圧縮（同一データ連続）
+        let all_zeros = vec![0u8; 90];
+        let compressed = pack_bits(&all_zeros);
+        println!(
+            "All zeros: {} -> {} bytes",
+            all_zeros.len(),
+            compressed.len()
+        );
+        assert!(compressed.len() < all_zeros.len(), "圧縮が効果的でない");
+
+        // テスト2: 非効果的な圧縮（ランダムデータ）
+        let random_data: Vec<u8> = (0..90).map(|i| (i * 37 + 17) as u8).collect();
+        let compressed_random = pack_bits(&random_data);
+        println!(
+            "Random data: {} -> {} bytes",
+            random_data.len(),
+            compressed_random.len()
+        );
+
+        // テスト3: 91バイト制限の確認
+        if compressed_random.len() > 90 {
+            println!("91バイト制限により非圧縮データが返される");
+            assert_eq!(compressed_random.len(), 91); // 89 + 90バイトの元データ
+            assert_eq!(compressed_random[0], 89); // 非圧縮指示
+        }
+
+        // テスト4: 混合パターン（部分的な圧縮効果）
+        let mut mixed_data = vec![0u8; 30];
+        mixed_data.extend(vec![255u8; 30]);
+        mixed_data.extend((0..30).map(|i| i as u8));
+        let compressed_mixed = pack_bits(&mixed_data);
+        println!(
+            "Mixed data: {} -> {} bytes",
+            mixed_data.len(),
+            compressed_mixed.len()
+        );
+    }
+
+    #[test]
+    fn test_pack_bits_edge_cases() {
+        // エッジケース1: 空のデータ
+        let empty_data = vec![];
+        let compressed_empty = pack_bits(&empty_data);
+        assert_eq!(compressed_empty, empty_data);
+
+        // エッジケース2: 90バイト以外のサイズ
+        let wrong_size = vec![42u8; 50];
+        let compressed_wrong = pack_bits(&wrong_size);
+        assert_eq!(compressed_wrong, wrong_size);
+
+        // エッジケース3: 単一バイトの繰り返し（最大圧縮）
+        let single_byte = vec![42u8; 90];
+        let compressed_single = pack_bits(&single_byte);
+        assert_eq!(compressed_single.len(), 2); // 長さ指示 + データ
+        assert_eq!(compressed_single[0], (-(90i8 - 1)) as u8); // -89
+        assert_eq!(compressed_single[1], 42);
+    }
+
+    #[test]
+    fn test_pack_bits_wide_row() {
+        // ワイド機の162バイト: 128バイトの連続を超える
+        let blank = vec![0u8; 162];
+        assert_eq!(pack_bits(&blank), vec![0x81, 0x00, 0xDF, 0x00]);
+        assert_eq!(unpack_bits(&pack_bits(&blank)).unwrap(), blank);
+
+        // 非効果的な圧縮: 2つのリテラル実行
+        let random_data: Vec<u8> = (0..162).map(|i| (i * 37 + 17) as u8).collect();
+        let packed = pack_bits(&random_data);
+        assert_eq!(packed.len(), 164);
+        assert_eq!((packed[0], packed[129]), (127, 33));
+        assert_eq!(unpack_bits(&packed).unwrap(), random_data);
+    }
+
+    #[test]
+    fn test_printer_errors() {
+        let mut buf = [0x00; 32];
+        buf[4] = 0x41;
+        buf[8] = 0b0000_1001;
+        buf[9] = 0b0001_0000;
+        let errors = PrinterErrors::from_buf(buf);
+        assert_eq!(
+            errors.iter().collect::<Vec<_>>(),
+            vec![
+                PrinterError::NoMedia,
+                PrinterError::CoverOpen,
+                PrinterError::UnknownError((0b0000_1000, 0)),
+            ]
+        );
+        assert_eq!(
+            Status::from_buf(buf).errors().primary(),
+            PrinterError::NoMedia
+        );
+        assert!(PrinterError::from_buf([0x00; 32]).is_no_error());
+        assert_eq!(
+            PrinterErrors::from(PrinterError::SystemError).primary(),
+            PrinterError::SystemError
+        );
+    }
+}
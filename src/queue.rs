@@ -0,0 +1,932 @@
+//! Background print queue.
+//!
+//! `JobQueue` owns a printer and prints submitted jobs one after another on a
+//! worker thread. Jobs get an id to query their state or cancel them, which
+//! is what a daemon needs to accept work from several clients.
+//!
+//! Jobs carry a priority: the queued job with the highest priority is printed
+//! next, so an urgent reprint does not wait for a batch run of hundreds of
+//! labels. A queued job is promoted by one level every time `AGING` jobs
+//! submitted after it were started first, so low priority work is never
+//! starved by a steady stream of urgent jobs.
+//!
+//! A `Throttle` paces the jobs of a high duty cycle line: fixed pauses
+//! between jobs and labels, a rest after printing without a break for too
+//! long, and a longer rest once the printer had to cool down its print head,
+//! before it shuts down from overheating.
+//!
+//! Jobs failing with a transient error, such as a USB timeout, can be retried
+//! with `JobQueue::set_retry()`, resuming after the last printed label. With
+//! `JobQueue::persist()` the queued jobs are kept in a directory, so a kiosk
+//! restarted in the middle of a run prints them in the same order.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use crate::printer::{PrintEvent, PrinterHandle};
+use crate::Matrix;
+
+/// Identifier of a job submitted to a `JobQueue`.
+pub type JobId = u64;
+
+/// Priority of a job, higher priorities are printed first.
+pub type Priority = u8;
+
+/// Number of finished jobs kept for `JobQueue::jobs()`.
+const FINISHED_JOBS: usize = 100;
+
+/// Number of jobs overtaking a queued job before it is promoted by one
+/// priority level.
+const AGING: u32 = 4;
+
+/// Pacing of the jobs of a `JobQueue`.
+///
+/// No pauses are made by default.
+///
+/// # Example
+/// ```rust
+/// # use std::time::Duration;
+/// # use ql_label::Throttle;
+/// // Rest 30 seconds after every 5 minutes of printing and 2 minutes after
+/// // the printer had to cool down
+/// let throttle = Throttle::new()
+///     .job_pause(Duration::from_millis(500))
+///     .duty_cycle(Duration::from_secs(300), Duration::from_secs(30))
+///     .cooling_pause(Duration::from_secs(120));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Throttle {
+    job_pause: Duration,
+    page_pause: Duration,
+    duty_cycle: Option<(Duration, Duration)>,
+    cooling_pause: Duration,
+}
+
+impl Throttle {
+    /// Throttle making no pauses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wait at least `pause` between the end of a job and the start of the
+    /// next one.
+    pub fn job_pause(self, pause: Duration) -> Self {
+        Throttle {
+            job_pause: pause,
+            ..self
+        }
+    }
+
+    /// Wait `pause` after every label of a job except the last one.
+    pub fn page_pause(self, pause: Duration) -> Self {
+        Throttle {
+            page_pause: pause,
+            ..self
+        }
+    }
+
+    /// Rest for `rest` before starting a job once the printer has been busy
+    /// for `busy` without a break of at least `rest`.
+    pub fn duty_cycle(self, busy: Duration, rest: Duration) -> Self {
+        Throttle {
+            duty_cycle: Some((busy, rest)),
+            ..self
+        }
+    }
+
+    /// Wait `pause` before the next job when the printer had to cool down
+    /// its print head during a job.
+    pub fn cooling_pause(self, pause: Duration) -> Self {
+        Throttle {
+            cooling_pause: pause,
+            ..self
+        }
+    }
+}
+
+/// State of a job in a `JobQueue`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobState {
+    /// Waiting for the jobs ahead of it.
+    Queued,
+    /// Being printed.
+    Printing,
+    /// Waiting for the operator to install the media of the printer's
+    /// configuration, see `Config::wait_for_media_change()`.
+    WaitingForMedia,
+    /// Every page was printed.
+    Done,
+    /// Printing failed with the given error.
+    Failed(String),
+    /// Cancelled before all pages were printed.
+    Cancelled,
+}
+
+impl JobState {
+    /// Whether the job is finished, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, Self::Queued | Self::Printing | Self::WaitingForMedia)
+    }
+}
+
+/// A job known to a `JobQueue`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobInfo {
+    pub id: JobId,
+    /// Number of pages submitted.
+    pub pages: usize,
+    /// Number of pages printed so far.
+    pub printed: usize,
+    /// Priority the job was submitted with.
+    pub priority: Priority,
+    /// Number of times printing was retried after a transient error, see
+    /// `JobQueue::set_retry()`.
+    pub retries: u32,
+    pub state: JobState,
+}
+
+struct Pending {
+    id: JobId,
+    pages: Vec<Matrix>,
+    priority: Priority,
+    // Jobs submitted later but started first
+    overtaken: u32,
+}
+
+struct Queue {
+    next_id: JobId,
+    // In submission order
+    pending: Vec<Pending>,
+    jobs: BTreeMap<JobId, JobInfo>,
+    // Set to stop the job being printed at the next label boundary
+    cancel_current: bool,
+    // Attempts after a transient error and the delay before each of them
+    retry: (u32, Duration),
+    // Directory the queued jobs are saved in, see `JobQueue::persist()`
+    journal: Option<PathBuf>,
+    shutdown: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    wakeup: Condvar,
+}
+
+impl Shared {
+    fn queue(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Queue {
+    /// Queue a job, saving it to the journal if there is one.
+    fn push(&mut self, pages: Vec<Matrix>, priority: Priority) -> JobId {
+        let id = self.next_id;
+        self.next_id += 1;
+        if let Some(dir) = &self.journal {
+            if let Err(err) = save_job(&job_path(dir, id), priority, &pages) {
+                warn!("Job {} is not persisted: {}", id, err);
+            }
+        }
+        self.jobs.insert(
+            id,
+            JobInfo {
+                id,
+                pages: pages.len(),
+                printed: 0,
+                priority,
+                retries: 0,
+                state: JobState::Queued,
+            },
+        );
+        self.pending.push(Pending {
+            id,
+            pages,
+            priority,
+            overtaken: 0,
+        });
+        id
+    }
+
+    /// Take the job to print next, ageing the older jobs it overtakes.
+    fn take_next(&mut self) -> Option<Pending> {
+        let next = self.pending.remove(next(
+            self.pending.iter().map(|job| (job.priority, job.overtaken)),
+        )?);
+        for job in self.pending.iter_mut().take_while(|job| job.id < next.id) {
+            job.overtaken += 1;
+        }
+        Some(next)
+    }
+
+    /// Ids of the queued jobs in printing order, unless more jobs are
+    /// submitted.
+    fn order(&self) -> Vec<JobId> {
+        let mut pending: Vec<(JobId, Priority, u32)> = self
+            .pending
+            .iter()
+            .map(|job| (job.id, job.priority, job.overtaken))
+            .collect();
+        let mut order = Vec::with_capacity(pending.len());
+        while let Some(i) = next(
+            pending
+                .iter()
+                .map(|&(_, priority, overtaken)| (priority, overtaken)),
+        ) {
+            let (id, ..) = pending.remove(i);
+            for job in pending.iter_mut().take_while(|job| job.0 < id) {
+                job.2 += 1;
+            }
+            order.push(id);
+        }
+        order
+    }
+}
+
+/// Index of the job to print next among queued jobs given as their priority
+/// and number of times they were overtaken, in submission order.
+fn next(jobs: impl Iterator<Item = (Priority, u32)>) -> Option<usize> {
+    // The first of the jobs with the highest priority after ageing
+    let mut best: Option<(usize, u32)> = None;
+    for (i, (priority, overtaken)) in jobs.enumerate() {
+        let effective = priority as u32 + overtaken / AGING;
+        if best.is_none_or(|(_, highest)| effective > highest) {
+            best = Some((i, effective));
+        }
+    }
+    best.map(|(i, _)| i)
+}
+
+/// Print queue running jobs on a background thread.
+///
+/// Dropping the queue finishes the job being printed and discards the
+/// remaining ones.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{Config, ContinuousType, Emulator, JobQueue, JobState, Media, Model, Printer};
+/// let media = Media::Continuous(ContinuousType::Continuous62);
+/// let emulator = Emulator::new(Model::QL800, Some(media));
+/// let config = Config::new(Model::QL800, "emulator".to_string(), media);
+/// let queue = JobQueue::new(Printer::with_transport(config, emulator.clone())?.handle());
+///
+/// let id = queue.submit(vec![vec![vec![0xFF; 90]; 100]]);
+/// assert_eq!(queue.wait(id).unwrap().state, JobState::Done);
+/// assert_eq!(emulator.pages().len(), 1);
+/// # Ok::<(), ql_label::Error>(())
+/// ```
+pub struct JobQueue {
+    printer: PrinterHandle,
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl JobQueue {
+    /// Start a queue printing on `printer`.
+    pub fn new(printer: PrinterHandle) -> Self {
+        Self::with_throttle(printer, Throttle::new())
+    }
+
+    /// Start a queue printing on `printer`, pacing the jobs with `throttle`.
+    pub fn with_throttle(printer: PrinterHandle, throttle: Throttle) -> Self {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                next_id: 1,
+                pending: Vec::new(),
+                jobs: BTreeMap::new(),
+                cancel_current: false,
+                retry: (0, Duration::ZERO),
+                journal: None,
+                shutdown: false,
+            }),
+            wakeup: Condvar::new(),
+        });
+        let worker = {
+            let printer = printer.clone();
+            let shared = shared.clone();
+            std::thread::spawn(move || run(printer, shared, throttle))
+        };
+        JobQueue {
+            printer,
+            shared,
+            worker: Some(worker),
+        }
+    }
+
+    /// Printer the jobs are printed on.
+    pub fn printer(&self) -> &PrinterHandle {
+        &self.printer
+    }
+
+    /// Add a job printing `pages` with the default priority 0, returning its
+    /// id.
+    pub fn submit(&self, pages: Vec<Matrix>) -> JobId {
+        self.submit_with_priority(pages, 0)
+    }
+
+    /// Add a job printing `pages` ahead of the queued jobs with a lower
+    /// `priority`, returning its id.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, ContinuousType, Emulator, JobQueue, JobState, Media, Model, Printer};
+    /// # let media = Media::Continuous(ContinuousType::Continuous62);
+    /// # let emulator = Emulator::new(Model::QL800, Some(media));
+    /// # let config = Config::new(Model::QL800, "emulator".to_string(), media);
+    /// # let queue = JobQueue::new(Printer::with_transport(config, emulator.clone())?.handle());
+    /// let batch: Vec<_> = (0..3).map(|_| queue.submit(vec![vec![vec![0xFF; 90]; 100]])).collect();
+    /// let reprint = queue.submit_with_priority(vec![vec![vec![0xFF; 90]; 100]], 10);
+    ///
+    /// // The reprint is printed before the queued jobs of the batch
+    /// let order: Vec<_> = queue.jobs().iter().map(|job| job.id).collect();
+    /// assert!(order.iter().position(|&id| id == reprint) < order.iter().position(|&id| id == batch[2]));
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub fn submit_with_priority(&self, pages: Vec<Matrix>, priority: Priority) -> JobId {
+        let id = self.shared.queue().push(pages, priority);
+        self.shared.wakeup.notify_all();
+        id
+    }
+
+    /// Retry a job failing with a transient error up to `attempts` times,
+    /// waiting `delay` before each attempt.
+    ///
+    /// Transient errors are those of `Error::is_transient()`, e.g. a USB
+    /// timeout. The job resumes after the last label it printed. Jobs are
+    /// not retried by default.
+    pub fn set_retry(&self, attempts: u32, delay: Duration) {
+        self.shared.queue().retry = (attempts, delay);
+    }
+
+    /// Keep the queued jobs in `dir`, returning the ids of the jobs restored
+    /// from it.
+    ///
+    /// Jobs saved in `dir` by a previous run, e.g. before a restart, are
+    /// queued again in the order they were submitted, with their priority
+    /// and new ids. From now on every submitted job is saved there until it
+    /// is finished. A job that was interrupted while printing is printed
+    /// again from its first label.
+    ///
+    /// The restored jobs are saved under their new ids before the old files
+    /// are removed, so they survive a crash in between. Files that can't be
+    /// read, e.g. truncated by a power loss, are renamed to `.corrupt` and
+    /// skipped with a warning.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Config, ContinuousType, Emulator, JobQueue, JobState, Media, Model, Printer};
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let emulator = Emulator::new(Model::QL800, Some(media));
+    /// let config = Config::new(Model::QL800, "emulator".to_string(), media);
+    /// let queue = JobQueue::new(Printer::with_transport(config, emulator.clone())?.handle());
+    ///
+    /// let dir = std::env::temp_dir().join("ql-label-doctest-journal");
+    /// for id in queue.persist(&dir)? {
+    ///     queue.wait(id);
+    /// }
+    /// let id = queue.submit(vec![vec![vec![0xFF; 90]; 100]]);
+    /// assert_eq!(queue.wait(id).unwrap().state, JobState::Done);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    pub fn persist<P: AsRef<Path>>(&self, dir: P) -> Result<Vec<JobId>, String> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+            .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "job"))
+            .collect();
+        paths.sort();
+        let last = paths
+            .iter()
+            .filter_map(|path| path.file_stem()?.to_str()?.parse::<JobId>().ok())
+            .max();
+        let mut saved = Vec::new();
+        for path in paths {
+            match load_job(&path) {
+                Ok(job) => saved.push((path, job)),
+                Err(err) => {
+                    warn!("Skipping saved job: {}", err);
+                    let corrupt = path.with_extension("corrupt");
+                    if let Err(e) = std::fs::rename(&path, &corrupt) {
+                        warn!("Failed to rename {}: {}", path.display(), e);
+                    }
+                }
+            }
+        }
+
+        // Queued at once, so the worker picks the first by priority
+        let mut queue = self.shared.queue();
+        // New ids after the saved ones, so no saved file is overwritten
+        if let Some(last) = last {
+            queue.next_id = queue.next_id.max(last + 1);
+        }
+        let first = queue.next_id;
+        for (id, (_, (priority, pages))) in (first..).zip(&saved) {
+            let path = job_path(dir, id);
+            if let Err(err) = save_job(&path, *priority, pages) {
+                // Nothing is restored, the saved jobs are kept as they are
+                for id in first..id {
+                    let _ = std::fs::remove_file(job_path(dir, id));
+                }
+                return Err(err);
+            }
+        }
+        for (path, _) in &saved {
+            if let Err(e) = std::fs::remove_file(path) {
+                warn!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+        let ids = saved
+            .into_iter()
+            .map(|(_, (priority, pages))| queue.push(pages, priority))
+            .collect();
+        queue.journal = Some(dir.to_path_buf());
+        self.shared.wakeup.notify_all();
+        Ok(ids)
+    }
+
+    /// Cancel a job.
+    ///
+    /// A queued job is removed, a job being printed stops after the current
+    /// label and a job waiting for media once the media was changed. Returns
+    /// `false` if the job is unknown or already finished.
+    pub fn cancel(&self, id: JobId) -> bool {
+        let mut queue = self.shared.queue();
+        let state = match queue.jobs.get(&id) {
+            Some(job) => job.state.clone(),
+            None => return false,
+        };
+        match state {
+            JobState::Queued => {
+                queue.pending.retain(|job| job.id != id);
+                finish(&mut queue, id, JobState::Cancelled);
+                self.shared.wakeup.notify_all();
+                true
+            }
+            JobState::Printing | JobState::WaitingForMedia => {
+                queue.cancel_current = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// State of the job `id`.
+    pub fn job(&self, id: JobId) -> Option<JobInfo> {
+        self.shared.queue().jobs.get(&id).cloned()
+    }
+
+    /// Unfinished jobs in printing order, followed by recently finished ones.
+    pub fn jobs(&self) -> Vec<JobInfo> {
+        let queue = self.shared.queue();
+        let mut jobs: Vec<JobInfo> = queue
+            .jobs
+            .values()
+            .filter(|job| !job.state.is_finished() && job.state != JobState::Queued)
+            .cloned()
+            .collect();
+        jobs.extend(
+            queue
+                .order()
+                .iter()
+                .filter_map(|id| queue.jobs.get(id).cloned()),
+        );
+        jobs.extend(
+            queue
+                .jobs
+                .values()
+                .filter(|job| job.state.is_finished())
+                .cloned(),
+        );
+        jobs
+    }
+
+    /// Block until the job `id` is finished, returning its final state.
+    pub fn wait(&self, id: JobId) -> Option<JobInfo> {
+        let mut queue = self.shared.queue();
+        loop {
+            match queue.jobs.get(&id) {
+                Some(job) if job.state.is_finished() => return Some(job.clone()),
+                Some(_) => {
+                    queue = self
+                        .shared
+                        .wakeup
+                        .wait(queue)
+                        .unwrap_or_else(|e| e.into_inner())
+                }
+                None => return None,
+            }
+        }
+    }
+}
+
+impl Drop for JobQueue {
+    fn drop(&mut self) {
+        self.shared.queue().shutdown = true;
+        self.shared.wakeup.notify_all();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Record the final `state` of a job and forget the oldest finished jobs.
+fn finish(queue: &mut Queue, id: JobId, state: JobState) {
+    if let Some(job) = queue.jobs.get_mut(&id) {
+        job.state = state;
+    }
+    if let Some(dir) = &queue.journal {
+        let path = job_path(dir, id);
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                warn!("Failed to remove {}: {}", path.display(), e)
+            }
+            _ => {}
+        }
+    }
+    let finished: Vec<JobId> = queue
+        .jobs
+        .values()
+        .filter(|job| job.state.is_finished())
+        .map(|job| job.id)
+        .collect();
+    for id in finished
+        .iter()
+        .take(finished.len().saturating_sub(FINISHED_JOBS))
+    {
+        queue.jobs.remove(id);
+    }
+}
+
+/// File job `id` is saved in, named to sort in submission order.
+fn job_path(dir: &Path, id: JobId) -> PathBuf {
+    dir.join(format!("{:016}.job", id))
+}
+
+/// Save a job as its priority followed by the pages, every page as its row
+/// count and every row as its length and bytes, numbers in little endian.
+///
+/// The job is written to a temporary file first and renamed into place, so
+/// `path` never holds a partly written job.
+fn save_job(path: &Path, priority: Priority, pages: &[Matrix]) -> Result<(), String> {
+    let mut buf = vec![priority];
+    buf.extend_from_slice(&(pages.len() as u32).to_le_bytes());
+    for page in pages {
+        buf.extend_from_slice(&(page.len() as u32).to_le_bytes());
+        for row in page {
+            buf.extend_from_slice(&(row.len() as u32).to_le_bytes());
+            buf.extend_from_slice(row);
+        }
+    }
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, buf)
+        .and_then(|()| std::fs::rename(&temp, path))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Load a job saved with `save_job()`.
+fn load_job(path: &Path) -> Result<(Priority, Vec<Matrix>), String> {
+    let buf =
+        std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let invalid = || format!("Invalid job file: {}", path.display());
+    let mut reader = buf.as_slice();
+    let read_u32 = |reader: &mut &[u8]| {
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes).map_err(|_| invalid())?;
+        Ok::<usize, String>(u32::from_le_bytes(bytes) as usize)
+    };
+
+    let mut priority = [0; 1];
+    reader.read_exact(&mut priority).map_err(|_| invalid())?;
+    let mut pages = Vec::new();
+    for _ in 0..read_u32(&mut reader)? {
+        let mut page = Vec::new();
+        for _ in 0..read_u32(&mut reader)? {
+            let len = read_u32(&mut reader)?;
+            if reader.len() < len {
+                return Err(invalid());
+            }
+            let (row, rest) = reader.split_at(len);
+            page.push(row.to_vec());
+            reader = rest;
+        }
+        pages.push(page);
+    }
+    Ok((priority[0], pages))
+}
+
+/// Sleep for `duration` unless the queue shuts down first, returning `false`
+/// on shutdown.
+fn pause(shared: &Shared, duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    let mut queue = shared.queue();
+    while !queue.shutdown {
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        queue = shared
+            .wakeup
+            .wait_timeout(queue, deadline - now)
+            .unwrap_or_else(|e| e.into_inner())
+            .0;
+    }
+    false
+}
+
+/// Worker thread printing the queued jobs.
+fn run(printer: PrinterHandle, shared: Arc<Shared>, throttle: Throttle) {
+    // Start of the current stretch of printing without a rest
+    let mut busy_since: Option<Instant> = None;
+    // End of the last job and whether the printer cooled down during it
+    let mut last_job: Option<(Instant, bool)> = None;
+    loop {
+        if let Some((end, cooled)) = last_job {
+            let mut wait = throttle.job_pause;
+            if cooled {
+                wait = wait.max(throttle.cooling_pause);
+            }
+            if let (Some((busy, rest)), Some(since)) = (throttle.duty_cycle, busy_since) {
+                if end.duration_since(since) >= busy {
+                    wait = wait.max(rest);
+                }
+            }
+            if !pause(&shared, wait.saturating_sub(end.elapsed())) {
+                return;
+            }
+        }
+
+        let (id, pages) = {
+            let mut queue = shared.queue();
+            loop {
+                if queue.shutdown {
+                    return;
+                }
+                if let Some(next) = queue.take_next() {
+                    break (next.id, next.pages);
+                }
+                queue = shared.wakeup.wait(queue).unwrap_or_else(|e| e.into_inner());
+            }
+        };
+        let start = Instant::now();
+        busy_since = match (throttle.duty_cycle, busy_since, last_job) {
+            (Some((_, rest)), Some(since), Some((end, _))) if start - end < rest => Some(since),
+            _ => Some(start),
+        };
+        let cooling_count = printer.cooling_count();
+        let page_count = pages.len();
+        {
+            let mut queue = shared.queue();
+            queue.cancel_current = false;
+            if let Some(job) = queue.jobs.get_mut(&id) {
+                job.state = JobState::Printing;
+            }
+        }
+
+        // Pages printed by the previous attempts
+        let mut printed = 0;
+        let result = loop {
+            let remaining = pages[printed..].iter().cloned();
+            let result = printer.print_with_progress(remaining, |event| {
+                match event {
+                    PrintEvent::PagePrinted { page, .. } => {
+                        if let Some(job) = shared.queue().jobs.get_mut(&id) {
+                            job.printed += 1;
+                        }
+                        // Shutting down finishes the job without pausing
+                        if printed + page + 1 < page_count {
+                            pause(&shared, throttle.page_pause);
+                        }
+                    }
+                    PrintEvent::MediaChangeRequested { .. } | PrintEvent::MediaChanged => {
+                        if let Some(job) = shared.queue().jobs.get_mut(&id) {
+                            job.state = match event {
+                                PrintEvent::MediaChanged => JobState::Printing,
+                                _ => JobState::WaitingForMedia,
+                            };
+                        }
+                    }
+                    _ => {}
+                }
+                if shared.queue().cancel_current {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            });
+
+            let delay = {
+                let mut queue = shared.queue();
+                let (attempts, delay) = queue.retry;
+                let stopped = queue.cancel_current || queue.shutdown;
+                match (&result, queue.jobs.get_mut(&id)) {
+                    (Err(err), Some(job))
+                        if err.is_transient() && job.retries < attempts && !stopped =>
+                    {
+                        job.retries += 1;
+                        printed = job.printed;
+                        warn!("Job {} failed: {}, retry {}", id, err, job.retries);
+                        delay
+                    }
+                    _ => break result,
+                }
+            };
+            if !pause(&shared, delay) {
+                break result;
+            }
+        };
+        last_job = Some((Instant::now(), printer.cooling_count() > cooling_count));
+
+        let mut queue = shared.queue();
+        let complete = queue
+            .jobs
+            .get(&id)
+            .is_some_and(|job| job.printed == job.pages);
+        let state = match result {
+            Ok(()) if complete => JobState::Done,
+            Ok(()) => JobState::Cancelled,
+            Err(err) => JobState::Failed(err.to_string()),
+        };
+        finish(&mut queue, id, state);
+        shared.wakeup.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+    use crate::error::Error;
+    use crate::media::{ContinuousType, Media};
+    use crate::model::Model;
+    use crate::printer::{Config, Printer};
+    use crate::transport::Transport;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Emulator failing the given write
+    struct Flaky {
+        emulator: Emulator,
+        writes: AtomicUsize,
+        fail: usize,
+    }
+
+    impl Transport for Flaky {
+        fn write(&self, buf: &[u8], timeout: Duration) -> Result<usize, Error> {
+            if self.writes.fetch_add(1, Ordering::Relaxed) == self.fail {
+                return Err(Error::DeviceOffline);
+            }
+            self.emulator.write(buf, timeout)
+        }
+
+        fn read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, Error> {
+            self.emulator.read(buf, timeout)
+        }
+    }
+
+    fn queue(priorities: &[Priority]) -> Queue {
+        Queue {
+            next_id: priorities.len() as JobId + 1,
+            pending: priorities
+                .iter()
+                .enumerate()
+                .map(|(i, &priority)| Pending {
+                    id: i as JobId + 1,
+                    pages: Vec::new(),
+                    priority,
+                    overtaken: 0,
+                })
+                .collect(),
+            jobs: BTreeMap::new(),
+            cancel_current: false,
+            retry: (0, Duration::ZERO),
+            journal: None,
+            shutdown: false,
+        }
+    }
+
+    #[test]
+    fn test_priority_order() {
+        let queue = queue(&[0, 2, 1, 2, 0]);
+        assert_eq!(queue.order(), vec![2, 4, 3, 1, 5]);
+    }
+
+    #[test]
+    fn test_aging() {
+        // A batch job waiting behind a steady stream of urgent jobs
+        let mut queue = queue(&[0]);
+        let mut started = Vec::new();
+        for _ in 0..6 {
+            for _ in 0..2 {
+                let id = queue.next_id;
+                queue.next_id += 1;
+                queue.pending.push(Pending {
+                    id,
+                    pages: Vec::new(),
+                    priority: 1,
+                    overtaken: 0,
+                });
+            }
+            started.push(queue.take_next().unwrap().id);
+        }
+        // Promoted to priority 1 after 4 jobs overtook it, then first in line
+        assert_eq!(started, vec![2, 3, 4, 5, 1, 6]);
+    }
+
+    #[test]
+    fn test_retry() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let emulator = Emulator::new(Model::QL800, Some(media));
+        // Fails sending the second page
+        let flaky = Flaky {
+            emulator: emulator.clone(),
+            writes: AtomicUsize::new(0),
+            fail: 3,
+        };
+        let config = Config::new(Model::QL800, "emulator".to_string(), media);
+        let printer = Printer::with_transport(config, flaky).unwrap();
+        let queue = JobQueue::new(printer.handle());
+        queue.set_retry(1, Duration::from_millis(10));
+
+        let pages = (1..=3).map(|n| vec![vec![0xFF; 90]; 100 * n]).collect();
+        let job = queue.wait(queue.submit(pages)).unwrap();
+        assert_eq!(job.state, JobState::Done);
+        assert_eq!((job.printed, job.retries), (3, 1));
+        let rows: Vec<_> = emulator
+            .pages()
+            .iter()
+            .map(|page| page.black.len())
+            .collect();
+        assert_eq!(rows, vec![100, 200, 300]);
+    }
+
+    #[test]
+    fn test_persist() {
+        let dir = std::env::temp_dir().join(format!("ql-label-journal-{}", std::process::id()));
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let emulator = Emulator::new(Model::QL800, Some(media));
+        let config = Config::new(Model::QL800, "emulator".to_string(), media);
+        let printer = Printer::with_transport(config, emulator.clone())
+            .unwrap()
+            .handle();
+
+        // Stopped while two jobs are queued
+        let throttle = Throttle::new().job_pause(Duration::from_secs(60));
+        let queue = JobQueue::with_throttle(printer.clone(), throttle);
+        assert!(queue.persist(&dir).unwrap().is_empty());
+        queue.wait(queue.submit(vec![vec![vec![0xFF; 90]; 100]]));
+        queue.submit(vec![vec![vec![0xFF; 90]; 200]]);
+        queue.submit_with_priority(vec![vec![vec![0xFF; 90]; 300]; 2], 1);
+        drop(queue);
+
+        // A job file truncated by a power loss is set aside
+        std::fs::write(dir.join("0000000000000009.job"), [0x00, 0x01]).unwrap();
+
+        let queue = JobQueue::new(printer);
+        let restored = queue.persist(&dir).unwrap();
+        assert_eq!(restored.len(), 2);
+        for id in restored {
+            assert_eq!(queue.wait(id).unwrap().state, JobState::Done);
+        }
+        let rows: Vec<_> = emulator
+            .pages()
+            .iter()
+            .map(|page| page.black.len())
+            .collect();
+        assert_eq!(rows, vec![100, 300, 300, 200]);
+        let corrupt = dir.join("0000000000000009.corrupt");
+        std::fs::remove_file(corrupt).unwrap();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cooling_pause() {
+        let media = Media::Continuous(ContinuousType::Continuous62);
+        let emulator = Emulator::new(Model::QL800, Some(media));
+        let config = Config::new(Model::QL800, "emulator".to_string(), media);
+        let printer = Printer::with_transport(config, emulator.clone()).unwrap();
+        let throttle = Throttle::new().cooling_pause(Duration::from_millis(300));
+        let queue = JobQueue::with_throttle(printer.handle(), throttle);
+
+        emulator.set_cooling(true);
+        let first = queue.submit(vec![vec![vec![0xFF; 90]; 100]; 2]);
+        queue.wait(first).unwrap();
+        let cooled = Instant::now();
+        assert_eq!(queue.printer().cooling_count(), 2);
+
+        emulator.set_cooling(false);
+        let second = queue.submit(vec![vec![vec![0xFF; 90]; 100]]);
+        assert_eq!(queue.wait(second).unwrap().state, JobState::Done);
+        assert!(cooled.elapsed() >= Duration::from_millis(250));
+        assert_eq!(emulator.pages().len(), 3);
+    }
+}
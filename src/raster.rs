@@ -0,0 +1,543 @@
+//! Raster sources producing label rows on demand.
+//!
+//! `Printer::print()` accepts any type implementing `RasterSource` for each
+//! page. A plain `Matrix` is the simplest source, while `RasterFn` generates
+//! rows lazily so very long labels never have to be held in memory as a
+//! whole.
+
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use crate::Matrix;
+
+/// A page of raster rows that can be produced one at a time.
+///
+/// The printer needs to know the number of rows before the first one is sent,
+/// so sources must report it up front through `raster_count()`.
+pub trait RasterSource {
+    /// Iterator over the rows of the page.
+    type Rows: Iterator<Item = Vec<u8>>;
+
+    /// Number of rows `into_rows()` will produce.
+    fn raster_count(&self) -> u32;
+
+    /// Consume the source and return its rows in print order.
+    fn into_rows(self) -> Self::Rows;
+
+    /// Feed amount (margin) for this page in dots, overriding the value of
+    /// `Config`. `None` uses the configured feed.
+    fn feed_dots(&self) -> Option<u16> {
+        None
+    }
+
+    /// Whether this page starts a new document within the job.
+    ///
+    /// The print information of such a page carries the starting page flag
+    /// like the first page of a job, so the printer applies the feed of a
+    /// new document. `false` by default.
+    fn starts_document(&self) -> bool {
+        false
+    }
+
+    /// Whether `other` prints exactly the same label as this page.
+    ///
+    /// A run of identical pages in a job is encoded once and sent as copies,
+    /// see `Printer::print_copies()`. Sources that can't be compared without
+    /// consuming them return `false`, the default.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Matrix, RasterSource};
+    /// let label: Matrix = vec![vec![0xFF; 90]; 100];
+    /// assert!(label.same_page(&label.clone()));
+    /// assert!(!label.same_page(&vec![vec![0xFF; 90]; 99]));
+    /// ```
+    fn same_page(&self, _other: &Self) -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
+
+    /// Hash of the rows and options of this page, used as the key of
+    /// `Config::encoding_cache()`, where pages with the same hash are also
+    /// compared row by row. Sources that can't be hashed without consuming
+    /// them return `None`, the default, and are always encoded.
+    fn content_hash(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl RasterSource for Matrix {
+    type Rows = std::vec::IntoIter<Vec<u8>>;
+
+    fn raster_count(&self) -> u32 {
+        self.len() as u32
+    }
+
+    fn into_rows(self) -> Self::Rows {
+        self.into_iter()
+    }
+
+    fn same_page(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn content_hash(&self) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        Some(hasher.finish())
+    }
+}
+
+/// Raster source calling a function for every row.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{RasterFn, RasterSource};
+/// // A 1 metre long striped banner at 300 DPI, generated row by row
+/// let banner = RasterFn::new(11811, |y| {
+///     if (y / 50) % 2 == 0 { vec![0xFF; 90] } else { vec![0x00; 90] }
+/// });
+///
+/// assert_eq!(banner.raster_count(), 11811);
+/// assert_eq!(banner.into_rows().nth(60), Some(vec![0x00; 90]));
+/// ```
+pub struct RasterFn<F> {
+    count: u32,
+    row: F,
+}
+
+impl<F> RasterFn<F>
+where
+    F: FnMut(u32) -> Vec<u8>,
+{
+    /// Create a source of `count` rows where row `y` is produced by `row(y)`.
+    pub fn new(count: u32, row: F) -> Self {
+        RasterFn { count, row }
+    }
+}
+
+impl<F> RasterSource for RasterFn<F>
+where
+    F: FnMut(u32) -> Vec<u8>,
+{
+    type Rows = std::iter::Map<std::ops::Range<u32>, F>;
+
+    fn raster_count(&self) -> u32 {
+        self.count
+    }
+
+    fn into_rows(self) -> Self::Rows {
+        (0..self.count).map(self.row)
+    }
+}
+
+/// Raster source taking its rows from an iterator, e.g. rows decoded from a
+/// file or received over the network while the label prints.
+///
+/// The print information announces the number of rows before the first one
+/// is sent, so it must be known up front: rows after `count` are dropped,
+/// and blank rows are printed when the iterator ends early.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{RasterIter, RasterSource};
+/// let rows = (0..3).map(|_| vec![0xFF; 90]);
+/// let page = RasterIter::new(4, rows);
+///
+/// assert_eq!(page.raster_count(), 4);
+/// assert_eq!(page.into_rows().last(), Some(vec![0x00; 90]));
+/// ```
+pub struct RasterIter<I> {
+    count: u32,
+    rows: I,
+}
+
+impl<I> RasterIter<I>
+where
+    I: Iterator<Item = Vec<u8>>,
+{
+    /// Create a source of `count` rows taken from `rows`.
+    pub fn new(count: u32, rows: impl IntoIterator<IntoIter = I>) -> Self {
+        RasterIter {
+            count,
+            rows: rows.into_iter(),
+        }
+    }
+}
+
+impl<I> RasterSource for RasterIter<I>
+where
+    I: Iterator<Item = Vec<u8>>,
+{
+    type Rows = std::iter::Take<std::iter::Chain<std::iter::Fuse<I>, std::iter::Repeat<Vec<u8>>>>;
+
+    fn raster_count(&self) -> u32 {
+        self.count
+    }
+
+    fn into_rows(self) -> Self::Rows {
+        // Blank rows of normal width are padded on wide printers
+        let blank = vec![0x00; crate::NORMAL_PRINTER_WIDTH as usize / 8];
+        self.rows
+            .fuse()
+            .chain(std::iter::repeat(blank))
+            .take(self.count as usize)
+    }
+}
+
+/// A page with its own print options.
+///
+/// Wraps any raster source to override options of `Config` for a single
+/// page, so mixed batches such as a header label with a wide margin followed
+/// by compact labels can be printed in one job.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{Page, RasterSource};
+/// // A single dot at the left edge of the image (the last pin)
+/// let mut row = vec![0x00; 90];
+/// row[89] = 0x01;
+///
+/// let header = Page::new(vec![row; 200])
+///     .set_feed_in_dots(120)
+///     .left_margin_dots(4);
+/// assert_eq!(header.feed_dots(), Some(120));
+///
+/// // The dot moved 4 dots to the right
+/// assert_eq!(header.into_rows().next().unwrap()[89], 0x10);
+/// ```
+#[derive(Clone)]
+pub struct Page<S> {
+    source: S,
+    feed: Option<u16>,
+    left_margin: u32,
+    new_document: bool,
+}
+
+impl<S: RasterSource> Page<S> {
+    /// Wrap a raster source without any overrides.
+    pub fn new(source: S) -> Self {
+        Page {
+            source,
+            feed: None,
+            left_margin: 0,
+            new_document: false,
+        }
+    }
+
+    /// Set the feed amount (margin) before and after this page in dots.
+    ///
+    /// The value is checked against the media when printing, see
+    /// `Config::set_feed_in_dots()`.
+    pub fn set_feed_in_dots(self, feed: u16) -> Self {
+        Page {
+            feed: Some(feed),
+            ..self
+        }
+    }
+
+    /// Move the content of this page `dots` dots to the right, away from the
+    /// left edge of the tape. Content moved past the right edge is cut off.
+    pub fn left_margin_dots(self, dots: u32) -> Self {
+        Page {
+            left_margin: dots,
+            ..self
+        }
+    }
+
+    /// Mark this page as the first page of a new document, see
+    /// `RasterSource::starts_document()`.
+    ///
+    /// Use it to batch several documents into one job while keeping the
+    /// feed at the start of each document.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Page, RasterSource};
+    /// let label = vec![vec![0xFF; 90]; 200];
+    /// let pages = vec![
+    ///     Page::new(label.clone()),
+    ///     Page::new(label.clone()),
+    ///     Page::new(label).new_document(),
+    /// ];
+    /// assert!(pages[2].starts_document());
+    /// ```
+    pub fn new_document(self) -> Self {
+        Page {
+            new_document: true,
+            ..self
+        }
+    }
+}
+
+impl<S: RasterSource> RasterSource for Page<S> {
+    type Rows = ShiftedRows<S::Rows>;
+
+    fn raster_count(&self) -> u32 {
+        self.source.raster_count()
+    }
+
+    fn into_rows(self) -> Self::Rows {
+        ShiftedRows {
+            rows: self.source.into_rows(),
+            shift: self.left_margin as i32,
+        }
+    }
+
+    fn feed_dots(&self) -> Option<u16> {
+        self.feed
+    }
+
+    fn starts_document(&self) -> bool {
+        self.new_document
+    }
+
+    fn same_page(&self, other: &Self) -> bool {
+        self.feed == other.feed
+            && self.left_margin == other.left_margin
+            && self.new_document == other.new_document
+            && self.source.same_page(&other.source)
+    }
+
+    fn content_hash(&self) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+        (self.source.content_hash()?, self.feed, self.left_margin).hash(&mut hasher);
+        Some(hasher.finish())
+    }
+}
+
+/// Rows of a `Page` moved by its left margin.
+pub struct ShiftedRows<I> {
+    rows: I,
+    shift: i32,
+}
+
+impl<I: Iterator<Item = Vec<u8>>> Iterator for ShiftedRows<I> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        let row = self.rows.next()?;
+        Some(shift_row(row, self.shift))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rows.size_hint()
+    }
+}
+
+/// Rows of a page moved by the calibrated print offset of a printer.
+///
+/// The number of rows is kept: rows moved past the end of the page are
+/// dropped and the gap is filled with blank rows.
+pub(crate) struct OffsetRows<I> {
+    rows: I,
+    shift: i32,
+    skip: u32,
+    pad: u32,
+    remaining: u32,
+    width: usize,
+}
+
+impl<I: Iterator<Item = Vec<u8>>> OffsetRows<I> {
+    /// Move `rows` `x` dots to the right and `y` rows towards the end of the
+    /// page, negative values move the other way.
+    pub(crate) fn new(rows: I, count: u32, x: i32, y: i32) -> Self {
+        OffsetRows {
+            rows,
+            shift: x,
+            skip: if y < 0 { y.unsigned_abs() } else { 0 },
+            pad: if y > 0 { y as u32 } else { 0 },
+            remaining: count,
+            width: 90,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Vec<u8>>> Iterator for OffsetRows<I> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        while self.skip > 0 {
+            self.skip -= 1;
+            self.rows.next();
+        }
+        if self.pad > 0 {
+            self.pad -= 1;
+            return Some(vec![0x00; self.width]);
+        }
+        match self.rows.next() {
+            Some(row) => {
+                self.width = row.len();
+                Some(shift_row(row, self.shift))
+            }
+            None => Some(vec![0x00; self.width]),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining as usize, Some(self.remaining as usize))
+    }
+}
+
+/// A page as sent to the printer: either a whole page, or a segment of a
+/// page longer than the printer accepts.
+pub(crate) enum Segmented<S: RasterSource> {
+    Whole(S),
+    Part(Segment<S::Rows>),
+}
+
+/// Consecutive rows of a page split by `Segmented::split()`. All segments
+/// of a page read from the same rows, so they must be sent in order.
+pub(crate) struct Segment<I> {
+    rows: Rc<RefCell<I>>,
+    count: u32,
+    feed: Option<u16>,
+    starts_document: bool,
+    last: bool,
+}
+
+impl<S: RasterSource> Segmented<S> {
+    /// Split `image` into segments of at most `max_rows` rows, `None` keeps
+    /// it whole. The rows are still produced one at a time.
+    ///
+    /// The segments differ by at most one row, so none of them is shorter
+    /// than half of `max_rows` and the printer accepts every one of them.
+    pub(crate) fn split(image: S, max_rows: Option<u32>) -> Vec<Self> {
+        let total = image.raster_count();
+        let max_rows = match max_rows {
+            Some(max_rows) if total > max_rows && max_rows > 0 => max_rows,
+            _ => return vec![Segmented::Whole(image)],
+        };
+        let feed = image.feed_dots();
+        let starts_document = image.starts_document();
+        let rows = Rc::new(RefCell::new(image.into_rows()));
+        let count = total.div_ceil(max_rows);
+        // The first `total % count` segments take one of the leftover rows
+        let (length, longer) = (total / count, total % count);
+        (0..count)
+            .map(|i| {
+                Segmented::Part(Segment {
+                    rows: rows.clone(),
+                    count: length + u32::from(i < longer),
+                    feed,
+                    starts_document: starts_document && i == 0,
+                    last: i + 1 == count,
+                })
+            })
+            .collect()
+    }
+
+    /// Whether more segments of the same page follow, so the tape must not
+    /// be cut after this one.
+    pub(crate) fn continues(&self) -> bool {
+        matches!(self, Segmented::Part(segment) if !segment.last)
+    }
+}
+
+impl<S: RasterSource> RasterSource for Segmented<S> {
+    type Rows = SegmentedRows<S::Rows>;
+
+    fn raster_count(&self) -> u32 {
+        match self {
+            Segmented::Whole(image) => image.raster_count(),
+            Segmented::Part(segment) => segment.count,
+        }
+    }
+
+    fn into_rows(self) -> Self::Rows {
+        match self {
+            Segmented::Whole(image) => SegmentedRows::Whole(image.into_rows()),
+            Segmented::Part(segment) => SegmentedRows::Part {
+                remaining: segment.count,
+                rows: segment.rows,
+            },
+        }
+    }
+
+    fn feed_dots(&self) -> Option<u16> {
+        match self {
+            Segmented::Whole(image) => image.feed_dots(),
+            Segmented::Part(segment) => segment.feed,
+        }
+    }
+
+    fn starts_document(&self) -> bool {
+        match self {
+            Segmented::Whole(image) => image.starts_document(),
+            Segmented::Part(segment) => segment.starts_document,
+        }
+    }
+
+    fn same_page(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Segmented::Whole(image), Segmented::Whole(other)) => image.same_page(other),
+            _ => false,
+        }
+    }
+
+    fn content_hash(&self) -> Option<u64> {
+        match self {
+            Segmented::Whole(image) => image.content_hash(),
+            Segmented::Part(_) => None,
+        }
+    }
+}
+
+/// Rows of a `Segmented` page.
+pub(crate) enum SegmentedRows<I> {
+    Whole(I),
+    Part {
+        rows: Rc<RefCell<I>>,
+        remaining: u32,
+    },
+}
+
+impl<I: Iterator<Item = Vec<u8>>> Iterator for SegmentedRows<I> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        match self {
+            SegmentedRows::Whole(rows) => rows.next(),
+            SegmentedRows::Part { rows, remaining } => {
+                if *remaining == 0 {
+                    return None;
+                }
+                *remaining -= 1;
+                rows.borrow_mut().next()
+            }
+        }
+    }
+}
+
+/// Move the content of a row `shift` dots to the right, or to the left when
+/// `shift` is negative.
+///
+/// Pin 0 is the most significant bit of the first byte and corresponds to
+/// the right edge of the image, so moving content to the right of the label
+/// shifts the bit string to the left.
+fn shift_row(row: Vec<u8>, shift: i32) -> Vec<u8> {
+    if shift == 0 {
+        return row;
+    }
+    let bit = |pin: i64| -> u8 {
+        if pin < 0 || pin >= row.len() as i64 * 8 {
+            return 0;
+        }
+        (row[(pin / 8) as usize] >> (7 - pin % 8)) & 1
+    };
+    (0..row.len() as i64)
+        .map(|i| {
+            (0..8).fold(0u8, |byte, b| {
+                byte | (bit(i * 8 + b + shift as i64) << (7 - b))
+            })
+        })
+        .collect()
+}
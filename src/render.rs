@@ -0,0 +1,188 @@
+//! Printer rows from any image.
+//!
+//! `LabelImage` does the preparation every application otherwise repeats
+//! before `step_filter_normal()`: flattening transparency and colors to gray,
+//! turning the image to match the label, scaling it to the printable area of
+//! the media and placing it on the pins of the print head.
+//!
+//! Requires the `image` feature.
+
+use image::{DynamicImage, GrayImage, Luma};
+
+use crate::{
+    layout::{Canvas, Orientation, Resampling, Resolution},
+    media::Media,
+    model::Model,
+    utils::{self, Dither, Rotation},
+    Matrix,
+};
+
+/// Conversion of images to printer rows for a media and model.
+///
+/// The image is turned a quarter clockwise when its longer side matches the
+/// longer side of the label that way; images wider than tall print along
+/// continuous tape. `rotation()` turns it by a given amount instead. It is
+/// then scaled to fit the printable area and
+/// centered. On continuous tape the label is as long as the image needs.
+///
+/// Normal-width media is aligned at its right edge on the 1296 pins of wide
+/// printers, like brother_ql does.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{ContinuousType, Dither, LabelImage, Media, Model};
+/// use image::{DynamicImage, GrayImage, Luma};
+///
+/// // A banner twice as wide as tall
+/// let banner = DynamicImage::ImageLuma8(GrayImage::from_pixel(400, 200, Luma([0])));
+/// let media = Media::Continuous(ContinuousType::Continuous62);
+///
+/// let matrix = LabelImage::from_dynamic_image(&banner, &media, &Model::QL800);
+/// assert_eq!(matrix[0].len(), 90);
+/// // Turned along the tape, twice the printable width of 696 dots long
+/// assert_eq!(matrix.len(), 1392);
+///
+/// let wide = LabelImage::new(media, Model::QL1100)
+///     .dither(Dither::Atkinson)
+///     .render(&banner);
+/// assert_eq!(wide[0].len(), 162);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelImage {
+    media: Media,
+    model: Model,
+    resolution: Resolution,
+    resampling: Resampling,
+    dither: Option<Dither>,
+    threshold: u8,
+    // `None` turns the image automatically
+    rotation: Option<Rotation>,
+}
+
+impl LabelImage {
+    /// Conversion for `media` on `model` in normal resolution, scaling with
+    /// `Resampling::Nearest` and a threshold of 128.
+    pub fn new(media: Media, model: Model) -> Self {
+        LabelImage {
+            media,
+            model,
+            resolution: Resolution::Normal,
+            resampling: Resampling::default(),
+            dither: None,
+            threshold: 128,
+            rotation: None,
+        }
+    }
+
+    /// Convert `image` for `media` on `model` with the default settings.
+    pub fn from_dynamic_image(image: &DynamicImage, media: &Media, model: &Model) -> Matrix {
+        Self::new(*media, *model).render(image)
+    }
+
+    /// Resolution the rows are printed at, see `Config::high_resolution()`.
+    pub fn resolution(self, resolution: Resolution) -> Self {
+        LabelImage { resolution, ..self }
+    }
+
+    /// Filter scaling the image, `Resampling::Lanczos3` suits photos.
+    pub fn resampling(self, resampling: Resampling) -> Self {
+        LabelImage { resampling, ..self }
+    }
+
+    /// Dither the image with `algorithm` instead of applying the threshold.
+    pub fn dither(self, algorithm: Dither) -> Self {
+        LabelImage {
+            dither: Some(algorithm),
+            ..self
+        }
+    }
+
+    /// Gray levels up to `threshold` print black when not dithering.
+    pub fn threshold(self, threshold: u8) -> Self {
+        LabelImage { threshold, ..self }
+    }
+
+    /// Turn the image by `rotation`, clockwise, instead of matching its
+    /// longer side to the label.
+    ///
+    /// After turning, the top of the image is the edge of the label leaving
+    /// the printer first and its width lies across the tape; `Rotation::None`
+    /// prints the image as it is.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{ContinuousType, LabelImage, Media, Model, Rotation};
+    /// use image::{DynamicImage, GrayImage, Luma};
+    ///
+    /// let banner = DynamicImage::ImageLuma8(GrayImage::from_pixel(400, 200, Luma([0])));
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    ///
+    /// // Printed upright across the tape, half the printable width of 696 dots long
+    /// let upright = LabelImage::new(media, Model::QL800)
+    ///     .rotation(Rotation::None)
+    ///     .render(&banner);
+    /// assert_eq!(upright.len(), 348);
+    /// ```
+    pub fn rotation(self, rotation: Rotation) -> Self {
+        LabelImage {
+            rotation: Some(rotation),
+            ..self
+        }
+    }
+
+    /// Convert `image` to printer rows.
+    pub fn render(&self, image: &DynamicImage) -> Matrix {
+        let mut gray = flatten(image);
+        if let Some(rotation) = self.rotation {
+            let (width, height, pixels) =
+                utils::rotate_gray(gray.width(), gray.height(), gray.as_raw(), rotation);
+            gray = GrayImage::from_raw(width, height, pixels).expect("Rotated image size");
+        }
+        let (width, height) = gray.dimensions();
+        let mut canvas = Canvas::new(self.media, self.resolution);
+
+        let (across, along) = canvas.size_mm();
+        let wide_label = matches!(self.media, Media::DieCut(_)) && across > along;
+        let rotate = self.rotation.is_none() && (width > height) != wide_label;
+        if rotate {
+            canvas = canvas.orientation(Orientation::Landscape);
+        }
+        if let Media::Continuous(_) = self.media {
+            let (along_px, across_px) = if rotate {
+                (width, height)
+            } else {
+                (height, width)
+            };
+            canvas = canvas.length_mm(across * along_px as f32 / across_px.max(1) as f32);
+        }
+        canvas.fit_image(&gray, self.resampling);
+
+        let rows = canvas.raster_count();
+        let mut head = canvas.head_pixels();
+        let threshold = match self.dither {
+            Some(algorithm) => {
+                utils::dither(algorithm, canvas.pins(), rows, &mut head);
+                128
+            }
+            None => self.threshold,
+        };
+        let mut matrix = utils::step_filter(threshold, canvas.pins(), rows, &head);
+
+        // Rows start with the right edge of the head, the extra pins are on the left
+        let bytes = (self.model.pins() / 8) as usize;
+        for row in &mut matrix {
+            row.resize(bytes, 0x00);
+        }
+        matrix
+    }
+}
+
+/// Grayscale pixels of `image`, transparent areas turned white.
+pub(crate) fn flatten(image: &DynamicImage) -> GrayImage {
+    let gray = image.to_luma_alpha8();
+    GrayImage::from_fn(gray.width(), gray.height(), |x, y| {
+        let [value, alpha] = gray.get_pixel(x, y).0;
+        let (value, alpha) = (value as u32, alpha as u32);
+        Luma([((value * alpha + 255 * (255 - alpha)) / 255) as u8])
+    })
+}
@@ -0,0 +1,276 @@
+//! Straightening and cropping of scanned or photographed labels.
+//!
+//! To reprint a damaged label from a picture of it, the content has to be
+//! level and cropped before it is converted to raster rows. `Deskew` finds
+//! the skew angle from the projection profile of the dark pixels: the rows
+//! of text and barcodes line up with the angle where the profile is the
+//! sharpest. The image is then rotated back and cropped to the content.
+//!
+//! Requires the `image` feature.
+
+use image::{GrayImage, Luma};
+
+/// Angle step of the coarse skew search in degrees.
+const COARSE_STEP: f32 = 0.5;
+/// Angle step of the refined skew search in degrees.
+const FINE_STEP: f32 = 0.05;
+/// Most dark pixels sampled to find the skew angle.
+const MAX_SAMPLES: usize = 50_000;
+
+/// Content found by `Deskew::detect()`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScanContent {
+    /// Skew of the content in degrees, clockwise as seen on the image.
+    pub angle: f32,
+    /// Left edge of the content in the straightened image.
+    pub x: u32,
+    /// Top edge of the content in the straightened image.
+    pub y: u32,
+    /// Width of the content in pixels.
+    pub width: u32,
+    /// Height of the content in pixels.
+    pub height: u32,
+}
+
+/// Preprocessing straightening and cropping a scanned or photographed label.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::Deskew;
+/// use image::{GrayImage, Luma};
+///
+/// // A bar tilted by 3 degrees on a white page
+/// let mut scan = GrayImage::from_pixel(400, 300, Luma([255]));
+/// for x in 50..350u32 {
+///     let y = 150.0 + (x as f32 - 200.0) * 3f32.to_radians().tan();
+///     for dy in 0..10 {
+///         scan.put_pixel(x, y as u32 + dy, Luma([0]));
+///     }
+/// }
+///
+/// let deskew = Deskew::new().margin(4);
+/// let content = deskew.detect(&scan).unwrap();
+/// assert!((content.angle - 3.0).abs() < 0.2);
+///
+/// let label = deskew.apply(&scan);
+/// assert!(label.height() < 30);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deskew {
+    threshold: u8,
+    max_angle: f32,
+    margin: u32,
+}
+
+impl Default for Deskew {
+    fn default() -> Self {
+        Deskew {
+            threshold: 128,
+            max_angle: 15.0,
+            margin: 0,
+        }
+    }
+}
+
+impl Deskew {
+    /// Preprocessing with a threshold of 128, skew up to 15 degrees and no
+    /// margin around the content.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pixels darker than `threshold` are content, the others background.
+    pub fn threshold(self, threshold: u8) -> Self {
+        Deskew { threshold, ..self }
+    }
+
+    /// Largest skew searched for in degrees, in both directions.
+    pub fn max_angle(self, degrees: f32) -> Self {
+        Deskew {
+            max_angle: degrees.abs(),
+            ..self
+        }
+    }
+
+    /// Background kept around the content in pixels when cropping.
+    pub fn margin(self, pixels: u32) -> Self {
+        Deskew {
+            margin: pixels,
+            ..self
+        }
+    }
+
+    /// Find the skew angle of `image` and the bounding box of its content
+    /// once straightened. `None` when the image has no content.
+    pub fn detect(&self, image: &GrayImage) -> Option<ScanContent> {
+        let angle = self.skew_angle(image)?;
+        let straight = rotate(image, angle);
+        let (x, y, width, height) = self.bounds(&straight)?;
+        Some(ScanContent {
+            angle,
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+
+    /// Straighten `image` and crop it to its content plus the margin.
+    ///
+    /// An image without content is returned unchanged. Convert the result
+    /// with `Canvas::draw_gray()` or `step_filter_image()` once it is scaled
+    /// to the label.
+    pub fn apply(&self, image: &GrayImage) -> GrayImage {
+        let angle = match self.skew_angle(image) {
+            Some(angle) => angle,
+            None => return image.clone(),
+        };
+        let straight = rotate(image, angle);
+        match self.bounds(&straight) {
+            Some((x, y, width, height)) => {
+                let left = x.saturating_sub(self.margin);
+                let top = y.saturating_sub(self.margin);
+                let right = (x + width + self.margin).min(straight.width());
+                let bottom = (y + height + self.margin).min(straight.height());
+                image::imageops::crop_imm(&straight, left, top, right - left, bottom - top)
+                    .to_image()
+            }
+            None => straight,
+        }
+    }
+
+    /// Angle where the rows of content line up the best.
+    fn skew_angle(&self, image: &GrayImage) -> Option<f32> {
+        let dark: Vec<(f32, f32)> = image
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel[0] < self.threshold)
+            .map(|(x, y, _)| (x as f32, y as f32))
+            .collect();
+        if dark.is_empty() {
+            return None;
+        }
+        let stride = dark.len().div_ceil(MAX_SAMPLES);
+        let samples: Vec<_> = dark.into_iter().step_by(stride).collect();
+
+        let search = |from: f32, to: f32, step: f32| {
+            let steps = ((to - from) / step).round() as i32;
+            (0..=steps)
+                .map(|i| from + i as f32 * step)
+                .map(|angle| (angle, sharpness(&samples, angle)))
+                // The smallest angle wins ties, keeping level content at 0
+                .max_by(|(a, a_score), (b, b_score)| {
+                    a_score.cmp(b_score).then(b.abs().total_cmp(&a.abs()))
+                })
+                .map(|(angle, _)| angle)
+        };
+        let coarse = search(-self.max_angle, self.max_angle, COARSE_STEP)?;
+        search(coarse - COARSE_STEP, coarse + COARSE_STEP, FINE_STEP)
+    }
+
+    /// Bounding box of the content. Rows and columns with less than 1% of
+    /// their pixels dark, such as specks of dust, are ignored.
+    fn bounds(&self, image: &GrayImage) -> Option<(u32, u32, u32, u32)> {
+        let (width, height) = image.dimensions();
+        let mut rows = vec![0u32; height as usize];
+        let mut columns = vec![0u32; width as usize];
+        for (x, y, pixel) in image.enumerate_pixels() {
+            if pixel[0] < self.threshold {
+                rows[y as usize] += 1;
+                columns[x as usize] += 1;
+            }
+        }
+        let (top, bottom) = span(&rows, (width / 100).max(2))?;
+        let (left, right) = span(&columns, (height / 100).max(2))?;
+        Some((left, top, right - left + 1, bottom - top + 1))
+    }
+}
+
+/// Sum of the squared counts of the projection profile of `points` along
+/// `angle`, the highest when the rows of content are parallel to it.
+fn sharpness(points: &[(f32, f32)], angle: f32) -> u64 {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let mut profile = std::collections::HashMap::new();
+    for &(x, y) in points {
+        let row = (y * cos - x * sin).round() as i32;
+        *profile.entry(row).or_insert(0u64) += 1;
+    }
+    profile.values().map(|count| count * count).sum()
+}
+
+/// First and last index of `counts` holding at least `min` pixels.
+fn span(counts: &[u32], min: u32) -> Option<(u32, u32)> {
+    let first = counts.iter().position(|&count| count >= min)?;
+    let last = counts.iter().rposition(|&count| count >= min)?;
+    Some((first as u32, last as u32))
+}
+
+/// Rotate `image` by `angle` degrees counterclockwise, undoing a clockwise
+/// skew. The result is enlarged to hold the whole image, the corners are
+/// filled with white.
+fn rotate(image: &GrayImage, angle: f32) -> GrayImage {
+    if angle == 0.0 {
+        return image.clone();
+    }
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let (width, height) = (image.width() as f32, image.height() as f32);
+    let out_width = (width * cos.abs() + height * sin.abs()).ceil();
+    let out_height = (width * sin.abs() + height * cos.abs()).ceil();
+    let (cx, cy) = (width / 2.0, height / 2.0);
+    let (ox, oy) = (out_width / 2.0, out_height / 2.0);
+
+    GrayImage::from_fn(out_width as u32, out_height as u32, |u, v| {
+        let (du, dv) = (u as f32 + 0.5 - ox, v as f32 + 0.5 - oy);
+        let x = du * cos - dv * sin + cx - 0.5;
+        let y = du * sin + dv * cos + cy - 0.5;
+        Luma([bilinear(image, x, y)])
+    })
+}
+
+/// Pixel value at `(x, y)` interpolated between its neighbours, white
+/// outside of the image.
+fn bilinear(image: &GrayImage, x: f32, y: f32) -> u8 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let pixel = |x: f32, y: f32| {
+        if x < 0.0 || y < 0.0 || x >= image.width() as f32 || y >= image.height() as f32 {
+            255.0
+        } else {
+            image.get_pixel(x as u32, y as u32)[0] as f32
+        }
+    };
+    let top = pixel(x0, y0) * (1.0 - fx) + pixel(x0 + 1.0, y0) * fx;
+    let bottom = pixel(x0, y0 + 1.0) * (1.0 - fx) + pixel(x0 + 1.0, y0 + 1.0) * fx;
+    (top * (1.0 - fy) + bottom * fy).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_content() {
+        // Two rows of blocks with a speck of dust in a corner
+        let mut scan = GrayImage::from_pixel(300, 200, Luma([255]));
+        for (x, y) in [(40, 50), (120, 50), (200, 50), (40, 120), (120, 120)] {
+            for dx in 0..60 {
+                for dy in 0..30 {
+                    scan.put_pixel(x + dx, y + dy, Luma([0]));
+                }
+            }
+        }
+        scan.put_pixel(290, 5, Luma([0]));
+
+        let content = Deskew::new().detect(&scan).unwrap();
+        assert_eq!(content.angle, 0.0);
+        assert_eq!(
+            (content.x, content.y, content.width, content.height),
+            (40, 50, 220, 100)
+        );
+
+        let cropped = Deskew::new().margin(5).apply(&scan);
+        assert_eq!(cropped.dimensions(), (230, 110));
+        assert!(Deskew::new()
+            .detect(&GrayImage::from_pixel(10, 10, Luma([255])))
+            .is_none());
+    }
+}
@@ -0,0 +1,120 @@
+//! `tiny-skia` integration.
+//!
+//! Enabled with the `tiny-skia` feature. `SkiaCanvas` wraps a `tiny_skia`
+//! `Pixmap` covering the printable area of a label, so anti-aliased paths,
+//! strokes and gradients can be drawn with the full tiny-skia API. The
+//! result is composited over white and dithered to the printer's 1-bit rows.
+//!
+//! # Example
+//! ```rust
+//! use ql_label::{ContinuousType, Media, Resolution, SkiaCanvas};
+//! use tiny_skia::{FillRule, Paint, PathBuilder};
+//!
+//! let media = Media::Continuous(ContinuousType::Continuous62);
+//! let mut canvas = SkiaCanvas::new(media, Resolution::Normal).length_mm(20.0);
+//!
+//! // Coordinates are in millimetres thanks to `transform()`
+//! let circle = PathBuilder::from_circle(10.0, 10.0, 8.0).unwrap();
+//! let mut paint = Paint::default();
+//! paint.set_color_rgba8(0, 0, 0, 255);
+//! paint.anti_alias = true;
+//! let transform = canvas.transform();
+//! canvas
+//!     .pixmap_mut()
+//!     .fill_path(&circle, &paint, FillRule::Winding, transform, None);
+//!
+//! let matrix = canvas.to_matrix();
+//! assert_eq!(matrix[0].len(), 90);
+//! ```
+
+use tiny_skia::{Color, Pixmap, Transform};
+
+use crate::{
+    layout::{Canvas, Orientation, Resolution},
+    media::Media,
+    Matrix,
+};
+
+const MM_PER_INCH: f32 = 25.4;
+
+/// Vector drawing surface for a label backed by a `tiny_skia::Pixmap`.
+pub struct SkiaCanvas {
+    canvas: Canvas,
+    pixmap: Pixmap,
+}
+
+impl SkiaCanvas {
+    /// Create a white surface for the printable area of the given media.
+    ///
+    /// # Arguments
+    /// * `media` - Media loaded in the printer
+    /// * `resolution` - Print resolution mode
+    pub fn new(media: Media, resolution: Resolution) -> Self {
+        Self::from_canvas(Canvas::new(media, resolution))
+    }
+
+    /// Set the label length for continuous media in millimetres.
+    ///
+    /// Has no effect for die-cut labels. Anything already drawn is discarded.
+    pub fn length_mm(self, mm: f32) -> Self {
+        Self::from_canvas(self.canvas.length_mm(mm))
+    }
+
+    /// Set the orientation of the content.
+    ///
+    /// In `Orientation::Landscape` the x axis runs along the tape. Anything
+    /// already drawn is discarded.
+    pub fn orientation(self, orientation: Orientation) -> Self {
+        Self::from_canvas(self.canvas.orientation(orientation))
+    }
+
+    fn from_canvas(canvas: Canvas) -> Self {
+        let mut pixmap = Pixmap::new(canvas.width_dots(), canvas.length_dots())
+            .expect("canvas size is never zero");
+        pixmap.fill(Color::WHITE);
+        SkiaCanvas { canvas, pixmap }
+    }
+
+    /// Transform mapping millimetres on the label to pixmap dots.
+    ///
+    /// Pass it to tiny-skia drawing calls to lay out content in physical units.
+    pub fn transform(&self) -> Transform {
+        let (dpi_x, dpi_y) = self.canvas.dpi();
+        Transform::from_scale(dpi_x as f32 / MM_PER_INCH, dpi_y as f32 / MM_PER_INCH)
+    }
+
+    /// The underlying pixmap.
+    pub fn pixmap(&self) -> &Pixmap {
+        &self.pixmap
+    }
+
+    /// The underlying pixmap for drawing.
+    pub fn pixmap_mut(&mut self) -> &mut Pixmap {
+        &mut self.pixmap
+    }
+
+    /// Composite the pixmap over white and convert it to a grayscale `Canvas`.
+    pub fn to_canvas(&self) -> Canvas {
+        let mut canvas = self.canvas.clone();
+        let width = self.pixmap.width();
+
+        for (index, pixel) in self.pixmap.pixels().iter().enumerate() {
+            // Premultiplied colour over a white background
+            let white = 255 - pixel.alpha() as u32;
+            let r = pixel.red() as u32 + white;
+            let g = pixel.green() as u32 + white;
+            let b = pixel.blue() as u32 + white;
+            let luma = (r * 299 + g * 587 + b * 114) / 1000;
+
+            let x = index as u32 % width;
+            let y = index as u32 / width;
+            canvas.set_dot(x, y, luma as u8);
+        }
+        canvas
+    }
+
+    /// Convert the drawing to printer rows with Floyd–Steinberg dithering.
+    pub fn to_matrix(&self) -> Matrix {
+        self.to_canvas().to_matrix_dithered()
+    }
+}
@@ -0,0 +1,156 @@
+//! Print statistics collected over the lifetime of a printer.
+//!
+//! `Printer` counts the jobs, labels and errors it handles together with the
+//! tape consumed per media type. Set `Config::statistics_file()` to keep the
+//! numbers across runs, e.g. to estimate when a roll will run out.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::media::Media;
+
+/// Tape consumed on one media type.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MediaUsage {
+    /// Number of labels printed.
+    pub labels: u64,
+    /// Tape length consumed in millimetres, including feed margins.
+    pub length_mm: f64,
+}
+
+/// Counters of printed labels, consumed tape and errors.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{ContinuousType, Media, Statistics};
+/// let media = Media::Continuous(ContinuousType::Continuous62);
+/// let stats = Statistics::load("/tmp/ql-label-doctest-missing.stats")?;
+///
+/// assert_eq!(stats.labels(), 0);
+/// assert_eq!(stats.media_usage(media).length_mm, 0.0);
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Statistics {
+    jobs: u64,
+    labels: u64,
+    errors: u64,
+    media: BTreeMap<String, MediaUsage>,
+}
+
+impl Statistics {
+    /// Create empty statistics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load statistics saved with `save()`.
+    ///
+    /// A missing file yields empty statistics, so the first run of an
+    /// application does not need special handling.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        Self::parse(&text)
+    }
+
+    /// Save statistics as a small `key=value` text file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_text())
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Number of print jobs started.
+    pub fn jobs(&self) -> u64 {
+        self.jobs
+    }
+
+    /// Number of labels printed on all media.
+    pub fn labels(&self) -> u64 {
+        self.labels
+    }
+
+    /// Number of print jobs that failed.
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// Tape consumed on `media`.
+    pub fn media_usage(&self, media: Media) -> MediaUsage {
+        self.media.get(&media.name()).copied().unwrap_or_default()
+    }
+
+    /// Forget the usage of `media` in this copy of the statistics, see
+    /// `Printer::reset_media_usage()` to reset the numbers of a printer.
+    pub fn reset_media(&mut self, media: Media) {
+        self.media.remove(&media.name());
+    }
+
+    pub(crate) fn record_job(&mut self) {
+        self.jobs += 1;
+    }
+
+    pub(crate) fn record_error(&mut self) {
+        self.errors += 1;
+    }
+
+    pub(crate) fn record_label(&mut self, media: Media, length_mm: f64) {
+        self.labels += 1;
+        let usage = self.media.entry(media.name()).or_default();
+        usage.labels += 1;
+        usage.length_mm += length_mm;
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = format!(
+            "jobs={}\nlabels={}\nerrors={}\n",
+            self.jobs, self.labels, self.errors
+        );
+        for (key, usage) in &self.media {
+            text.push_str(&format!("{}.labels={}\n", key, usage.labels));
+            text.push_str(&format!("{}.length_mm={}\n", key, usage.length_mm));
+        }
+        text
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut stats = Self::new();
+
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid statistics line: {}", line))?;
+            let invalid = || format!("Invalid value for {}: {}", key, value);
+
+            match key.rsplit_once('.') {
+                None => {
+                    let value = value.parse::<u64>().map_err(|_| invalid())?;
+                    match key {
+                        "jobs" => stats.jobs = value,
+                        "labels" => stats.labels = value,
+                        "errors" => stats.errors = value,
+                        _ => return Err(format!("Unknown statistics key: {}", key)),
+                    }
+                }
+                Some((media, field)) => {
+                    let usage = stats.media.entry(media.to_string()).or_default();
+                    match field {
+                        "labels" => {
+                            usage.labels = value.parse().map_err(|_| invalid())?;
+                        }
+                        "length_mm" => {
+                            usage.length_mm = value.parse().map_err(|_| invalid())?;
+                        }
+                        _ => return Err(format!("Unknown statistics key: {}", key)),
+                    }
+                }
+            }
+        }
+        Ok(stats)
+    }
+}
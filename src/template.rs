@@ -0,0 +1,275 @@
+//! P-touch Template mode without any I/O.
+//!
+//! The QL-810W, QL-820NWB and QL-1100 series with network interface keep
+//! label templates transferred with P-touch Transfer Manager. In template
+//! mode a job only selects a template by its key, fills its text and barcode
+//! objects and starts printing, which is much less data than the raster rows
+//! of every label. Like `protocol`, this module only needs `core` and
+//! `alloc`; `Printer::print_template()` sends the commands.
+//!
+//! # Example
+//! ```rust
+//! # use ql_label::template::{self, Template};
+//! let job = Template::new(3)
+//!     .text("name", "Jane Doe")
+//!     .barcode("code", "4901234567894")
+//!     .copies(2)
+//!     .encode()?;
+//!
+//! assert!(job.starts_with(&[0x1B, 0x69, 0x61, template::TEMPLATE_MODE]));
+//! assert!(job.ends_with(b"^CN002^FF"));
+//! # Ok::<(), String>(())
+//! ```
+
+use alloc::{format, string::String, vec::Vec};
+use core::convert::TryFrom;
+
+/// Mode of `ESC i a` switching the printer to P-touch Template mode.
+pub const TEMPLATE_MODE: u8 = 0x03;
+
+/// Append the command switching the printer to template mode (ESC i a).
+pub fn switch_mode(buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&[0x1B, 0x69, 0x61, TEMPLATE_MODE]);
+}
+
+/// Append the command resetting the template mode settings (^II), e.g. the
+/// number of copies.
+pub fn initialize(buf: &mut Vec<u8>) {
+    buf.extend_from_slice(b"^II");
+}
+
+/// Append the command selecting the template stored under `key` (^TS).
+///
+/// # Returns
+/// * `Ok(())` - The command was appended
+/// * `Err(String)` - `key` is 0, templates are stored under keys 1 to 255
+pub fn select(buf: &mut Vec<u8>, key: u8) -> Result<(), String> {
+    if key == 0 {
+        return Err("Template key 0, use 1 to 255".into());
+    }
+    buf.extend_from_slice(format!("^TS{:03}", key).as_bytes());
+    Ok(())
+}
+
+/// Append the command selecting the object named `name` of the template
+/// (^ON), the target of the next `insert_data()`.
+///
+/// # Returns
+/// * `Ok(())` - The command was appended
+/// * `Err(String)` - `name` is empty or contains a NUL character
+pub fn select_object(buf: &mut Vec<u8>, name: &str) -> Result<(), String> {
+    if name.is_empty() || name.contains('\0') {
+        return Err(format!("Invalid template object name {:?}", name));
+    }
+    buf.extend_from_slice(b"^ON");
+    buf.extend_from_slice(name.as_bytes());
+    buf.push(0x00);
+    Ok(())
+}
+
+/// Append the command replacing the data of the selected object (^DI).
+///
+/// # Returns
+/// * `Ok(())` - The command was appended
+/// * `Err(String)` - `data` is longer than 65535 bytes
+pub fn insert_data(buf: &mut Vec<u8>, data: &[u8]) -> Result<(), String> {
+    let len = u16::try_from(data.len())
+        .map_err(|_| format!("Template data of {} bytes, at most 65535", data.len()))?;
+    buf.extend_from_slice(b"^DI");
+    buf.extend_from_slice(&len.to_le_bytes());
+    buf.extend_from_slice(data);
+    Ok(())
+}
+
+/// Append the command setting the number of copies printed (^CN).
+///
+/// # Returns
+/// * `Ok(())` - The command was appended
+/// * `Err(String)` - `copies` is not 1 to 999
+pub fn set_copies(buf: &mut Vec<u8>, copies: u16) -> Result<(), String> {
+    if !(1..=999).contains(&copies) {
+        return Err(format!("{} copies, use 1 to 999", copies));
+    }
+    buf.extend_from_slice(format!("^CN{:03}", copies).as_bytes());
+    Ok(())
+}
+
+/// Append the command printing the template (^FF).
+pub fn print(buf: &mut Vec<u8>) {
+    buf.extend_from_slice(b"^FF");
+}
+
+/// Template stored in the printer, filled with the data of one label.
+///
+/// Objects are filled by the name given to them in P-touch Editor. Text is
+/// sent as UTF-8, so the template must use a font covering the characters.
+/// Objects not filled print the data stored with the template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    key: u8,
+    objects: Vec<(String, Vec<u8>)>,
+    copies: u16,
+}
+
+impl Template {
+    /// Template stored under `key`, printed once.
+    pub fn new(key: u8) -> Self {
+        Template {
+            key,
+            objects: Vec::new(),
+            copies: 1,
+        }
+    }
+
+    /// Replace the text of the object named `object`.
+    pub fn text(mut self, object: &str, text: &str) -> Self {
+        self.objects.push((object.into(), text.as_bytes().to_vec()));
+        self
+    }
+
+    /// Replace the data encoded by the barcode object named `object`.
+    ///
+    /// The symbology and its options are part of the template; the data
+    /// must be valid for them or the printer reports an error.
+    pub fn barcode(mut self, object: &str, data: &str) -> Self {
+        self.objects.push((object.into(), data.as_bytes().to_vec()));
+        self
+    }
+
+    /// Print `copies` labels, 1 to 999.
+    pub fn copies(self, copies: u16) -> Self {
+        Template { copies, ..self }
+    }
+
+    /// Key the template is stored under.
+    pub fn key(&self) -> u8 {
+        self.key
+    }
+
+    /// Objects filled in order, with their data.
+    pub fn objects(&self) -> &[(String, Vec<u8>)] {
+        &self.objects
+    }
+
+    /// Commands switching to template mode and printing the template.
+    ///
+    /// # Returns
+    /// * `Ok(Vec<u8>)` - The commands
+    /// * `Err(String)` - The key, an object or the number of copies is
+    ///   invalid, see the command functions of this module
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let mut buf = Vec::new();
+        switch_mode(&mut buf);
+        initialize(&mut buf);
+        select(&mut buf, self.key)?;
+        for (name, data) in &self.objects {
+            select_object(&mut buf, name)?;
+            insert_data(&mut buf, data)?;
+        }
+        set_copies(&mut buf, self.copies)?;
+        print(&mut buf);
+        Ok(buf)
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn push_object(&mut self, name: String, data: Vec<u8>) {
+        self.objects.push((name, data));
+    }
+}
+
+/// Template mode command, decoded by the `Emulator`.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Command {
+    /// ^II
+    Initialize,
+    /// ^TS : Select the template stored under the key.
+    Select(u8),
+    /// ^ON : Select the object by name.
+    SelectObject(String),
+    /// ^DI : Data of the selected object.
+    InsertData(Vec<u8>),
+    /// ^CN : Number of copies.
+    Copies(u16),
+    /// ^FF : Print the template.
+    Print,
+    /// Byte not starting any known command.
+    Unknown(u8),
+}
+
+#[cfg(feature = "std")]
+impl Command {
+    /// Parse the template command at the start of `buf`.
+    ///
+    /// Returns the command and the number of bytes it takes, or `None` when
+    /// `buf` ends in the middle of a command.
+    pub(crate) fn parse(buf: &[u8]) -> Option<(Command, usize)> {
+        let number = |digits: &[u8]| core::str::from_utf8(digits).ok()?.parse::<u16>().ok();
+        if *buf.first()? != b'^' {
+            return Some((Command::Unknown(buf[0]), 1));
+        }
+        let command = match buf.get(1..3)? {
+            b"II" => (Command::Initialize, 3),
+            b"FF" => (Command::Print, 3),
+            b"TS" => match number(buf.get(3..6)?) {
+                Some(key) => (Command::Select(key as u8), 6),
+                None => (Command::Unknown(b'^'), 1),
+            },
+            b"CN" => match number(buf.get(3..6)?) {
+                Some(copies) => (Command::Copies(copies), 6),
+                None => (Command::Unknown(b'^'), 1),
+            },
+            b"ON" => {
+                let len = buf[3..].iter().position(|&b| b == 0x00)?;
+                let name = String::from_utf8_lossy(&buf[3..3 + len]).into_owned();
+                (Command::SelectObject(name), 4 + len)
+            }
+            b"DI" => {
+                let n = buf.get(3..5)?;
+                let len = u16::from_le_bytes([n[0], n[1]]) as usize;
+                (Command::InsertData(buf.get(5..5 + len)?.to_vec()), 5 + len)
+            }
+            _ => (Command::Unknown(b'^'), 1),
+        };
+        Some(command)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_and_parse() {
+        let template = Template::new(12)
+            .text("title", "Lot 7")
+            .barcode("code", "12345")
+            .copies(3);
+        let buf = template.encode().unwrap();
+        assert_eq!(&buf[..4], &[0x1B, 0x69, 0x61, TEMPLATE_MODE]);
+
+        let mut commands = Vec::new();
+        let mut rest = &buf[4..];
+        while let Some((command, len)) = Command::parse(rest) {
+            commands.push(command);
+            rest = &rest[len..];
+        }
+        assert_eq!(
+            commands,
+            vec![
+                Command::Initialize,
+                Command::Select(12),
+                Command::SelectObject("title".into()),
+                Command::InsertData(b"Lot 7".to_vec()),
+                Command::SelectObject("code".into()),
+                Command::InsertData(b"12345".to_vec()),
+                Command::Copies(3),
+                Command::Print,
+            ]
+        );
+
+        assert!(Template::new(0).encode().is_err());
+        assert!(Template::new(1).copies(1000).encode().is_err());
+        assert!(Template::new(1).text("", "x").encode().is_err());
+    }
+}
@@ -0,0 +1,573 @@
+//! Text rendering with TrueType / OpenType fonts.
+//!
+//! Enabled with the `text` feature. Text is drawn onto a `Canvas` through a
+//! `FontChain`: every character is rendered with the first font of the chain
+//! that contains it, so a Latin font can be combined with a Japanese font for
+//! product names mixing both scripts.
+//!
+//! With the `shaping` feature each run of text is shaped with `rustybuzz`,
+//! which applies kerning, ligatures and mark positioning from the font's
+//! OpenType tables. Without it glyphs are placed one after another using
+//! their advance widths and simple kerning.
+//!
+//! # Example
+//! ```rust,no_run
+//! use ql_label::{Canvas, ContinuousType, Font, FontChain, Media, Resolution};
+//!
+//! let fonts = FontChain::new(Font::open("fonts/NotoSans-Regular.ttf")?)
+//!     .fallback(Font::open("fonts/NotoSansJP-Regular.otf")?);
+//!
+//! let media = Media::Continuous(ContinuousType::Continuous62);
+//! let mut canvas = Canvas::new(media, Resolution::Normal).length_mm(20.0);
+//! canvas.draw_text(2.0, 2.0, "Rust 入門 2nd ed.", &fonts, 6.0);
+//!
+//! let matrix = canvas.to_matrix();
+//! # Ok::<(), String>(())
+//! ```
+
+use ab_glyph::{point, Font as _, FontVec, Glyph, GlyphId, PxScale, ScaleFont};
+use std::path::Path;
+
+use crate::{
+    layout::{Canvas, Orientation, Resolution, HEAD_DPI},
+    media::Media,
+    Matrix,
+};
+
+/// A font loaded from TrueType or OpenType data.
+pub struct Font {
+    inner: FontVec,
+    #[cfg_attr(not(feature = "shaping"), allow(dead_code))]
+    index: u32,
+}
+
+impl Font {
+    /// Load a font from the contents of a font file.
+    ///
+    /// # Arguments
+    /// * `data` - TrueType (`.ttf`) or OpenType (`.otf`) font data
+    ///
+    /// # Returns
+    /// The font, or an error message if the data is not a valid font.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, String> {
+        Self::from_bytes_and_index(data, 0)
+    }
+
+    /// Load a single face from a font collection (`.ttc`).
+    ///
+    /// # Arguments
+    /// * `data` - Font collection data
+    /// * `index` - Index of the face in the collection
+    pub fn from_bytes_and_index(data: Vec<u8>, index: u32) -> Result<Self, String> {
+        let inner = FontVec::try_from_vec_and_index(data, index)
+            .map_err(|e| format!("Invalid font data: {}", e))?;
+        Ok(Font { inner, index })
+    }
+
+    /// Load a font from a file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)
+            .map_err(|e| format!("Failed to read font {}: {}", path.display(), e))?;
+        Self::from_bytes(data)
+    }
+
+    /// Whether the font contains a glyph for `c`.
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.inner.glyph_id(c).0 != 0
+    }
+
+    /// Scale for an em size given in pixels horizontally and vertically.
+    ///
+    /// `ab_glyph` scales by the line height, while label sizes are given per
+    /// em like in any word processor.
+    fn scale(&self, em_x: f32, em_y: f32) -> PxScale {
+        let height = self.inner.height_unscaled();
+        let upem = self.inner.units_per_em().unwrap_or(height);
+        PxScale {
+            x: em_x * height / upem,
+            y: em_y * height / upem,
+        }
+    }
+}
+
+/// Ordered list of fonts used to render text.
+///
+/// The first font is the primary font and defines the line metrics. Fonts
+/// added with `fallback()` are only used for characters missing from all
+/// fonts before them.
+pub struct FontChain {
+    fonts: Vec<Font>,
+}
+
+/// A glyph positioned relative to the start of the baseline, in pixels.
+pub(crate) struct PlacedGlyph {
+    font: usize,
+    id: GlyphId,
+    x: f32,
+    y: f32,
+}
+
+impl FontChain {
+    /// Create a chain with a single primary font.
+    pub fn new(font: Font) -> Self {
+        FontChain { fonts: vec![font] }
+    }
+
+    /// Append a fallback font to the chain.
+    pub fn fallback(mut self, font: Font) -> Self {
+        self.fonts.push(font);
+        self
+    }
+
+    /// Fonts of the chain, primary font first.
+    pub fn fonts(&self) -> &[Font] {
+        &self.fonts
+    }
+
+    /// Width of a single line of text in millimetres.
+    ///
+    /// # Arguments
+    /// * `text` - Text to measure
+    /// * `size` - Font size (em height) in millimetres
+    pub fn text_width(&self, text: &str, size: f32) -> f32 {
+        let em = size * HEAD_DPI as f32 / 25.4;
+        let (_, width) = self.layout(text, em, em);
+        width * 25.4 / HEAD_DPI as f32
+    }
+
+    /// Split text into runs rendered with the same font.
+    fn runs<'a>(&self, text: &'a str) -> Vec<(usize, &'a str)> {
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+
+        for (offset, c) in text.char_indices() {
+            let current = runs.last().map(|&(font, _)| font);
+            let font = match current {
+                // Spaces and combining marks stay with the text they belong to
+                Some(font) if is_neutral(c) => font,
+                _ => self.fonts.iter().position(|f| f.has_glyph(c)).unwrap_or(0),
+            };
+            if current != Some(font) {
+                runs.push((font, offset));
+            }
+        }
+
+        runs.iter()
+            .enumerate()
+            .map(|(i, &(font, start))| {
+                let end = runs.get(i + 1).map_or(text.len(), |&(_, end)| end);
+                (font, &text[start..end])
+            })
+            .collect()
+    }
+
+    /// Position the glyphs of a line of text.
+    ///
+    /// # Returns
+    /// The glyphs and the total advance width, both in pixels.
+    pub(crate) fn layout(&self, text: &str, em_x: f32, em_y: f32) -> (Vec<PlacedGlyph>, f32) {
+        let mut glyphs = Vec::new();
+        let mut pen = 0.0;
+
+        for (index, run) in self.runs(text) {
+            let font = &self.fonts[index];
+            let scale = font.scale(em_x, em_y);
+            for (id, x, y, advance) in shape_run(font, run, scale) {
+                glyphs.push(PlacedGlyph {
+                    font: index,
+                    id,
+                    x: pen + x,
+                    y,
+                });
+                pen += advance;
+            }
+        }
+        (glyphs, pen)
+    }
+}
+
+/// Shape a run of text with rustybuzz.
+///
+/// Returns glyph id, x / y offset and advance for each glyph, in pixels.
+#[cfg(feature = "shaping")]
+fn shape_run(font: &Font, text: &str, scale: PxScale) -> Vec<(GlyphId, f32, f32, f32)> {
+    let face = match rustybuzz::Face::from_slice(font.inner.as_slice(), font.index) {
+        Some(face) => face,
+        None => return Vec::new(),
+    };
+    // Pixels per font unit
+    let height = font.inner.height_unscaled();
+    let sx = scale.x / height;
+    let sy = scale.y / height;
+
+    let mut buffer = rustybuzz::UnicodeBuffer::new();
+    buffer.push_str(text);
+    buffer.guess_segment_properties();
+    let output = rustybuzz::shape(&face, &[], buffer);
+
+    output
+        .glyph_infos()
+        .iter()
+        .zip(output.glyph_positions())
+        .map(|(info, pos)| {
+            (
+                GlyphId(info.glyph_id as u16),
+                pos.x_offset as f32 * sx,
+                pos.y_offset as f32 * sy,
+                pos.x_advance as f32 * sx,
+            )
+        })
+        .collect()
+}
+
+/// Place glyphs one after another using advance widths and kerning.
+///
+/// Returns glyph id, x / y offset and advance for each glyph, in pixels.
+#[cfg(not(feature = "shaping"))]
+fn shape_run(font: &Font, text: &str, scale: PxScale) -> Vec<(GlyphId, f32, f32, f32)> {
+    let scaled = font.inner.as_scaled(scale);
+    let mut glyphs: Vec<(GlyphId, f32, f32, f32)> = Vec::new();
+
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(last) = glyphs.last_mut() {
+            last.3 += scaled.kern(last.0, id);
+        }
+        glyphs.push((id, 0.0, 0.0, scaled.h_advance(id)));
+    }
+    glyphs
+}
+
+/// Characters that do not pick a font on their own.
+fn is_neutral(c: char) -> bool {
+    c.is_whitespace()
+        || matches!(c as u32,
+            0x0300..=0x036F     // Combining diacritical marks
+            | 0x1AB0..=0x1AFF   // Combining diacritical marks extended
+            | 0x200C..=0x200D   // ZWNJ / ZWJ
+            | 0x20D0..=0x20FF   // Combining marks for symbols
+            | 0x3099..=0x309A   // Combining (semi-)voiced sound marks
+            | 0xFE00..=0xFE0F   // Variation selectors
+            | 0xE0100..=0xE01EF // Ideographic variation selectors
+        )
+}
+
+impl Canvas {
+    /// Draw a single line of text in black.
+    ///
+    /// Glyphs are rendered with anti-aliasing into the grayscale canvas, so
+    /// they can be binarized with `to_matrix()` or `to_matrix_dithered()`.
+    ///
+    /// # Arguments
+    /// * `x`, `y` - Top-left corner of the line in millimetres
+    /// * `text` - Text to draw
+    /// * `fonts` - Fonts used to render the text
+    /// * `size` - Font size (em height) in millimetres
+    ///
+    /// # Returns
+    /// The advance width of the text in millimetres.
+    pub fn draw_text(&mut self, x: f32, y: f32, text: &str, fonts: &FontChain, size: f32) -> f32 {
+        let (dpi_x, dpi_y) = self.dpi();
+        let em_x = size * dpi_x as f32 / 25.4;
+        let em_y = size * dpi_y as f32 / 25.4;
+        let (x0, y0) = self.to_dots(x, y);
+
+        let primary = &fonts.fonts[0];
+        let ascent = primary.inner.as_scaled(primary.scale(em_x, em_y)).ascent();
+        let baseline = y0 as f32 + ascent;
+
+        let (glyphs, width) = fonts.layout(text, em_x, em_y);
+        for placed in glyphs {
+            let font = &fonts.fonts[placed.font];
+            let glyph = Glyph {
+                id: placed.id,
+                scale: font.scale(em_x, em_y),
+                position: point(x0 as f32 + placed.x, baseline - placed.y),
+            };
+            if let Some(outline) = font.inner.outline_glyph(glyph) {
+                let bounds = outline.px_bounds();
+                outline.draw(|gx, gy, coverage| {
+                    let px = bounds.min.x as i32 + gx as i32;
+                    let py = bounds.min.y as i32 + gy as i32;
+                    if px < 0 || py < 0 {
+                        return;
+                    }
+                    let value = (255.0 * (1.0 - coverage.min(1.0))) as u8;
+                    if let Some(old) = self.dot(px as u32, py as u32) {
+                        self.put(px as u32, py as u32, old.min(value));
+                    }
+                });
+            }
+        }
+        width * 25.4 / dpi_x as f32
+    }
+}
+
+/// Horizontal alignment of the lines of a `TextLabel`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextAlign {
+    /// Lines start at the left margin.
+    #[default]
+    Left,
+    /// Lines are centered between the margins.
+    Center,
+    /// Lines end at the right margin.
+    Right,
+}
+
+/// A label holding only text, such as a name tag or a shelf label.
+///
+/// Lines longer than the printable width are wrapped between words, and
+/// between the characters of scripts written without spaces. On die-cut
+/// labels the text is centered vertically, continuous tape is cut to the
+/// length of the text.
+///
+/// # Example
+/// ```rust,no_run
+/// use ql_label::{DieCutType, Font, FontChain, Media, TextAlign, TextLabel};
+///
+/// let fonts = FontChain::new(Font::open("fonts/NotoSans-Regular.ttf")?);
+/// let media = Media::DieCut(DieCutType::DieCut62x29);
+///
+/// let matrix = TextLabel::new(media, &fonts)
+///     .text("Ferris the Crab")
+///     .text("Rust Meetup Tokyo")
+///     .size_mm(5.0)
+///     .align(TextAlign::Center)
+///     .to_matrix();
+/// # Ok::<(), String>(())
+/// ```
+pub struct TextLabel<'a> {
+    media: Media,
+    fonts: &'a FontChain,
+    resolution: Resolution,
+    orientation: Orientation,
+    lines: Vec<String>,
+    size: f32,
+    line_spacing: f32,
+    margin: f32,
+    align: TextAlign,
+    wrap: bool,
+}
+
+impl<'a> TextLabel<'a> {
+    /// Empty label for `media` rendered with `fonts`, 4 mm text with 1.2
+    /// line spacing, left aligned and wrapped, with a 1.5 mm margin.
+    pub fn new(media: Media, fonts: &'a FontChain) -> Self {
+        TextLabel {
+            media,
+            fonts,
+            resolution: Resolution::Normal,
+            orientation: Orientation::Portrait,
+            lines: Vec::new(),
+            size: 4.0,
+            line_spacing: 1.2,
+            margin: 1.5,
+            align: TextAlign::Left,
+            wrap: true,
+        }
+    }
+
+    /// Append text, every `'\n'` starts a new line.
+    pub fn text(mut self, text: &str) -> Self {
+        self.lines.extend(text.lines().map(str::to_string));
+        self
+    }
+
+    /// Font size (em height) in millimetres.
+    pub fn size_mm(self, size: f32) -> Self {
+        TextLabel { size, ..self }
+    }
+
+    /// Distance between baselines as a multiple of the font size.
+    pub fn line_spacing(self, factor: f32) -> Self {
+        TextLabel {
+            line_spacing: factor,
+            ..self
+        }
+    }
+
+    /// Blank space around the text in millimetres.
+    pub fn margin_mm(self, mm: f32) -> Self {
+        TextLabel { margin: mm, ..self }
+    }
+
+    /// Horizontal alignment of the lines.
+    pub fn align(self, align: TextAlign) -> Self {
+        TextLabel { align, ..self }
+    }
+
+    /// Whether long lines are wrapped, otherwise they are clipped.
+    ///
+    /// Text along continuous tape is never wrapped, the tape grows instead.
+    pub fn wrap(self, wrap: bool) -> Self {
+        TextLabel { wrap, ..self }
+    }
+
+    /// Resolution the label is printed at.
+    pub fn resolution(self, resolution: Resolution) -> Self {
+        TextLabel { resolution, ..self }
+    }
+
+    /// Print the text across the tape (`Portrait`) or along it
+    /// (`Landscape`), see `Canvas::orientation()`.
+    pub fn orientation(self, orientation: Orientation) -> Self {
+        TextLabel {
+            orientation,
+            ..self
+        }
+    }
+
+    /// Render the label on a canvas, e.g. to add a logo or a barcode.
+    pub fn to_canvas(&self) -> Canvas {
+        let mut canvas = Canvas::new(self.media, self.resolution).orientation(self.orientation);
+        let continuous = matches!(self.media, Media::Continuous(_));
+        let along_tape = continuous && self.orientation == Orientation::Landscape;
+
+        let measure = |text: &str| self.fonts.text_width(text, self.size);
+        let lines: Vec<String> = if self.wrap && !along_tape {
+            let (width, _) = canvas.size_mm();
+            let width = width - 2.0 * self.margin;
+            self.lines
+                .iter()
+                .flat_map(|line| wrap_line(line, width, measure))
+                .collect()
+        } else {
+            self.lines.clone()
+        };
+        let line_height = self.size * self.line_spacing;
+        let block = self.size + line_height * lines.len().saturating_sub(1) as f32;
+
+        if along_tape {
+            let longest = lines.iter().map(|line| measure(line)).fold(0.0, f32::max);
+            canvas = canvas.length_mm(longest + 2.0 * self.margin);
+        } else if continuous {
+            canvas = canvas.length_mm(block + 2.0 * self.margin);
+        }
+        let mut canvas = canvas.margin_mm(self.margin);
+
+        let (width, height) = canvas.size_mm();
+        let top = ((height - block) / 2.0).max(0.0);
+        for (i, line) in lines.iter().enumerate() {
+            let x = match self.align {
+                TextAlign::Left => 0.0,
+                TextAlign::Center => (width - measure(line)) / 2.0,
+                TextAlign::Right => width - measure(line),
+            };
+            canvas.draw_text(
+                x.max(0.0),
+                top + line_height * i as f32,
+                line,
+                self.fonts,
+                self.size,
+            );
+        }
+        canvas
+    }
+
+    /// Render the label to printer rows.
+    pub fn to_matrix(&self) -> Matrix {
+        self.to_canvas().to_matrix()
+    }
+}
+
+/// Split `line` into lines at most `width` wide as measured by `measure`.
+///
+/// Lines break at spaces and between CJK characters. A word wider than
+/// `width` is broken between any characters.
+fn wrap_line(line: &str, width: f32, measure: impl Fn(&str) -> f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+
+    for token in tokens(line) {
+        let candidate = format!("{}{}", current, token);
+        if current.trim().is_empty() || measure(candidate.trim_end()) <= width {
+            current = candidate;
+            continue;
+        }
+        lines.push(current.trim_end().to_string());
+        current = token.trim_start().to_string();
+    }
+    // Words that still don't fit are broken anywhere
+    lines.push(current.trim_end().to_string());
+    lines
+        .into_iter()
+        .flat_map(|line| {
+            if measure(&line) <= width || line.chars().count() < 2 {
+                return vec![line];
+            }
+            let mut parts = vec![String::new()];
+            for c in line.chars() {
+                let last = parts.last_mut().unwrap();
+                last.push(c);
+                if last.chars().count() > 1 && measure(last) > width {
+                    last.pop();
+                    parts.push(c.to_string());
+                }
+            }
+            parts
+        })
+        .collect()
+}
+
+/// Words with their trailing spaces, CJK characters on their own.
+fn tokens(line: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut chars = line.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        let end = chars.peek().map_or(line.len(), |&(next, _)| next);
+        let next = chars.peek().map(|&(_, next)| next);
+        let boundary = match next {
+            None => true,
+            Some(next) => {
+                (c.is_whitespace() && !next.is_whitespace())
+                    || is_cjk(c)
+                    || (is_cjk(next) && !c.is_whitespace())
+            }
+        };
+        if boundary {
+            tokens.push(&line[start..end]);
+            start = end;
+        }
+    }
+    tokens
+}
+
+/// Characters of scripts that break lines between any two characters.
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF     // Hiragana, katakana
+        | 0x3400..=0x4DBF   // CJK extension A
+        | 0x4E00..=0x9FFF   // CJK unified ideographs
+        | 0xAC00..=0xD7AF   // Hangul syllables
+        | 0xF900..=0xFAFF   // CJK compatibility ideographs
+        | 0xFF00..=0xFFEF   // Half- and full-width forms
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_line() {
+        // Every character is 1 mm wide
+        let measure = |text: &str| text.chars().count() as f32;
+
+        assert_eq!(
+            wrap_line("the quick brown fox", 10.0, measure),
+            vec!["the quick", "brown fox"]
+        );
+        assert_eq!(
+            wrap_line("短い日本語の文章です", 4.0, measure),
+            vec!["短い日本", "語の文章", "です"]
+        );
+        assert_eq!(wrap_line("Rust入門", 5.0, measure), vec!["Rust入", "門"]);
+        assert_eq!(
+            wrap_line("abcdefghij", 4.0, measure),
+            vec!["abcd", "efgh", "ij"]
+        );
+        assert_eq!(wrap_line("", 4.0, measure), vec![""]);
+    }
+}
@@ -0,0 +1,489 @@
+//! Byte transports between `Printer` and a device.
+//!
+//! `Printer::new()` talks to a printer over USB. Any other channel, such as
+//! the `Emulator`, the `MockTransport` used in tests or the
+//! `BluetoothTransport` of the `bluetooth` feature, implements `Transport`
+//! and is passed to `Printer::with_transport()`.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::printer::DeviceInfo;
+
+/// Channel carrying raster commands to a printer and status messages back.
+///
+/// `Printer` serializes all calls, but it is shared between threads, so
+/// implementations must be `Send` and `Sync`.
+pub trait Transport: Send + Sync {
+    /// Write `buf` to the printer, returning the number of bytes sent.
+    fn write(&self, buf: &[u8], timeout: Duration) -> Result<usize, Error>;
+
+    /// Read a 32-byte status message into `buf`, returning its length.
+    ///
+    /// Return `Error::ReadStatusTimeout` when nothing arrived within
+    /// `timeout`.
+    fn read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, Error>;
+
+    /// Open the connection again after it failed, e.g. when the printer was
+    /// unplugged or power-cycled, see `Config::reconnect()`.
+    ///
+    /// The default does nothing, for transports that recover by
+    /// themselves.
+    fn reconnect(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Descriptors of the device the transport is connected to, see
+    /// `Printer::device_info()`.
+    ///
+    /// The default knows none of them.
+    fn device_info(&self) -> DeviceInfo {
+        DeviceInfo::default()
+    }
+}
+
+/// Transport recording every byte written and replaying canned status
+/// messages.
+///
+/// Unlike the `Emulator`, the mock doesn't interpret the commands: reads
+/// return the queued replies in order and `Error::ReadStatusTimeout` once
+/// none is left. Clones share the same state, so pass one clone to
+/// `Printer::with_transport()` and assert on the command stream through
+/// another.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{Config, ContinuousType, Error, Media, MockTransport, Model, Printer};
+/// let media = Media::Continuous(ContinuousType::Continuous62);
+/// let mock = MockTransport::new();
+/// let printer = Printer::with_transport(Config::new_any(Model::QL800, media), mock.clone())?;
+///
+/// // Status of a QL-800 with 62 mm continuous tape
+/// let mut status = [0x00; 32];
+/// status[..5].copy_from_slice(&[0x80, 0x20, 0x42, 0x34, 0x38]);
+/// status[10] = 62;
+/// status[11] = 0x0A;
+/// mock.push_reply(status);
+///
+/// assert_eq!(printer.check_status()?.model(), Model::QL800);
+/// assert!(mock.written().ends_with(&[0x1B, 0x69, 0x53]));
+/// assert!(matches!(printer.check_status(), Err(Error::ReadStatusTimeout)));
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    written: Vec<u8>,
+    replies: VecDeque<[u8; 32]>,
+}
+
+impl MockTransport {
+    /// Create a mock without any reply queued.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a 32-byte status message returned by the next read.
+    pub fn push_reply(&self, status: [u8; 32]) {
+        self.state().replies.push_back(status);
+    }
+
+    /// Number of replies not read yet.
+    pub fn pending_replies(&self) -> usize {
+        self.state().replies.len()
+    }
+
+    /// Every byte written so far.
+    pub fn written(&self) -> Vec<u8> {
+        self.state().written.clone()
+    }
+
+    /// Forget the bytes written so far, e.g. between two jobs.
+    pub fn clear_written(&self) {
+        self.state().written.clear();
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Transport for MockTransport {
+    fn write(&self, buf: &[u8], _timeout: Duration) -> Result<usize, Error> {
+        self.state().written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn read(&self, buf: &mut [u8], _timeout: Duration) -> Result<usize, Error> {
+        let status = self
+            .state()
+            .replies
+            .pop_front()
+            .ok_or(Error::ReadStatusTimeout)?;
+        let n = buf.len().min(status.len());
+        buf[..n].copy_from_slice(&status[..n]);
+        Ok(n)
+    }
+}
+
+#[cfg(feature = "usb")]
+pub(crate) use self::usb::{Endpoint, UsbConnection, UsbTransport};
+
+#[cfg(feature = "usb")]
+mod usb {
+    use rusb::{Context, DeviceHandle};
+    use std::sync::{Mutex, MutexGuard};
+    use std::time::Duration;
+
+    use super::Transport;
+    use crate::error::Error;
+    use crate::printer::{DeviceInfo, Printer};
+
+    #[derive(Debug, Clone, Copy)]
+    #[allow(dead_code)]
+    pub(crate) struct Endpoint {
+        pub(crate) config: u8,
+        pub(crate) iface: u8,
+        pub(crate) setting: u8,
+        pub(crate) address: u8,
+    }
+
+    /// Bulk endpoints of a claimed USB printer interface.
+    pub(crate) struct UsbConnection {
+        pub(crate) handle: DeviceHandle<Context>,
+        pub(crate) endpoint_out: Endpoint,
+        pub(crate) endpoint_in: Endpoint,
+    }
+
+    /// USB printer, opened again by its product id and serial number on
+    /// `reconnect()`.
+    pub(crate) struct UsbTransport {
+        pid: u16,
+        serial: String,
+        // `None` after a failed reconnect
+        connection: Mutex<Option<UsbConnection>>,
+    }
+
+    impl UsbTransport {
+        pub(crate) fn new(pid: u16, serial: String, connection: UsbConnection) -> Self {
+            UsbTransport {
+                pid,
+                serial,
+                connection: Mutex::new(Some(connection)),
+            }
+        }
+
+        fn connection(&self) -> MutexGuard<'_, Option<UsbConnection>> {
+            self.connection.lock().unwrap_or_else(|e| e.into_inner())
+        }
+    }
+
+    impl Transport for UsbTransport {
+        fn write(&self, buf: &[u8], timeout: Duration) -> Result<usize, Error> {
+            let connection = self.connection();
+            let connection = connection.as_ref().ok_or(Error::DeviceOffline)?;
+            Ok(connection
+                .handle
+                .write_bulk(connection.endpoint_out.address, buf, timeout)?)
+        }
+
+        fn read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, Error> {
+            let connection = self.connection();
+            let connection = connection.as_ref().ok_or(Error::DeviceOffline)?;
+            match connection
+                .handle
+                .read_bulk(connection.endpoint_in.address, buf, timeout)
+            {
+                Ok(n) => Ok(n),
+                Err(rusb::Error::Timeout) => Err(Error::ReadStatusTimeout),
+                Err(e) => Err(Error::UsbError(e)),
+            }
+        }
+
+        fn reconnect(&self) -> Result<(), Error> {
+            let mut connection = self.connection();
+            // Release the interface before claiming it again
+            *connection = None;
+            *connection = Some(Printer::reopen_device(self.pid, &self.serial)?);
+            Ok(())
+        }
+
+        fn device_info(&self) -> DeviceInfo {
+            let connection = self.connection();
+            let handle = match connection.as_ref() {
+                Some(connection) => &connection.handle,
+                None => return DeviceInfo::default(),
+            };
+            let desc = match handle.device().device_descriptor() {
+                Ok(desc) => desc,
+                Err(_) => return DeviceInfo::default(),
+            };
+            let timeout = Duration::from_secs(1);
+            let language = handle
+                .read_languages(timeout)
+                .ok()
+                .and_then(|languages| languages.first().copied());
+            let version = desc.device_version();
+            DeviceInfo {
+                vendor_id: Some(desc.vendor_id()),
+                product_id: Some(desc.product_id()),
+                manufacturer: language
+                    .and_then(|lang| handle.read_manufacturer_string(lang, &desc, timeout).ok()),
+                product: language
+                    .and_then(|lang| handle.read_product_string(lang, &desc, timeout).ok()),
+                serial: language
+                    .and_then(|lang| handle.read_serial_number_string(lang, &desc, timeout).ok()),
+                // bcdDevice 0x0105 is version 1.05
+                firmware_version: Some(format!(
+                    "{}.{}{}",
+                    version.major(),
+                    version.minor(),
+                    version.sub_minor()
+                )),
+                ..DeviceInfo::default()
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "bluetooth", target_os = "linux"))]
+pub use self::bluetooth::BluetoothTransport;
+
+#[cfg(all(feature = "bluetooth", target_os = "linux"))]
+mod bluetooth {
+    use std::fs::File;
+    use std::io::{self, Read, Write};
+    use std::mem;
+    use std::os::unix::io::FromRawFd;
+    use std::sync::{Mutex, MutexGuard};
+    use std::time::Duration;
+
+    use super::Transport;
+    use crate::error::Error;
+    use crate::printer::DeviceInfo;
+
+    // See <bluetooth/rfcomm.h>, libc doesn't have them
+    const BTPROTO_RFCOMM: libc::c_int = 3;
+
+    #[repr(C)]
+    struct SockaddrRc {
+        rc_family: libc::sa_family_t,
+        rc_bdaddr: [u8; 6],
+        rc_channel: u8,
+    }
+
+    /// Printer paired over Bluetooth, printing through its serial port
+    /// profile (SPP) on an RFCOMM channel.
+    ///
+    /// The QL-820NWB accepts the same raster commands and answers with the
+    /// same status messages as over USB, so `Printer::with_transport()`
+    /// prints through it like through USB. Pair the printer first, e.g. with
+    /// `bluetoothctl`; connecting uses the kernel's RFCOMM sockets, so this
+    /// transport is only available on Linux.
+    ///
+    /// Transfers are much slower than USB: a 62 mm label of 10 cm takes a
+    /// few seconds.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use ql_label::{BluetoothTransport, Config, ContinuousType, Media, Model, Printer};
+    /// let media = Media::Continuous(ContinuousType::Continuous62);
+    /// let transport = BluetoothTransport::connect("00:80:92:AB:CD:EF")?;
+    /// let printer = Printer::with_transport(Config::new_any(Model::QL820NWB, media), transport)?;
+    /// printer.print(vec![vec![vec![0xFF; 90]; 100]].into_iter())?;
+    /// # Ok::<(), ql_label::Error>(())
+    /// ```
+    pub struct BluetoothTransport {
+        address: [u8; 6],
+        channel: u8,
+        // `None` after a failed reconnect
+        socket: Mutex<Option<File>>,
+    }
+
+    impl BluetoothTransport {
+        /// RFCOMM channel of the serial port profile of Brother printers.
+        pub const DEFAULT_CHANNEL: u8 = 1;
+
+        /// Connect to the printer with the Bluetooth `address`, e.g.
+        /// `"00:80:92:AB:CD:EF"`, on `DEFAULT_CHANNEL`.
+        ///
+        /// # Returns
+        /// * `Ok(BluetoothTransport)` - Connected
+        /// * `Err(Error::InvalidConfig)` - `address` is not six hex bytes
+        ///   separated by colons
+        /// * `Err(Error::Io)` - The printer is off, not paired or out of range
+        pub fn connect(address: &str) -> Result<Self, Error> {
+            Self::connect_channel(address, Self::DEFAULT_CHANNEL)
+        }
+
+        /// Connect to the printer with the Bluetooth `address` on the RFCOMM
+        /// `channel`, see `connect()`.
+        pub fn connect_channel(address: &str, channel: u8) -> Result<Self, Error> {
+            let address = parse_address(address)?;
+            let socket = open(address, channel)?;
+            Ok(BluetoothTransport {
+                address,
+                channel,
+                socket: Mutex::new(Some(socket)),
+            })
+        }
+
+        fn socket(&self) -> MutexGuard<'_, Option<File>> {
+            self.socket.lock().unwrap_or_else(|e| e.into_inner())
+        }
+    }
+
+    impl Transport for BluetoothTransport {
+        fn write(&self, buf: &[u8], timeout: Duration) -> Result<usize, Error> {
+            let socket = self.socket();
+            let mut socket = socket.as_ref().ok_or(Error::DeviceOffline)?;
+            set_timeout(socket, libc::SO_SNDTIMEO, timeout)?;
+            socket.write_all(buf)?;
+            Ok(buf.len())
+        }
+
+        fn read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, Error> {
+            let socket = self.socket();
+            let mut socket = socket.as_ref().ok_or(Error::DeviceOffline)?;
+            set_timeout(socket, libc::SO_RCVTIMEO, timeout)?;
+            // RFCOMM is a stream, a status message may arrive in pieces
+            let mut len = 0;
+            while len < buf.len() {
+                match socket.read(&mut buf[len..]) {
+                    Ok(0) => return Err(Error::DeviceOffline),
+                    Ok(n) => len += n,
+                    Err(e) if is_timeout(&e) && len > 0 => break,
+                    Err(e) if is_timeout(&e) => return Err(Error::ReadStatusTimeout),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            Ok(len)
+        }
+
+        fn reconnect(&self) -> Result<(), Error> {
+            let mut socket = self.socket();
+            // Close the connection before opening another one
+            *socket = None;
+            *socket = Some(open(self.address, self.channel)?);
+            Ok(())
+        }
+
+        fn device_info(&self) -> DeviceInfo {
+            let [a, b, c, d, e, f] = self.address;
+            DeviceInfo {
+                serial: Some(format!(
+                    "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}",
+                    f, e, d, c, b, a
+                )),
+                ..DeviceInfo::default()
+            }
+        }
+    }
+
+    /// Address in the byte order of `bdaddr_t`, least significant first.
+    fn parse_address(address: &str) -> Result<[u8; 6], Error> {
+        let invalid = || Error::InvalidConfig(format!("Invalid Bluetooth address {}", address));
+        let mut bytes = [0x00; 6];
+        let mut parts = address.split(':');
+        for byte in bytes.iter_mut().rev() {
+            let part = parts
+                .next()
+                .filter(|part| part.len() == 2)
+                .ok_or_else(invalid)?;
+            *byte = u8::from_str_radix(part, 16).map_err(|_| invalid())?;
+        }
+        match parts.next() {
+            Some(_) => Err(invalid()),
+            None => Ok(bytes),
+        }
+    }
+
+    fn open(address: [u8; 6], channel: u8) -> Result<File, Error> {
+        // SAFETY: plain socket(2) call, the descriptor is owned by the File
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_BLUETOOTH,
+                libc::SOCK_STREAM | libc::SOCK_CLOEXEC,
+                BTPROTO_RFCOMM,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        // SAFETY: `fd` is a new socket nothing else owns, closed on drop
+        let socket = unsafe { File::from_raw_fd(fd) };
+        let addr = SockaddrRc {
+            rc_family: libc::AF_BLUETOOTH as libc::sa_family_t,
+            rc_bdaddr: address,
+            rc_channel: channel,
+        };
+        // SAFETY: `addr` is a valid sockaddr_rc of the given length
+        let result = unsafe {
+            libc::connect(
+                fd,
+                &addr as *const SockaddrRc as *const libc::sockaddr,
+                mem::size_of::<SockaddrRc>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(socket)
+    }
+
+    fn set_timeout(socket: &File, option: libc::c_int, timeout: Duration) -> Result<(), Error> {
+        use std::os::unix::io::AsRawFd;
+
+        // A zero timeval blocks forever
+        let timeout = timeout.max(Duration::from_millis(1));
+        let tv = libc::timeval {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+        };
+        // SAFETY: `tv` is a valid timeval of the given length
+        let result = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_SOCKET,
+                option,
+                &tv as *const libc::timeval as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    fn is_timeout(err: &io::Error) -> bool {
+        matches!(
+            err.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        )
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_parse_address() {
+            assert_eq!(
+                parse_address("00:80:92:AB:cd:EF").unwrap(),
+                [0xEF, 0xCD, 0xAB, 0x92, 0x80, 0x00]
+            );
+            assert!(parse_address("00:80:92:AB:CD").is_err());
+            assert!(parse_address("00:80:92:AB:CD:EF:01").is_err());
+            assert!(parse_address("00:80:92:AB:CD:G0").is_err());
+            assert!(parse_address("0080:92:AB:CD:EF").is_err());
+        }
+    }
+}
@@ -3,7 +3,7 @@
 //! This module provides functions to convert various image formats to the
 //! bitmap format required by Brother P-Touch printers.
 
-use crate::Matrix;
+use crate::{layout::Resolution, Matrix};
 
 /// Container for two-color (black and red) bitmap data.
 ///
@@ -31,7 +31,7 @@ impl TwoColorMatrix {
     ///
     /// # Example
     /// ```rust
-    /// # use ptouch::{TwoColorMatrix, Matrix};
+    /// # use ql_label::{TwoColorMatrix, Matrix};
     /// let black_data: Matrix = vec![vec![0xFF; 90]; 300]; // 300 lines, 90 bytes each
     /// let red_data: Matrix = vec![vec![0x00; 90]; 300];   // Same dimensions
     /// 
@@ -51,6 +51,20 @@ impl TwoColorMatrix {
         
         Ok(TwoColorMatrix { black, red })
     }
+
+    /// Create a two-color matrix printing only `red`, the black matrix is
+    /// left empty.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::TwoColorMatrix;
+    /// let stamp = TwoColorMatrix::red_only(vec![vec![0xFF; 90]; 300]);
+    /// assert!(stamp.black.iter().all(|row| row == &vec![0x00; 90]));
+    /// ```
+    pub fn red_only(red: Matrix) -> Self {
+        let black = red.iter().map(|row| vec![0x00; row.len()]).collect();
+        TwoColorMatrix { black, red }
+    }
     
     /// Convert two-color data to alternating matrix format for printing.
     ///
@@ -63,7 +77,7 @@ impl TwoColorMatrix {
     ///
     /// # Example
     /// ```rust
-    /// # use ptouch::{TwoColorMatrix, Matrix};
+    /// # use ql_label::{TwoColorMatrix, Matrix};
     /// # let black_data: Matrix = vec![vec![0xFF; 90]; 2];
     /// # let red_data: Matrix = vec![vec![0x00; 90]; 2];
     /// let two_color = TwoColorMatrix::new(black_data, red_data)?;
@@ -81,6 +95,195 @@ impl TwoColorMatrix {
         
         result
     }
+
+    /// Rotate both colors by the same amount.
+    ///
+    /// See `rotate()` for the orientation conventions.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{Rotation, TwoColorMatrix};
+    /// let two_color = TwoColorMatrix::new(vec![vec![0xFF; 2]; 8], vec![vec![0x00; 2]; 8])?;
+    /// let rotated = two_color.rotate(Rotation::Cw90);
+    /// assert_eq!(rotated.black.len(), 16);
+    /// assert_eq!(rotated.red[0].len(), 1);
+    /// # Ok::<(), String>(())
+    /// ```
+    pub fn rotate(&self, rotation: Rotation) -> TwoColorMatrix {
+        TwoColorMatrix {
+            black: rotate(&self.black, rotation),
+            red: rotate(&self.red, rotation),
+        }
+    }
+
+    /// Mirror both colors left to right.
+    pub fn flip_horizontal(&self) -> TwoColorMatrix {
+        TwoColorMatrix {
+            black: flip_horizontal(&self.black),
+            red: flip_horizontal(&self.red),
+        }
+    }
+
+    /// Mirror both colors top to bottom.
+    pub fn flip_vertical(&self) -> TwoColorMatrix {
+        TwoColorMatrix {
+            black: flip_vertical(&self.black),
+            red: flip_vertical(&self.red),
+        }
+    }
+}
+
+/// Rotation applied to label data, clockwise as seen on the source image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    None,
+    Cw90,
+    Ccw90,
+    Half,
+}
+
+/// Rotate 1-bit bitmap data.
+///
+/// Directions refer to the image as it was before conversion with
+/// `step_filter_normal()`, so rotating clockwise here gives the same result as
+/// rotating the source image clockwise. A quarter turn swaps width and height;
+/// when the new width is not a multiple of 8 the rows are padded with blank
+/// pixels on the left edge.
+///
+/// # Arguments
+/// * `matrix` - 1-bit bitmap data, all rows of the same length
+/// * `rotation` - Rotation to apply
+///
+/// # Returns
+/// Rotated Matrix
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{rotate, Matrix, Rotation};
+/// let matrix: Matrix = vec![vec![0xFF; 90]; 300];
+/// let rotated = rotate(&matrix, Rotation::Ccw90);
+/// assert_eq!(rotated.len(), 720);
+/// assert_eq!(rotated[0].len(), 38); // 300 pixels rounded up to whole bytes
+/// ```
+pub fn rotate(matrix: &Matrix, rotation: Rotation) -> Matrix {
+    let height = matrix.len() as u32;
+    let width = matrix.first().map(|row| row.len() as u32 * 8).unwrap_or(0);
+
+    match rotation {
+        Rotation::None => matrix.clone(),
+        Rotation::Half => flip_vertical(&flip_horizontal(matrix)),
+        Rotation::Cw90 | Rotation::Ccw90 => {
+            let mut rotated = vec![vec![0u8; height.div_ceil(8) as usize]; width as usize];
+            for (y, row) in rotated.iter_mut().enumerate() {
+                let y = y as u32;
+                for x in 0..height {
+                    let (sx, sy) = match rotation {
+                        Rotation::Cw90 => (y, height - 1 - x),
+                        _ => (width - 1 - y, x),
+                    };
+                    if get_pixel(&matrix[sy as usize], width, sx) {
+                        set_pixel(row, height, x);
+                    }
+                }
+            }
+            rotated
+        }
+    }
+}
+
+/// Rotate 8-bit grayscale pixels, one byte per pixel row by row.
+///
+/// Turning the pixels before `step_filter_normal()` instead of the packed
+/// rows with `rotate()` lets a landscape design be drawn naturally: the
+/// rotated image is as wide as the print head and no bits are padded.
+///
+/// # Arguments
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `pixels` - Grayscale image data (width × height bytes)
+/// * `rotation` - Rotation to apply, clockwise as seen on the image
+///
+/// # Returns
+/// Width and height of the rotated image, and its pixels
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{rotate_gray, Rotation};
+/// // 3 x 2 pixels, the left column black
+/// let pixels = [0, 255, 255, 0, 255, 255];
+/// let (width, height, rotated) = rotate_gray(3, 2, &pixels, Rotation::Cw90);
+/// assert_eq!((width, height), (2, 3));
+/// // The left column is now the top row
+/// assert_eq!(rotated, [0, 0, 255, 255, 255, 255]);
+/// ```
+pub fn rotate_gray(
+    width: u32,
+    height: u32,
+    pixels: &[u8],
+    rotation: Rotation,
+) -> (u32, u32, Vec<u8>) {
+    let (w, h) = (width as usize, height as usize);
+    let pixel = |x: usize, y: usize| pixels[y * w + x];
+    match rotation {
+        Rotation::None => (width, height, pixels[..w * h].to_vec()),
+        Rotation::Half => {
+            let rotated = pixels[..w * h].iter().rev().copied().collect();
+            (width, height, rotated)
+        }
+        Rotation::Cw90 => {
+            let rotated = (0..w)
+                .flat_map(|y| (0..h).map(move |x| (y, h - 1 - x)))
+                .map(|(x, y)| pixel(x, y))
+                .collect();
+            (height, width, rotated)
+        }
+        Rotation::Ccw90 => {
+            let rotated = (0..w)
+                .flat_map(|y| (0..h).map(move |x| (w - 1 - y, x)))
+                .map(|(x, y)| pixel(x, y))
+                .collect();
+            (height, width, rotated)
+        }
+    }
+}
+
+/// Mirror 1-bit bitmap data left to right.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{flip_horizontal, Matrix};
+/// let matrix: Matrix = vec![vec![0x01, 0x00]];
+/// assert_eq!(flip_horizontal(&matrix), vec![vec![0x00, 0x80]]);
+/// ```
+pub fn flip_horizontal(matrix: &Matrix) -> Matrix {
+    matrix
+        .iter()
+        .map(|row| row.iter().rev().map(|byte| byte.reverse_bits()).collect())
+        .collect()
+}
+
+/// Mirror 1-bit bitmap data top to bottom.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{flip_vertical, Matrix};
+/// let matrix: Matrix = vec![vec![0xFF], vec![0x00]];
+/// assert_eq!(flip_vertical(&matrix), vec![vec![0x00], vec![0xFF]]);
+/// ```
+pub fn flip_vertical(matrix: &Matrix) -> Matrix {
+    matrix.iter().rev().cloned().collect()
+}
+
+// Rows are stored right to left: the first bit of a row (MSB of byte 0) is the
+// right-most pixel of the source image. `width` is the logical width in pixels.
+fn get_pixel(row: &[u8], width: u32, x: u32) -> bool {
+    let pin = (width - 1 - x) as usize;
+    row[pin / 8] & (0x80 >> (pin % 8)) != 0
+}
+
+fn set_pixel(row: &mut [u8], width: u32, x: u32) {
+    let pin = (width - 1 - x) as usize;
+    row[pin / 8] |= 0x80 >> (pin % 8);
 }
 
 /// Convert grayscale image to 1-bit bitmap for normal-width printers (720 pixels).
@@ -92,24 +295,29 @@ impl TwoColorMatrix {
 /// # Arguments
 /// * `threshold` - Grayscale threshold (0-255). Pixels below this become black (1)
 /// * `length` - Image height in pixels
-/// * `bytes` - Grayscale image data (width × height bytes)
+/// * `bytes` - Grayscale image data (width × height bytes), owned or borrowed
 ///
 /// # Returns
 /// Matrix containing 1-bit bitmap data (`Vec<Vec<u8>>`)
 ///
 /// # Example
 /// ```rust
-/// # use ptouch::{step_filter_normal, Matrix};
+/// # use ql_label::{step_filter_normal, Matrix};
 /// let width = 720;
 /// let height = 100;
 /// let grayscale_data = vec![128u8; (width * height) as usize]; // Gray image
 /// 
-/// let bitmap = step_filter_normal(80, height, grayscale_data);
+/// let bitmap = step_filter_normal(80, height, &grayscale_data); // No copy of the frame
 /// assert_eq!(bitmap.len(), height as usize);
 /// assert_eq!(bitmap[0].len(), 90); // 720 pixels / 8 = 90 bytes
 /// ```
-pub fn step_filter_normal(threshold: u8, length: u32, bytes: Vec<u8>) -> Matrix {
-    step_filter(threshold, crate::NORMAL_PRINTER_WIDTH, length, bytes)
+pub fn step_filter_normal(threshold: u8, length: u32, bytes: impl AsRef<[u8]>) -> Matrix {
+    step_filter(
+        threshold,
+        crate::NORMAL_PRINTER_WIDTH,
+        length,
+        bytes.as_ref(),
+    )
 }
 
 /// Convert grayscale image to 1-bit bitmap for wide printers (1296 pixels).
@@ -120,14 +328,14 @@ pub fn step_filter_normal(threshold: u8, length: u32, bytes: Vec<u8>) -> Matrix
 /// # Arguments
 /// * `threshold` - Grayscale threshold (0-255). Pixels below this become black (1)
 /// * `length` - Image height in pixels
-/// * `bytes` - Grayscale image data (width × height bytes)
+/// * `bytes` - Grayscale image data (width × height bytes), owned or borrowed
 ///
 /// # Returns
 /// Matrix containing 1-bit bitmap data (`Vec<Vec<u8>>`)
 ///
 /// # Example
 /// ```rust
-/// # use ptouch::{step_filter_wide, Matrix, WIDE_PRINTER_WIDTH};
+/// # use ql_label::{step_filter_wide, Matrix, WIDE_PRINTER_WIDTH};
 /// let width = WIDE_PRINTER_WIDTH;
 /// let height = 100;
 /// let grayscale_data = vec![128u8; (width * height) as usize];
@@ -136,14 +344,93 @@ pub fn step_filter_normal(threshold: u8, length: u32, bytes: Vec<u8>) -> Matrix
 /// assert_eq!(bitmap.len(), height as usize);
 /// assert_eq!(bitmap[0].len(), 162); // 1296 pixels / 8 = 162 bytes
 /// ```
-pub fn step_filter_wide(threshold: u8, length: u32, bytes: Vec<u8>) -> Matrix {
-    step_filter(threshold, crate::WIDE_PRINTER_WIDTH, length, bytes)
+pub fn step_filter_wide(threshold: u8, length: u32, bytes: impl AsRef<[u8]>) -> Matrix {
+    step_filter(threshold, crate::WIDE_PRINTER_WIDTH, length, bytes.as_ref())
+}
+
+/// Convert a grayscale image drawn in another orientation than it is printed
+/// to 1-bit bitmap.
+///
+/// The image is turned with `rotate_gray()` first, so its rotated width must
+/// be the pin count of the printer (720 or 1296). Designs for continuous
+/// tape are usually landscape, as long as the label and as high as the tape
+/// is wide: `Rotation::Cw90` prints them starting at their left edge,
+/// `Rotation::Ccw90` starting at their right edge.
+///
+/// # Arguments
+/// * `threshold` - Grayscale threshold (0-255). Pixels below this become black (1)
+/// * `rotation` - Rotation from the image to the printed orientation
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `bytes` - Grayscale image data (width × height bytes), owned or borrowed
+///
+/// # Returns
+/// Matrix containing 1-bit bitmap data (`Vec<Vec<u8>>`)
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{step_filter_rotated, Rotation};
+/// // A 1000 pixel long landscape label for 62 mm tape, black on the left
+/// let mut gray = vec![255u8; 1000 * 720];
+/// for row in gray.chunks_mut(1000) {
+///     row[..10].fill(0);
+/// }
+///
+/// let bitmap = step_filter_rotated(128, Rotation::Cw90, 1000, 720, &gray);
+/// assert_eq!(bitmap.len(), 1000);
+/// assert_eq!(bitmap[0], vec![0xFF; 90]);
+/// assert_eq!(bitmap[10], vec![0x00; 90]);
+/// ```
+pub fn step_filter_rotated(
+    threshold: u8,
+    rotation: Rotation,
+    width: u32,
+    height: u32,
+    bytes: impl AsRef<[u8]>,
+) -> Matrix {
+    let (width, length, pixels) = rotate_gray(width, height, bytes.as_ref(), rotation);
+    step_filter(threshold, width, length, &pixels)
 }
 
-fn step_filter(threshold: u8, width: u32, length: u32, bytes: Vec<u8>) -> Matrix {
+/// Convert a grayscale `image` buffer to 1-bit bitmap without copying it.
+///
+/// Accepts owned images as well as views over borrowed pixel data, such as
+/// `ImageBuffer<Luma<u8>, &[u8]>` wrapping a frame from a camera or renderer.
+/// The image width must be the pin count of the printer (720 or 1296).
+///
+/// Requires the `image` feature.
+///
+/// # Arguments
+/// * `threshold` - Grayscale threshold (0-255). Pixels below this become black (1)
+/// * `image` - Grayscale image
+///
+/// # Returns
+/// Matrix containing 1-bit bitmap data (`Vec<Vec<u8>>`)
+///
+/// # Example
+/// ```rust
+/// # use ql_label::step_filter_image;
+/// use image::{ImageBuffer, Luma};
+///
+/// let frame = vec![255u8; 720 * 100];
+/// let view: ImageBuffer<Luma<u8>, &[u8]> = ImageBuffer::from_raw(720, 100, &frame[..]).unwrap();
+///
+/// let bitmap = step_filter_image(80, &view);
+/// assert_eq!(bitmap.len(), 100);
+/// ```
+#[cfg(feature = "image")]
+pub fn step_filter_image<C>(threshold: u8, image: &image::ImageBuffer<image::Luma<u8>, C>) -> Matrix
+where
+    C: std::ops::Deref<Target = [u8]>,
+{
+    let (width, length) = image.dimensions();
+    step_filter(threshold, width, length, image.as_raw())
+}
+
+pub(crate) fn step_filter(threshold: u8, width: u32, length: u32, bytes: &[u8]) -> Matrix {
     // convert to black and white data
     // threshold = 80 seems to work fine if original data is monochrome.
-    // TODO: Add support for a dithering algorithm to print photos
+    // Photos and gradients are dithered first, see `dither()`.
     //
     // width must be
     let mut bw: Vec<Vec<u8>> = Vec::new();
@@ -156,7 +443,7 @@ fn step_filter(threshold: u8, width: u32, length: u32, bytes: Vec<u8>) -> Matrix
             for i in 0..8 {
                 let pixel = bytes[(index + i) as usize];
                 let value: u8 = if pixel > threshold { 0 } else { 1 };
-                tmp = tmp | (value << i);
+                tmp |= value << i;
             }
             buf.push(tmp);
         }
@@ -166,6 +453,433 @@ fn step_filter(threshold: u8, width: u32, length: u32, bytes: Vec<u8>) -> Matrix
     bw
 }
 
+/// Binarize grayscale pixels in place with Floyd–Steinberg error diffusion.
+///
+/// Every pixel ends up either `0` (black) or `255` (white), so the result can
+/// be packed with `step_filter` using any threshold.
+pub(crate) fn floyd_steinberg(width: u32, length: u32, pixels: &mut [u8]) {
+    let width = width as usize;
+    let mut errors: Vec<i16> = pixels.iter().map(|&p| p as i16).collect();
+
+    for y in 0..length as usize {
+        for x in 0..width {
+            let index = y * width + x;
+            let old = errors[index].clamp(0, 255);
+            let new = if old < 128 { 0 } else { 255 };
+            let error = old - new;
+            pixels[index] = new as u8;
+
+            if x + 1 < width {
+                errors[index + 1] += error * 7 / 16;
+            }
+            if y + 1 < length as usize {
+                let below = index + width;
+                if x > 0 {
+                    errors[below - 1] += error * 3 / 16;
+                }
+                errors[below] += error * 5 / 16;
+                if x + 1 < width {
+                    errors[below + 1] += error / 16;
+                }
+            }
+        }
+    }
+}
+
+/// Binarize grayscale pixels in place with Atkinson error diffusion.
+///
+/// Only 3/4 of the error is passed on, which keeps highlights and shadows
+/// clean at the cost of some detail in them.
+fn atkinson(width: u32, length: u32, pixels: &mut [u8]) {
+    let (width, length) = (width as usize, length as usize);
+    let mut errors: Vec<i16> = pixels.iter().map(|&p| p as i16).collect();
+
+    for y in 0..length {
+        for x in 0..width {
+            let index = y * width + x;
+            let old = errors[index].clamp(0, 255);
+            let new = if old < 128 { 0 } else { 255 };
+            let error = (old - new) / 8;
+            pixels[index] = new as u8;
+
+            for (dx, dy) in [(1, 0), (2, 0), (-1, 1), (0, 1), (1, 1), (0, 2)] {
+                let (nx, ny) = (x as isize + dx, y + dy);
+                if nx >= 0 && (nx as usize) < width && ny < length {
+                    errors[ny * width + nx as usize] += error;
+                }
+            }
+        }
+    }
+}
+
+/// Binarize grayscale pixels in place against an 8 x 8 Bayer matrix.
+///
+/// Every pixel is compared to its own threshold, so the pattern is regular
+/// and stable between frames, and no error runs across the image.
+fn bayer(width: u32, length: u32, pixels: &mut [u8]) {
+    let width = width as usize;
+    for (index, pixel) in pixels.iter_mut().enumerate().take(width * length as usize) {
+        let (x, y) = (index % width, index / width);
+        // Index of the dot in the 8 x 8 matrix, the lowest bits weigh the most
+        let mut rank = 0;
+        for bit in 0..3 {
+            let (bx, by) = ((x >> bit) & 1, (y >> bit) & 1);
+            rank = rank * 4 + [[0, 2], [3, 1]][by][bx];
+        }
+        let threshold = ((2 * rank + 1) * 255 / 128) as u8;
+        *pixel = if *pixel < threshold { 0 } else { 255 };
+    }
+}
+
+/// Dithering algorithm used by `dither_normal()` and `dither_wide()`.
+///
+/// Dithering approximates gray levels with a pattern of black dots, where
+/// `step_filter_normal()` turns every pixel darker than a threshold black
+/// and the others white.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dither {
+    /// Floyd–Steinberg error diffusion. Smooth gradients, the best choice
+    /// for most photos.
+    #[default]
+    FloydSteinberg,
+    /// Atkinson error diffusion. Higher contrast with clean highlights and
+    /// shadows, suited to logos and screenshots.
+    Atkinson,
+    /// Ordered dithering with an 8 x 8 Bayer matrix. A regular cross-hatch
+    /// pattern that does not shift when part of the image changes.
+    Bayer,
+}
+
+/// Binarize grayscale pixels in place with `algorithm`, leaving every pixel
+/// either `0` (black) or `255` (white).
+pub(crate) fn dither(algorithm: Dither, width: u32, length: u32, pixels: &mut [u8]) {
+    match algorithm {
+        Dither::FloydSteinberg => floyd_steinberg(width, length, pixels),
+        Dither::Atkinson => atkinson(width, length, pixels),
+        Dither::Bayer => bayer(width, length, pixels),
+    }
+}
+
+/// Convert a grayscale photo or gradient to 1-bit bitmap for normal-width
+/// printers (720 pixels) with dithering.
+///
+/// # Arguments
+/// * `algorithm` - Dithering algorithm
+/// * `length` - Image height in pixels
+/// * `bytes` - Grayscale image data (width × height bytes)
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{dither_normal, Dither};
+/// // A gradient from black to white across the label
+/// let gradient: Vec<u8> = (0..720 * 100).map(|i| (i % 720 * 255 / 719) as u8).collect();
+///
+/// for algorithm in [Dither::FloydSteinberg, Dither::Atkinson, Dither::Bayer] {
+///     let bitmap = dither_normal(algorithm, 100, &gradient);
+///     assert_eq!(bitmap.len(), 100);
+///     assert_eq!(bitmap[0].len(), 90);
+///
+///     // Roughly half of the dots are black
+///     let black: u32 = bitmap.iter().flatten().map(|byte| byte.count_ones()).sum();
+///     assert!((black as f32 / (720.0 * 100.0) - 0.5).abs() < 0.05);
+/// }
+/// ```
+pub fn dither_normal(algorithm: Dither, length: u32, bytes: impl AsRef<[u8]>) -> Matrix {
+    let mut pixels = bytes.as_ref().to_vec();
+    dither(algorithm, crate::NORMAL_PRINTER_WIDTH, length, &mut pixels);
+    step_filter(128, crate::NORMAL_PRINTER_WIDTH, length, &pixels)
+}
+
+/// Convert a grayscale photo or gradient to 1-bit bitmap for wide printers
+/// (1296 pixels) with dithering, see `dither_normal()`.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{dither_wide, Dither, WIDE_PRINTER_WIDTH};
+/// let gray = vec![128u8; (WIDE_PRINTER_WIDTH * 100) as usize];
+/// let bitmap = dither_wide(Dither::Bayer, 100, gray);
+/// assert_eq!(bitmap[0].len(), 162);
+/// ```
+pub fn dither_wide(algorithm: Dither, length: u32, bytes: impl AsRef<[u8]>) -> Matrix {
+    let mut pixels = bytes.as_ref().to_vec();
+    dither(algorithm, crate::WIDE_PRINTER_WIDTH, length, &mut pixels);
+    step_filter(128, crate::WIDE_PRINTER_WIDTH, length, &pixels)
+}
+
+/// Contrast enhancement applied by `enhance_contrast()`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Contrast {
+    /// Stretch the gray levels linearly to the full range. The given
+    /// fraction of the darkest and of the lightest pixels is clipped, so a
+    /// few stray pixels don't limit the stretch.
+    Stretch(f32),
+    /// Equalize the histogram of the whole image.
+    Equalize,
+    /// Equalize the histogram of `tiles` x `tiles` regions separately and
+    /// blend them (CLAHE). Bins holding more than `clip_limit` times the
+    /// average count are clipped, limiting the noise boosted in flat areas.
+    /// A `clip_limit` of 0 disables clipping.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use ql_label::{enhance_contrast, Contrast};
+    /// // Faded marks on gray paper on the left, on white paper on the right
+    /// let mut pixels: Vec<u8> = (0..64 * 64)
+    ///     .map(|i| {
+    ///         let (x, y) = (i % 64, i / 64);
+    ///         let paper = if x < 32 { 90 } else { 200 };
+    ///         paper + ((x + y) % 2) as u8 * 20
+    ///     })
+    ///     .collect();
+    /// enhance_contrast(64, 64, &mut pixels, Contrast::Adaptive { tiles: 4, clip_limit: 0.0 });
+    ///
+    /// // The marks on the left now stand out as much as on the right
+    /// let left: Vec<u8> = (0..64 * 64).filter(|i| i % 64 < 16).map(|i| pixels[i]).collect();
+    /// assert!(left.iter().max().unwrap() - left.iter().min().unwrap() > 100);
+    /// ```
+    Adaptive { tiles: u32, clip_limit: f32 },
+}
+
+/// Enhance the contrast of grayscale pixels in place.
+///
+/// Low-contrast scans binarize to nearly all white or all black. Apply this
+/// before `Canvas::draw_gray()` or `step_filter_normal()`, so thresholding
+/// and dithering get the full range of gray levels.
+///
+/// # Arguments
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `pixels` - Grayscale data (width × height bytes, 0 = black)
+/// * `contrast` - Enhancement to apply
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{enhance_contrast, Contrast};
+/// // A faded scan using gray levels 100 to 150 only
+/// let mut pixels: Vec<u8> = (0..100).map(|i| 100 + (i % 51) as u8).collect();
+/// enhance_contrast(10, 10, &mut pixels, Contrast::Stretch(0.0));
+///
+/// assert_eq!(pixels.iter().min(), Some(&0));
+/// assert_eq!(pixels.iter().max(), Some(&255));
+/// ```
+pub fn enhance_contrast(width: u32, height: u32, pixels: &mut [u8], contrast: Contrast) {
+    // Rows missing from `pixels` are ignored
+    let height = height.min(pixels.len() as u32 / width.max(1));
+    let pixels = &mut pixels[..(width * height) as usize];
+    match contrast {
+        Contrast::Stretch(clip) => stretch_contrast(pixels, clip),
+        Contrast::Equalize => equalize_tiles(width, height, pixels, 1, 0.0),
+        Contrast::Adaptive { tiles, clip_limit } => {
+            equalize_tiles(width, height, pixels, tiles.max(1), clip_limit)
+        }
+    }
+}
+
+fn histogram(pixels: impl Iterator<Item = u8>) -> [u32; 256] {
+    let mut histogram = [0u32; 256];
+    for pixel in pixels {
+        histogram[pixel as usize] += 1;
+    }
+    histogram
+}
+
+fn stretch_contrast(pixels: &mut [u8], clip: f32) {
+    let histogram = histogram(pixels.iter().copied());
+    let clipped = (pixels.len() as f32 * clip.clamp(0.0, 0.5)) as u32;
+    let low = clip_level(&histogram, clipped, 0..256);
+    let high = clip_level(&histogram, clipped, (0..256).rev());
+    let (low, high) = match (low, high) {
+        (Some(low), Some(high)) if high > low => (low as f32, high as f32),
+        _ => return,
+    };
+    for pixel in pixels.iter_mut() {
+        let value = (*pixel as f32 - low) * 255.0 / (high - low);
+        *pixel = value.clamp(0.0, 255.0).round() as u8;
+    }
+}
+
+/// First of `levels` at which more than `clipped` pixels were counted.
+fn clip_level(
+    histogram: &[u32; 256],
+    clipped: u32,
+    levels: impl Iterator<Item = usize>,
+) -> Option<usize> {
+    let mut count = 0;
+    for level in levels {
+        count += histogram[level];
+        if count > clipped {
+            return Some(level);
+        }
+    }
+    None
+}
+
+/// Histogram equalization of `tiles` x `tiles` regions, interpolated
+/// bilinearly between the centers of the regions. A `clip_limit` of 0
+/// disables clipping.
+fn equalize_tiles(width: u32, height: u32, pixels: &mut [u8], tiles: u32, clip_limit: f32) {
+    let tiles = tiles.min(width).min(height).max(1);
+    // Tiles differ by a pixel at most, none is empty
+    let bound = |tile: u32, size: u32| (tile as u64 * size as u64 / tiles as u64) as u32;
+
+    // Mapping of the gray levels of every tile
+    let mut maps = Vec::with_capacity((tiles * tiles) as usize);
+    for ty in 0..tiles {
+        for tx in 0..tiles {
+            let (x0, y0) = (bound(tx, width), bound(ty, height));
+            let (x1, y1) = (bound(tx + 1, width), bound(ty + 1, height));
+            let pixels = &*pixels;
+            let mut histogram = histogram(
+                (y0..y1).flat_map(|y| (x0..x1).map(move |x| pixels[(y * width + x) as usize])),
+            );
+            let count = (x1 - x0) * (y1 - y0);
+            if clip_limit > 0.0 {
+                let limit = ((clip_limit * count as f32 / 256.0) as u32).max(1);
+                let mut excess = 0;
+                for bin in histogram.iter_mut() {
+                    excess += bin.saturating_sub(limit);
+                    *bin = (*bin).min(limit);
+                }
+                for (i, bin) in histogram.iter_mut().enumerate() {
+                    *bin += excess / 256 + ((i as u32) < excess % 256) as u32;
+                }
+            }
+            let mut map = [0u8; 256];
+            let mut cumulative = 0;
+            for (level, &bin) in histogram.iter().enumerate() {
+                cumulative += bin;
+                map[level] = (cumulative as f32 * 255.0 / count.max(1) as f32).round() as u8;
+            }
+            maps.push(map);
+        }
+    }
+
+    // Position of a pixel between the centers of the tiles around it
+    let locate = |position: u32, size: u32| {
+        let size = size as f32 / tiles as f32;
+        let t = ((position as f32 + 0.5) / size - 0.5).clamp(0.0, (tiles - 1) as f32);
+        let first = (t as u32).min(tiles.saturating_sub(2));
+        (first, (first + 1).min(tiles - 1), t - first as f32)
+    };
+    for y in 0..height {
+        let (ty0, ty1, fy) = locate(y, height);
+        for x in 0..width {
+            let (tx0, tx1, fx) = locate(x, width);
+            let index = (y * width + x) as usize;
+            let level = pixels[index] as usize;
+            let map = |tx: u32, ty: u32| maps[(ty * tiles + tx) as usize][level] as f32;
+            let top = map(tx0, ty0) * (1.0 - fx) + map(tx1, ty0) * fx;
+            let bottom = map(tx0, ty1) * (1.0 - fx) + map(tx1, ty1) * fx;
+            pixels[index] = (top * (1.0 - fy) + bottom * fy).round() as u8;
+        }
+    }
+}
+
+/// Unsharp mask applied by `sharpen()`.
+///
+/// Use `for_resolution()` for text printed at 300 or 600 DPI.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sharpen {
+    /// Standard deviation of the Gaussian blur in pixels.
+    pub radius: f32,
+    /// Strength of the sharpening, 1.0 doubles the contrast of edges.
+    pub amount: f32,
+    /// Differences to the blurred image below this value are left alone,
+    /// so flat areas and paper texture are not sharpened.
+    pub threshold: u8,
+}
+
+impl Sharpen {
+    /// Settings keeping 6 to 8 point text readable at `resolution`.
+    ///
+    /// The high resolution mode has twice the dots along the tape, so
+    /// strokes are wider and take a wider blur.
+    pub fn for_resolution(resolution: Resolution) -> Self {
+        match resolution {
+            Resolution::Normal => Sharpen {
+                radius: 0.8,
+                amount: 1.2,
+                threshold: 4,
+            },
+            Resolution::High => Sharpen {
+                radius: 1.5,
+                amount: 1.0,
+                threshold: 4,
+            },
+        }
+    }
+}
+
+/// Sharpen grayscale pixels in place with an unsharp mask.
+///
+/// Resizing blurs small text, which then breaks up into illegible blobs
+/// when binarized. Apply this after scaling the image to the label and
+/// before thresholding or dithering.
+///
+/// # Arguments
+/// * `width` - Image width in pixels
+/// * `height` - Image height in pixels
+/// * `pixels` - Grayscale data (width × height bytes, 0 = black)
+/// * `sharpen` - Unsharp mask settings
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{sharpen, Resolution, Sharpen};
+/// // A blurred edge from white to black
+/// let mut pixels: Vec<u8> = [255, 255, 224, 160, 96, 32, 0, 0].repeat(8);
+/// sharpen(8, 8, &mut pixels, Sharpen::for_resolution(Resolution::Normal));
+///
+/// // The edge got steeper
+/// assert!(pixels[2] > 224 && pixels[5] < 32);
+/// ```
+pub fn sharpen(width: u32, height: u32, pixels: &mut [u8], sharpen: Sharpen) {
+    // Rows missing from `pixels` are ignored
+    let height = height.min(pixels.len() as u32 / width.max(1));
+    let (width, height) = (width as usize, height as usize);
+    let pixels = &mut pixels[..width * height];
+    let blurred = gaussian_blur(width, height, pixels, sharpen.radius);
+
+    for (pixel, blurred) in pixels.iter_mut().zip(blurred) {
+        let difference = *pixel as f32 - blurred;
+        if difference.abs() >= sharpen.threshold as f32 {
+            let value = *pixel as f32 + sharpen.amount * difference;
+            *pixel = value.clamp(0.0, 255.0).round() as u8;
+        }
+    }
+}
+
+/// Separable Gaussian blur with a standard deviation of `sigma` pixels,
+/// repeating the edge pixels.
+fn gaussian_blur(width: usize, height: usize, pixels: &[u8], sigma: f32) -> Vec<f32> {
+    let reach = (sigma * 3.0).ceil().max(1.0) as i32;
+    let kernel: Vec<f32> = (-reach..=reach)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f32 = kernel.iter().sum();
+    let kernel: Vec<f32> = kernel.iter().map(|weight| weight / total).collect();
+
+    let blur = |source: &dyn Fn(i32, i32) -> f32, dx: i32, dy: i32| {
+        let mut out = vec![0.0; width * height];
+        for y in 0..height as i32 {
+            for x in 0..width as i32 {
+                out[y as usize * width + x as usize] = kernel
+                    .iter()
+                    .zip(-reach..=reach)
+                    .map(|(weight, i)| weight * source(x + i * dx, y + i * dy))
+                    .sum();
+            }
+        }
+        out
+    };
+    let clamp = |x: i32, y: i32| {
+        let x = x.clamp(0, width as i32 - 1) as usize;
+        let y = y.clamp(0, height as i32 - 1) as usize;
+        y * width + x
+    };
+    let horizontal = blur(&|x, y| pixels[clamp(x, y)] as f32, 1, 0);
+    blur(&|x, y| horizontal[clamp(x, y)], 0, 1)
+}
+
 /// Convert RGB image data to two-color bitmap for printing.
 ///
 /// This function analyzes RGB pixel data and separates it into black and red
@@ -188,7 +902,7 @@ fn step_filter(threshold: u8, width: u32, length: u32, bytes: Vec<u8>) -> Matrix
 ///
 /// # Example
 /// ```rust
-/// # use ptouch::{convert_rgb_to_two_color};
+/// # use ql_label::{convert_rgb_to_two_color};
 /// let width = 720;
 /// let height = 100;
 /// // Create simple RGB data: red stripe at top, black at bottom
@@ -210,6 +924,65 @@ pub fn convert_rgb_to_two_color(
     width: u32,
     height: u32,
     rgb_data: &[u8],
+) -> Result<TwoColorMatrix, String> {
+    convert_rgb_to_two_color_with(width, height, rgb_data, &ColorThresholds::default())
+}
+
+/// Thresholds deciding which pixels of an RGB image print in red or black.
+///
+/// Print `Printer::print_two_color_calibration_page()` to see how each
+/// color renders, then adjust the thresholds to the colors of the source
+/// images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorThresholds {
+    /// Pixels with a red component above this value can be red.
+    pub red_min: u8,
+    /// Red pixels have green and blue components below this value.
+    pub red_max_other: u8,
+    /// Pixels that are not red and darker than this value are black.
+    pub black_max: u8,
+}
+
+impl Default for ColorThresholds {
+    fn default() -> Self {
+        ColorThresholds {
+            red_min: 200,
+            red_max_other: 100,
+            black_max: 128,
+        }
+    }
+}
+
+impl ColorThresholds {
+    fn is_red(&self, r: u8, g: u8, b: u8) -> bool {
+        r > self.red_min && g < self.red_max_other && b < self.red_max_other
+    }
+
+    fn is_black(&self, r: u8, g: u8, b: u8) -> bool {
+        let brightness = ((r as u32 + g as u32 + b as u32) / 3) as u8;
+        brightness < self.black_max && !self.is_red(r, g, b)
+    }
+}
+
+/// Convert RGB image data to two-color format with custom thresholds, see
+/// `convert_rgb_to_two_color()`.
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{convert_rgb_to_two_color_with, ColorThresholds};
+/// // A dark orange pixel, not red enough for the default thresholds
+/// let rgb: Vec<u8> = [180, 60, 0].repeat(8);
+/// let thresholds = ColorThresholds { red_min: 150, ..ColorThresholds::default() };
+///
+/// let two_color = convert_rgb_to_two_color_with(8, 1, &rgb, &thresholds)?;
+/// assert_eq!(two_color.red[0], vec![0xFF]);
+/// # Ok::<(), String>(())
+/// ```
+pub fn convert_rgb_to_two_color_with(
+    width: u32,
+    height: u32,
+    rgb_data: &[u8],
+    thresholds: &ColorThresholds,
 ) -> Result<TwoColorMatrix, String> {
     if rgb_data.len() != (width * height * 3) as usize {
         return Err("RGB data size doesn't match width * height * 3".to_string());
@@ -235,9 +1008,9 @@ pub fn convert_rgb_to_two_color(
                     let g = rgb_data[pixel_index + 1];
                     let b = rgb_data[pixel_index + 2];
 
-                    if is_red_pixel(r, g, b) {
+                    if thresholds.is_red(r, g, b) {
                         red_byte |= 1 << i;
-                    } else if is_black_pixel(r, g, b) {
+                    } else if thresholds.is_black(r, g, b) {
                         black_byte |= 1 << i;
                     }
                 }
@@ -254,11 +1027,130 @@ pub fn convert_rgb_to_two_color(
     TwoColorMatrix::new(black_matrix, red_matrix)
 }
 
-fn is_red_pixel(r: u8, g: u8, b: u8) -> bool {
-    r > 200 && g < 100 && b < 100
+/// Remove fully blank rows from the start and end of a label.
+///
+/// Images exported from design tools often carry white padding which is
+/// printed as wasted tape on continuous media. Trailing blank rows are kept
+/// when needed so the result is at least `min_length` rows long.
+///
+/// # Arguments
+/// * `matrix` - 1-bit bitmap data
+/// * `min_length` - Minimum number of rows to return
+///
+/// # Returns
+/// Matrix without leading and trailing blank rows
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{trim_blank_rows, Matrix};
+/// let mut matrix: Matrix = vec![vec![0x00; 90]; 10];
+/// matrix.push(vec![0xFF; 90]);
+/// matrix.extend(vec![vec![0x00; 90]; 10]);
+///
+/// assert_eq!(trim_blank_rows(matrix.clone(), 0).len(), 1);
+/// assert_eq!(trim_blank_rows(matrix, 5).len(), 5);
+/// ```
+pub fn trim_blank_rows(matrix: Matrix, min_length: usize) -> Matrix {
+    let width = matrix.first().map(|row| row.len()).unwrap_or(0);
+    let is_blank = |row: &Vec<u8>| row.iter().all(|&b| b == 0);
+
+    let mut trimmed: Matrix = match (
+        matrix.iter().position(|row| !is_blank(row)),
+        matrix.iter().rposition(|row| !is_blank(row)),
+    ) {
+        (Some(first), Some(last)) => matrix
+            .into_iter()
+            .skip(first)
+            .take(last - first + 1)
+            .collect(),
+        _ => Matrix::new(),
+    };
+
+    while trimmed.len() < min_length {
+        trimmed.push(vec![0x00; width]);
+    }
+    trimmed
+}
+
+/// Split a long label into consecutive pages.
+///
+/// Content longer than the printable maximum, or longer than a die-cut label,
+/// can be printed as a multi-page job by splitting it every `page_length`
+/// rows. The last page keeps whatever rows remain.
+///
+/// When `marks` is set, a dotted line is drawn on both sides of every split
+/// so the pieces can be lined up again after printing.
+///
+/// # Arguments
+/// * `matrix` - 1-bit bitmap data
+/// * `page_length` - Maximum number of rows per page
+/// * `marks` - Draw alignment marks at page boundaries
+///
+/// # Returns
+/// Pages ready to be passed to `Printer::print()`
+///
+/// # Example
+/// ```rust
+/// # use ql_label::{split_pages, Matrix};
+/// let matrix: Matrix = vec![vec![0x00; 90]; 2500];
+/// let pages = split_pages(matrix, 1000, true);
+///
+/// assert_eq!(pages.len(), 3);
+/// assert_eq!(pages[2].len(), 500);
+/// assert_eq!(pages[0][999][0], 0x88); // alignment mark
+/// ```
+pub fn split_pages(matrix: Matrix, page_length: usize, marks: bool) -> Vec<Matrix> {
+    let page_length = page_length.max(1);
+    let mut pages: Vec<Matrix> = Vec::new();
+    let mut rows = matrix.into_iter().peekable();
+
+    while rows.peek().is_some() {
+        pages.push(rows.by_ref().take(page_length).collect());
+    }
+
+    if marks {
+        let count = pages.len();
+        for (i, page) in pages.iter_mut().enumerate() {
+            if i > 0 {
+                if let Some(row) = page.first_mut() {
+                    draw_split_mark(row);
+                }
+            }
+            if i + 1 < count {
+                if let Some(row) = page.last_mut() {
+                    draw_split_mark(row);
+                }
+            }
+        }
+    }
+
+    pages
+}
+
+fn draw_split_mark(row: &mut [u8]) {
+    for byte in row.iter_mut() {
+        *byte |= 0x88;
+    }
 }
 
-fn is_black_pixel(r: u8, g: u8, b: u8) -> bool {
-    let brightness = ((r as u32 + g as u32 + b as u32) / 3) as u8;
-    brightness < 128 && !is_red_pixel(r, g, b)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_equalize_uneven_tiles() {
+        // 10 pixels don't split evenly into 3, 7 or 9 tiles
+        for tiles in [3, 7, 9] {
+            let mut pixels = vec![100; 100];
+            let contrast = Contrast::Adaptive {
+                tiles,
+                clip_limit: 0.0,
+            };
+            enhance_contrast(10, 10, &mut pixels, contrast);
+
+            // Every tile maps the only gray level to white, borders aren't
+            // darkened by empty tiles
+            assert_eq!(pixels, vec![255; 100], "{} tiles", tiles);
+        }
+    }
 }
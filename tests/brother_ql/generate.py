@@ -0,0 +1,63 @@
+#!/usr/bin/env python3
+"""Write the brother_ql reference output of the conformance tests.
+
+The labels must match LABELS in src/conformance.rs. Run from this directory
+with brother_ql installed (pip install brother_ql) and commit the .bin files:
+
+    python3 generate.py
+"""
+
+from PIL import Image
+from brother_ql.conversion import convert
+from brother_ql.raster import BrotherQLRaster
+
+
+def stripes(x, y, width, rows):
+    return (x + y) % 16 < 4
+
+
+def border(x, y, width, rows):
+    return x < 4 or y < 4 or x >= width - 4 or y >= rows - 4
+
+
+def checker(x, y, width, rows):
+    return (x // 16 + y // 16) % 2 == 0
+
+
+# name, model, label, compress, printable width, rows, pattern
+LABELS = [
+    ("62-stripes", "QL-820NWB", "62", False, 696, 200, stripes),
+    ("62-stripes-packed", "QL-820NWB", "62", True, 696, 200, stripes),
+    ("29-border", "QL-800", "29", False, 306, 300, border),
+    ("29x90-checker", "QL-820NWB", "29x90", True, 306, 991, checker),
+]
+
+
+def main():
+    for name, model, label, compress, width, rows, pattern in LABELS:
+        image = Image.new("L", (width, rows), 255)
+        for y in range(rows):
+            for x in range(width):
+                if pattern(x, y, width, rows):
+                    image.putpixel((x, y), 0)
+
+        qlr = BrotherQLRaster(model)
+        qlr.exception_on_warning = True
+        data = convert(
+            qlr,
+            [image],
+            label,
+            cut=True,
+            compress=compress,
+            dither=False,
+            hq=True,
+            red=False,
+            rotate="0",
+        )
+        with open(name + ".bin", "wb") as f:
+            f.write(data)
+        print("{}: {} bytes".format(name, len(data)))
+
+
+if __name__ == "__main__":
+    main()